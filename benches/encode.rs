@@ -0,0 +1,64 @@
+//! Benchmarks of the transmit path: encoding frames as SLCAN commands
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use slcan_fd::{encode_frame, Can2Frame, CanFdFrame, CanFrame, Command, ExtendedId, StandardId};
+
+fn frames() -> [(&'static str, CanFrame); 3] {
+    let standard = StandardId::new(0x123).unwrap();
+    let extended = ExtendedId::new(0x1234_5678).unwrap();
+
+    [
+        (
+            "can2_8",
+            Can2Frame::new_data(standard, &[0xAB; 8]).unwrap().into(),
+        ),
+        (
+            "canfd_16",
+            CanFdFrame::new(extended, &[0xCD; 16]).unwrap().into(),
+        ),
+        (
+            "canfd_64",
+            CanFdFrame::new(extended, &[0xEF; 64]).unwrap().into(),
+        ),
+    ]
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+
+    for (name, frame) in frames() {
+        let command = Command::TransmitFrame(frame.clone().try_into().unwrap());
+
+        group.bench_function(format!("encode_into/{name}"), |b| {
+            let mut buffer = [0; Command::MAX_ENCODED_LEN];
+            b.iter(|| black_box(&command).encode_into(&mut buffer))
+        });
+
+        group.bench_function(format!("encode/{name}"), |b| {
+            b.iter(|| black_box(&command).encode())
+        });
+
+        group.bench_function(format!("encode_to_vec/{name}"), |b| {
+            let mut buffer = Vec::with_capacity(Command::MAX_ENCODED_LEN);
+            b.iter(|| {
+                buffer.clear();
+                black_box(&command).encode_to_vec(&mut buffer);
+            })
+        });
+
+        group.bench_function(format!("as_bytes/{name}"), |b| {
+            b.iter(|| black_box(&command).as_bytes())
+        });
+
+        group.bench_function(format!("encode_frame/{name}"), |b| {
+            b.iter(|| encode_frame(black_box(&frame)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
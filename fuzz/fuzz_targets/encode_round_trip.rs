@@ -0,0 +1,17 @@
+//! Encodes arbitrary frames and checks that parsing the encoded line gives
+//! back the same frame
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use slcan_fd::{encode_frame, parse_frame_from_bytes, CanFrame};
+
+fuzz_target!(|frame: CanFrame| {
+    let Ok(line) = encode_frame(&frame) else {
+        // SLCAN has no command for CAN XL frames
+        assert!(matches!(frame, CanFrame::CanXl(_)));
+        return;
+    };
+
+    assert_eq!(parse_frame_from_bytes(&line).unwrap(), frame);
+});
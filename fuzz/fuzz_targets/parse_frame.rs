@@ -0,0 +1,11 @@
+//! Feeds mostly well formed SLCAN lines to the parser, which must reject
+//! anything invalid without panicking
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use slcan_fd::{fuzz::RawLine, parse_frame_from_bytes};
+
+fuzz_target!(|line: RawLine| {
+    let _ = parse_frame_from_bytes(&line.0);
+});
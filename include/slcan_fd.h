@@ -0,0 +1,73 @@
+/*
+ * C bindings for the slcan_fd crate, exported by its cdylib build with the
+ * `ffi` feature:
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * See the documentation of the `slcan_fd::ffi` module.
+ */
+
+#ifndef SLCAN_FD_H
+#define SLCAN_FD_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SLCAN_OK 0
+#define SLCAN_ERROR_INVALID_ARGUMENT (-1)
+#define SLCAN_ERROR_IO (-2)
+#define SLCAN_ERROR_CHANNEL_CLOSED (-3)
+#define SLCAN_ERROR_LISTEN_ONLY (-4)
+#define SLCAN_ERROR_BUS_OFF (-5)
+#define SLCAN_ERROR_DEVICE_UNRESPONSIVE (-6)
+
+#define SLCAN_FRAME_EXTENDED (1u << 0)
+#define SLCAN_FRAME_REMOTE (1u << 1)
+#define SLCAN_FRAME_FD (1u << 2)
+#define SLCAN_FRAME_BRS (1u << 3)
+
+#define SLCAN_MODE_NORMAL 0
+#define SLCAN_MODE_SILENT 1
+
+typedef struct SlcanSocket slcan_socket;
+
+typedef struct {
+    /* The standard or extended ID, see SLCAN_FRAME_EXTENDED */
+    uint32_t id;
+    /* A combination of the SLCAN_FRAME_* flags */
+    uint32_t flags;
+    /* The number of data bytes, or the DLC of a remote frame */
+    uint8_t len;
+    uint8_t data[64];
+} slcan_frame;
+
+/* Returns a description of the last error on the calling thread, valid until
+ * the next call into this library on the same thread */
+const char *slcan_last_error(void);
+
+/* Opens the serial port at path and closes the channel of the gateway behind
+ * it. Returns NULL on failure. */
+slcan_socket *slcan_open(const char *path);
+
+/* Closes the channel, sets the bit rates and operating mode and opens the
+ * channel again. A data_bps of 0 leaves the data bit rate unchanged. */
+int slcan_configure(slcan_socket *socket, uint32_t nominal_bps, uint32_t data_bps, int mode);
+
+/* Sends a frame through the gateway */
+int slcan_send(slcan_socket *socket, const slcan_frame *frame);
+
+/* Waits up to timeout_ms for a frame. Returns 1 if a frame was received, 0 if
+ * the timeout passed or a negative error code. */
+int slcan_recv(slcan_socket *socket, slcan_frame *frame, uint32_t timeout_ms);
+
+/* Closes the channel and the serial port and frees the socket */
+void slcan_close(slcan_socket *socket);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SLCAN_FD_H */
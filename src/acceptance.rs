@@ -0,0 +1,280 @@
+use embedded_can::Id;
+
+/// An acceptance filter of a socket: frames with an ID of the same kind as
+/// `id` pass if the bits of their ID selected by `mask` equal those of `id`.
+///
+/// Filters are added with `CanSocket::add_filter`. See
+/// `CanSocket::set_filter_banks` for how they are mapped onto the filter banks
+/// of the gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AcceptanceFilter {
+    /// The ID to compare against
+    pub id: Id,
+    /// The bits of the ID which have to match
+    pub mask: u32,
+}
+
+impl AcceptanceFilter {
+    /// Creates a filter passing the IDs whose bits selected by `mask` equal
+    /// those of `id`
+    pub fn new(id: impl Into<Id>, mask: u32) -> Self {
+        Self {
+            id: id.into(),
+            mask,
+        }
+    }
+
+    /// Creates a filter passing only `id`
+    pub fn exact(id: impl Into<Id>) -> Self {
+        let id = id.into();
+        let mask = match id {
+            Id::Standard(_) => 0x7FF,
+            Id::Extended(_) => 0x1FFF_FFFF,
+        };
+
+        Self { id, mask }
+    }
+
+    /// Returns true if frames with the given ID pass the filter
+    pub fn matches(&self, id: Id) -> bool {
+        match (id, self.id) {
+            (Id::Standard(id), Id::Standard(filter)) => {
+                (id.as_raw() ^ filter.as_raw()) as u32 & self.mask == 0
+            }
+            (Id::Extended(id), Id::Extended(filter)) => {
+                (id.as_raw() ^ filter.as_raw()) & self.mask == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the narrowest filter passing everything both filters pass, or
+    /// `None` if they are for different kinds of IDs
+    fn merge(&self, other: &AcceptanceFilter) -> Option<AcceptanceFilter> {
+        let differing = match (self.id, other.id) {
+            (Id::Standard(a), Id::Standard(b)) => (a.as_raw() ^ b.as_raw()) as u32,
+            (Id::Extended(a), Id::Extended(b)) => a.as_raw() ^ b.as_raw(),
+            _ => return None,
+        };
+
+        Some(AcceptanceFilter {
+            id: self.id,
+            mask: self.mask & other.mask & !differing,
+        })
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for AcceptanceFilter {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "AcceptanceFilter {{ id: ");
+        crate::frame::format_id(self.id, f);
+        defmt::write!(f, ", mask: {=u32:#010X} }}", self.mask);
+    }
+}
+
+/// Encodes the command (without the CR line ending) which programs a filter
+/// bank, or clears it for `None`. See `CanSocket::set_filter_banks`.
+pub(crate) type FilterBankEncoder = Box<dyn Fn(usize, Option<&AcceptanceFilter>) -> Vec<u8> + Send>;
+
+/// The acceptance filters of a socket and the filter banks of the gateway
+/// they are mapped onto
+#[derive(Default)]
+pub(crate) struct AcceptanceFilters {
+    filters: Vec<AcceptanceFilter>,
+    banks: Vec<Option<AcceptanceFilter>>,
+    encoder: Option<FilterBankEncoder>,
+}
+
+impl AcceptanceFilters {
+    pub fn filters(&self) -> &[AcceptanceFilter] {
+        &self.filters
+    }
+
+    pub fn banks(&self) -> &[Option<AcceptanceFilter>] {
+        &self.banks
+    }
+
+    /// Returns true if frames with the given ID pass the filters, which is
+    /// the case for all IDs if there are no filters
+    pub fn accepts(&self, id: Id) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|filter| filter.matches(id))
+    }
+
+    pub fn set_banks(&mut self, count: usize, encoder: Option<FilterBankEncoder>) {
+        self.banks = vec![None; count];
+        self.encoder = encoder;
+    }
+
+    pub fn push(&mut self, filter: AcceptanceFilter) {
+        self.filters.push(filter);
+    }
+
+    pub fn remove(&mut self, filter: &AcceptanceFilter) -> bool {
+        let count = self.filters.len();
+        self.filters.retain(|other| other != filter);
+        self.filters.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Works out the contents of the filter banks for the current filters
+    /// and returns the commands for the banks which changed (or all banks if
+    /// `all` is set), marking them as programmed
+    pub fn update_banks(&mut self, all: bool) -> Vec<Vec<u8>> {
+        let Some(encoder) = self.encoder.as_ref() else {
+            return Vec::new();
+        };
+
+        let mut planned = self.plan();
+        planned.resize(self.banks.len(), None);
+
+        let mut commands = Vec::new();
+        for (index, (bank, planned)) in self.banks.iter_mut().zip(planned).enumerate() {
+            if all || *bank != planned {
+                commands.push(encoder(index, planned.as_ref()));
+                *bank = planned;
+            }
+        }
+
+        commands
+    }
+
+    /// Fits the filters into the banks. If there are too many, the pair of
+    /// filters which loses the fewest mask bits is merged until they fit, so
+    /// the banks pass a superset of the frames and the rest is filtered in
+    /// software. If they can't be fit (e.g. a single bank for both standard
+    /// and extended IDs), all banks are left empty so every frame passes.
+    fn plan(&self) -> Vec<Option<AcceptanceFilter>> {
+        let mut planned: Vec<AcceptanceFilter> = Vec::new();
+        for filter in &self.filters {
+            if !planned.contains(filter) {
+                planned.push(*filter);
+            }
+        }
+
+        while planned.len() > self.banks.len() {
+            let mut best: Option<(usize, usize, AcceptanceFilter)> = None;
+
+            for i in 0..planned.len() {
+                for j in i + 1..planned.len() {
+                    let Some(merged) = planned[i].merge(&planned[j]) else {
+                        continue;
+                    };
+
+                    if best.as_ref().map_or(true, |(_, _, best)| {
+                        merged.mask.count_ones() > best.mask.count_ones()
+                    }) {
+                        best = Some((i, j, merged));
+                    }
+                }
+            }
+
+            let Some((i, j, merged)) = best else {
+                return Vec::new();
+            };
+
+            planned[i] = merged;
+            planned.remove(j);
+        }
+
+        planned.into_iter().map(Some).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, StandardId};
+
+    use super::*;
+
+    fn standard(id: u16) -> Id {
+        StandardId::new(id).unwrap().into()
+    }
+
+    /// Creates filters for `count` banks whose commands name the bank and
+    /// the mask it was programmed with
+    fn filters(count: usize) -> AcceptanceFilters {
+        let mut filters = AcceptanceFilters::default();
+        filters.set_banks(
+            count,
+            Some(Box::new(|index, filter| match filter {
+                Some(filter) => format!("{index}:{:03X}", filter.mask).into_bytes(),
+                None => format!("{index}:-").into_bytes(),
+            })),
+        );
+        filters
+    }
+
+    #[test]
+    fn merges_the_closest_filters_when_banks_run_out() {
+        let mut filters = filters(2);
+        for id in [0x100, 0x200, 0x101] {
+            filters.push(AcceptanceFilter::exact(standard(id)));
+        }
+
+        assert_eq!(filters.update_banks(false), [b"0:7FE", b"1:7FF"]);
+        assert_eq!(
+            filters.banks(),
+            [
+                Some(AcceptanceFilter::new(standard(0x100), 0x7FE)),
+                Some(AcceptanceFilter::exact(standard(0x200))),
+            ]
+        );
+
+        // The banks pass a superset of the filters, the rest is left to
+        // software
+        for id in [0x100, 0x101, 0x200] {
+            assert!(filters.accepts(standard(id)));
+        }
+        assert!(!filters.accepts(standard(0x102)));
+    }
+
+    #[test]
+    fn merges_filters_down_to_a_single_bank() {
+        let mut filters = filters(1);
+        for id in [0x100, 0x101, 0x102, 0x104] {
+            filters.push(AcceptanceFilter::exact(standard(id)));
+        }
+
+        filters.update_banks(false);
+        assert_eq!(
+            filters.banks(),
+            [Some(AcceptanceFilter::new(standard(0x100), 0x7F8))]
+        );
+    }
+
+    #[test]
+    fn leaves_banks_empty_when_id_kinds_cannot_be_merged() {
+        let mut filters = filters(1);
+        filters.push(AcceptanceFilter::exact(standard(0x100)));
+        filters.push(AcceptanceFilter::exact(ExtendedId::new(0x100).unwrap()));
+
+        assert_eq!(filters.update_banks(false), Vec::<Vec<u8>>::new());
+        assert_eq!(filters.banks(), [None]);
+    }
+
+    #[test]
+    fn only_reprograms_banks_which_changed() {
+        let mut filters = filters(3);
+        filters.push(AcceptanceFilter::exact(standard(0x100)));
+        assert_eq!(filters.update_banks(false), [b"0:7FF"]);
+
+        // Duplicates take no bank of their own
+        filters.push(AcceptanceFilter::exact(standard(0x100)));
+        filters.push(AcceptanceFilter::new(standard(0x200), 0x700));
+        assert_eq!(filters.update_banks(false), [b"1:700"]);
+
+        filters.remove(&AcceptanceFilter::exact(standard(0x100)));
+        assert_eq!(
+            filters.update_banks(false),
+            [b"0:700".to_vec(), b"1:-".to_vec()]
+        );
+        assert_eq!(
+            filters.update_banks(true),
+            [b"0:700".to_vec(), b"1:-".to_vec(), b"2:-".to_vec()]
+        );
+    }
+}
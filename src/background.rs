@@ -0,0 +1,659 @@
+//! Reading a socket from a background task and fanning the received frames
+//! out to multiple consumers.
+//!
+//! [`BackgroundReader::spawn`] moves a [`CanSocket`] into a tokio task which
+//! reads continuously. Any number of consumers can then
+//! [`subscribe`](BackgroundReader::subscribe) with an [`IdFilter`] and only
+//! receive the frames they are interested in, e.g. a diagnostic task
+//! listening for its response IDs next to a logger receiving everything.
+//! Frames can still be sent through [`BackgroundReader::send`], or from any
+//! number of tasks at once through cheap clones of the [`CanSender`] returned
+//! by [`BackgroundReader::sender`]. [`BackgroundReader::spawn_heartbeat`]
+//! keeps transmitting a periodic frame, and
+//! [`BackgroundReader::shutdown_on`] stops reading on a shutdown signal.
+//!
+//! Like the receive operations of the SocketCAN broadcast manager,
+//! [`BackgroundReader::watch_content`] only reports the frames of an ID
+//! whose (masked) content changed, and when the ID stops arriving.
+//!
+//! ```no_run
+//! # async fn run(socket: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>) {
+//! use slcan_fd::background::{BackgroundReader, IdFilter};
+//!
+//! let reader = BackgroundReader::spawn(socket);
+//!
+//! let mut diagnostics = reader.subscribe(IdFilter::new().with_standard_range(0x7E8..=0x7EF));
+//! let mut everything = reader.subscribe(IdFilter::all());
+//!
+//! while let Some(frame) = diagnostics.recv().await {
+//!     println!("{:?}", frame);
+//! }
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+
+use crate::{
+    frame::CanFrame, heartbeat::Heartbeat, tokio::CanSocket, Id, ReadError, RxFrame, SendError,
+};
+
+pub use crate::filter::IdFilter;
+
+/// The number of frames buffered for each subscriber by default
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// A stream of the received frames which passed the filter given to
+/// [`BackgroundReader::subscribe`]
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: mpsc::Receiver<RxFrame>,
+}
+
+impl Subscription {
+    /// Waits for the next frame. Returns `None` once the background reader
+    /// has stopped and all buffered frames were received.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe.
+    pub async fn recv(&mut self) -> Option<RxFrame> {
+        self.receiver.recv().await
+    }
+
+    /// Returns the next buffered frame without waiting, if any
+    pub fn try_recv(&mut self) -> Option<RxFrame> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = RxFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<RxFrame>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// A [`Heartbeat`] transmitted by [`BackgroundReader::spawn_heartbeat`].
+/// Dropping it stops the heartbeat.
+#[derive(Debug)]
+pub struct HeartbeatTask {
+    task: JoinHandle<SendError>,
+}
+
+impl HeartbeatTask {
+    /// Returns true if the heartbeat has stopped because sending failed
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stops the heartbeat. Returns the error which stopped it earlier, if
+    /// any.
+    pub async fn stop(mut self) -> Option<SendError> {
+        self.task.abort();
+
+        match (&mut self.task).await {
+            Ok(error) => Some(error),
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => None,
+        }
+    }
+}
+
+impl Drop for HeartbeatTask {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Selects the frames reported by [`BackgroundReader::watch_content`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentFilter {
+    id: Id,
+    mask: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+impl ContentFilter {
+    /// Creates a filter which reports the frames with `id` whenever their
+    /// data or length changes
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            id: id.into(),
+            mask: None,
+            timeout: None,
+        }
+    }
+
+    /// Only compares the bits of the data which are set in `mask`, e.g.
+    /// `[0x00, 0xFF]` to ignore an alive counter in the first byte. Bytes
+    /// beyond the end of the mask are ignored entirely.
+    pub fn with_mask(mut self, mask: &[u8]) -> Self {
+        self.mask = Some(mask.to_vec());
+        self
+    }
+
+    /// Reports [`ContentEvent::Timeout`] when no frame with the ID arrives
+    /// within `timeout`, e.g. because the sending node went silent
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the ID of the frames which are watched
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns the length and the masked data of a frame, which are compared
+    /// with the previous frame. Remote frames have no content.
+    fn content(&self, frame: &CanFrame) -> Option<(usize, Vec<u8>)> {
+        let data = frame.data()?;
+
+        let masked = match &self.mask {
+            Some(mask) => data
+                .iter()
+                .zip(mask)
+                .map(|(byte, mask)| byte & mask)
+                .collect(),
+            None => data.to_vec(),
+        };
+
+        Some((data.len(), masked))
+    }
+}
+
+/// An event reported by a [ContentWatch]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentEvent {
+    /// A frame arrived whose content differs from the previous one. The
+    /// first frame and the first one after a timeout are always reported.
+    Changed(RxFrame),
+    /// No frame arrived within the timeout. Reported once until frames
+    /// arrive again.
+    Timeout,
+}
+
+/// The changes of the frames selected by a [ContentFilter], returned by
+/// [`BackgroundReader::watch_content`]. Dropping it stops watching.
+#[derive(Debug)]
+pub struct ContentWatch {
+    receiver: mpsc::Receiver<ContentEvent>,
+    task: JoinHandle<()>,
+}
+
+impl ContentWatch {
+    /// Waits for the next event. Returns `None` once the background reader
+    /// has stopped and all buffered events were received.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe.
+    pub async fn recv(&mut self) -> Option<ContentEvent> {
+        self.receiver.recv().await
+    }
+
+    /// Returns the next buffered event without waiting, if any
+    pub fn try_recv(&mut self) -> Option<ContentEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Stream for ContentWatch {
+    type Item = ContentEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ContentEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for ContentWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A handle for sending frames through the socket of a [`BackgroundReader`],
+/// returned by [`BackgroundReader::sender`]. Clones are cheap and can be
+/// moved into other tasks, which then transmit concurrently without sharing
+/// the socket. Frames are sent in the order in which they were submitted.
+///
+/// The background task keeps running while any sender exists, even after
+/// the [`BackgroundReader`] was dropped, until it is shut down or fails.
+#[derive(Debug, Clone)]
+pub struct CanSender {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl CanSender {
+    /// Sends a frame through the socket and waits for the result. See
+    /// `CanSocket::send`.
+    pub async fn send(&self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+        let (reply, response) = oneshot::channel();
+
+        self.requests
+            .send(Request::Send(frame.into(), reply))
+            .map_err(|_| stopped())?;
+
+        response.await.map_err(|_| stopped())?
+    }
+
+    /// Returns true if the background task has stopped, so frames can't be
+    /// sent anymore
+    pub fn is_closed(&self) -> bool {
+        self.requests.is_closed()
+    }
+}
+
+struct Subscriber {
+    filter: IdFilter,
+    sender: mpsc::Sender<RxFrame>,
+}
+
+#[derive(Debug)]
+enum Request {
+    Send(CanFrame, oneshot::Sender<Result<(), SendError>>),
+    Stop,
+}
+
+/// Owns a [`CanSocket`] which is read continuously by a background task.
+/// See the [module documentation](self).
+pub struct BackgroundReader<P> {
+    /// `None` once the background task has stopped
+    subscribers: Arc<Mutex<Option<Vec<Subscriber>>>>,
+    requests: mpsc::UnboundedSender<Request>,
+    task: JoinHandle<Result<CanSocket<P>, ReadError>>,
+}
+
+impl<P> BackgroundReader<P>
+where
+    P: AsyncRead + AsyncWrite + Send + 'static,
+{
+    /// Moves `socket` into a new tokio task which reads from it until
+    /// [`shutdown`](BackgroundReader::shutdown) is called or an I/O error
+    /// occurs. Lines which can't be parsed are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn spawn(socket: CanSocket<P>) -> Self {
+        let subscribers = Arc::new(Mutex::new(Some(Vec::new())));
+        let (requests, receiver) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(run(socket, subscribers.clone(), receiver));
+
+        Self {
+            subscribers,
+            requests,
+            task,
+        }
+    }
+
+    /// Subscribes to the received frames which pass `filter`, buffering up to
+    /// [`DEFAULT_CAPACITY`] frames. See
+    /// [`subscribe_with_capacity`](BackgroundReader::subscribe_with_capacity).
+    pub fn subscribe(&self, filter: IdFilter) -> Subscription {
+        self.subscribe_with_capacity(filter, DEFAULT_CAPACITY)
+    }
+
+    /// Subscribes to the received frames which pass `filter`.
+    ///
+    /// Up to `capacity` frames are buffered for the subscription. Frames
+    /// arriving while the buffer is full are dropped for this subscription
+    /// only, so a slow consumer never holds up the others. Dropping the
+    /// [`Subscription`] unsubscribes. If the background task has already
+    /// stopped, the subscription ends right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn subscribe_with_capacity(&self, filter: IdFilter, capacity: usize) -> Subscription {
+        let (sender, receiver) = mpsc::channel(capacity);
+
+        if let Some(subscribers) = self.subscribers.lock().unwrap().as_mut() {
+            subscribers.push(Subscriber { filter, sender });
+        }
+
+        Subscription { receiver }
+    }
+
+    /// Sends a frame through the socket. See `CanSocket::send`.
+    pub async fn send(&self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+        self.sender().send(frame).await
+    }
+
+    /// Returns a handle for sending frames from other tasks. See
+    /// [CanSender].
+    pub fn sender(&self) -> CanSender {
+        CanSender {
+            requests: self.requests.clone(),
+        }
+    }
+
+    /// Transmits the frames of `heartbeat` through the socket every period
+    /// until the returned [`HeartbeatTask`] is stopped or dropped, or a frame
+    /// fails to send. If sending a frame is delayed, the following frames
+    /// keep their period relative to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the period of the heartbeat is zero.
+    pub fn spawn_heartbeat(&self, mut heartbeat: Heartbeat) -> HeartbeatTask {
+        let requests = self.requests.clone();
+        let mut interval = tokio::time::interval(heartbeat.period());
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let task = tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                let (reply, response) = oneshot::channel();
+                let request = Request::Send(heartbeat.next_frame(), reply);
+
+                if requests.send(request).is_err() {
+                    return stopped();
+                }
+                match response.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return e,
+                    Err(_) => return stopped(),
+                }
+            }
+        });
+
+        HeartbeatTask { task }
+    }
+
+    /// Watches the frames selected by `filter` in the background, like a
+    /// receive operation of the SocketCAN broadcast manager (`RX_SETUP`):
+    /// only the frames whose content changed are reported, and the timeout
+    /// of the filter is reported when the frames stop arriving.
+    ///
+    /// ```no_run
+    /// # async fn run(socket: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>) {
+    /// use slcan_fd::background::{BackgroundReader, ContentEvent, ContentFilter};
+    /// use slcan_fd::StandardId;
+    /// use std::time::Duration;
+    ///
+    /// let reader = BackgroundReader::spawn(socket);
+    ///
+    /// // Ignore the alive counter in the first byte
+    /// let filter = ContentFilter::new(StandardId::new(0x120).unwrap())
+    ///     .with_mask(&[0x00, 0xFF, 0xFF, 0xFF])
+    ///     .with_timeout(Duration::from_millis(500));
+    /// let mut watch = reader.watch_content(filter);
+    ///
+    /// while let Some(event) = watch.recv().await {
+    ///     match event {
+    ///         ContentEvent::Changed(frame) => println!("{:?}", frame.frame),
+    ///         ContentEvent::Timeout => println!("0x120 went silent"),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn watch_content(&self, filter: ContentFilter) -> ContentWatch {
+        let mut subscription = self.subscribe(IdFilter::new().with_id(filter.id));
+        let (sender, receiver) = mpsc::channel(DEFAULT_CAPACITY);
+
+        let task = tokio::spawn(async move {
+            let mut last = None;
+            let mut timed_out = false;
+
+            loop {
+                let frame = match filter.timeout.filter(|_| !timed_out) {
+                    Some(timeout) => match time::timeout(timeout, subscription.recv()).await {
+                        Ok(frame) => frame,
+                        Err(_) => {
+                            timed_out = true;
+                            last = None;
+
+                            if sender.send(ContentEvent::Timeout).await.is_err() {
+                                return;
+                            }
+                            continue;
+                        }
+                    },
+                    None => subscription.recv().await,
+                };
+
+                let Some(frame) = frame else {
+                    return;
+                };
+                timed_out = false;
+
+                let content = filter.content(&frame.frame);
+                if last.as_ref() == Some(&content) {
+                    continue;
+                }
+                last = Some(content);
+
+                if sender.send(ContentEvent::Changed(frame)).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        ContentWatch { receiver, task }
+    }
+
+    /// Stops the background task once `signal` completes, as part of an
+    /// application wide graceful shutdown. Any future can be used, such as
+    /// `tokio_util::sync::CancellationToken::cancelled_owned` or
+    /// `tokio::signal::ctrl_c`. Subscriptions and heartbeats end like after
+    /// [`shutdown`](BackgroundReader::shutdown), which still returns the
+    /// socket afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn shutdown_on(&self, signal: impl Future + Send + 'static) {
+        let requests = self.requests.clone();
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = signal => {
+                    let _ = requests.send(Request::Stop);
+                }
+                // Don't keep waiting for the signal once the task stopped
+                _ = requests.closed() => {}
+            }
+        });
+    }
+
+    /// Returns true if the background task has stopped, e.g. because of an
+    /// I/O error
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stops the background task and returns the socket, or the error which
+    /// stopped the task earlier. Subscriptions receive any frames which were
+    /// already buffered and then end.
+    pub async fn shutdown(self) -> Result<CanSocket<P>, ReadError> {
+        let _ = self.requests.send(Request::Stop);
+
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+fn stopped() -> SendError {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "the background reader has stopped",
+    )
+    .into()
+}
+
+async fn run<P: AsyncRead + AsyncWrite>(
+    socket: CanSocket<P>,
+    subscribers: Arc<Mutex<Option<Vec<Subscriber>>>>,
+    requests: mpsc::UnboundedReceiver<Request>,
+) -> Result<CanSocket<P>, ReadError> {
+    let result = read_loop(socket, &subscribers, requests).await;
+
+    // End the subscriptions right away, even if the reader is kept around
+    // after being stopped by `shutdown_on` or an error
+    subscribers.lock().unwrap().take();
+
+    result
+}
+
+async fn read_loop<P: AsyncRead + AsyncWrite>(
+    mut socket: CanSocket<P>,
+    subscribers: &Mutex<Option<Vec<Subscriber>>>,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+) -> Result<CanSocket<P>, ReadError> {
+    loop {
+        // Reading is cancel safe, so it can be interrupted by requests
+        // without losing any partially received line
+        let request = tokio::select! {
+            request = requests.recv() => request,
+            result = socket.read_rx_frame() => {
+                match result {
+                    Ok(frame) => dispatch(subscribers, frame),
+                    Err(ReadError::Slcan(_) | ReadError::LineTooLong(_)) => {}
+                    Err(e) => return Err(e),
+                }
+
+                continue;
+            }
+        };
+
+        match request {
+            Some(Request::Send(frame, reply)) => {
+                let _ = reply.send(socket.send(frame).await);
+            }
+            Some(Request::Stop) | None => return Ok(socket),
+        }
+    }
+}
+
+fn dispatch(subscribers: &Mutex<Option<Vec<Subscriber>>>, frame: RxFrame) {
+    let mut subscribers = subscribers.lock().unwrap();
+    let Some(subscribers) = subscribers.as_mut() else {
+        return;
+    };
+    let id = frame.frame.id();
+
+    subscribers.retain(|subscriber| {
+        if subscriber.filter.matches(id) {
+            match subscriber.sender.try_send(frame.clone()) {
+                Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => true,
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            }
+        } else {
+            !subscriber.sender.is_closed()
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncWriteExt, DuplexStream};
+
+    use super::*;
+    use crate::{ExtendedId, StandardId};
+
+    /// Spawns a reader whose gateway is emulated through the returned stream
+    fn spawn() -> (BackgroundReader<DuplexStream>, DuplexStream) {
+        let (port, gateway) = tokio::io::duplex(1024);
+        (BackgroundReader::spawn(CanSocket::new(port)), gateway)
+    }
+
+    fn standard(id: u16) -> Id {
+        StandardId::new(id).unwrap().into()
+    }
+
+    async fn next_id(subscription: &mut Subscription) -> Id {
+        subscription.recv().await.unwrap().frame.id()
+    }
+
+    #[tokio::test]
+    async fn dispatches_frames_to_matching_subscribers() {
+        let (reader, mut gateway) = spawn();
+        let extended = ExtendedId::new(0x100).unwrap();
+
+        let mut range = reader.subscribe(IdFilter::new().with_standard_range(0x100..=0x1FF));
+        let mut everything = reader.subscribe(IdFilter::all());
+        let mut single = reader.subscribe(IdFilter::new().with_id(extended));
+
+        // Lines which can't be parsed are skipped
+        gateway
+            .write_all(b"t1001AA\rxyz\rt2001BB\rT000001001CC\r")
+            .await
+            .unwrap();
+
+        assert_eq!(next_id(&mut everything).await, standard(0x100));
+        assert_eq!(next_id(&mut everything).await, standard(0x200));
+        assert_eq!(next_id(&mut everything).await, extended.into());
+
+        reader.shutdown().await.unwrap();
+
+        assert_eq!(next_id(&mut range).await, standard(0x100));
+        assert!(range.recv().await.is_none());
+        assert_eq!(next_id(&mut single).await, extended.into());
+        assert!(single.recv().await.is_none());
+        assert!(everything.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_frames_only_for_full_subscriptions() {
+        let (reader, mut gateway) = spawn();
+
+        let mut slow = reader.subscribe_with_capacity(IdFilter::all(), 1);
+        let mut fast = reader.subscribe(IdFilter::all());
+
+        gateway.write_all(b"t1000\rt2000\rt3000\r").await.unwrap();
+        for id in [0x100, 0x200, 0x300] {
+            assert_eq!(next_id(&mut fast).await, standard(id));
+        }
+
+        assert_eq!(next_id(&mut slow).await, standard(0x100));
+        assert!(slow.try_recv().is_none());
+
+        // The subscription keeps receiving once there is room again
+        gateway.write_all(b"t4000\r").await.unwrap();
+        assert_eq!(next_id(&mut fast).await, standard(0x400));
+        assert_eq!(next_id(&mut slow).await, standard(0x400));
+    }
+
+    #[tokio::test]
+    async fn unsubscribes_dropped_subscriptions() {
+        let (reader, mut gateway) = spawn();
+
+        let matching = reader.subscribe(IdFilter::new().with_id(standard(0x100)));
+        let other = reader.subscribe(IdFilter::new().with_id(standard(0x200)));
+        let mut everything = reader.subscribe(IdFilter::all());
+        drop(matching);
+        drop(other);
+
+        gateway.write_all(b"t1000\r").await.unwrap();
+        assert_eq!(next_id(&mut everything).await, standard(0x100));
+
+        let subscribers = reader.subscribers.lock().unwrap();
+        assert_eq!(subscribers.as_ref().map(Vec::len), Some(1));
+    }
+}
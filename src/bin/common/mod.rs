@@ -0,0 +1,85 @@
+//! Option parsing and port setup shared by the command line tools
+
+use std::{fmt::Display, process, str::FromStr};
+
+use slcan_fd::{tokio::CanSocket, DataBitRate, Dialect, NominalBitRate, OperatingMode};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// The bit rates and mode a tool configures the gateway with
+pub struct PortOptions {
+    pub nominal_bit_rate: NominalBitRate,
+    pub data_bit_rate: Option<DataBitRate>,
+    pub silent: bool,
+    pub dialect: Dialect,
+}
+
+impl Default for PortOptions {
+    fn default() -> Self {
+        Self {
+            nominal_bit_rate: NominalBitRate::Rate500Kbit,
+            data_bit_rate: None,
+            silent: false,
+            dialect: Dialect::Standard,
+        }
+    }
+}
+
+impl PortOptions {
+    /// Handles the options common to all tools (`-b`, `-d` and `-u`). Returns
+    /// false if `flag` isn't one of them.
+    pub fn parse(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        match flag {
+            "-b" => self.nominal_bit_rate = parse_value(flag, args, NominalBitRate::from_bps),
+            "-d" => self.data_bit_rate = Some(parse_value(flag, args, DataBitRate::from_bps)),
+            "-u" => self.dialect = Dialect::UsbTin,
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Opens the serial port at `path` and the CAN channel of the gateway
+    /// behind it
+    pub async fn open(&self, path: &str) -> std::io::Result<CanSocket<SerialStream>> {
+        #[allow(unused_mut)]
+        let mut port = tokio_serial::new(path, 115_200).open_native_async()?;
+
+        #[cfg(unix)]
+        port.set_exclusive(false)?;
+
+        let mut can = CanSocket::new(port);
+        can.set_dialect(self.dialect);
+
+        can.close().await?;
+        if let Some(rate) = self.data_bit_rate {
+            can.set_data_bit_rate(rate).await?;
+        }
+        if self.silent {
+            can.set_operating_mode(OperatingMode::Silent).await?;
+        }
+        can.open(self.nominal_bit_rate).await?;
+
+        Ok(can)
+    }
+}
+
+/// Parses the value following `flag`, exiting with an error if it is missing
+/// or invalid
+pub fn parse_value<T, E: Display>(
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+    parse: impl FnOnce(u32) -> Result<T, E>,
+) -> T {
+    let Some(value) = args.next() else {
+        fail(format!("{} requires a value", flag));
+    };
+
+    let value = u32::from_str(&value).unwrap_or_else(|e| fail(format!("{}: {}", flag, e)));
+    parse(value).unwrap_or_else(|e| fail(format!("{}: {}", flag, e)))
+}
+
+/// Prints `message` and exits with an error
+pub fn fail(message: impl Display) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
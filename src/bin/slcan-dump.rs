@@ -0,0 +1,161 @@
+//! Prints the frames received by an SLCAN gateway in the candump log format,
+//! or colored with the bytes which changed highlighted, optionally writing
+//! them to a log file as well.
+
+mod common;
+
+use std::{
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use common::{fail, PortOptions};
+use slcan_fd::{
+    fmt::ColorFormatter,
+    logging::{
+        candump::CandumpWriter, create_log, csv::CsvWriter, trc::TrcWriter, Compression, LogFormat,
+        LogRecord, LogWriter,
+    },
+    IdFilter, ReadError,
+};
+
+const USAGE: &str = "\
+usage: slcan-dump [options] <tty>
+
+options:
+  -b <bit/s>      nominal bit rate (default 500000)
+  -c              print the frames in aligned columns with a color per ID
+                  and the bytes which changed highlighted, instead of the
+                  candump log format
+  -d <bit/s>      data bit rate of CAN FD frames with BRS
+  -f <filter>     only show frames whose ID matches the filter, e.g.
+                  100:7F0,200-2FF,!205 (repeatable)
+  -l <file>       also write the frames to a log file, whose format is
+                  picked from its extension (.log, .csv or .trc, optionally
+                  followed by .gz or .zst)
+  -s              listen only (silent mode)
+  -u              talk to a USBtin adapter";
+
+fn create_writer(path: &str) -> io::Result<Box<dyn LogWriter>> {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match Compression::from_path(path).extension() {
+        Some(extension) => name
+            .strip_suffix(&format!(".{}", extension))
+            .unwrap_or(&name),
+        None => &name,
+    };
+    let format = [LogFormat::Csv, LogFormat::Trc]
+        .into_iter()
+        .find(|format| name.ends_with(&format!(".{}", format.extension())))
+        .unwrap_or_default();
+
+    let file = create_log(path)?;
+
+    Ok(match format {
+        LogFormat::Candump => Box::new(CandumpWriter::new(file)),
+        LogFormat::Csv => Box::new(CsvWriter::new(file)),
+        LogFormat::Trc => Box::new(TrcWriter::new(file)),
+    })
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut options = PortOptions::default();
+    let mut filters: Vec<String> = Vec::new();
+    let mut log_path = None;
+    let mut color = None;
+    let mut tty = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if options.parse(&arg, &mut args) {
+            continue;
+        }
+
+        match arg.as_str() {
+            "-c" => color = Some(ColorFormatter::new()),
+            "-f" => filters.push(args.next().unwrap_or_else(|| fail("-f requires a filter"))),
+            "-l" => log_path = Some(args.next().unwrap_or_else(|| fail("-l requires a file"))),
+            "-s" => options.silent = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => fail(format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ => tty = Some(arg),
+        }
+    }
+
+    let Some(tty) = tty else {
+        fail(USAGE);
+    };
+
+    let filter = filters
+        .join(",")
+        .parse::<IdFilter>()
+        .unwrap_or_else(|e| fail(format!("-f: {}", e)));
+
+    let mut log = log_path.map(|path| {
+        create_writer(&path).unwrap_or_else(|e| fail(format!("failed to create {}: {}", path, e)))
+    });
+    let mut stdout = CandumpWriter::new(io::stdout().lock());
+
+    let mut can = options
+        .open(&tty)
+        .await
+        .unwrap_or_else(|e| fail(format!("failed to open {}: {}", tty, e)));
+
+    loop {
+        let result = tokio::select! {
+            result = can.read_rx_frame() => result,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        let frame = match result {
+            Ok(frame) => frame,
+            Err(ReadError::Io(e)) => fail(e),
+            Err(e) => {
+                eprintln!("warning: {}", e);
+                continue;
+            }
+        };
+
+        if !filter.matches(frame.frame.id()) {
+            continue;
+        }
+
+        let record = LogRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            channel: frame.channel,
+            direction: frame.direction,
+            frame: frame.frame,
+        };
+
+        if let Some(formatter) = color.as_mut() {
+            println!("{}", formatter.format(&record.frame));
+        } else if let Err(e) = stdout.write_record(&record).and_then(|_| stdout.flush()) {
+            fail(e);
+        }
+        if let Some(log) = log.as_mut() {
+            if let Err(e) = log.write_record(&record) {
+                fail(e);
+            }
+        }
+    }
+
+    if let Some(mut log) = log {
+        if let Err(e) = log.flush() {
+            fail(e);
+        }
+    }
+
+    if let Err(e) = can.shutdown().await {
+        fail(e);
+    }
+}
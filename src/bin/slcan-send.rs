@@ -0,0 +1,81 @@
+//! Sends frames given in the cansend notation through an SLCAN gateway.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{fail, PortOptions};
+use slcan_fd::CanFrame;
+
+const USAGE: &str = "\
+usage: slcan-send [options] <tty> <frame>...
+
+Frames are given in the notation of cansend, e.g. 123#DEADBEEF,
+1F334455#R or 123##1112233 (CAN FD with BRS).
+
+options:
+  -b <bit/s>      nominal bit rate (default 500000)
+  -d <bit/s>      data bit rate of CAN FD frames with BRS
+  -t <ms>         time to wait for the gateway to acknowledge each frame
+                  (default 100)
+  -u              talk to a USBtin adapter";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut options = PortOptions::default();
+    let mut timeout = Duration::from_millis(100);
+    let mut tty = None;
+    let mut frames = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if options.parse(&arg, &mut args) {
+            continue;
+        }
+
+        match arg.as_str() {
+            "-t" => {
+                timeout = common::parse_value("-t", &mut args, |ms| {
+                    Ok::<_, String>(Duration::from_millis(ms.into()))
+                })
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => fail(format!("unknown option {}\n\n{}", arg, USAGE)),
+            _ if tty.is_none() => tty = Some(arg),
+            _ => frames.push(
+                arg.parse::<CanFrame>()
+                    .unwrap_or_else(|e| fail(format!("invalid frame '{}': {}", arg, e))),
+            ),
+        }
+    }
+
+    let Some(tty) = tty else {
+        fail(USAGE);
+    };
+    if frames.is_empty() {
+        fail(USAGE);
+    }
+
+    let mut can = options
+        .open(&tty)
+        .await
+        .unwrap_or_else(|e| fail(format!("failed to open {}: {}", tty, e)));
+
+    for frame in frames {
+        let result = can
+            .send_and_confirm(frame.clone(), timeout)
+            .await
+            .and_then(|status| status.into_result());
+
+        if let Err(e) = result {
+            fail(format!("failed to send {}: {}", frame, e));
+        }
+    }
+
+    if let Err(e) = can.shutdown().await {
+        fail(e);
+    }
+}
@@ -0,0 +1,182 @@
+//! A blocking facade over the async [`CanSocket`](crate::tokio::CanSocket),
+//! for synchronous applications which want its cancel safe parser and
+//! features without enabling the separate `sync` backend.
+//!
+//! [`CanSocket`] owns the async socket along with a single threaded tokio
+//! runtime and drives each operation to completion on the calling thread:
+//!
+//! ```no_run
+//! use slcan_fd::{blocking::CanSocket, NominalBitRate};
+//! use std::time::Duration;
+//! use tokio_serial::SerialPortBuilderExt;
+//!
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut can = CanSocket::connect(|| {
+//!     tokio_serial::new("/dev/ttyUSB0", 115_200)
+//!         .open_native_async()
+//!         .map_err(Into::into)
+//! })?;
+//!
+//! can.open(NominalBitRate::Rate500Kbit)?;
+//!
+//! while let Some(frame) = can.read_timeout(Duration::from_secs(1))? {
+//!     println!("{:?}", frame);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Methods which aren't wrapped can be run through
+//! [`block_on`](CanSocket::block_on), and the ones which don't wait through
+//! [`get_mut`](CanSocket::get_mut).
+
+use std::{future::Future, io, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    runtime::{Builder, Runtime},
+    time,
+};
+
+use crate::{
+    command::{DataBitRate, OperatingMode, TimestampMode},
+    frame::{CanFrame, RxFrame},
+    tokio::CanSocket as AsyncCanSocket,
+    Id, NominalBitRate, ReadError, SendError, TransmitStatus,
+};
+
+/// A blocking interface into a CAN FD network through a serial (USB)
+/// gateway device, backed by the async
+/// [`CanSocket`](crate::tokio::CanSocket). See the
+/// [module documentation](self).
+pub struct CanSocket<P> {
+    // Declared first so the socket is dropped while the runtime still exists
+    socket: AsyncCanSocket<P>,
+    runtime: Runtime,
+}
+
+impl<P: AsyncRead + AsyncWrite> CanSocket<P> {
+    /// Constructs a new CanSocket from a port which doesn't need a tokio
+    /// runtime to be created. Ports which register with the runtime, such as
+    /// the streams of `tokio-serial`, must be created through
+    /// [`connect`](CanSocket::connect) instead.
+    pub fn new(port: P) -> io::Result<Self> {
+        Self::connect(|| Ok(port))
+    }
+
+    /// Creates the runtime and then the port by calling `open` within it
+    pub fn connect(open: impl FnOnce() -> io::Result<P>) -> io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+
+        let port = {
+            let _guard = runtime.enter();
+            open()?
+        };
+
+        Ok(Self {
+            socket: AsyncCanSocket::new(port),
+            runtime,
+        })
+    }
+
+    /// Runs an operation of the async socket to completion, e.g. one which
+    /// isn't wrapped by this facade
+    ///
+    /// ```no_run
+    /// # fn run(can: &mut slcan_fd::blocking::CanSocket<tokio_serial::SerialStream>) -> std::io::Result<()> {
+    /// let capabilities = can.block_on(|can| can.capabilities(std::time::Duration::from_millis(100)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_on<'a, F: Future>(
+        &'a mut self,
+        operation: impl FnOnce(&'a mut AsyncCanSocket<P>) -> F,
+    ) -> F::Output {
+        self.runtime.block_on(operation(&mut self.socket))
+    }
+
+    /// Returns the async socket
+    pub fn get_ref(&self) -> &AsyncCanSocket<P> {
+        &self.socket
+    }
+
+    /// Returns the async socket, e.g. for calling its methods which don't
+    /// wait
+    pub fn get_mut(&mut self) -> &mut AsyncCanSocket<P> {
+        &mut self.socket
+    }
+
+    /// Returns the async socket along with the runtime it was driven by
+    pub fn into_inner(self) -> (AsyncCanSocket<P>, Runtime) {
+        (self.socket, self.runtime)
+    }
+
+    /// See `tokio::CanSocket::open`
+    pub fn open(&mut self, nominal_bit_rate: NominalBitRate) -> io::Result<()> {
+        self.block_on(|socket| socket.open(nominal_bit_rate))
+    }
+
+    /// See `tokio::CanSocket::close`
+    pub fn close(&mut self) -> io::Result<()> {
+        self.block_on(|socket| socket.close())
+    }
+
+    /// See `tokio::CanSocket::set_data_bit_rate`
+    pub fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()> {
+        self.block_on(|socket| socket.set_data_bit_rate(rate))
+    }
+
+    /// See `tokio::CanSocket::set_operating_mode`
+    pub fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()> {
+        self.block_on(|socket| socket.set_operating_mode(mode))
+    }
+
+    /// See `tokio::CanSocket::set_timestamp_mode`
+    pub fn set_timestamp_mode(&mut self, mode: TimestampMode) -> io::Result<()> {
+        self.block_on(|socket| socket.set_timestamp_mode(mode))
+    }
+
+    /// See `tokio::CanSocket::send`
+    pub fn send(&mut self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+        self.block_on(|socket| socket.send(frame))
+    }
+
+    /// See `tokio::CanSocket::send_and_confirm`
+    pub fn send_and_confirm(
+        &mut self,
+        frame: impl Into<CanFrame>,
+        timeout: Duration,
+    ) -> Result<TransmitStatus, SendError> {
+        self.block_on(|socket| socket.send_and_confirm(frame, timeout))
+    }
+
+    /// Blocks until a frame is received. See `tokio::CanSocket::read`.
+    pub fn read(&mut self) -> Result<CanFrame, ReadError> {
+        self.block_on(|socket| socket.read())
+    }
+
+    /// Blocks until a frame is received, or returns `None` once `timeout`
+    /// passed. A partially received line is kept for the next read.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<CanFrame>, ReadError> {
+        self.block_on(|socket| async move {
+            match time::timeout(timeout, socket.read()).await {
+                Ok(result) => result.map(Some),
+                Err(_) => Ok(None),
+            }
+        })
+    }
+
+    /// See `tokio::CanSocket::read_rx_frame`
+    pub fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError> {
+        self.block_on(|socket| socket.read_rx_frame())
+    }
+
+    /// See `tokio::CanSocket::await_frame`
+    pub fn await_frame(
+        &mut self,
+        id: impl Into<Id>,
+        timeout: Duration,
+    ) -> Result<Option<CanFrame>, ReadError> {
+        self.block_on(|socket| socket.await_frame(id, timeout))
+    }
+}
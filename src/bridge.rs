@@ -0,0 +1,226 @@
+//! Forwarding frames between two CAN interfaces, optionally translating
+//! their IDs on the way.
+//!
+//! An [`IdTranslator`] describes how IDs are rewritten, either through an
+//! explicit table or through offsets and conversions between standard and
+//! extended IDs. This is commonly needed when splicing two networks which use
+//! conflicting IDs. With the `tokio` feature, [`Bridge`] forwards frames in
+//! both directions between two sockets, applying a translator per direction.
+//!
+//! ```
+//! use slcan_fd::{bridge::IdTranslator, ExtendedId, Id, StandardId};
+//!
+//! let std = |id| Id::Standard(StandardId::new(id).unwrap());
+//!
+//! let translator = IdTranslator::new()
+//!     .with_mapping(std(0x100), ExtendedId::new(0x18FF0100).unwrap())
+//!     .with_standard_offset(0x200);
+//!
+//! assert_eq!(translator.translate(std(0x100)), Some(ExtendedId::new(0x18FF0100).unwrap().into()));
+//! assert_eq!(translator.translate(std(0x123)), Some(std(0x323)));
+//! assert_eq!(translator.translate(std(0x7FF)), None);
+//! ```
+
+use std::collections::HashMap;
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use crate::frame::CanFrame;
+
+/// Rewrites the IDs of forwarded frames. See the
+/// [module documentation](self).
+///
+/// Explicit mappings take precedence. All other IDs have the offset for their
+/// kind added and are then converted to the other kind if requested. Frames
+/// whose translated ID is out of range are dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdTranslator {
+    mappings: HashMap<Id, Id>,
+    standard_offset: i32,
+    extended_offset: i64,
+    standard_to_extended: bool,
+    extended_to_standard: bool,
+}
+
+impl IdTranslator {
+    /// Creates a translator which leaves all IDs unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translates `from` into `to`, which may be of a different kind
+    pub fn with_mapping(mut self, from: impl Into<Id>, to: impl Into<Id>) -> Self {
+        self.mappings.insert(from.into(), to.into());
+        self
+    }
+
+    /// Adds `offset` to all standard IDs without an explicit mapping
+    pub fn with_standard_offset(mut self, offset: i32) -> Self {
+        self.standard_offset = offset;
+        self
+    }
+
+    /// Adds `offset` to all extended IDs without an explicit mapping
+    pub fn with_extended_offset(mut self, offset: i64) -> Self {
+        self.extended_offset = offset;
+        self
+    }
+
+    /// Converts all standard IDs without an explicit mapping into extended
+    /// IDs with the same value (after applying the standard offset)
+    pub fn with_standard_to_extended(mut self, enabled: bool) -> Self {
+        self.standard_to_extended = enabled;
+        self
+    }
+
+    /// Converts all extended IDs without an explicit mapping into standard
+    /// IDs with the same value (after applying the extended offset). Frames
+    /// whose ID doesn't fit into 11 bits are dropped.
+    pub fn with_extended_to_standard(mut self, enabled: bool) -> Self {
+        self.extended_to_standard = enabled;
+        self
+    }
+
+    /// Returns the translated ID, or `None` if it is out of range and the
+    /// frame should be dropped
+    pub fn translate(&self, id: Id) -> Option<Id> {
+        if let Some(&mapped) = self.mappings.get(&id) {
+            return Some(mapped);
+        }
+
+        let (raw, extended) = match id {
+            Id::Standard(id) => {
+                let raw = id.as_raw() as i64 + self.standard_offset as i64;
+                (raw, self.standard_to_extended)
+            }
+            Id::Extended(id) => {
+                let raw = id.as_raw() as i64 + self.extended_offset;
+                (raw, !self.extended_to_standard)
+            }
+        };
+
+        let raw = u32::try_from(raw).ok()?;
+
+        if extended {
+            ExtendedId::new(raw).map(Id::Extended)
+        } else {
+            let raw = u16::try_from(raw).ok()?;
+            StandardId::new(raw).map(Id::Standard)
+        }
+    }
+
+    /// Returns the frame with its ID translated, or `None` if it should be
+    /// dropped
+    pub fn translate_frame(&self, frame: CanFrame) -> Option<CanFrame> {
+        let id = self.translate(frame.id())?;
+        Some(frame.with_id(id))
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::forward::{Bridge, BridgeError, BridgeSide};
+
+#[cfg(feature = "tokio")]
+mod forward {
+    use tokio::io::{AsyncRead, AsyncWrite};
+
+    use super::IdTranslator;
+    use crate::{frame::FrameDirection, tokio::CanSocket, ReadError, RxFrame, SendError};
+
+    /// One of the two sockets connected by a [Bridge]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum BridgeSide {
+        A,
+        B,
+    }
+
+    /// Errors which stop a [Bridge]
+    #[derive(Debug, thiserror::Error)]
+    pub enum BridgeError {
+        #[error("Failed to read from side {0:?}: {1}")]
+        Read(BridgeSide, ReadError),
+        #[error("Failed to forward a frame to side {0:?}: {1}")]
+        Send(BridgeSide, SendError),
+    }
+
+    /// Forwards the frames received by each of two sockets to the other one,
+    /// translating their IDs with an [IdTranslator] per direction.
+    ///
+    /// Only frames received from the bus are forwarded, echoes of
+    /// transmitted frames are not. Lines which can't be parsed are skipped.
+    #[derive(Debug, Clone, Default)]
+    pub struct Bridge {
+        a_to_b: IdTranslator,
+        b_to_a: IdTranslator,
+    }
+
+    impl Bridge {
+        /// Creates a bridge which forwards all frames unchanged
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Sets the translator applied to frames forwarded from A to B
+        pub fn with_a_to_b(mut self, translator: IdTranslator) -> Self {
+            self.a_to_b = translator;
+            self
+        }
+
+        /// Sets the translator applied to frames forwarded from B to A
+        pub fn with_b_to_a(mut self, translator: IdTranslator) -> Self {
+            self.b_to_a = translator;
+            self
+        }
+
+        /// Forwards frames until reading from or sending to either socket
+        /// fails. Both sockets must already be open.
+        ///
+        /// # Cancel Safety
+        ///
+        /// Cancelling only loses a frame if it happens while the frame is
+        /// being forwarded.
+        pub async fn run<A, B>(
+            &self,
+            a: &mut CanSocket<A>,
+            b: &mut CanSocket<B>,
+        ) -> Result<(), BridgeError>
+        where
+            A: AsyncRead + AsyncWrite,
+            B: AsyncRead + AsyncWrite,
+        {
+            loop {
+                let (from, result) = tokio::select! {
+                    result = a.read_rx_frame() => (BridgeSide::A, result),
+                    result = b.read_rx_frame() => (BridgeSide::B, result),
+                };
+
+                let frame = match result {
+                    Ok(RxFrame {
+                        frame,
+                        direction: FrameDirection::Rx,
+                        ..
+                    }) => frame,
+                    Ok(_) | Err(ReadError::Slcan(_) | ReadError::LineTooLong(_)) => continue,
+                    Err(e) => return Err(BridgeError::Read(from, e)),
+                };
+
+                let result = match from {
+                    BridgeSide::A => match self.a_to_b.translate_frame(frame) {
+                        Some(frame) => b.send(frame).await,
+                        None => Ok(()),
+                    },
+                    BridgeSide::B => match self.b_to_a.translate_frame(frame) {
+                        Some(frame) => a.send(frame).await,
+                        None => Ok(()),
+                    },
+                };
+
+                let to = match from {
+                    BridgeSide::A => BridgeSide::B,
+                    BridgeSide::B => BridgeSide::A,
+                };
+                result.map_err(|e| BridgeError::Send(to, e))?;
+            }
+        }
+    }
+}
@@ -0,0 +1,433 @@
+//! A bit level model of how long frames occupy the bus, for estimating the
+//! bus load and planning the capacity of heavily loaded networks.
+//!
+//! [`frame_bits`] counts the bits of a frame as it is sent on the wire,
+//! including its stuff bits, split into the bits sent at the nominal bit rate
+//! and the bits of the data phase of CAN FD frames with bit rate switching.
+//! [`BusLoadMeter`] adds up the frames seen within a rolling window.
+//!
+//! ```
+//! use slcan_fd::{
+//!     busload::{frame_bits, Stuffing},
+//!     Can2Frame, CanFrame, DataBitRate, NominalBitRate, StandardId,
+//! };
+//!
+//! let frame = CanFrame::from(Can2Frame::new_data(StandardId::new(0x123).unwrap(), &[0; 8]).unwrap());
+//!
+//! assert_eq!(frame_bits(&frame, Stuffing::None).total(), 111);
+//! assert_eq!(frame_bits(&frame, Stuffing::WorstCase).total(), 135);
+//!
+//! let bits = frame_bits(&frame, Stuffing::Exact);
+//! println!(
+//!     "{} bits, {:?} at 500 Kbit/s",
+//!     bits.total(),
+//!     bits.duration(NominalBitRate::Rate500Kbit, DataBitRate::Rate2Mbit)
+//! );
+//! ```
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use embedded_can::Id;
+
+use crate::{
+    command::{DataBitRate, NominalBitRate},
+    frame::{Can2Frame, CanFdFrame, CanFrame},
+};
+
+/// The bits after the CRC sequence of a classic CAN frame which are never
+/// stuffed: CRC delimiter, ACK slot and delimiter, EOF and the interframe
+/// space
+const CAN2_TRAILER_BITS: u32 = 1 + 2 + 7 + 3;
+/// The bits of a CAN FD frame which are sent at the nominal bit rate again
+/// after the data phase: ACK slot and delimiter, EOF and the interframe space
+const FD_TRAILER_BITS: u32 = 2 + 7 + 3;
+/// The stuff count of a CAN FD frame, i.e. the gray coded number of dynamic
+/// stuff bits and its parity bit
+const FD_STUFF_COUNT_BITS: u32 = 4;
+
+/// How [`frame_bits`] accounts for the stuff bits of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Stuffing {
+    /// Stuff bits are left out, which gives a lower bound
+    None,
+    /// The stuff bits the frame needs for its ID and data, computed from the
+    /// actual bit sequence including the CRC
+    #[default]
+    Exact,
+    /// The most stuff bits any frame of the same format and length may need,
+    /// for worst case planning
+    WorstCase,
+}
+
+/// The number of bits a frame occupies on the bus, including the interframe
+/// space. See [`frame_bits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FrameBits {
+    /// The bits sent at the nominal bit rate
+    pub nominal: u32,
+    /// The bits of the data phase of CAN FD frames with bit rate switching,
+    /// which are sent at the data bit rate
+    pub data: u32,
+}
+
+impl FrameBits {
+    /// Returns the number of bits at either bit rate
+    pub fn total(&self) -> u32 {
+        self.nominal + self.data
+    }
+
+    /// Returns how long the frame occupies a bus running at the given bit
+    /// rates
+    pub fn duration(
+        &self,
+        nominal_bit_rate: NominalBitRate,
+        data_bit_rate: DataBitRate,
+    ) -> Duration {
+        Duration::from_secs_f64(
+            self.nominal as f64 / nominal_bit_rate.bits_per_second() as f64
+                + self.data as f64 / data_bit_rate.bits_per_second() as f64,
+        )
+    }
+}
+
+/// Returns the number of bits `frame` occupies on the bus, with its stuff
+/// bits accounted for according to `stuffing`.
+///
+/// CAN FD frames follow ISO 11898-1:2015, with the stuff count and the fixed
+/// stuff bits of the CRC field. Their error state indicator is assumed to be
+/// error active. CAN XL frames are estimated without stuff bits.
+pub fn frame_bits(frame: &CanFrame, stuffing: Stuffing) -> FrameBits {
+    match frame {
+        CanFrame::Can2(frame) => can2_bits(frame, stuffing),
+        CanFrame::CanFd(frame) => can_fd_bits(frame, stuffing),
+        CanFrame::CanXl(frame) => FrameBits {
+            nominal: 48,
+            data: 60 + 8 * frame.data().len() as u32,
+        },
+    }
+}
+
+fn can2_bits(frame: &Can2Frame, stuffing: Stuffing) -> FrameBits {
+    let mut stream = BitStream::default();
+
+    stream.push(false); // SOF
+    match frame.id() {
+        Id::Standard(id) => {
+            stream.push_field(id.as_raw().into(), 11);
+            stream.push(frame.is_remote()); // RTR
+            stream.push(false); // IDE
+            stream.push(false); // r0
+        }
+        Id::Extended(id) => {
+            stream.push_field(id.as_raw() >> 18, 11);
+            stream.push(true); // SRR
+            stream.push(true); // IDE
+            stream.push_field(id.as_raw() & 0x3FFFF, 18);
+            stream.push(frame.is_remote()); // RTR
+            stream.push(false); // r1
+            stream.push(false); // r0
+        }
+    }
+    stream.push_field(frame.dlc() as u32, 4);
+    for &byte in frame.data().unwrap_or_default() {
+        stream.push_field(byte.into(), 8);
+    }
+
+    let crc = stream.crc;
+    stream.push_field(crc.into(), 15);
+
+    let stuff_bits = match stuffing {
+        Stuffing::None => 0,
+        Stuffing::Exact => stream.stuff_bits,
+        Stuffing::WorstCase => worst_case_stuff_bits(stream.bits),
+    };
+
+    FrameBits {
+        nominal: stream.bits + stuff_bits + CAN2_TRAILER_BITS,
+        data: 0,
+    }
+}
+
+fn can_fd_bits(frame: &CanFdFrame, stuffing: Stuffing) -> FrameBits {
+    let mut stream = BitStream::default();
+
+    stream.push(false); // SOF
+    match frame.id() {
+        Id::Standard(id) => {
+            stream.push_field(id.as_raw().into(), 11);
+            stream.push(false); // RRS
+            stream.push(false); // IDE
+        }
+        Id::Extended(id) => {
+            stream.push_field(id.as_raw() >> 18, 11);
+            stream.push(true); // SRR
+            stream.push(true); // IDE
+            stream.push_field(id.as_raw() & 0x3FFFF, 18);
+            stream.push(false); // RRS
+        }
+    }
+    stream.push(true); // FDF
+    stream.push(false); // res
+
+    // The bit rate is switched at the BRS bit, so stuff bits following it
+    // belong to the data phase
+    let arbitration = stream.clone();
+
+    stream.push(frame.is_bit_rate_switched()); // BRS
+    stream.push(false); // ESI
+    stream.push_field(u8::from(frame.dlc()).into(), 4);
+    for &byte in frame.data() {
+        stream.push_field(byte.into(), 8);
+    }
+
+    let arbitration_bits = arbitration.bits + 1;
+    let dynamic_bits = stream.bits - arbitration_bits;
+    let crc_bits = if frame.data().len() > 16 { 21 } else { 17 };
+    // A fixed stuff bit precedes the stuff count and follows every 4 bits
+    // of the stuff count and the CRC
+    let fixed_stuff_bits = 1 + (FD_STUFF_COUNT_BITS + crc_bits - 1) / 4;
+
+    let (arbitration_stuff_bits, dynamic_stuff_bits, fixed_stuff_bits) = match stuffing {
+        Stuffing::None => (0, 0, 0),
+        Stuffing::Exact => (
+            arbitration.stuff_bits,
+            stream.stuff_bits - arbitration.stuff_bits,
+            fixed_stuff_bits,
+        ),
+        Stuffing::WorstCase => {
+            let arbitration = worst_case_stuff_bits(arbitration_bits);
+            let total = worst_case_stuff_bits(stream.bits);
+            (arbitration, total - arbitration, fixed_stuff_bits)
+        }
+    };
+
+    // The CRC delimiter is the last bit of the data phase
+    let nominal = arbitration_bits + arbitration_stuff_bits + FD_TRAILER_BITS;
+    let data =
+        dynamic_bits + dynamic_stuff_bits + FD_STUFF_COUNT_BITS + crc_bits + fixed_stuff_bits + 1;
+
+    if frame.is_bit_rate_switched() {
+        FrameBits { nominal, data }
+    } else {
+        FrameBits {
+            nominal: nominal + data,
+            data: 0,
+        }
+    }
+}
+
+/// The bits of a frame up to its CRC field, pushed one at a time to count
+/// the stuff bits inserted after every 5 consecutive equal bits
+#[derive(Debug, Clone, Default)]
+struct BitStream {
+    bits: u32,
+    stuff_bits: u32,
+    last: bool,
+    run: u32,
+    /// The CRC-15 of classic CAN frames over the bits pushed so far
+    crc: u16,
+}
+
+impl BitStream {
+    fn push(&mut self, bit: bool) {
+        self.bits += 1;
+        self.crc = crc15(self.crc, bit);
+
+        if self.run > 0 && bit == self.last {
+            self.run += 1;
+        } else {
+            self.last = bit;
+            self.run = 1;
+        }
+
+        // The stuff bit starts the next run
+        if self.run == 5 {
+            self.stuff_bits += 1;
+            self.last = !bit;
+            self.run = 1;
+        }
+    }
+
+    /// Pushes the lowest `width` bits of `value`, most significant bit first
+    fn push_field(&mut self, value: u32, width: u32) {
+        for i in (0..width).rev() {
+            self.push(value >> i & 1 != 0);
+        }
+    }
+}
+
+/// Returns the most stuff bits a dynamically stuffed sequence of `bits` bits
+/// may need, since the first stuff bit needs 5 bits and every further one
+/// only 4 more
+fn worst_case_stuff_bits(bits: u32) -> u32 {
+    bits.saturating_sub(1) / 4
+}
+
+/// Advances the CRC-15 of classic CAN frames by one bit
+fn crc15(crc: u16, bit: bool) -> u16 {
+    let next = (crc >> 14 & 1 != 0) ^ bit;
+    let crc = crc << 1 & 0x7FFF;
+
+    if next {
+        crc ^ 0x4599
+    } else {
+        crc
+    }
+}
+
+/// Measures the bus load over a rolling window from the frames seen on the
+/// bus, e.g. all frames read from a socket with TX echo enabled.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use slcan_fd::{busload::BusLoadMeter, DataBitRate, NominalBitRate};
+///
+/// let mut meter = BusLoadMeter::new(
+///     NominalBitRate::Rate500Kbit,
+///     DataBitRate::Rate2Mbit,
+///     Duration::from_secs(1),
+/// );
+///
+/// // For every frame received or sent
+/// # let frame: slcan_fd::CanFrame = "123#DEADBEEF".parse().unwrap();
+/// meter.record(&frame);
+///
+/// println!("{:.1}% bus load", meter.load() * 100.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BusLoadMeter {
+    nominal_bit_rate: NominalBitRate,
+    data_bit_rate: DataBitRate,
+    stuffing: Stuffing,
+    window: Duration,
+    frames: VecDeque<(Instant, Duration)>,
+    busy: Duration,
+}
+
+impl BusLoadMeter {
+    /// Creates a meter for a bus running at the given bit rates, which
+    /// averages the load over `window`. Stuff bits are counted exactly by
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(
+        nominal_bit_rate: NominalBitRate,
+        data_bit_rate: DataBitRate,
+        window: Duration,
+    ) -> Self {
+        assert!(
+            !window.is_zero(),
+            "the window of a bus load meter must not be zero"
+        );
+
+        Self {
+            nominal_bit_rate,
+            data_bit_rate,
+            stuffing: Stuffing::default(),
+            window,
+            frames: VecDeque::new(),
+            busy: Duration::ZERO,
+        }
+    }
+
+    /// Sets how stuff bits are accounted for, e.g.
+    /// [`Stuffing::WorstCase`] for a pessimistic estimate
+    pub fn with_stuffing(mut self, stuffing: Stuffing) -> Self {
+        self.stuffing = stuffing;
+        self
+    }
+
+    /// Returns the length of the window the load is averaged over
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Records a frame which was just seen on the bus
+    pub fn record(&mut self, frame: &CanFrame) {
+        self.record_at(frame, Instant::now());
+    }
+
+    /// Records a frame which was seen on the bus at `at`, e.g. when
+    /// replaying a log. Frames should be recorded in chronological order.
+    pub fn record_at(&mut self, frame: &CanFrame, at: Instant) {
+        let busy =
+            frame_bits(frame, self.stuffing).duration(self.nominal_bit_rate, self.data_bit_rate);
+
+        self.frames.push_back((at, busy));
+        self.busy += busy;
+    }
+
+    /// Returns the fraction of the last window (0 to 1) the bus was busy
+    /// with the recorded frames. Frames which fell out of the window are
+    /// forgotten.
+    pub fn load(&mut self) -> f32 {
+        self.load_at(Instant::now())
+    }
+
+    /// Same as [`load`](BusLoadMeter::load), for the window ending at `now`
+    pub fn load_at(&mut self, now: Instant) -> f32 {
+        while let Some(&(at, busy)) = self.frames.front() {
+            if now.saturating_duration_since(at) < self.window {
+                break;
+            }
+
+            self.busy -= busy;
+            self.frames.pop_front();
+        }
+
+        (self.busy.as_secs_f64() / self.window.as_secs_f64()).min(1.0) as f32
+    }
+
+    /// Forgets all recorded frames
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.busy = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StandardId;
+
+    #[test]
+    fn computes_the_can_crc() {
+        // The check value of CRC-15/CAN
+        let mut stream = BitStream::default();
+        for byte in b"123456789" {
+            stream.push_field((*byte).into(), 8);
+        }
+
+        assert_eq!(stream.crc, 0x059E);
+    }
+
+    #[test]
+    fn stuff_bits_start_the_next_run() {
+        let mut stream = BitStream::default();
+        for bit in [0, 0, 0, 0, 0, 1, 1, 1, 1] {
+            stream.push(bit != 0);
+        }
+
+        // The stuff bit after the zeros is the first of 5 ones
+        assert_eq!(stream.stuff_bits, 2);
+    }
+
+    #[test]
+    fn counts_the_stuff_bits_of_a_dominant_frame() {
+        // SOF, ID, RTR, IDE, r0, DLC and the CRC (which is 0 as well) make
+        // 34 dominant bits in a row, which need a stuff bit after every 5
+        let frame = CanFrame::from(Can2Frame::new_data(StandardId::ZERO, &[]).unwrap());
+
+        assert_eq!(frame_bits(&frame, Stuffing::None).total(), 34 + 13);
+        assert_eq!(frame_bits(&frame, Stuffing::Exact).total(), 34 + 6 + 13);
+        assert_eq!(frame_bits(&frame, Stuffing::WorstCase).total(), 34 + 8 + 13);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::command::DataBitRate;
+
+/// The features supported by a gateway, as determined by
+/// `CanSocket::capabilities`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    /// The answer to the firmware version query (`V`), if the gateway sent
+    /// one. Its format differs between firmwares.
+    pub version: Option<String>,
+    /// Whether CAN FD frames are supported
+    pub fd: bool,
+    /// Whether CAN FD frames can be sent with bit rate switching, i.e. the
+    /// data bit rate can be set (`Y`)
+    pub brs: bool,
+    /// The data bit rates accepted by the gateway, from the slowest to the
+    /// fastest
+    pub data_bit_rates: Vec<DataBitRate>,
+    /// Whether received frames can carry hardware timestamps (`Z`)
+    pub timestamps: bool,
+    /// Whether auto retransmission can be configured (`A`)
+    pub auto_retransmission: bool,
+    /// Whether the gateway can filter frames in hardware with an acceptance
+    /// code and mask, as the original Lawicel adapters do
+    pub hw_filters: bool,
+}
+
+impl Capabilities {
+    /// Guesses the capabilities from the answer to the version query, before
+    /// they are refined by trial commands
+    pub(crate) fn from_version(version: Option<&[u8]>) -> Self {
+        let version = version.map(|version| String::from_utf8_lossy(version).into_owned());
+        let lowercase = version.as_deref().unwrap_or_default().to_lowercase();
+
+        Self {
+            fd: lowercase.contains("fd") || lowercase.contains("canable2"),
+            // Lawicel firmwares answer with `V` followed by the hardware and
+            // software version as 2 digits each
+            hw_filters: matches!(
+                version.as_deref().map(str::as_bytes),
+                Some([b'V', digits @ ..]) if digits.len() == 4 && digits.iter().all(u8::is_ascii_digit)
+            ),
+            version,
+            ..Self::default()
+        }
+    }
+}
@@ -1,11 +1,14 @@
+use std::io;
+
 use embedded_can::{ExtendedId, Id, StandardId};
 use num_enum::IntoPrimitive;
 
-use crate::frame::CanFrame;
+use crate::frame::{Can2Frame, CanFdFrame, CanFrame};
 
 /// Represents the various different commands that can be send to the CAN
 /// gateway
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum CommandKind {
     /// Set the nominal bit rate to a standard CAN [bit rate](NominalBitRate)
@@ -16,9 +19,15 @@ pub enum CommandKind {
     SetMode = b'M',
     /// Enables or disables auto retransmission of frames
     SetAutoRetransmission = b'A',
+    /// Sets the resolution of the timestamps appended to received frames
+    SetTimestampMode = b'Z',
 
     /// Open the CAN channel in normal mode (sending & receiving)
     Open = b'O',
+    /// Open the CAN channel in listen only mode ([Dialect::UsbTin] only)
+    OpenListenOnly = b'L',
+    /// Open the CAN channel in loopback mode ([Dialect::UsbTin] only)
+    OpenLoopback = b'l',
     /// Close the CAN channel
     Close = b'C',
 
@@ -49,6 +58,7 @@ pub enum CommandKind {
 /// The bit rate used for CAN 2.0 frames, CAN FD frames without BRS, and the
 /// message ID arbitration for CAN FD frames with BRS
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum NominalBitRate {
     /// Transmits and receives at 10 Kbit/s
@@ -73,20 +83,146 @@ pub enum NominalBitRate {
     Rate83_3Kbit = b'9',
 }
 
+impl NominalBitRate {
+    /// All nominal bit rates, from the slowest to the fastest
+    pub const ALL: [NominalBitRate; 10] = [
+        Self::Rate10Kbit,
+        Self::Rate20Kbit,
+        Self::Rate50Kbit,
+        Self::Rate83_3Kbit,
+        Self::Rate100Kbit,
+        Self::Rate125Kbit,
+        Self::Rate250Kbit,
+        Self::Rate500Kbit,
+        Self::Rate800Kbit,
+        Self::Rate1Mbit,
+    ];
+
+    /// Returns the bit rate in bits per second
+    pub fn bits_per_second(&self) -> u32 {
+        match self {
+            Self::Rate10Kbit => 10_000,
+            Self::Rate20Kbit => 20_000,
+            Self::Rate50Kbit => 50_000,
+            Self::Rate83_3Kbit => 83_333,
+            Self::Rate100Kbit => 100_000,
+            Self::Rate125Kbit => 125_000,
+            Self::Rate250Kbit => 250_000,
+            Self::Rate500Kbit => 500_000,
+            Self::Rate800Kbit => 800_000,
+            Self::Rate1Mbit => 1_000_000,
+        }
+    }
+
+    /// Returns the nominal bit rate with the given number of bits per
+    /// second, e.g. `500_000` for 500 Kbit/s. 83.3 Kbit/s is `83_333`.
+    ///
+    /// ```
+    /// use slcan_fd::NominalBitRate;
+    ///
+    /// assert_eq!(NominalBitRate::from_bps(500_000), Ok(NominalBitRate::Rate500Kbit));
+    /// assert!(NominalBitRate::from_bps(400_000).is_err());
+    /// ```
+    pub fn from_bps(bps: u32) -> Result<Self, UnsupportedBitRate> {
+        Self::ALL
+            .into_iter()
+            .find(|rate| rate.bits_per_second() == bps)
+            .ok_or_else(|| UnsupportedBitRate {
+                bps,
+                supported: Self::ALL.map(|rate| rate.bits_per_second()).to_vec(),
+            })
+    }
+}
+
+impl TryFrom<u32> for NominalBitRate {
+    type Error = UnsupportedBitRate;
+
+    /// See [`NominalBitRate::from_bps`]
+    fn try_from(bps: u32) -> Result<Self, Self::Error> {
+        Self::from_bps(bps)
+    }
+}
+
 /// The bit rate used for the data and CRC sections of CAN FD frames with BRS
 /// enabled
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, Default)]
+///
+/// Only 2 and 5 Mbit/s are supported by all CAN FD firmwares, the other rates
+/// are rejected by some. See
+/// [`Capabilities::data_bit_rates`](crate::Capabilities::data_bit_rates).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, IntoPrimitive, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum DataBitRate {
+    /// Transmits and receives at 1 Mbit/s
+    Rate1Mbit = b'1',
     /// Transmits and receives at 2 Mbit/s
     #[default]
     Rate2Mbit = b'2',
+    /// Transmits and receives at 4 Mbit/s
+    Rate4Mbit = b'4',
     /// Transmits and receives at 5 Mbit/s
     Rate5Mbit = b'5',
+    /// Transmits and receives at 8 Mbit/s
+    Rate8Mbit = b'8',
+}
+
+impl DataBitRate {
+    /// All data bit rates, from the slowest to the fastest
+    pub const ALL: [DataBitRate; 5] = [
+        Self::Rate1Mbit,
+        Self::Rate2Mbit,
+        Self::Rate4Mbit,
+        Self::Rate5Mbit,
+        Self::Rate8Mbit,
+    ];
+
+    /// Returns the bit rate in bits per second
+    pub fn bits_per_second(&self) -> u32 {
+        match self {
+            Self::Rate1Mbit => 1_000_000,
+            Self::Rate2Mbit => 2_000_000,
+            Self::Rate4Mbit => 4_000_000,
+            Self::Rate5Mbit => 5_000_000,
+            Self::Rate8Mbit => 8_000_000,
+        }
+    }
+
+    /// Returns the data bit rate with the given number of bits per second,
+    /// e.g. `2_000_000` for 2 Mbit/s
+    pub fn from_bps(bps: u32) -> Result<Self, UnsupportedBitRate> {
+        Self::ALL
+            .into_iter()
+            .find(|rate| rate.bits_per_second() == bps)
+            .ok_or_else(|| UnsupportedBitRate {
+                bps,
+                supported: Self::ALL.map(|rate| rate.bits_per_second()).to_vec(),
+            })
+    }
+}
+
+impl TryFrom<u32> for DataBitRate {
+    type Error = UnsupportedBitRate;
+
+    /// See [`DataBitRate::from_bps`]
+    fn try_from(bps: u32) -> Result<Self, Self::Error> {
+        Self::from_bps(bps)
+    }
+}
+
+/// Returned when converting a number of bits per second into a
+/// [`NominalBitRate`] or [`DataBitRate`] which has no variant for it
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unsupported bit rate of {bps} bit/s, the supported rates are {supported:?}")]
+pub struct UnsupportedBitRate {
+    /// The requested bit rate in bits per second
+    pub bps: u32,
+    /// The supported bit rates in bits per second
+    pub supported: Vec<u32>,
 }
 
 /// Operating mode of the gateway which changes its fundamental behavior
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum OperatingMode {
     /// Default mode where the gateway can send and receive frames on the bus
@@ -95,10 +231,45 @@ pub enum OperatingMode {
     /// Sometimes called "Listen Only" mode where the device can only listen
     /// to frames on the bus
     Silent = b'1',
+    /// Frames are received back by the gateway itself instead of being sent
+    /// on the bus, e.g. for testing without a bus. Not all firmwares support
+    /// it.
+    Loopback = b'2',
+}
+
+/// The variant of the SLCAN command set spoken by the gateway. See
+/// `CanSocket::set_dialect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Dialect {
+    /// The command set of CAN FD capable firmwares such as the CANable 2's,
+    /// where the operating mode is set with `M` before the channel is opened
+    #[default]
+    Standard,
+    /// The command set of USBtin adapters, which sticks to the original
+    /// Lawicel protocol: `M` sets the acceptance code instead of the
+    /// operating mode, so the channel is opened in listen only or loopback
+    /// mode with `L` or `l` instead. CAN FD, data bit rates and auto
+    /// retransmission are not supported.
+    UsbTin,
+}
+
+/// How the gateway computes the CRC of CAN FD frames. See
+/// `CanSocket::set_fd_crc_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FdCrcMode {
+    /// The CRC of ISO 11898-1:2015, which includes the stuff bit count
+    #[default]
+    Iso,
+    /// The original Bosch CRC, needed to talk to legacy CAN FD controllers
+    /// which predate the ISO standard
+    NonIso,
 }
 
 /// The auto retransmission policy of the gateway
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum AutoRetransmissionMode {
     /// Frames will not be retransmitted if an error occurs while transmitting
@@ -108,99 +279,461 @@ pub enum AutoRetransmissionMode {
     Enabled = b'1',
 }
 
+/// Whether the gateway appends a timestamp to each received frame, and if so
+/// at which resolution. See [`Timestamp`](crate::Timestamp).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum TimestampMode {
+    /// Frames are received without timestamps
+    #[default]
+    Disabled = b'0',
+    /// Frames carry a 16-bit millisecond timestamp (4 hex digits)
+    Milliseconds = b'1',
+    /// Frames carry a 32-bit microsecond timestamp (8 hex digits)
+    Microseconds = b'2',
+}
+
+impl TimestampMode {
+    /// Returns the number of hex digits the gateway appends to each frame
+    pub fn num_digits(&self) -> usize {
+        match self {
+            Self::Disabled => 0,
+            Self::Milliseconds => 4,
+            Self::Microseconds => 8,
+        }
+    }
+}
+
+/// The error returned when a CAN XL frame is to be encoded, since SLCAN has
+/// no command for transmitting them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[error("SLCAN has no command for CAN XL frames")]
+pub struct UnsupportedFrame;
+
+/// A frame which can be transmitted over SLCAN, i.e. a CAN 2.0 or CAN FD
+/// frame. Converting a [CanFrame] fails for CAN XL frames, so encoding a
+/// [`Command::TransmitFrame`] never has to.
+///
+/// ```
+/// use slcan_fd::{CanFrame, CanXlFrame, SlcanFrame, StandardId};
+///
+/// let frame = CanXlFrame::new(StandardId::new(0x123).unwrap(), &[1]).unwrap();
+/// assert!(SlcanFrame::try_from(CanFrame::CanXl(frame)).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlcanFrame(CanFrame);
+
+impl SlcanFrame {
+    /// Returns the frame
+    pub fn frame(&self) -> &CanFrame {
+        &self.0
+    }
+
+    /// Consumes self and returns the frame
+    pub fn into_frame(self) -> CanFrame {
+        self.0
+    }
+}
+
+impl From<Can2Frame> for SlcanFrame {
+    fn from(frame: Can2Frame) -> Self {
+        Self(CanFrame::Can2(frame))
+    }
+}
+
+impl From<CanFdFrame> for SlcanFrame {
+    fn from(frame: CanFdFrame) -> Self {
+        Self(CanFrame::CanFd(frame))
+    }
+}
+
+impl TryFrom<CanFrame> for SlcanFrame {
+    type Error = UnsupportedFrame;
+
+    fn try_from(frame: CanFrame) -> Result<Self, UnsupportedFrame> {
+        match frame {
+            CanFrame::Can2(_) | CanFrame::CanFd(_) => Ok(Self(frame)),
+            _ => Err(UnsupportedFrame),
+        }
+    }
+}
+
+impl From<SlcanFrame> for CanFrame {
+    fn from(frame: SlcanFrame) -> Self {
+        frame.0
+    }
+}
+
 /// A command sent to the CAN gateway along with it's attached data
-#[derive(Debug)]
+///
+/// ```
+/// use slcan_fd::{Can2Frame, Command, StandardId};
+///
+/// let frame = Can2Frame::new_data(StandardId::new(0x123).unwrap(), &[0xAB]).unwrap();
+///
+/// let mut buffer = [0; Command::MAX_ENCODED_LEN];
+/// let len = Command::TransmitFrame(frame.into()).encode_into(&mut buffer);
+/// assert_eq!(&buffer[..len], b"t1231AB");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Command {
+    /// Sets the nominal bit rate (`S`)
     SetNominalBitRate(NominalBitRate),
+    /// Sets the data bit rate of CAN FD frames with BRS (`Y`)
     SetDataBitRate(DataBitRate),
+    /// Sets the operating mode (`M`)
     SetMode(OperatingMode),
+    /// Enables or disables auto retransmission (`A`)
     SetAutoRetransmission(AutoRetransmissionMode),
+    /// Sets the timestamp resolution of received frames (`Z`)
+    SetTimestampMode(TimestampMode),
+    /// Opens the CAN channel (`O`)
     Open,
+    /// Opens the CAN channel in listen only mode (`L`, [Dialect::UsbTin] only)
+    OpenListenOnly,
+    /// Opens the CAN channel in loopback mode (`l`, [Dialect::UsbTin] only)
+    OpenLoopback,
+    /// Closes the CAN channel (`C`)
     Close,
-    TransmitFrame(CanFrame),
+    /// Asks for the firmware version (`V`)
+    GetFirmwareVersion,
+    /// Asks for the value of the error register (`E`)
+    GetErrorRegister,
+    /// Transmits a frame, using the command matching its ID and type
+    TransmitFrame(SlcanFrame),
 }
 
 impl Command {
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut result = Vec::new();
+    /// The maximum length of an encoded command, excluding the CR line
+    /// ending (command + extended id + dlc + 64 bytes of data as hex)
+    pub const MAX_ENCODED_LEN: usize = 1 + 8 + 1 + 128;
+
+    /// Serializes the command into `buffer` without a line ending and returns
+    /// the number of bytes written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is shorter than the encoded command. A buffer of
+    /// [`MAX_ENCODED_LEN`](Command::MAX_ENCODED_LEN) bytes always suffices.
+    pub fn encode_into(&self, buffer: &mut [u8]) -> usize {
+        let mut out = Encoder { buffer, len: 0 };
 
         match self {
             Command::SetNominalBitRate(rate) => {
-                result.push(CommandKind::SetNominalBitRate.into());
-                result.push((*rate).into());
+                out.push(CommandKind::SetNominalBitRate.into());
+                out.push((*rate).into());
             }
             Command::SetDataBitRate(rate) => {
-                result.push(CommandKind::SetDataBitRate.into());
-                result.push((*rate).into());
+                out.push(CommandKind::SetDataBitRate.into());
+                out.push((*rate).into());
             }
             Command::SetMode(mode) => {
-                result.push(CommandKind::SetMode.into());
-                result.push((*mode).into());
+                out.push(CommandKind::SetMode.into());
+                out.push((*mode).into());
             }
             Command::SetAutoRetransmission(mode) => {
-                result.push(CommandKind::SetAutoRetransmission.into());
-                result.push((*mode).into());
+                out.push(CommandKind::SetAutoRetransmission.into());
+                out.push((*mode).into());
+            }
+            Command::SetTimestampMode(mode) => {
+                out.push(CommandKind::SetTimestampMode.into());
+                out.push((*mode).into());
             }
-            Command::Open => result.push(CommandKind::Open.into()),
-            Command::Close => result.push(CommandKind::Close.into()),
-            Command::TransmitFrame(frame) => match frame {
-                CanFrame::Can2(frame) => {
-                    match frame.id() {
-                        Id::Standard(id) => {
-                            if frame.is_remote() {
-                                result.push(CommandKind::TransmitStandardRemoteFrame.into());
-                            } else {
-                                result.push(CommandKind::TransmitStandardDataFrame.into());
-                            }
-
-                            result.extend(standard_id_to_hex(id));
-                        }
-                        Id::Extended(id) => {
-                            if frame.is_remote() {
-                                result.push(CommandKind::TransmitExtendedRemoteFrame.into());
-                            } else {
-                                result.push(CommandKind::TransmitExtendedDataFrame.into());
-                            }
-
-                            result.extend(extended_id_to_hex(id));
-                        }
+            Command::Open => out.push(CommandKind::Open.into()),
+            Command::OpenListenOnly => out.push(CommandKind::OpenListenOnly.into()),
+            Command::OpenLoopback => out.push(CommandKind::OpenLoopback.into()),
+            Command::Close => out.push(CommandKind::Close.into()),
+            Command::GetFirmwareVersion => out.push(CommandKind::GetFirmwareVersion.into()),
+            Command::GetErrorRegister => out.push(CommandKind::GetErrorRegister.into()),
+            Command::TransmitFrame(frame) => encode_frame_into(frame, &mut out),
+        }
+
+        out.len
+    }
+
+    /// Returns the length of the encoded command, excluding the CR line
+    /// ending
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Command::SetNominalBitRate(_)
+            | Command::SetDataBitRate(_)
+            | Command::SetMode(_)
+            | Command::SetAutoRetransmission(_)
+            | Command::SetTimestampMode(_) => 2,
+            Command::Open
+            | Command::OpenListenOnly
+            | Command::OpenLoopback
+            | Command::Close
+            | Command::GetFirmwareVersion
+            | Command::GetErrorRegister => 1,
+            Command::TransmitFrame(frame) => encoded_frame_len(frame),
+        }
+    }
+
+    /// Serializes the command into a new vector of exactly the encoded length,
+    /// without a line ending. See [`encode_into`](Command::encode_into) for
+    /// encoding without allocating.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![0; self.encoded_len()];
+        self.encode_into(&mut buffer);
+
+        buffer
+    }
+
+    /// Serializes the command without a line ending and appends it to `out`,
+    /// e.g. for encoding many commands into one reused buffer. `out` only
+    /// grows if it lacks the capacity for the encoded command.
+    pub fn encode_to_vec(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.resize(start + self.encoded_len(), 0);
+        self.encode_into(&mut out[start..]);
+    }
+
+    /// Serializes the command with its CR line ending and writes it to
+    /// `writer` in a single `write_all`, so it isn't split into several USB
+    /// packets (which the CANable fails to parse). The writer isn't flushed.
+    ///
+    /// ```
+    /// use slcan_fd::Command;
+    ///
+    /// let mut out = Vec::new();
+    /// Command::Open.write_to(&mut out)?;
+    /// assert_eq!(out, b"O\r");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn write_to(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writer.write_all(self.encode().as_line())
+    }
+
+    /// Async version of [`write_to`](Command::write_to) for tokio writers
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_async(
+        &self,
+        writer: &mut (impl tokio::io::AsyncWrite + Unpin),
+    ) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        writer.write_all(self.encode().as_line()).await
+    }
+
+    /// Serializes the command into a [CommandBuffer] on the stack, so it can
+    /// be sent without allocating
+    ///
+    /// ```
+    /// use slcan_fd::{Command, NominalBitRate};
+    ///
+    /// let encoded = Command::SetNominalBitRate(NominalBitRate::Rate500Kbit).encode();
+    /// assert_eq!(&encoded[..], b"S6");
+    /// assert_eq!(encoded.as_line(), b"S6\r");
+    /// ```
+    pub fn encode(&self) -> CommandBuffer {
+        let mut buffer = CommandBuffer {
+            bytes: [0; Command::MAX_ENCODED_LEN + 1],
+            len: 0,
+        };
+        buffer.len = self.encode_into(&mut buffer.bytes);
+        buffer.bytes[buffer.len] = b'\r';
+
+        buffer
+    }
+}
+
+/// A command encoded by [`Command::encode`], stored inline with room for the
+/// longest command and its CR line ending. It dereferences to the encoded
+/// command without the line ending.
+#[derive(Clone, Copy)]
+pub struct CommandBuffer {
+    bytes: [u8; Command::MAX_ENCODED_LEN + 1],
+    len: usize,
+}
+
+impl CommandBuffer {
+    /// Returns the encoded command without the line ending
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns the encoded command followed by its CR line ending, ready to
+    /// be written to the gateway in a single write
+    pub fn as_line(&self) -> &[u8] {
+        &self.bytes[..=self.len]
+    }
+}
+
+impl std::ops::Deref for CommandBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for CommandBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl std::fmt::Debug for CommandBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CommandBuffer(\"{}\")", self.as_bytes().escape_ascii())
+    }
+}
+
+impl PartialEq for CommandBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for CommandBuffer {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CommandBuffer {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "CommandBuffer({=[u8]:a})", self.as_bytes());
+    }
+}
+
+/// Encodes a frame as the SLCAN command which transmits it, e.g. `t1231AB` for
+/// a standard data frame. The CR line ending is not included. This is the
+/// counterpart of [`parse_frame_from_bytes`](crate::parse_frame_from_bytes)
+/// for users with their own transport. See
+/// [`Command::encode_into`] for encoding without allocating.
+///
+/// Returns [UnsupportedFrame] for CAN XL frames.
+///
+/// ```
+/// use slcan_fd::{encode_frame, parse_frame_from_bytes, CanFdFrame, CanFrame, ExtendedId};
+///
+/// let frame = CanFrame::CanFd(CanFdFrame::new(ExtendedId::new(0x1234).unwrap(), &[1, 2]).unwrap());
+/// let line = encode_frame(&frame).unwrap();
+///
+/// assert_eq!(&line[..], b"B0000123420102");
+/// assert_eq!(parse_frame_from_bytes(&line).unwrap(), frame);
+/// ```
+pub fn encode_frame(frame: &CanFrame) -> Result<Vec<u8>, UnsupportedFrame> {
+    let frame = SlcanFrame::try_from(frame.clone())?;
+
+    let mut buffer = vec![0; encoded_frame_len(&frame)];
+    let mut out = Encoder {
+        buffer: &mut buffer,
+        len: 0,
+    };
+    encode_frame_into(&frame, &mut out);
+
+    Ok(buffer)
+}
+
+/// [SlcanFrame] only holds CAN 2.0 and CAN FD frames
+const NOT_SLCAN_FRAME: &str = "SlcanFrame never holds a CAN XL frame";
+
+/// Returns the length of the command transmitting `frame`: the specifier, the
+/// ID, the DLC and two hex digits per data byte
+fn encoded_frame_len(frame: &SlcanFrame) -> usize {
+    let data_len = match frame.frame() {
+        CanFrame::Can2(frame) => frame.data().map_or(0, <[u8]>::len),
+        CanFrame::CanFd(frame) => frame.data().len(),
+        _ => unreachable!("{}", NOT_SLCAN_FRAME),
+    };
+    let id_len = match frame.frame().id() {
+        Id::Standard(_) => 3,
+        Id::Extended(_) => 8,
+    };
+
+    1 + id_len + 1 + 2 * data_len
+}
+
+fn encode_frame_into(frame: &SlcanFrame, out: &mut Encoder) {
+    match frame.frame() {
+        CanFrame::Can2(frame) => {
+            match frame.id() {
+                Id::Standard(id) => {
+                    if frame.is_remote() {
+                        out.push(CommandKind::TransmitStandardRemoteFrame.into());
+                    } else {
+                        out.push(CommandKind::TransmitStandardDataFrame.into());
                     }
 
-                    result.push(to_hex_digit(frame.dlc() as u32));
+                    out.extend(&standard_id_to_hex(id));
+                }
+                Id::Extended(id) => {
+                    if frame.is_remote() {
+                        out.push(CommandKind::TransmitExtendedRemoteFrame.into());
+                    } else {
+                        out.push(CommandKind::TransmitExtendedDataFrame.into());
+                    }
 
-                    if let Some(data) = frame.data() {
-                        result.extend(bytes_to_hex(data));
+                    out.extend(&extended_id_to_hex(id));
+                }
+            }
+
+            out.push(to_hex_digit(frame.dlc() as u32));
+
+            if let Some(data) = frame.data() {
+                out.extend_hex(data);
+            }
+        }
+        CanFrame::CanFd(frame) => {
+            match frame.id() {
+                Id::Standard(id) => {
+                    if frame.is_bit_rate_switched() {
+                        out.push(CommandKind::TransmitStandardFdFrameWithBrs.into());
+                    } else {
+                        out.push(CommandKind::TransmitStandardFdFrameNoBrs.into());
                     }
+
+                    out.extend(&standard_id_to_hex(id));
                 }
-                CanFrame::CanFd(frame) => {
-                    match frame.id() {
-                        Id::Standard(id) => {
-                            if frame.is_bit_rate_switched() {
-                                result.push(CommandKind::TransmitStandardFdFrameWithBrs.into());
-                            } else {
-                                result.push(CommandKind::TransmitStandardFdFrameNoBrs.into());
-                            }
-
-                            result.extend(standard_id_to_hex(id));
-                        }
-                        Id::Extended(id) => {
-                            if frame.is_bit_rate_switched() {
-                                result.push(CommandKind::TransmitExtendedFdFrameWithBrs.into());
-                            } else {
-                                result.push(CommandKind::TransmitExtendedFdFrameNoBrs.into());
-                            }
-
-                            result.extend(extended_id_to_hex(id));
-                        }
+                Id::Extended(id) => {
+                    if frame.is_bit_rate_switched() {
+                        out.push(CommandKind::TransmitExtendedFdFrameWithBrs.into());
+                    } else {
+                        out.push(CommandKind::TransmitExtendedFdFrameNoBrs.into());
                     }
 
-                    result.push(to_hex_digit(frame.dlc() as u32));
-                    result.extend(bytes_to_hex(frame.data()));
+                    out.extend(&extended_id_to_hex(id));
                 }
-            },
+            }
+
+            out.push(to_hex_digit(frame.dlc() as u32));
+            out.extend_hex(frame.data());
         }
+        _ => unreachable!("{}", NOT_SLCAN_FRAME),
+    }
+}
 
-        result
+/// Appends bytes to the buffer passed to [`Command::encode_into`]
+struct Encoder<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Encoder<'_> {
+    fn push(&mut self, byte: u8) {
+        self.buffer[self.len] = byte;
+        self.len += 1;
+    }
+
+    fn extend(&mut self, bytes: &[u8]) {
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn extend_hex(&mut self, data: &[u8]) {
+        // Slicing once up front saves a bounds check per digit
+        let out = &mut self.buffer[self.len..self.len + 2 * data.len()];
+
+        for (digits, byte) in out.chunks_exact_mut(2).zip(data) {
+            digits[0] = to_hex_digit((byte >> 4) as u32);
+            digits[1] = to_hex_digit(*byte as u32);
+        }
+
+        self.len += 2 * data.len();
     }
 }
 
@@ -234,14 +767,3 @@ fn extended_id_to_hex(id: ExtendedId) -> [u8; 8] {
         to_hex_digit(raw),
     ]
 }
-
-fn bytes_to_hex(data: &[u8]) -> Vec<u8> {
-    let mut buf = Vec::<u8>::with_capacity(2 * data.len());
-
-    for byte in data {
-        buf.push(to_hex_digit((byte >> 4) as u32));
-        buf.push(to_hex_digit(*byte as u32));
-    }
-
-    buf
-}
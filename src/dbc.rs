@@ -0,0 +1,1177 @@
+//! Decoding and encoding of CAN signals described by a DBC database.
+//!
+//! Only the parts of the DBC format needed to locate and scale signals are
+//! understood: message (`BO_`) and signal (`SG_`) definitions, including
+//! simple (`M`, `m3`) and extended (`m3M`, `SG_MUL_VAL_`) multiplexing.
+//! Everything else in the file (comments, attributes, value tables, ...) is
+//! ignored.
+//!
+//! With the `tokio` feature, `SignalStream` turns a stream of received
+//! frames into a stream of decoded signal values.
+//!
+//! ```
+//! use slcan_fd::{dbc::Database, Can2Frame, StandardId};
+//!
+//! let db = Database::parse(
+//!     r#"
+//! BO_ 256 Engine: 8 ECU
+//!  SG_ Speed : 0|16@1+ (0.01,0) [0|655.35] "km/h" Dash
+//! "#,
+//! )
+//! .unwrap();
+//!
+//! let frame = Can2Frame::new_data(StandardId::new(256).unwrap(), &[0x10, 0x27, 0, 0, 0, 0, 0, 0]).unwrap();
+//! let decoded = db.decode(&frame.into()).unwrap();
+//!
+//! assert_eq!(decoded.signal("Speed").unwrap().value, 100.0);
+//! ```
+
+#[cfg(feature = "tokio")]
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+#[cfg(feature = "tokio")]
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use crate::frame::{Can2Frame, CanFdFrame, CanFrame};
+#[cfg(feature = "tokio")]
+use crate::{frame::RxFrame, timestamp::Timestamp};
+
+/// Bit 31 of a DBC message ID marks it as an extended ID
+const DBC_EXTENDED_FLAG: u32 = 1 << 31;
+
+/// Errors which can arise while parsing a DBC file
+#[derive(Debug, thiserror::Error)]
+pub enum DbcError {
+    #[error("Syntax error on line {line}: {reason}")]
+    Syntax { line: usize, reason: &'static str },
+    #[error("Signal on line {0} appears before any message definition")]
+    OrphanSignal(usize),
+    #[error("Extended multiplexing on line {0} refers to an unknown message or signal")]
+    UnknownMultiplexTarget(usize),
+    /// A message of a database converted from the `can-dbc` crate has an ID
+    /// which is out of range
+    #[error("Message ID 0x{0:X} is out of range")]
+    InvalidMessageId(u32),
+    /// The extended multiplexing of a database converted from the `can-dbc`
+    /// crate refers to a signal which doesn't exist
+    #[error("Extended multiplexing refers to the unknown signal {0:?}")]
+    UnknownMultiplexSignal(String),
+    /// A signal of a database converted from the `can-dbc` crate is empty or
+    /// wider than 64 bits
+    #[error("Signal {signal:?} has a size of {size} bits, which is not between 1 and 64")]
+    InvalidSignalSize { signal: String, size: u64 },
+}
+
+/// The order in which the bits of a signal are laid out in the frame data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Intel byte order (`@1`), where the start bit is the least significant
+    /// bit of the signal
+    LittleEndian,
+    /// Motorola byte order (`@0`), where the start bit is the most significant
+    /// bit of the signal
+    BigEndian,
+}
+
+/// Whether the raw value of a signal is signed (two's complement)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Unsigned,
+    Signed,
+}
+
+/// The condition under which a multiplexed signal is present in a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiplexCondition {
+    /// The name of the multiplexor (switch) signal
+    pub switch: String,
+    /// The raw values of the switch for which the signal is present
+    pub values: Vec<RangeInclusive<u64>>,
+}
+
+/// The definition of a single signal within a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalDefinition {
+    pub name: String,
+    pub start_bit: u16,
+    pub size: u16,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    /// Whether other signals of the message are multiplexed by this one
+    pub is_multiplexor: bool,
+    /// The condition under which this signal is present, or `None` if it is
+    /// always present
+    pub multiplexed_by: Option<MultiplexCondition>,
+}
+
+/// The definition of a message and the signals it contains
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageDefinition {
+    pub id: Id,
+    pub name: String,
+    pub size: usize,
+    pub signals: Vec<SignalDefinition>,
+}
+
+/// A set of message definitions, usually parsed from a DBC file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Database {
+    messages: Vec<MessageDefinition>,
+}
+
+/// The value of a single signal decoded from a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodedSignal<'a> {
+    pub definition: &'a SignalDefinition,
+    /// The raw value as it appears in the frame (sign extended for signed
+    /// signals)
+    pub raw: i64,
+    /// The physical value (`raw * factor + offset`)
+    pub value: f64,
+}
+
+/// The signals of a frame decoded according to its message definition. Only
+/// the signals which are present given the current multiplexor values are
+/// included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMessage<'a> {
+    pub definition: &'a MessageDefinition,
+    pub signals: Vec<DecodedSignal<'a>>,
+}
+
+impl<'a> DecodedMessage<'a> {
+    /// Finds a decoded signal by name
+    pub fn signal(&self, name: &str) -> Option<&DecodedSignal<'a>> {
+        self.signals.iter().find(|s| s.definition.name == name)
+    }
+}
+
+impl Database {
+    /// Creates a database from a list of message definitions
+    pub fn new(messages: Vec<MessageDefinition>) -> Self {
+        Self { messages }
+    }
+
+    /// Parses the contents of a DBC file
+    pub fn parse(text: &str) -> Result<Self, DbcError> {
+        let mut messages: Vec<MessageDefinition> = Vec::new();
+        let mut extended_multiplexing = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if let Some(rest) = line.strip_prefix("BO_ ") {
+                messages.push(parse_message(rest, line_number)?);
+            } else if let Some(rest) = line.strip_prefix("SG_ ") {
+                let signal = parse_signal(rest, line_number)?;
+                messages
+                    .last_mut()
+                    .ok_or(DbcError::OrphanSignal(line_number))?
+                    .signals
+                    .push(signal);
+            } else if let Some(rest) = line.strip_prefix("SG_MUL_VAL_ ") {
+                extended_multiplexing.push((line_number, rest));
+            }
+        }
+
+        messages.retain(|m| m.name != "VECTOR__INDEPENDENT_SIG_MSG");
+        resolve_simple_multiplexing(&mut messages);
+
+        // Extended multiplexing explicitly names the switch and value ranges
+        for (line_number, rest) in extended_multiplexing {
+            let (id, signal, condition) = parse_extended_multiplexing(rest, line_number)?;
+
+            let signal = messages
+                .iter_mut()
+                .find(|m| m.id == id)
+                .and_then(|m| m.signals.iter_mut().find(|s| s.name == signal))
+                .ok_or(DbcError::UnknownMultiplexTarget(line_number))?;
+
+            signal.multiplexed_by = Some(condition);
+        }
+
+        Ok(Self { messages })
+    }
+
+    /// Returns all message definitions
+    pub fn messages(&self) -> &[MessageDefinition] {
+        &self.messages
+    }
+
+    /// Finds a message definition by ID
+    pub fn message_by_id(&self, id: Id) -> Option<&MessageDefinition> {
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    /// Finds a message definition by name
+    pub fn message_by_name(&self, name: &str) -> Option<&MessageDefinition> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+
+    /// Decodes the signals of a frame. Returns `None` if the database has no
+    /// definition for the frame's ID or the frame is a remote frame.
+    pub fn decode(&self, frame: &CanFrame) -> Option<DecodedMessage<'_>> {
+        let (id, data) = match frame {
+            CanFrame::Can2(frame) => (frame.id(), frame.data()?),
+            CanFrame::CanFd(frame) => (frame.id(), frame.data()),
+            CanFrame::CanXl(frame) => (frame.id(), frame.data()),
+        };
+
+        self.message_by_id(id)?.decode(data)
+    }
+}
+
+impl MessageDefinition {
+    /// Finds a signal definition by name
+    pub fn signal(&self, name: &str) -> Option<&SignalDefinition> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// Decodes the signals present in `data`, taking multiplexing into
+    /// account. Signals which do not fit in `data` are skipped.
+    pub fn decode(&self, data: &[u8]) -> Option<DecodedMessage<'_>> {
+        let signals = self
+            .signals
+            .iter()
+            .filter(|signal| self.is_present(signal, data, 0))
+            .filter_map(|signal| {
+                let raw = signal.extract_raw(data)?;
+
+                Some(DecodedSignal {
+                    definition: signal,
+                    raw,
+                    value: raw as f64 * signal.factor + signal.offset,
+                })
+            })
+            .collect();
+
+        Some(DecodedMessage {
+            definition: self,
+            signals,
+        })
+    }
+
+    /// Determines whether a signal is present by walking up its chain of
+    /// multiplexors
+    fn is_present(&self, signal: &SignalDefinition, data: &[u8], depth: usize) -> bool {
+        let Some(condition) = &signal.multiplexed_by else {
+            return true;
+        };
+
+        // Guard against malformed databases with circular multiplexing
+        if depth > self.signals.len() {
+            return false;
+        }
+
+        let Some(switch) = self.signal(&condition.switch) else {
+            return false;
+        };
+
+        if !self.is_present(switch, data, depth + 1) {
+            return false;
+        }
+
+        let Some(value) = switch.extract_raw(data) else {
+            return false;
+        };
+
+        condition
+            .values
+            .iter()
+            .any(|range| range.contains(&(value as u64)))
+    }
+}
+
+impl SignalDefinition {
+    /// Extracts the raw value of the signal from the frame data, or returns
+    /// `None` if the signal does not fit in `data`
+    pub fn extract_raw(&self, data: &[u8]) -> Option<i64> {
+        if !self.has_raw_size() {
+            return None;
+        }
+
+        let mut value = 0u64;
+
+        for (i, position) in self.bit_positions().enumerate() {
+            let byte = *data.get(position / 8)?;
+            let bit = (byte >> (position % 8)) & 1;
+
+            match self.byte_order {
+                ByteOrder::LittleEndian => value |= (bit as u64) << i,
+                ByteOrder::BigEndian => value = (value << 1) | bit as u64,
+            }
+        }
+
+        Some(match self.value_type {
+            ValueType::Unsigned => value as i64,
+            ValueType::Signed => {
+                let shift = 64 - self.size as u32;
+                ((value << shift) as i64) >> shift
+            }
+        })
+    }
+
+    /// Converts a physical value into the raw value stored in the frame.
+    ///
+    /// The value is first clamped to the signal's `[min|max]` range (unless
+    /// the range is empty, which DBC files use for "unspecified"), then
+    /// scaled and rounded, and finally clamped to what fits in `size` bits.
+    pub fn physical_to_raw(&self, value: f64) -> i64 {
+        let value = if self.min < self.max {
+            value.clamp(self.min, self.max)
+        } else {
+            value
+        };
+
+        let factor = if self.factor == 0.0 { 1.0 } else { self.factor };
+        let raw = ((value - self.offset) / factor).round();
+
+        let (min, max) = match (self.value_type, self.size.min(64)) {
+            (ValueType::Unsigned, 64) => (0.0, u64::MAX as f64),
+            (ValueType::Unsigned, size) => (0.0, ((1u64 << size) - 1) as f64),
+            (ValueType::Signed, size) => {
+                let half = 2f64.powi(size as i32 - 1);
+                (-half, half - 1.0)
+            }
+        };
+
+        // Saturating float to int conversion takes care of the 64-bit cases
+        match self.value_type {
+            ValueType::Unsigned => raw.clamp(min, max) as u64 as i64,
+            ValueType::Signed => raw.clamp(min, max) as i64,
+        }
+    }
+
+    /// Writes a raw value into the frame data. Bits of the signal which do
+    /// not fit in `data` are ignored, as are signals which are empty or wider
+    /// than 64 bits.
+    pub fn insert_raw(&self, data: &mut [u8], raw: i64) {
+        if !self.has_raw_size() {
+            return;
+        }
+
+        let raw = raw as u64;
+
+        for (i, position) in self.bit_positions().enumerate() {
+            let shift = match self.byte_order {
+                ByteOrder::LittleEndian => i,
+                ByteOrder::BigEndian => self.size as usize - 1 - i,
+            };
+            let bit = ((raw >> shift) & 1) as u8;
+
+            if let Some(byte) = data.get_mut(position / 8) {
+                *byte = (*byte & !(1 << (position % 8))) | (bit << (position % 8));
+            }
+        }
+    }
+
+    /// Whether the raw value of the signal fits in 1 to 64 bits
+    fn has_raw_size(&self) -> bool {
+        (1..=64).contains(&self.size)
+    }
+
+    /// Iterates over the bit positions of the signal (`byte * 8 + bit`) in
+    /// the order they are stored: LSB first for little endian signals and
+    /// MSB first for big endian signals
+    fn bit_positions(&self) -> impl Iterator<Item = usize> {
+        let byte_order = self.byte_order;
+        let mut position = self.start_bit as usize;
+
+        (0..self.size).map(move |_| {
+            let current = position;
+
+            position = match byte_order {
+                ByteOrder::LittleEndian => position + 1,
+                // Motorola signals continue at the MSB of the next byte
+                ByteOrder::BigEndian if position % 8 == 0 => position + 15,
+                ByteOrder::BigEndian => position.wrapping_sub(1),
+            };
+
+            current
+        })
+    }
+}
+
+/// Errors which can arise while encoding signal values into a frame
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeError {
+    #[error("The database has no message named {0:?}")]
+    UnknownMessage(String),
+    #[error("Message {message:?} has no signal named {signal:?}")]
+    UnknownSignal { message: String, signal: String },
+    #[error("Message {0:?} has a size which does not fit in a CAN FD frame")]
+    InvalidMessageSize(String),
+    #[error("Signal {signal:?} of message {message:?} is empty or wider than 64 bits")]
+    InvalidSignalSize { message: String, signal: String },
+}
+
+/// Builds frames from physical signal values according to a [`Database`].
+///
+/// ```
+/// use slcan_fd::dbc::{Database, SignalEncoder};
+///
+/// let db = Database::parse(
+///     r#"
+/// BO_ 256 Engine: 8 ECU
+///  SG_ Speed : 0|16@1+ (0.01,0) [0|655.35] "km/h" Dash
+/// "#,
+/// )
+/// .unwrap();
+///
+/// let frame = SignalEncoder::new(&db).encode("Engine", &[("Speed", 42.5)]).unwrap();
+/// assert_eq!(db.decode(&frame).unwrap().signal("Speed").unwrap().value, 42.5);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SignalEncoder<'a> {
+    database: &'a Database,
+}
+
+impl<'a> SignalEncoder<'a> {
+    /// Creates an encoder for the messages of `database`
+    pub fn new(database: &'a Database) -> Self {
+        Self { database }
+    }
+
+    /// Encodes a frame for the message named `message` with the given
+    /// physical signal values. See [`SignalDefinition::physical_to_raw`] for
+    /// how values are scaled and clamped.
+    ///
+    /// Signals which are not listed are set to a raw value of 0. If a
+    /// multiplexed signal is listed but its multiplexor is not, the
+    /// multiplexor is set to the first value for which the signal is
+    /// present.
+    ///
+    /// Messages of up to 8 bytes produce a CAN 2.0 frame and larger messages
+    /// produce a CAN FD frame (padded to the next valid length).
+    pub fn encode(&self, message: &str, values: &[(&str, f64)]) -> Result<CanFrame, EncodeError> {
+        let definition = self
+            .database
+            .message_by_name(message)
+            .ok_or_else(|| EncodeError::UnknownMessage(message.to_string()))?;
+
+        let mut data = vec![0u8; definition.size];
+
+        let check_size = |signal: &SignalDefinition| {
+            if signal.has_raw_size() {
+                Ok(())
+            } else {
+                Err(EncodeError::InvalidSignalSize {
+                    message: message.to_string(),
+                    signal: signal.name.clone(),
+                })
+            }
+        };
+
+        for (name, value) in values {
+            let signal = definition
+                .signal(name)
+                .ok_or_else(|| EncodeError::UnknownSignal {
+                    message: message.to_string(),
+                    signal: name.to_string(),
+                })?;
+
+            check_size(signal)?;
+            signal.insert_raw(&mut data, signal.physical_to_raw(*value));
+
+            // Select the multiplexor values which make this signal present
+            let mut condition = signal.multiplexed_by.as_ref();
+
+            while let Some(current) = condition {
+                let listed = values.iter().any(|(name, _)| *name == current.switch);
+
+                let Some(switch) = definition.signal(&current.switch) else {
+                    break;
+                };
+
+                if !listed {
+                    if let Some(range) = current.values.first() {
+                        check_size(switch)?;
+                        switch.insert_raw(&mut data, *range.start() as i64);
+                    }
+                }
+
+                condition = switch.multiplexed_by.as_ref();
+            }
+        }
+
+        let invalid_size = || EncodeError::InvalidMessageSize(message.to_string());
+
+        Ok(if data.len() <= 8 {
+            Can2Frame::new_data(definition.id, &data)
+                .ok_or_else(invalid_size)?
+                .into()
+        } else {
+            CanFdFrame::new_padded(definition.id, &data)
+                .ok_or_else(invalid_size)?
+                .into()
+        })
+    }
+}
+
+/// The new value of a signal, decoded from a frame by a [SignalStream]
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalUpdate<'a> {
+    /// The name of the message the signal belongs to
+    pub message: &'a str,
+    /// The name of the signal
+    pub signal: &'a str,
+    /// The physical value (`raw * factor + offset`)
+    pub value: f64,
+    /// The hardware timestamp of the frame, if timestamps are enabled
+    pub timestamp: Option<Timestamp>,
+}
+
+/// Decodes a stream of frames into a stream of [signal updates](SignalUpdate),
+/// for dashboards and loggers which only care about the physical values.
+///
+/// Every signal present in a frame yields one update, in the order the
+/// signals are defined in the message. Frames with IDs the database has no
+/// definition for and remote frames are skipped.
+///
+/// The frames usually come from a `Subscription` of the `background`
+/// feature, but any [Unpin] stream of [RxFrame]s works; others can be
+/// pinned with [`Box::pin`].
+///
+/// ```no_run
+/// # #[cfg(feature = "background")]
+/// # async fn run(socket: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>, db: slcan_fd::dbc::Database) {
+/// use slcan_fd::background::{BackgroundReader, IdFilter};
+/// use slcan_fd::dbc::SignalStream;
+///
+/// let reader = BackgroundReader::spawn(socket);
+/// let mut signals = SignalStream::new(reader.subscribe(IdFilter::all()), &db);
+///
+/// while let Some(update) = signals.recv().await {
+///     println!("{}.{} = {}", update.message, update.signal, update.value);
+/// }
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct SignalStream<'a, S> {
+    frames: S,
+    database: &'a Database,
+    pending: VecDeque<SignalUpdate<'a>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<'a, S> SignalStream<'a, S>
+where
+    S: futures_core::Stream<Item = RxFrame> + Unpin,
+{
+    /// Creates a stream which decodes the frames yielded by `frames`
+    /// according to `database`
+    pub fn new(frames: S, database: &'a Database) -> Self {
+        Self {
+            frames,
+            database,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the database the frames are decoded with
+    pub fn database(&self) -> &'a Database {
+        self.database
+    }
+
+    /// Returns the underlying stream of frames, dropping the updates which
+    /// were decoded but not yielded yet
+    pub fn into_inner(self) -> S {
+        self.frames
+    }
+
+    /// Waits for the next signal update. Returns `None` once the stream of
+    /// frames has ended and all updates were yielded.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe if the underlying stream is.
+    pub async fn recv(&mut self) -> Option<SignalUpdate<'a>> {
+        poll_fn(|cx| self.poll_next_update(cx)).await
+    }
+
+    fn poll_next_update(&mut self, cx: &mut Context<'_>) -> Poll<Option<SignalUpdate<'a>>> {
+        loop {
+            if let Some(update) = self.pending.pop_front() {
+                return Poll::Ready(Some(update));
+            }
+
+            let Some(frame) = std::task::ready!(Pin::new(&mut self.frames).poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+
+            if let Some(decoded) = self.database.decode(&frame.frame) {
+                let message = decoded.definition.name.as_str();
+
+                self.pending
+                    .extend(decoded.signals.iter().map(|signal| SignalUpdate {
+                        message,
+                        signal: signal.definition.name.as_str(),
+                        value: signal.value,
+                        timestamp: frame.timestamp,
+                    }));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<'a, S> futures_core::Stream for SignalStream<'a, S>
+where
+    S: futures_core::Stream<Item = RxFrame> + Unpin,
+{
+    type Item = SignalUpdate<'a>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_update(cx)
+    }
+}
+
+/// Points the signals which are multiplexed with simple multiplexing (`m3`)
+/// at the one multiplexor of their message
+fn resolve_simple_multiplexing(messages: &mut [MessageDefinition]) {
+    for message in messages.iter_mut() {
+        let switch = message
+            .signals
+            .iter()
+            .find(|s| s.is_multiplexor && s.multiplexed_by.is_none())
+            .or_else(|| message.signals.iter().find(|s| s.is_multiplexor))
+            .map(|s| s.name.clone());
+
+        if let Some(switch) = switch {
+            for signal in message.signals.iter_mut() {
+                if let Some(condition) = signal.multiplexed_by.as_mut() {
+                    if signal.name != switch {
+                        condition.switch.clone_from(&switch);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn syntax(line: usize, reason: &'static str) -> DbcError {
+    DbcError::Syntax { line, reason }
+}
+
+fn dbc_id(raw: u32, line: usize) -> Result<Id, DbcError> {
+    if raw & DBC_EXTENDED_FLAG != 0 {
+        Ok(ExtendedId::new(raw & ExtendedId::MAX.as_raw())
+            .unwrap()
+            .into())
+    } else {
+        StandardId::new(raw as u16)
+            .filter(|_| raw <= StandardId::MAX.as_raw() as u32)
+            .map(Into::into)
+            .ok_or(syntax(line, "standard message ID out of range"))
+    }
+}
+
+/// Parses `<id> <name>: <size> <transmitter>`
+fn parse_message(rest: &str, line: usize) -> Result<MessageDefinition, DbcError> {
+    let (head, tail) = rest
+        .split_once(':')
+        .ok_or(syntax(line, "expected ':' in message definition"))?;
+
+    let mut head = head.split_whitespace();
+    let raw_id: u32 = head
+        .next()
+        .and_then(|id| id.parse().ok())
+        .ok_or(syntax(line, "invalid message ID"))?;
+    let name = head.next().ok_or(syntax(line, "missing message name"))?;
+    let size: usize = tail
+        .split_whitespace()
+        .next()
+        .and_then(|size| size.parse().ok())
+        .ok_or(syntax(line, "invalid message size"))?;
+
+    let id = if name == "VECTOR__INDEPENDENT_SIG_MSG" {
+        StandardId::ZERO.into()
+    } else {
+        dbc_id(raw_id, line)?
+    };
+
+    Ok(MessageDefinition {
+        id,
+        name: name.to_string(),
+        size,
+        signals: Vec::new(),
+    })
+}
+
+/// Parses `<name> [M|mX|mXM] : <start>|<size>@<order><sign> (<factor>,<offset>)
+/// [<min>|<max>] "<unit>" <receivers>`
+fn parse_signal(rest: &str, line: usize) -> Result<SignalDefinition, DbcError> {
+    let (head, tail) = rest
+        .split_once(':')
+        .ok_or(syntax(line, "expected ':' in signal definition"))?;
+
+    let mut head = head.split_whitespace();
+    let name = head.next().ok_or(syntax(line, "missing signal name"))?;
+
+    let (is_multiplexor, multiplexed_by) = match head.next() {
+        None => (false, None),
+        Some("M") => (true, None),
+        Some(indicator) => {
+            let value = indicator
+                .strip_prefix('m')
+                .ok_or(syntax(line, "invalid multiplexer indicator"))?;
+            let (value, is_multiplexor) = match value.strip_suffix('M') {
+                Some(value) => (value, true),
+                None => (value, false),
+            };
+            let value: u64 = value
+                .parse()
+                .map_err(|_| syntax(line, "invalid multiplexer value"))?;
+
+            let condition = MultiplexCondition {
+                // Resolved once the whole message has been parsed
+                switch: String::new(),
+                values: vec![value..=value],
+            };
+
+            (is_multiplexor, Some(condition))
+        }
+    };
+
+    let tail = tail.trim_start();
+
+    let (layout, tail) = tail
+        .split_once(' ')
+        .ok_or(syntax(line, "missing signal scaling"))?;
+    let (start_bit, layout) = layout
+        .split_once('|')
+        .ok_or(syntax(line, "expected '|' in signal layout"))?;
+    let (size, layout) = layout
+        .split_once('@')
+        .ok_or(syntax(line, "expected '@' in signal layout"))?;
+
+    let start_bit: u16 = start_bit
+        .parse()
+        .map_err(|_| syntax(line, "invalid signal start bit"))?;
+    let size: u16 = size
+        .parse()
+        .ok()
+        .filter(|size| (1..=64).contains(size))
+        .ok_or(syntax(line, "signal size must be between 1 and 64 bits"))?;
+
+    let mut layout = layout.chars();
+    let byte_order = match layout.next() {
+        Some('0') => ByteOrder::BigEndian,
+        Some('1') => ByteOrder::LittleEndian,
+        _ => return Err(syntax(line, "invalid signal byte order")),
+    };
+    let value_type = match layout.next() {
+        Some('+') => ValueType::Unsigned,
+        Some('-') => ValueType::Signed,
+        _ => return Err(syntax(line, "invalid signal value type")),
+    };
+
+    let (scaling, tail) = tail
+        .trim_start()
+        .strip_prefix('(')
+        .and_then(|t| t.split_once(')'))
+        .ok_or(syntax(line, "invalid signal scaling"))?;
+    let (factor, offset) =
+        parse_pair(scaling, ',').ok_or(syntax(line, "invalid signal scaling"))?;
+
+    let (range, tail) = tail
+        .trim_start()
+        .strip_prefix('[')
+        .and_then(|t| t.split_once(']'))
+        .ok_or(syntax(line, "invalid signal range"))?;
+    let (min, max) = parse_pair(range, '|').ok_or(syntax(line, "invalid signal range"))?;
+
+    let unit = tail
+        .trim_start()
+        .strip_prefix('"')
+        .and_then(|t| t.split_once('"'))
+        .map(|(unit, _)| unit)
+        .unwrap_or_default();
+
+    Ok(SignalDefinition {
+        name: name.to_string(),
+        start_bit,
+        size,
+        byte_order,
+        value_type,
+        factor,
+        offset,
+        min,
+        max,
+        unit: unit.to_string(),
+        is_multiplexor,
+        multiplexed_by,
+    })
+}
+
+/// Parses `<id> <signal> <switch> <a>-<b>, <c>-<d>;`
+fn parse_extended_multiplexing(
+    rest: &str,
+    line: usize,
+) -> Result<(Id, &str, MultiplexCondition), DbcError> {
+    let rest = rest.trim().trim_end_matches(';');
+    let mut parts = rest.splitn(4, char::is_whitespace);
+
+    let raw_id: u32 = parts
+        .next()
+        .and_then(|id| id.parse().ok())
+        .ok_or(syntax(line, "invalid message ID"))?;
+    let signal = parts.next().ok_or(syntax(line, "missing signal name"))?;
+    let switch = parts.next().ok_or(syntax(line, "missing switch name"))?;
+    let ranges = parts.next().ok_or(syntax(line, "missing switch values"))?;
+
+    let values = ranges
+        .split(',')
+        .map(|range| {
+            let (start, end) = range
+                .trim()
+                .split_once('-')
+                .ok_or(syntax(line, "invalid switch value range"))?;
+            let start = start
+                .trim()
+                .parse()
+                .map_err(|_| syntax(line, "invalid switch value"))?;
+            let end = end
+                .trim()
+                .parse()
+                .map_err(|_| syntax(line, "invalid switch value"))?;
+            Ok(start..=end)
+        })
+        .collect::<Result<_, DbcError>>()?;
+
+    Ok((
+        dbc_id(raw_id, line)?,
+        signal,
+        MultiplexCondition {
+            switch: switch.to_string(),
+            values,
+        },
+    ))
+}
+
+fn parse_pair(text: &str, separator: char) -> Option<(f64, f64)> {
+    let (a, b) = text.split_once(separator)?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+/// Converts the messages of a DBC file parsed with the
+/// [`can-dbc`](https://docs.rs/can-dbc) crate, for applications which parse
+/// their DBC files with it already. As with [`Database::parse`], only the
+/// message and signal definitions are taken over, including simple and
+/// extended multiplexing.
+///
+/// ```
+/// use slcan_fd::dbc::Database;
+///
+/// let dbc = can_dbc::DBC::try_from(
+///     r#"VERSION ""
+///
+/// NS_ :
+///
+/// BS_:
+///
+/// BU_: ECU Dash
+///
+/// BO_ 256 Engine: 8 ECU
+///  SG_ Speed : 0|16@1+ (0.01,0) [0|655.35] "km/h" Dash
+/// "#,
+/// )
+/// .unwrap();
+///
+/// let db = Database::try_from(&dbc).unwrap();
+/// assert_eq!(db.message_by_name("Engine").unwrap().signals[0].factor, 0.01);
+/// ```
+#[cfg(feature = "can-dbc")]
+impl TryFrom<&can_dbc::DBC> for Database {
+    type Error = DbcError;
+
+    fn try_from(dbc: &can_dbc::DBC) -> Result<Self, DbcError> {
+        let mut messages = dbc
+            .messages()
+            .iter()
+            .filter(|m| m.message_name() != "VECTOR__INDEPENDENT_SIG_MSG")
+            .map(|m| {
+                Ok(MessageDefinition {
+                    id: can_dbc_id(*m.message_id())?,
+                    name: m.message_name().clone(),
+                    size: *m.message_size() as usize,
+                    signals: m
+                        .signals()
+                        .iter()
+                        .map(can_dbc_signal)
+                        .collect::<Result<_, DbcError>>()?,
+                })
+            })
+            .collect::<Result<Vec<_>, DbcError>>()?;
+
+        resolve_simple_multiplexing(&mut messages);
+
+        for multiplex in dbc.extended_multiplex() {
+            let id = can_dbc_id(*multiplex.message_id())?;
+
+            let signal = messages
+                .iter_mut()
+                .find(|m| m.id == id)
+                .and_then(|m| {
+                    m.signals
+                        .iter_mut()
+                        .find(|s| s.name == *multiplex.signal_name())
+                })
+                .ok_or_else(|| DbcError::UnknownMultiplexSignal(multiplex.signal_name().clone()))?;
+
+            signal.multiplexed_by = Some(MultiplexCondition {
+                switch: multiplex.multiplexor_signal_name().clone(),
+                values: multiplex
+                    .mappings()
+                    .iter()
+                    .map(|mapping| *mapping.min_value()..=*mapping.max_value())
+                    .collect(),
+            });
+        }
+
+        Ok(Self { messages })
+    }
+}
+
+#[cfg(feature = "can-dbc")]
+fn can_dbc_id(id: can_dbc::MessageId) -> Result<Id, DbcError> {
+    match id {
+        can_dbc::MessageId::Standard(raw) => StandardId::new(raw).map(Into::into),
+        can_dbc::MessageId::Extended(raw) => ExtendedId::new(raw).map(Into::into),
+    }
+    .ok_or(DbcError::InvalidMessageId(id.raw()))
+}
+
+#[cfg(feature = "can-dbc")]
+fn can_dbc_signal(signal: &can_dbc::Signal) -> Result<SignalDefinition, DbcError> {
+    use can_dbc::MultiplexIndicator;
+
+    if !(1..=64).contains(&signal.signal_size) {
+        return Err(DbcError::InvalidSignalSize {
+            signal: signal.name().clone(),
+            size: signal.signal_size,
+        });
+    }
+
+    let condition = |value: u64| MultiplexCondition {
+        // Resolved once all messages have been converted
+        switch: String::new(),
+        values: vec![value..=value],
+    };
+
+    let (is_multiplexor, multiplexed_by) = match *signal.multiplexer_indicator() {
+        MultiplexIndicator::Plain => (false, None),
+        MultiplexIndicator::Multiplexor => (true, None),
+        MultiplexIndicator::MultiplexedSignal(value) => (false, Some(condition(value))),
+        MultiplexIndicator::MultiplexorAndMultiplexedSignal(value) => {
+            (true, Some(condition(value)))
+        }
+    };
+
+    Ok(SignalDefinition {
+        name: signal.name().clone(),
+        // Signals this large don't fit into any frame and are never decoded
+        start_bit: u16::try_from(signal.start_bit).unwrap_or(u16::MAX),
+        size: signal.signal_size as u16,
+        byte_order: match signal.byte_order() {
+            can_dbc::ByteOrder::LittleEndian => ByteOrder::LittleEndian,
+            can_dbc::ByteOrder::BigEndian => ByteOrder::BigEndian,
+        },
+        value_type: match signal.value_type() {
+            can_dbc::ValueType::Unsigned => ValueType::Unsigned,
+            can_dbc::ValueType::Signed => ValueType::Signed,
+        },
+        factor: signal.factor,
+        offset: signal.offset,
+        min: signal.min,
+        max: signal.max,
+        unit: signal.unit().clone(),
+        is_multiplexor,
+        multiplexed_by,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(name: &str, start_bit: u16, size: u16, byte_order: ByteOrder) -> SignalDefinition {
+        SignalDefinition {
+            name: name.to_string(),
+            start_bit,
+            size,
+            byte_order,
+            value_type: ValueType::Unsigned,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+            unit: String::new(),
+            is_multiplexor: false,
+            multiplexed_by: None,
+        }
+    }
+
+    fn message(size: usize, signals: Vec<SignalDefinition>) -> Database {
+        Database::new(vec![MessageDefinition {
+            id: StandardId::new(0x100).unwrap().into(),
+            name: "Message".to_string(),
+            size,
+            signals,
+        }])
+    }
+
+    #[test]
+    fn rejects_encoding_signals_wider_than_64_bits() {
+        let db = message(16, vec![signal("Wide", 0, 72, ByteOrder::LittleEndian)]);
+
+        assert!(matches!(
+            SignalEncoder::new(&db).encode("Message", &[("Wide", 1.0)]),
+            Err(EncodeError::InvalidSignalSize { signal, .. }) if signal == "Wide"
+        ));
+
+        let mut data = [0xAA; 16];
+        db.messages()[0].signals[0].insert_raw(&mut data, -1);
+        assert_eq!(data, [0xAA; 16]);
+    }
+
+    #[test]
+    fn encodes_64_bit_signals() {
+        for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let start_bit = match byte_order {
+                ByteOrder::LittleEndian => 0,
+                ByteOrder::BigEndian => 7,
+            };
+            let db = message(8, vec![signal("Full", start_bit, 64, byte_order)]);
+            let definition = &db.messages()[0].signals[0];
+
+            let mut data = [0; 8];
+            definition.insert_raw(&mut data, 0x0102_0304_0506_0708);
+            assert_eq!(definition.extract_raw(&data), Some(0x0102_0304_0506_0708));
+
+            let expected = match byte_order {
+                ByteOrder::LittleEndian => [8, 7, 6, 5, 4, 3, 2, 1],
+                ByteOrder::BigEndian => [1, 2, 3, 4, 5, 6, 7, 8],
+            };
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_signals_wider_than_64_bits() {
+        for layout in ["0|72@1+", "0|0@1+"] {
+            let dbc = format!("BO_ 256 Message: 16 ECU\n SG_ X : {layout} (1,0) [0|0] \"\" ECU\n");
+
+            assert!(matches!(
+                Database::parse(&dbc),
+                Err(DbcError::Syntax { line: 2, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn decodes_motorola_signals_across_byte_boundaries() {
+        let db = Database::parse(
+            "BO_ 256 Message: 8 ECU\n \
+             SG_ Short : 3|12@0- (1,0) [0|0] \"\" ECU\n \
+             SG_ Long : 21|16@0+ (1,0) [0|0] \"\" ECU\n",
+        )
+        .unwrap();
+        let definition = &db.messages()[0];
+
+        // Short takes the low nibble of byte 0 and all of byte 1, Long the
+        // low 6 bits of byte 2, byte 3 and the high 2 bits of byte 4
+        let data = [0xF8, 0x3C, 0x3F, 0xFF, 0xC0, 0, 0, 0];
+        let decoded = definition.decode(&data).unwrap();
+        assert_eq!(decoded.signal("Short").unwrap().raw, -0x7C4);
+        assert_eq!(decoded.signal("Long").unwrap().raw, 0xFFFF);
+
+        // Inserting leaves the neighbouring bits alone
+        let mut data = [0xF0, 0, 0xC0, 0, 0x3F, 0, 0, 0];
+        definition.signals[0].insert_raw(&mut data, 0x53C);
+        definition.signals[1].insert_raw(&mut data, 0x8001);
+        assert_eq!(data, [0xF5, 0x3C, 0xE0, 0x00, 0x7F, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_and_encodes_nested_multiplexing() {
+        let db = Database::parse(
+            "BO_ 256 Message: 8 ECU\n \
+             SG_ Mux M : 0|8@1+ (1,0) [0|0] \"\" ECU\n \
+             SG_ A m1 : 8|8@1+ (1,0) [0|0] \"\" ECU\n \
+             SG_ B m2M : 8|8@1+ (1,0) [0|0] \"\" ECU\n \
+             SG_ C m3 : 16|16@1+ (0.5,0) [0|0] \"\" ECU\n \
+             SG_ Always : 56|8@1+ (1,0) [0|0] \"\" ECU\n\
+             SG_MUL_VAL_ 256 C B 3-3, 5-7;\n",
+        )
+        .unwrap();
+        let definition = &db.messages()[0];
+
+        let present = |data: [u8; 8]| -> Vec<(&str, f64)> {
+            definition
+                .decode(&data)
+                .unwrap()
+                .signals
+                .iter()
+                .map(|signal| (signal.definition.name.as_str(), signal.value))
+                .collect()
+        };
+
+        assert_eq!(
+            present([1, 6, 0x34, 0x12, 0, 0, 0, 9]),
+            [("Mux", 1.0), ("A", 6.0), ("Always", 9.0)]
+        );
+        assert_eq!(
+            present([2, 6, 0x34, 0x12, 0, 0, 0, 9]),
+            [("Mux", 2.0), ("B", 6.0), ("C", 2330.0), ("Always", 9.0)]
+        );
+        assert_eq!(
+            present([2, 4, 0x34, 0x12, 0, 0, 0, 9]),
+            [("Mux", 2.0), ("B", 4.0), ("Always", 9.0)]
+        );
+        assert_eq!(
+            present([3, 6, 0x34, 0x12, 0, 0, 0, 9]),
+            [("Mux", 3.0), ("Always", 9.0)]
+        );
+
+        // Encoding a nested signal selects both of its multiplexors
+        let frame = SignalEncoder::new(&db)
+            .encode("Message", &[("C", 50.0)])
+            .unwrap();
+        assert_eq!(frame.data(), Some(&[2, 3, 100, 0, 0, 0, 0, 0][..]));
+
+        let frame = SignalEncoder::new(&db)
+            .encode("Message", &[("C", 50.0), ("B", 6.0)])
+            .unwrap();
+        assert_eq!(frame.data(), Some(&[2, 6, 100, 0, 0, 0, 0, 0][..]));
+    }
+
+    #[cfg(feature = "can-dbc")]
+    #[test]
+    fn rejects_can_dbc_signals_wider_than_64_bits() {
+        let dbc = can_dbc::DBC::try_from(
+            "VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_: ECU\n\n\
+             BO_ 256 Message: 16 ECU\n SG_ X : 0|72@1+ (1,0) [0|0] \"\" ECU\n",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            Database::try_from(&dbc),
+            Err(DbcError::InvalidSignalSize { size: 72, .. })
+        ));
+    }
+}
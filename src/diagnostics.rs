@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use crate::{
+    busload::{frame_bits, Stuffing},
+    command::{DataBitRate, NominalBitRate},
+    frame::CanFrame,
+    status::{BusState, BusStatus},
+};
+
+/// A fault the bus is suspected to have, as concluded by
+/// `CanSocket::diagnose`. The conclusions are heuristics which should be
+/// confirmed by measuring the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusFault {
+    /// Neither frames nor errors were seen, e.g. because the bus is idle or
+    /// the gateway isn't connected to it
+    NoTraffic,
+    /// Errors were detected but no frame was received, which is typical for
+    /// a bit rate which doesn't match the bus
+    WrongBitRate,
+    /// The controller went error passive or bus-off without receiving a
+    /// frame, which is typical for a bus line stuck at the dominant level
+    StuckDominant,
+    /// Frames were received but the error counters rose, e.g. because of a
+    /// missing termination or noise
+    IntermittentErrors,
+}
+
+/// The health of the bus over a sampling window, as reported by
+/// `CanSocket::diagnose`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusDiagnosis {
+    /// The length of the sampling window
+    pub duration: Duration,
+    /// The number of frames received
+    pub frames: u64,
+    /// The estimated fraction of the time the bus was busy (0 to 1), from
+    /// the lengths of the received frames without stuff bits. `None` if the
+    /// nominal bit rate of the socket isn't known.
+    pub bus_load: Option<f32>,
+    /// The bus state reported last before sampling
+    pub initial_status: BusStatus,
+    /// The bus state reported last while sampling
+    pub final_status: BusStatus,
+    /// The most severe bus state reported while sampling
+    pub worst_state: BusState,
+    /// The number of lines which could not be parsed, which hints at
+    /// problems with the link to the gateway rather than the bus
+    pub parse_errors: u64,
+    /// The fault the bus is suspected to have, if any
+    pub suspected_fault: Option<BusFault>,
+}
+
+impl BusDiagnosis {
+    /// Returns how much the receive error counter changed while sampling
+    pub fn rx_error_trend(&self) -> i32 {
+        self.final_status.rx_error_count as i32 - self.initial_status.rx_error_count as i32
+    }
+
+    /// Returns how much the transmit error counter changed while sampling
+    pub fn tx_error_trend(&self) -> i32 {
+        self.final_status.tx_error_count as i32 - self.initial_status.tx_error_count as i32
+    }
+}
+
+/// Collects the messages received during `CanSocket::diagnose`
+pub(crate) struct DiagnosisSampler {
+    nominal_bit_rate: Option<NominalBitRate>,
+    data_bit_rate: DataBitRate,
+    initial_status: BusStatus,
+    final_status: BusStatus,
+    worst_state: BusState,
+    frames: u64,
+    busy: Duration,
+    parse_errors: u64,
+}
+
+impl DiagnosisSampler {
+    pub fn new(
+        status: BusStatus,
+        nominal_bit_rate: Option<NominalBitRate>,
+        data_bit_rate: DataBitRate,
+    ) -> Self {
+        Self {
+            nominal_bit_rate,
+            data_bit_rate,
+            initial_status: status,
+            final_status: status,
+            worst_state: status.state,
+            frames: 0,
+            busy: Duration::ZERO,
+            parse_errors: 0,
+        }
+    }
+
+    pub fn record_frame(&mut self, frame: &CanFrame) {
+        self.frames += 1;
+
+        if let Some(nominal_bit_rate) = self.nominal_bit_rate {
+            self.busy +=
+                frame_bits(frame, Stuffing::None).duration(nominal_bit_rate, self.data_bit_rate);
+        }
+    }
+
+    pub fn record_status(&mut self, status: BusStatus) {
+        self.final_status = status;
+        self.worst_state = self.worst_state.max(status.state);
+    }
+
+    pub fn record_parse_error(&mut self) {
+        self.parse_errors += 1;
+    }
+
+    pub fn finish(self, duration: Duration) -> BusDiagnosis {
+        let errors_rose = self.final_status.rx_error_count > self.initial_status.rx_error_count
+            || self.final_status.tx_error_count > self.initial_status.tx_error_count
+            || self.worst_state > self.initial_status.state;
+
+        let suspected_fault = if self.frames == 0 {
+            if self.worst_state >= BusState::ErrorPassive {
+                Some(BusFault::StuckDominant)
+            } else if errors_rose || self.final_status.has_errors() {
+                Some(BusFault::WrongBitRate)
+            } else {
+                Some(BusFault::NoTraffic)
+            }
+        } else if errors_rose {
+            Some(BusFault::IntermittentErrors)
+        } else {
+            None
+        };
+
+        let bus_load = self
+            .nominal_bit_rate
+            .map(|_| (self.busy.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0) as f32);
+
+        BusDiagnosis {
+            duration,
+            frames: self.frames,
+            bus_load,
+            initial_status: self.initial_status,
+            final_status: self.final_status,
+            worst_state: self.worst_state,
+            parse_errors: self.parse_errors,
+            suspected_fault,
+        }
+    }
+}
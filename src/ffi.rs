@@ -0,0 +1,371 @@
+//! A C ABI for driving a gateway through the synchronous `CanSocket`, so
+//! existing C, C++ or Python test infrastructure can use this crate without
+//! writing any Rust.
+//!
+//! The functions are declared in `include/slcan_fd.h`. A socket is opened
+//! with `slcan_open`, configured and opened on the bus with
+//! `slcan_configure`, and released with `slcan_close`:
+//!
+//! ```c
+//! slcan_socket *can = slcan_open("/dev/ttyACM0");
+//! if (!can || slcan_configure(can, 500000, 2000000, SLCAN_MODE_NORMAL) < 0) {
+//!     fprintf(stderr, "%s\n", slcan_last_error());
+//!     return 1;
+//! }
+//!
+//! slcan_frame frame;
+//! while (slcan_recv(can, &frame, 1000) > 0) {
+//!     printf("%X [%u]\n", frame.id, frame.len);
+//! }
+//!
+//! slcan_close(can);
+//! ```
+//!
+//! Functions which can fail return [`SLCAN_OK`] or one of the negative
+//! `SLCAN_ERROR_*` codes and store a description of the error which is
+//! returned by [`slcan_last_error`].
+//!
+//! The crate is only built as an `rlib` by default, so the shared library is
+//! built explicitly:
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    fmt::Display,
+    ptr,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+use serialport::SerialPort;
+
+use crate::{
+    command::{DataBitRate, OperatingMode},
+    frame::{Can2Frame, CanFdFrame, CanFrame},
+    sync::CanSocket,
+    NominalBitRate, ReadError, SendError,
+};
+
+/// The call succeeded
+pub const SLCAN_OK: c_int = 0;
+/// A pointer was null, or a frame or bit rate is invalid
+pub const SLCAN_ERROR_INVALID_ARGUMENT: c_int = -1;
+/// Reading from or writing to the serial port failed
+pub const SLCAN_ERROR_IO: c_int = -2;
+/// The channel isn't open, see [`slcan_configure`]
+pub const SLCAN_ERROR_CHANNEL_CLOSED: c_int = -3;
+/// The gateway is in listen only mode and can't transmit
+pub const SLCAN_ERROR_LISTEN_ONLY: c_int = -4;
+/// The CAN controller is bus-off
+pub const SLCAN_ERROR_BUS_OFF: c_int = -5;
+/// The gateway stopped answering
+pub const SLCAN_ERROR_DEVICE_UNRESPONSIVE: c_int = -6;
+
+/// [`SlcanFrame::flags`]: the frame has an extended (29 bit) ID
+pub const SLCAN_FRAME_EXTENDED: u32 = 1 << 0;
+/// [`SlcanFrame::flags`]: the frame is a CAN 2.0 remote frame
+pub const SLCAN_FRAME_REMOTE: u32 = 1 << 1;
+/// [`SlcanFrame::flags`]: the frame is a CAN FD frame
+pub const SLCAN_FRAME_FD: u32 = 1 << 2;
+/// [`SlcanFrame::flags`]: the data of the CAN FD frame is sent at the data bit
+/// rate
+pub const SLCAN_FRAME_BRS: u32 = 1 << 3;
+
+/// [`slcan_configure`]: the gateway sends and receives frames
+pub const SLCAN_MODE_NORMAL: c_int = 0;
+/// [`slcan_configure`]: the gateway only listens to the bus
+pub const SLCAN_MODE_SILENT: c_int = 1;
+
+/// How long a single read of the serial port may block, which bounds how far
+/// [`slcan_recv`] may overshoot its timeout
+const PORT_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A CAN 2.0 or CAN FD frame (`slcan_frame` in C)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlcanFrame {
+    /// The standard or extended ID, see [`SLCAN_FRAME_EXTENDED`]
+    pub id: u32,
+    /// A combination of the `SLCAN_FRAME_*` flags
+    pub flags: u32,
+    /// The number of data bytes, or the DLC of a remote frame
+    pub len: u8,
+    /// The data, of which the first `len` bytes are used
+    pub data: [u8; 64],
+}
+
+/// A socket opened with [`slcan_open`] (`slcan_socket` in C)
+pub struct SlcanSocket {
+    socket: CanSocket<Box<dyn SerialPort>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Stores `message` for [`slcan_last_error`] and returns `code`
+fn fail(code: c_int, message: impl Display) -> c_int {
+    let message = CString::new(message.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = message);
+    code
+}
+
+fn read_error(error: ReadError) -> c_int {
+    let code = match error {
+        ReadError::BusOff => SLCAN_ERROR_BUS_OFF,
+        ReadError::DeviceUnresponsive => SLCAN_ERROR_DEVICE_UNRESPONSIVE,
+        _ => SLCAN_ERROR_IO,
+    };
+
+    fail(code, error)
+}
+
+fn send_error(error: SendError) -> c_int {
+    let code = match error {
+        SendError::ChannelClosed => SLCAN_ERROR_CHANNEL_CLOSED,
+        SendError::ListenOnly => SLCAN_ERROR_LISTEN_ONLY,
+        SendError::Unsupported
+        | SendError::InvalidId(_)
+        | SendError::InvalidLength(_)
+        | SendError::Blocked(_) => SLCAN_ERROR_INVALID_ARGUMENT,
+        _ => SLCAN_ERROR_IO,
+    };
+
+    fail(code, error)
+}
+
+impl SlcanFrame {
+    fn from_frame(frame: &CanFrame) -> Option<Self> {
+        let (id, mut flags) = match frame.id() {
+            Id::Standard(id) => (id.as_raw() as u32, 0),
+            Id::Extended(id) => (id.as_raw(), SLCAN_FRAME_EXTENDED),
+        };
+        let mut data = [0; 64];
+
+        let len = match frame {
+            CanFrame::Can2(frame) => match frame.data() {
+                Some(bytes) => {
+                    data[..bytes.len()].copy_from_slice(bytes);
+                    bytes.len()
+                }
+                None => {
+                    flags |= SLCAN_FRAME_REMOTE;
+                    frame.dlc()
+                }
+            },
+            CanFrame::CanFd(frame) => {
+                flags |= SLCAN_FRAME_FD;
+                if frame.is_bit_rate_switched() {
+                    flags |= SLCAN_FRAME_BRS;
+                }
+
+                data[..frame.data().len()].copy_from_slice(frame.data());
+                frame.data().len()
+            }
+            CanFrame::CanXl(_) => return None,
+        };
+
+        Some(Self {
+            id,
+            flags,
+            len: len as u8,
+            data,
+        })
+    }
+
+    fn to_frame(self) -> Option<CanFrame> {
+        let id = if self.flags & SLCAN_FRAME_EXTENDED != 0 {
+            Id::Extended(ExtendedId::new(self.id)?)
+        } else {
+            Id::Standard(StandardId::new(u16::try_from(self.id).ok()?)?)
+        };
+        let data = self.data.get(..self.len as usize)?;
+
+        if self.flags & SLCAN_FRAME_FD != 0 {
+            let frame = CanFdFrame::new(id, data)?
+                .with_bit_rate_switched(self.flags & SLCAN_FRAME_BRS != 0);
+            Some(frame.into())
+        } else if self.flags & SLCAN_FRAME_REMOTE != 0 {
+            Can2Frame::new_remote(id, self.len as usize).map(Into::into)
+        } else {
+            Can2Frame::new_data(id, data).map(Into::into)
+        }
+    }
+}
+
+/// Returns a description of the last error on the calling thread. The string
+/// is valid until the next call into this library on the same thread.
+#[no_mangle]
+pub extern "C" fn slcan_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+/// Opens the serial port at `path` and closes the channel of the gateway
+/// behind it, so it can be configured. Returns null if the port can't be
+/// opened.
+///
+/// # Safety
+///
+/// `path` must be null or point to a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn slcan_open(path: *const c_char) -> *mut SlcanSocket {
+    if path.is_null() {
+        fail(SLCAN_ERROR_INVALID_ARGUMENT, "The path is null");
+        return ptr::null_mut();
+    }
+
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let port = match serialport::new(path.as_ref(), 115_200)
+        .timeout(PORT_TIMEOUT)
+        .open()
+    {
+        Ok(port) => port,
+        Err(e) => {
+            fail(
+                SLCAN_ERROR_IO,
+                format_args!("Failed to open {}: {}", path, e),
+            );
+            return ptr::null_mut();
+        }
+    };
+
+    let mut socket = CanSocket::new(port);
+    if let Err(e) = socket.close() {
+        fail(SLCAN_ERROR_IO, e);
+        return ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(SlcanSocket { socket }))
+}
+
+/// Closes the channel, sets the bit rates and operating mode and opens the
+/// channel again. A `data_bps` of 0 leaves the data bit rate unchanged.
+///
+/// # Safety
+///
+/// `socket` must be null or a socket returned by [`slcan_open`] which wasn't
+/// closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn slcan_configure(
+    socket: *mut SlcanSocket,
+    nominal_bps: u32,
+    data_bps: u32,
+    mode: c_int,
+) -> c_int {
+    let Some(socket) = socket.as_mut().map(|socket| &mut socket.socket) else {
+        return fail(SLCAN_ERROR_INVALID_ARGUMENT, "The socket is null");
+    };
+
+    let nominal_bit_rate = match NominalBitRate::from_bps(nominal_bps) {
+        Ok(rate) => rate,
+        Err(e) => return fail(SLCAN_ERROR_INVALID_ARGUMENT, e),
+    };
+    let data_bit_rate = match data_bps {
+        0 => None,
+        bps => match DataBitRate::from_bps(bps) {
+            Ok(rate) => Some(rate),
+            Err(e) => return fail(SLCAN_ERROR_INVALID_ARGUMENT, e),
+        },
+    };
+    let mode = match mode {
+        SLCAN_MODE_NORMAL => OperatingMode::Normal,
+        SLCAN_MODE_SILENT => OperatingMode::Silent,
+        mode => {
+            return fail(
+                SLCAN_ERROR_INVALID_ARGUMENT,
+                format_args!("Invalid operating mode {}", mode),
+            )
+        }
+    };
+
+    let result = socket.close().and_then(|_| {
+        if let Some(rate) = data_bit_rate {
+            socket.set_data_bit_rate(rate)?;
+        }
+        socket.set_operating_mode(mode)?;
+        socket.open(nominal_bit_rate)
+    });
+
+    match result {
+        Ok(()) => SLCAN_OK,
+        Err(e) => fail(SLCAN_ERROR_IO, e),
+    }
+}
+
+/// Sends `frame` through the gateway
+///
+/// # Safety
+///
+/// `socket` must be null or a socket returned by [`slcan_open`] which wasn't
+/// closed yet, and `frame` must be null or point to a valid frame.
+#[no_mangle]
+pub unsafe extern "C" fn slcan_send(socket: *mut SlcanSocket, frame: *const SlcanFrame) -> c_int {
+    let (Some(socket), Some(frame)) = (socket.as_mut(), frame.as_ref()) else {
+        return fail(SLCAN_ERROR_INVALID_ARGUMENT, "The socket or frame is null");
+    };
+    let Some(frame) = frame.to_frame() else {
+        return fail(SLCAN_ERROR_INVALID_ARGUMENT, "The frame is invalid");
+    };
+
+    match socket.socket.send(frame) {
+        Ok(()) => SLCAN_OK,
+        Err(e) => send_error(e),
+    }
+}
+
+/// Waits up to `timeout_ms` milliseconds for a frame and stores it in
+/// `frame`. Returns 1 if a frame was received, 0 if the timeout passed or a
+/// negative error code. Lines which can't be parsed and CAN XL frames are
+/// skipped.
+///
+/// # Safety
+///
+/// `socket` must be null or a socket returned by [`slcan_open`] which wasn't
+/// closed yet, and `frame` must be null or point to writable memory for a
+/// frame.
+#[no_mangle]
+pub unsafe extern "C" fn slcan_recv(
+    socket: *mut SlcanSocket,
+    frame: *mut SlcanFrame,
+    timeout_ms: u32,
+) -> c_int {
+    let (Some(socket), Some(frame)) = (socket.as_mut(), frame.as_mut()) else {
+        return fail(SLCAN_ERROR_INVALID_ARGUMENT, "The socket or frame is null");
+    };
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.into());
+
+    loop {
+        match socket.socket.read() {
+            Ok(received) => {
+                if let Some(received) = SlcanFrame::from_frame(&received) {
+                    *frame = received;
+                    return 1;
+                }
+            }
+            Err(e) if e.is_would_block() => {}
+            Err(ReadError::Slcan(_) | ReadError::LineTooLong(_)) => {}
+            Err(e) => return read_error(e),
+        }
+
+        if Instant::now() >= deadline {
+            return 0;
+        }
+    }
+}
+
+/// Closes the channel and the serial port and frees the socket
+///
+/// # Safety
+///
+/// `socket` must be null or a socket returned by [`slcan_open`] which wasn't
+/// closed yet. It must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn slcan_close(socket: *mut SlcanSocket) {
+    if !socket.is_null() {
+        drop(Box::from_raw(socket));
+    }
+}
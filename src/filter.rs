@@ -0,0 +1,306 @@
+use std::{ops::RangeInclusive, str::FromStr};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+/// Selects frames by their ID, e.g. the frames delivered to a `Subscription`
+/// of the `background` feature or shown by a tool.
+///
+/// Filters are built with the `with_*` methods or parsed from a comma
+/// separated list of terms, similar to the filters of `candump`:
+///
+/// - `123` matches a single ID
+/// - `100:7F0` matches the IDs whose bits selected by the mask equal those of
+///   the ID (here 0x100 to 0x10F)
+/// - `200-2FF` matches a range of IDs
+/// - `!123` excludes the IDs matched by any of the above. A list with only
+///   exclusions matches all other IDs.
+///
+/// IDs are hexadecimal with an optional `0x` prefix. IDs with more than 3
+/// digits or above 0x7FF are extended IDs, so `00000123` is the extended ID
+/// 0x123. An empty list matches all IDs.
+///
+/// ```
+/// use slcan_fd::{ExtendedId, IdFilter, StandardId};
+///
+/// let filter: IdFilter = "0x100:0x7F0,0x200-0x2FF,!0x205".parse().unwrap();
+///
+/// assert!(filter.matches(StandardId::new(0x10A).unwrap().into()));
+/// assert!(filter.matches(StandardId::new(0x2AB).unwrap().into()));
+/// assert!(!filter.matches(StandardId::new(0x205).unwrap().into()));
+/// assert!(!filter.matches(ExtendedId::new(0x100).unwrap().into()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdFilter {
+    all: bool,
+    ids: Vec<Id>,
+    masks: Vec<(Id, u32)>,
+    standard_ranges: Vec<RangeInclusive<u16>>,
+    extended_ranges: Vec<RangeInclusive<u32>>,
+    excluded: Vec<IdFilter>,
+}
+
+impl IdFilter {
+    /// Creates a filter which matches no frames, to be extended with the
+    /// `with_*` methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a filter which matches every frame
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            ..Self::default()
+        }
+    }
+
+    /// Also matches frames with the given ID
+    pub fn with_id(mut self, id: impl Into<Id>) -> Self {
+        self.ids.push(id.into());
+        self
+    }
+
+    /// Also matches frames with any of the given IDs
+    pub fn with_ids<I: Into<Id>>(mut self, ids: impl IntoIterator<Item = I>) -> Self {
+        self.ids.extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Also matches frames with an ID of the same kind as `id` whose bits
+    /// selected by `mask` equal those of `id`
+    pub fn with_mask(mut self, id: impl Into<Id>, mask: u32) -> Self {
+        self.masks.push((id.into(), mask));
+        self
+    }
+
+    /// Also matches frames with a standard ID within `range`
+    pub fn with_standard_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.standard_ranges.push(range);
+        self
+    }
+
+    /// Also matches frames with an extended ID within `range`
+    pub fn with_extended_range(mut self, range: RangeInclusive<u32>) -> Self {
+        self.extended_ranges.push(range);
+        self
+    }
+
+    /// Rejects the frames matched by `filter`, even if they are matched by
+    /// this filter
+    pub fn excluding(mut self, filter: IdFilter) -> Self {
+        self.excluded.push(filter);
+        self
+    }
+
+    /// Returns true if frames with the given ID pass the filter
+    pub fn matches(&self, id: Id) -> bool {
+        if self.excluded.iter().any(|filter| filter.matches(id)) {
+            return false;
+        }
+
+        if self.all || self.ids.contains(&id) {
+            return true;
+        }
+
+        let masked = self.masks.iter().any(|&(filter, mask)| match (id, filter) {
+            (Id::Standard(id), Id::Standard(filter)) => {
+                (id.as_raw() ^ filter.as_raw()) as u32 & mask == 0
+            }
+            (Id::Extended(id), Id::Extended(filter)) => (id.as_raw() ^ filter.as_raw()) & mask == 0,
+            _ => false,
+        });
+
+        masked
+            || match id {
+                Id::Standard(id) => self
+                    .standard_ranges
+                    .iter()
+                    .any(|range| range.contains(&id.as_raw())),
+                Id::Extended(id) => self
+                    .extended_ranges
+                    .iter()
+                    .any(|range| range.contains(&id.as_raw())),
+            }
+    }
+}
+
+/// Errors which can arise while parsing an [`IdFilter`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterParseError {
+    #[error("Invalid filter term '{0}', expected <id>, <id>:<mask> or <first>-<last>")]
+    InvalidTerm(String),
+    #[error("The ID 0x{0:X} is out of the valid range (0..=0x1FFFFFFF)")]
+    IdOutOfRange(u32),
+}
+
+impl FromStr for IdFilter {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut included = IdFilter::new();
+        let mut excluded = IdFilter::new();
+        let mut any_included = false;
+
+        for term in s.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (negated, rule) = match term.strip_prefix('!') {
+                Some(rule) => (true, rule.trim()),
+                None => (false, term),
+            };
+            let invalid = || FilterParseError::InvalidTerm(term.to_owned());
+
+            let filter = if negated {
+                &mut excluded
+            } else {
+                any_included = true;
+                &mut included
+            };
+
+            *filter = if let Some((id, mask)) = rule.split_once(':') {
+                let mask = parse_hex(mask).ok_or_else(invalid)?.0;
+                std::mem::take(filter).with_mask(parse_id(id, invalid)?, mask)
+            } else if let Some((first, last)) = rule.split_once('-') {
+                let (first, first_digits) = parse_hex(first).ok_or_else(invalid)?;
+                let (last, last_digits) = parse_hex(last).ok_or_else(invalid)?;
+
+                if first > last {
+                    return Err(invalid());
+                }
+
+                let filter = std::mem::take(filter);
+                if is_extended(last, first_digits.max(last_digits)) {
+                    ExtendedId::new(last).ok_or(FilterParseError::IdOutOfRange(last))?;
+                    filter.with_extended_range(first..=last)
+                } else {
+                    filter.with_standard_range(first as u16..=last as u16)
+                }
+            } else {
+                std::mem::take(filter).with_id(parse_id(rule, invalid)?)
+            };
+        }
+
+        let filter = if any_included {
+            included
+        } else {
+            IdFilter::all()
+        };
+
+        if excluded == IdFilter::new() {
+            Ok(filter)
+        } else {
+            Ok(filter.excluding(excluded))
+        }
+    }
+}
+
+/// Parses a hex number with an optional `0x` prefix, returning it along with
+/// its number of digits
+fn parse_hex(s: &str) -> Option<(u32, usize)> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    Some((u32::from_str_radix(digits, 16).ok()?, digits.len()))
+}
+
+fn is_extended(value: u32, digits: usize) -> bool {
+    digits > 3 || value > StandardId::MAX.as_raw() as u32
+}
+
+fn parse_id(s: &str, invalid: impl Fn() -> FilterParseError) -> Result<Id, FilterParseError> {
+    let (value, digits) = parse_hex(s).ok_or_else(invalid)?;
+
+    if is_extended(value, digits) {
+        ExtendedId::new(value)
+            .map(Id::Extended)
+            .ok_or(FilterParseError::IdOutOfRange(value))
+    } else {
+        Ok(Id::Standard(
+            StandardId::new(value as u16).expect("checked above"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard(id: u16) -> Id {
+        StandardId::new(id).unwrap().into()
+    }
+
+    fn extended(id: u32) -> Id {
+        ExtendedId::new(id).unwrap().into()
+    }
+
+    fn parse(s: &str) -> IdFilter {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_everything_but_the_exclusions_of_a_list_without_inclusions() {
+        let filter = parse("!123, !200-2FF");
+
+        assert!(!filter.matches(standard(0x123)));
+        assert!(!filter.matches(standard(0x250)));
+        assert!(filter.matches(standard(0x124)));
+        assert!(filter.matches(standard(0x300)));
+        // The exclusions only apply to standard IDs
+        assert!(filter.matches(extended(0x123)));
+        assert!(filter.matches(extended(0x250)));
+    }
+
+    #[test]
+    fn matches_everything_with_an_empty_list() {
+        for list in ["", " ", ",", " , ,"] {
+            assert_eq!(parse(list), IdFilter::all());
+        }
+    }
+
+    #[test]
+    fn parses_every_kind_of_term() {
+        let filter = parse(" 0x123 ,0X1000:0xFFFFFF00,! 10F , 100:7F0,00000456,,7f0-800");
+
+        assert!(filter.matches(standard(0x123)));
+        assert!(filter.matches(standard(0x10A)));
+        assert!(!filter.matches(standard(0x10F)));
+        assert!(!filter.matches(standard(0x456)));
+        assert!(filter.matches(extended(0x456)));
+        assert!(filter.matches(extended(0x10FF)));
+        // A range is extended if its last ID is
+        assert!(filter.matches(extended(0x7F0)));
+        assert!(filter.matches(extended(0x800)));
+    }
+
+    #[test]
+    fn classifies_ids_by_their_digits_and_value() {
+        assert!(parse("7FF").matches(standard(0x7FF)));
+        assert!(parse("800").matches(extended(0x800)));
+        assert!(parse("0123").matches(extended(0x123)));
+        assert!(parse("0x123").matches(standard(0x123)));
+        assert!(parse("1FFFFFFF").matches(extended(0x1FFF_FFFF)));
+    }
+
+    #[test]
+    fn rejects_invalid_terms() {
+        for term in [
+            "xyz", "!", "!!123", "100:", ":7F0", "200-100", "100-", "1-2-3", "0x",
+        ] {
+            assert_eq!(
+                term.parse::<IdFilter>(),
+                Err(FilterParseError::InvalidTerm(term.to_owned())),
+                "{term}"
+            );
+        }
+
+        assert_eq!(
+            "123,20000000".parse::<IdFilter>(),
+            Err(FilterParseError::IdOutOfRange(0x2000_0000))
+        );
+        assert_eq!(
+            "!100-20000000".parse::<IdFilter>(),
+            Err(FilterParseError::IdOutOfRange(0x2000_0000))
+        );
+    }
+}
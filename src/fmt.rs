@@ -0,0 +1,417 @@
+//! Human friendly formatting of frames for terminals, e.g. while reverse
+//! engineering a bus.
+//!
+//! [`pretty`] lays frames out in aligned columns, so the bytes of frames with
+//! standard and extended IDs line up when printed one after another. [`diff`]
+//! compares two frames with the same ID and marks the bytes which changed.
+//! With the `color` feature, [`ColorFormatter`] does both at once for live
+//! traffic in terminals which understand ANSI escape codes.
+//!
+//! ```
+//! use slcan_fd::{fmt, CanFrame};
+//!
+//! let old: CanFrame = "123#0102030405060708".parse().unwrap();
+//! let new: CanFrame = "123#0102FF0405060709".parse().unwrap();
+//!
+//! assert_eq!(fmt::pretty(&old).to_string(), "     123        [ 8]  01 02 03 04 05 06 07 08");
+//!
+//! let diff = fmt::diff(&old, &new).unwrap();
+//! assert_eq!(diff.changed(), &[2, 7]);
+//! print!("{}", diff);
+//! ```
+//!
+//! prints
+//!
+//! ```text
+//!      123        [ 8]  01 02 03 04 05 06 07 08
+//!      123        [ 8]  01 02 FF 04 05 06 07 09
+//!                             ^^             ^^
+//! ```
+
+#[cfg(feature = "color")]
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter, Write};
+
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+/// The number of data bytes per row, after which the data of long frames
+/// wraps into the next row
+const BYTES_PER_ROW: usize = 16;
+
+/// The width of the columns in front of the data, which is separated from
+/// them by two spaces
+const PREFIX_WIDTH: usize = 20;
+
+/// Formats a frame as a single aligned row, or several for frames with more
+/// than 16 bytes of data. See [`pretty`].
+#[derive(Debug, Clone, Copy)]
+pub struct Pretty<'a> {
+    frame: &'a CanFrame,
+    ascii: bool,
+}
+
+/// Returns a [`Display`] implementation which formats the frame in aligned
+/// columns: the ID right aligned, the type of the frame (`FD` for CAN FD,
+/// followed by `B` if the bit rate is switched, or `XL`), the data length in
+/// brackets and the data bytes in hex. Remote frames show `remote request`
+/// instead of data.
+pub fn pretty(frame: &CanFrame) -> Pretty<'_> {
+    Pretty {
+        frame,
+        ascii: false,
+    }
+}
+
+impl Pretty<'_> {
+    /// Appends the data as ASCII to every row, with `.` for the bytes which
+    /// aren't printable, like `candump -a`
+    pub fn with_ascii(mut self, enabled: bool) -> Self {
+        self.ascii = enabled;
+        self
+    }
+}
+
+impl Display for Pretty<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_prefix(f, self.frame)?;
+
+        let Some(data) = self.frame.data() else {
+            return f.write_str("  remote request");
+        };
+
+        for (row, bytes) in data.chunks(BYTES_PER_ROW).enumerate() {
+            if row > 0 {
+                write!(f, "\n{:PREFIX_WIDTH$}", "")?;
+            }
+
+            write_bytes(f, bytes)?;
+
+            if self.ascii {
+                write_ascii(f, bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The differences between two frames with the same ID. See [`diff`].
+#[derive(Debug, Clone)]
+pub struct FrameDiff<'a> {
+    old: &'a CanFrame,
+    new: &'a CanFrame,
+    changed: Vec<usize>,
+}
+
+/// Compares two frames with the same ID, e.g. two consecutive frames with
+/// one ID while a signal is being changed. Returns `None` if the IDs differ.
+///
+/// The bytes past the end of the shorter frame count as changed. Remote
+/// frames are compared as frames without data.
+pub fn diff<'a>(old: &'a CanFrame, new: &'a CanFrame) -> Option<FrameDiff<'a>> {
+    if old.id() != new.id() {
+        return None;
+    }
+
+    let (a, b) = (old.data().unwrap_or(&[]), new.data().unwrap_or(&[]));
+    let changed = (0..a.len().max(b.len()))
+        .filter(|&i| a.get(i) != b.get(i))
+        .collect();
+
+    Some(FrameDiff { old, new, changed })
+}
+
+impl FrameDiff<'_> {
+    /// Returns the ID of the frames
+    pub fn id(&self) -> Id {
+        self.old.id()
+    }
+
+    /// Returns the indices of the data bytes which changed, in ascending
+    /// order
+    pub fn changed(&self) -> &[usize] {
+        &self.changed
+    }
+
+    /// Returns true if the frames have the same length, data and type
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && !self.length_changed() && !self.kind_changed()
+    }
+
+    /// Returns true if the frames have a different amount of data
+    pub fn length_changed(&self) -> bool {
+        data_len(self.old) != data_len(self.new)
+    }
+
+    /// Returns the bits of data byte `index` which changed, or 0 if the byte
+    /// is missing from both frames. A byte missing from one frame counts as
+    /// 0.
+    pub fn changed_bits(&self, index: usize) -> u8 {
+        let byte = |frame: &CanFrame| frame.data().and_then(|data| data.get(index)).copied();
+        byte(self.old).unwrap_or(0) ^ byte(self.new).unwrap_or(0)
+    }
+
+    fn kind_changed(&self) -> bool {
+        kind(self.old) != kind(self.new) || self.old.is_remote() != self.new.is_remote()
+    }
+
+    /// Writes a row of markers below the changed bytes in `range` of the
+    /// new frame, preceded by a marker below the length in the first row.
+    /// Nothing is written if there is nothing to mark.
+    fn write_markers(&self, f: &mut Formatter<'_>, range: std::ops::Range<usize>) -> fmt::Result {
+        let length_marker = range.start == 0 && self.length_changed();
+        let bytes: Vec<usize> = self
+            .changed
+            .iter()
+            .copied()
+            .filter(|i| range.contains(i))
+            .collect();
+
+        if !length_marker && bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut line = String::new();
+        if length_marker {
+            // Below the digits of the length, e.g. `[ 8]`
+            write!(line, "{:17}^^", "")?;
+        }
+
+        for i in bytes {
+            let column = PREFIX_WIDTH + 2 + (i - range.start) * 3;
+            write!(line, "{:width$}^^", "", width = column - line.len())?;
+        }
+
+        writeln!(f, "{}", line)
+    }
+}
+
+/// Prints both frames like [`pretty`], the new one below the old one, with
+/// `^^` below the bytes which changed (and the length, if it changed)
+impl Display for FrameDiff<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", pretty(self.old))?;
+
+        write_prefix(f, self.new)?;
+
+        let data = match self.new.data() {
+            None => {
+                return writeln!(f, "  remote request").and_then(|_| self.write_markers(f, 0..0))
+            }
+            Some([]) => return writeln!(f).and_then(|_| self.write_markers(f, 0..0)),
+            Some(data) => data,
+        };
+
+        for (row, bytes) in data.chunks(BYTES_PER_ROW).enumerate() {
+            if row > 0 {
+                write!(f, "{:PREFIX_WIDTH$}", "")?;
+            }
+
+            write_bytes(f, bytes)?;
+            f.write_char('\n')?;
+
+            let start = row * BYTES_PER_ROW;
+            self.write_markers(f, start..start + bytes.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn data_len(frame: &CanFrame) -> usize {
+    match frame {
+        CanFrame::Can2(frame) => frame.dlc(),
+        CanFrame::CanFd(frame) => frame.data().len(),
+        CanFrame::CanXl(frame) => frame.data().len(),
+    }
+}
+
+fn kind(frame: &CanFrame) -> &'static str {
+    match frame {
+        CanFrame::Can2(_) => "",
+        CanFrame::CanFd(frame) if frame.is_bit_rate_switched() => "FD B",
+        CanFrame::CanFd(_) => "FD",
+        CanFrame::CanXl(_) => "XL",
+    }
+}
+
+/// Formats the ID column, which is 8 characters wide
+fn id_column(id: Id) -> String {
+    match id {
+        Id::Standard(id) => format!("     {:03X}", id.as_raw()),
+        Id::Extended(id) => format!("{:08X}", id.as_raw()),
+    }
+}
+
+/// Writes the type and length columns which follow the ID column
+fn write_kind_and_length(f: &mut impl Write, frame: &CanFrame) -> fmt::Result {
+    write!(f, "  {:<4}  [{:>2}]", kind(frame), data_len(frame))
+}
+
+/// Writes the ID, type and length columns, which are [`PREFIX_WIDTH`]
+/// characters wide for frames with up to 99 bytes of data
+fn write_prefix(f: &mut Formatter<'_>, frame: &CanFrame) -> fmt::Result {
+    f.write_str(&id_column(frame.id()))?;
+    write_kind_and_length(f, frame)
+}
+
+/// Writes the bytes of a row, preceded by the gap to the columns in front
+fn write_bytes(f: &mut Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    f.write_char(' ')?;
+    bytes.iter().try_for_each(|byte| write!(f, " {:02X}", byte))
+}
+
+/// Writes the bytes of a row as ASCII, aligned with the ones of full rows
+fn write_ascii(f: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    let padding = (BYTES_PER_ROW - bytes.len()) * 3;
+    write!(f, "{:padding$}  '", "")?;
+
+    for &byte in bytes {
+        let c = if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        };
+        f.write_char(c)?;
+    }
+
+    f.write_char('\'')
+}
+
+/// The colors the IDs are shown in (red, green, yellow, blue, magenta and
+/// cyan), picked by the ID so neighbouring IDs differ
+#[cfg(feature = "color")]
+const ID_COLORS: [&str; 6] = ["31", "32", "33", "34", "35", "36"];
+
+/// The style of the bytes which changed since the previous frame (bold and
+/// inverted)
+#[cfg(feature = "color")]
+const CHANGED_STYLE: &str = "1;7";
+
+#[cfg(feature = "color")]
+const RESET: &str = "\x1b[0m";
+
+/// Formats live traffic for terminals, like `candump -c`: frames are laid
+/// out like [`pretty`] does, the ID of every frame is shown in a color picked
+/// by the ID and the bytes which changed since the previous frame with the
+/// same ID are highlighted. Requires a terminal which understands ANSI
+/// escape codes.
+///
+/// ```
+/// use slcan_fd::{fmt::ColorFormatter, CanFrame};
+///
+/// let mut formatter = ColorFormatter::new().with_ascii(true);
+///
+/// for line in ["123#0001", "456#11", "123#0002"] {
+///     let frame: CanFrame = line.parse().unwrap();
+///     println!("{}", formatter.format(&frame));
+/// }
+/// ```
+#[cfg(feature = "color")]
+#[derive(Debug, Clone)]
+pub struct ColorFormatter {
+    previous: HashMap<Id, Vec<u8>>,
+    highlight: bool,
+    ascii: bool,
+}
+
+#[cfg(feature = "color")]
+impl Default for ColorFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "color")]
+impl ColorFormatter {
+    /// Creates a formatter which highlights changed bytes and doesn't show
+    /// the data as ASCII
+    pub fn new() -> Self {
+        Self {
+            previous: HashMap::new(),
+            highlight: true,
+            ascii: false,
+        }
+    }
+
+    /// Sets whether the bytes which changed since the previous frame with the
+    /// same ID are highlighted (enabled by default)
+    pub fn with_highlight(mut self, enabled: bool) -> Self {
+        self.highlight = enabled;
+        self
+    }
+
+    /// Appends the data as ASCII to every row. See [`Pretty::with_ascii`].
+    pub fn with_ascii(mut self, enabled: bool) -> Self {
+        self.ascii = enabled;
+        self
+    }
+
+    /// Forgets the previous frames, so no bytes are highlighted in the next
+    /// frame of every ID
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+
+    /// Formats a frame as one or more rows, without a trailing line break,
+    /// and remembers its data for highlighting the bytes of the next frame
+    /// with the same ID
+    pub fn format(&mut self, frame: &CanFrame) -> String {
+        let id = frame.id();
+        let raw = match id {
+            Id::Standard(id) => id.as_raw() as usize,
+            Id::Extended(id) => id.as_raw() as usize,
+        };
+
+        let mut out = format!(
+            "\x1b[{}m{}{}",
+            ID_COLORS[raw % ID_COLORS.len()],
+            id_column(id),
+            RESET
+        );
+        // Writing to a string never fails
+        let _ = self.write_data(&mut out, frame);
+
+        match frame.data() {
+            Some(data) => self.previous.insert(id, data.to_vec()),
+            None => self.previous.remove(&id),
+        };
+
+        out
+    }
+
+    fn write_data(&self, out: &mut String, frame: &CanFrame) -> fmt::Result {
+        write_kind_and_length(out, frame)?;
+
+        let Some(data) = frame.data() else {
+            return out.write_str("  remote request");
+        };
+        let previous = self.previous.get(&frame.id()).filter(|_| self.highlight);
+
+        for (row, bytes) in data.chunks(BYTES_PER_ROW).enumerate() {
+            if row > 0 {
+                write!(out, "\n{:PREFIX_WIDTH$}", "")?;
+            }
+
+            out.write_char(' ')?;
+            for (i, byte) in bytes.iter().enumerate() {
+                let index = row * BYTES_PER_ROW + i;
+                let changed = previous.is_some_and(|previous| previous.get(index) != Some(byte));
+
+                if changed {
+                    write!(out, " \x1b[{}m{:02X}{}", CHANGED_STYLE, byte, RESET)?;
+                } else {
+                    write!(out, " {:02X}", byte)?;
+                }
+            }
+
+            if self.ascii {
+                write_ascii(out, bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// What `CanSocket::forward` does with a frame which fails to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardErrorPolicy {
+    /// Stops forwarding and returns the error
+    #[default]
+    Stop,
+    /// Drops the frame and continues with the next one. I/O errors which
+    /// aren't retryable still stop forwarding, since the port is most likely
+    /// gone.
+    Skip,
+    /// Sends the frame again up to `attempts` times, waiting `backoff` before
+    /// each attempt, if the error is retryable (see
+    /// [`SendError::is_retryable`](crate::SendError::is_retryable)). Stops
+    /// forwarding once the attempts are used up or on any other error.
+    Retry { attempts: u32, backoff: Duration },
+}
+
+/// Pacing and error handling for `CanSocket::forward`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardOptions {
+    interval: Duration,
+    error_policy: ForwardErrorPolicy,
+}
+
+impl ForwardOptions {
+    /// Creates options which forward frames as fast as they arrive and stop
+    /// at the first error
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits at least `interval` between frames, so a stream which yields
+    /// frames faster than the bus (or the gateway) can take them doesn't
+    /// overrun it
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets what happens to frames which fail to send
+    pub fn with_error_policy(mut self, policy: ForwardErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Returns the minimum time between frames
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns what happens to frames which fail to send
+    pub fn error_policy(&self) -> ForwardErrorPolicy {
+        self.error_policy
+    }
+}
+
+/// The number of frames handled by `CanSocket::forward`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ForwardStats {
+    /// The frames which were sent
+    pub sent: usize,
+    /// The frames which were dropped by [`ForwardErrorPolicy::Skip`]
+    pub skipped: usize,
+}
@@ -1,12 +1,118 @@
-use embedded_can::Id;
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use embedded_can::{ExtendedId, Id, StandardId};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-/// A joint enum which can hold either a CAN 2.0 frame or a CAN FD frame. See
-/// [`Can2Frame`] and [`CanFdFrame`].
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::timestamp::Timestamp;
+
+/// A joint enum which can hold a CAN 2.0, CAN FD or CAN XL frame. See
+/// [`Can2Frame`], [`CanFdFrame`] and [`CanXlFrame`]. More frame types may be
+/// added in the future, so matches outside this crate need a wildcard arm.
+///
+/// Frames don't implement `Ord`, since frames which differ only in their data
+/// are equal in arbitration. See [`arbitration_cmp`](CanFrame::arbitration_cmp)
+/// for ordering them by priority.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum CanFrame {
     Can2(Can2Frame),
     CanFd(CanFdFrame),
+    CanXl(CanXlFrame),
+}
+
+impl CanFrame {
+    /// Gets the message ID of the frame
+    pub fn id(&self) -> Id {
+        match self {
+            CanFrame::Can2(frame) => frame.id(),
+            CanFrame::CanFd(frame) => frame.id(),
+            CanFrame::CanXl(frame) => frame.id(),
+        }
+    }
+
+    /// Sets the message ID of the frame, keeping its data and flags. CAN XL
+    /// frames only have an 11 bit priority ID, so they take the 11 most
+    /// significant bits (the base ID) of an extended ID.
+    pub fn set_id(&mut self, id: impl Into<Id>) {
+        match self {
+            CanFrame::Can2(frame) => frame.id = id.into(),
+            CanFrame::CanFd(frame) => frame.id = id.into(),
+            CanFrame::CanXl(frame) => {
+                frame.priority = match id.into() {
+                    Id::Standard(id) => id,
+                    Id::Extended(id) => id.standard_id(),
+                }
+            }
+        }
+    }
+
+    /// Consumes self and returns a new self with the supplied message ID
+    pub fn with_id(mut self, id: impl Into<Id>) -> Self {
+        self.set_id(id);
+        self
+    }
+
+    /// Gets the data of the frame, or `None` for remote frames
+    pub fn data(&self) -> Option<&[u8]> {
+        match self {
+            CanFrame::Can2(frame) => frame.data(),
+            CanFrame::CanFd(frame) => Some(frame.data()),
+            CanFrame::CanXl(frame) => Some(frame.data()),
+        }
+    }
+
+    /// Gets the data of the frame for modifying it in place, or `None` for
+    /// remote frames
+    pub(crate) fn data_mut(&mut self) -> Option<&mut [u8]> {
+        match self {
+            CanFrame::Can2(frame) => {
+                let dlc = frame.dlc;
+                frame.data.as_mut().map(|data| &mut data[..dlc])
+            }
+            CanFrame::CanFd(frame) => Some(&mut frame.data[..frame.len]),
+            CanFrame::CanXl(frame) => Some(&mut frame.data),
+        }
+    }
+
+    /// Returns true if this is a CAN 2.0 remote frame
+    pub fn is_remote(&self) -> bool {
+        matches!(self, CanFrame::Can2(frame) if frame.is_remote())
+    }
+
+    /// Compares two frames like `==`, except that CAN FD frames are compared
+    /// with [`CanFdFrame::eq_ignoring_padding`], e.g. for comparing received
+    /// frames against expectations in tests
+    pub fn eq_ignoring_padding(&self, other: &CanFrame) -> bool {
+        match (self, other) {
+            (CanFrame::CanFd(frame), CanFrame::CanFd(other)) => frame.eq_ignoring_padding(other),
+            _ => self == other,
+        }
+    }
+
+    /// Orders frames the way CAN arbitration would, with the frame which
+    /// wins arbitration (i.e. has the higher priority) being less. Frames are
+    /// compared by ID as described by the `Ord` implementation of [`Id`], and
+    /// data frames win against remote frames with the same ID. Frames which
+    /// are equal in arbitration compare as equal regardless of their data.
+    ///
+    /// ```
+    /// use slcan_fd::{Can2Frame, CanFdFrame, CanFrame, ExtendedId, StandardId};
+    /// use std::cmp::Ordering;
+    ///
+    /// let id = StandardId::new(0x100).unwrap();
+    /// let data = CanFrame::Can2(Can2Frame::new_data(id, &[1]).unwrap());
+    /// let remote = CanFrame::Can2(Can2Frame::new_remote(id, 1).unwrap());
+    /// let extended = CanFrame::CanFd(CanFdFrame::new(ExtendedId::new(0x100).unwrap(), &[]).unwrap());
+    ///
+    /// assert_eq!(data.arbitration_cmp(&remote), Ordering::Less);
+    /// assert_eq!(extended.arbitration_cmp(&data), Ordering::Less);
+    /// ```
+    pub fn arbitration_cmp(&self, other: &CanFrame) -> Ordering {
+        self.id()
+            .cmp(&other.id())
+            .then_with(|| self.is_remote().cmp(&other.is_remote()))
+    }
 }
 
 impl From<Can2Frame> for CanFrame {
@@ -21,11 +127,17 @@ impl From<CanFdFrame> for CanFrame {
     }
 }
 
+impl From<CanXlFrame> for CanFrame {
+    fn from(frame: CanXlFrame) -> Self {
+        Self::CanXl(frame)
+    }
+}
+
 /// Represents a CAN 2.0 frame which supports RTR (Remote Transmission Request).
 ///
 /// The DLC can be up to 8 bytes, and the data if absent means that it is an
 /// RTR frame.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Can2Frame {
     id: Id,
     dlc: usize,
@@ -90,7 +202,8 @@ impl Can2Frame {
 /// The integer value of the enum maps to the DLC used in the CAN protocol and
 /// not the actual number of bytes associated with each variant. To obtain
 /// that, see [`FdDataLengthCode::get_num_bytes`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(u8)]
 pub enum FdDataLengthCode {
     Bytes0 = 0,
@@ -155,10 +268,11 @@ impl FdDataLengthCode {
 /// Represents a CAN FD frame which can store up to 64 data bytes and
 /// optionally supports transmitting at a higher data bit rate (this defaults
 /// to true). See [`DataBitRate`](crate::DataBitRate).
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CanFdFrame {
     id: Id,
-    data: heapless::Vec<u8, 64>,
+    len: usize,
+    data: [u8; 64],
     bit_rate_switched: bool,
 }
 
@@ -168,9 +282,13 @@ impl CanFdFrame {
     pub fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
         FdDataLengthCode::for_length(data.len())?;
 
+        let mut copy = [0u8; 64];
+        copy[..data.len()].copy_from_slice(data);
+
         Some(Self {
             id: id.into(),
-            data: heapless::Vec::<u8, 64>::from_slice(data).unwrap(),
+            len: data.len(),
+            data: copy,
             bit_rate_switched: true,
         })
     }
@@ -181,12 +299,14 @@ impl CanFdFrame {
     pub fn new_padded(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
         let dlc = FdDataLengthCode::for_length(data.len())?;
 
-        let mut data = heapless::Vec::<u8, 64>::from_slice(data).unwrap();
-        data.extend((data.len()..dlc.get_num_bytes()).map(|_| 0));
+        // The bytes past the data are already 0
+        let mut copy = [0u8; 64];
+        copy[..data.len()].copy_from_slice(data);
 
         Some(Self {
             id: id.into(),
-            data,
+            len: dlc.get_num_bytes(),
+            data: copy,
             bit_rate_switched: true,
         })
     }
@@ -198,12 +318,50 @@ impl CanFdFrame {
 
     /// Gets the DLC (Data Length Code) of the frame
     pub fn dlc(&self) -> FdDataLengthCode {
-        FdDataLengthCode::for_length(self.data.len()).unwrap()
+        FdDataLengthCode::for_length(self.len).unwrap()
     }
 
     /// Gets the data associated with the frame (length will match DLC)
     pub fn data(&self) -> &[u8] {
-        &self.data
+        &self.data[..self.len]
+    }
+
+    /// Gets the data without the zero bytes at its end, such as the ones
+    /// added by [`new_padded`](CanFdFrame::new_padded). Zero bytes which
+    /// were part of the original data are removed as well.
+    pub fn data_trimmed(&self) -> &[u8] {
+        let len = self
+            .data()
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        &self.data[..len]
+    }
+
+    /// Compares two frames like `==`, except that zero bytes at the end of
+    /// the data are ignored, so frames whose data was padded to different
+    /// lengths compare as equal.
+    ///
+    /// ```
+    /// use slcan_fd::{CanFdFrame, StandardId};
+    ///
+    /// let id = StandardId::new(0x123).unwrap();
+    /// let padded = CanFdFrame::new_padded(id, &[1; 10]).unwrap();
+    /// assert_eq!(padded.data().len(), 12);
+    /// assert_eq!(padded.data_trimmed(), &[1; 10]);
+    ///
+    /// // The same data, padded to 16 bytes by another device
+    /// let mut data = [0; 16];
+    /// data[..10].fill(1);
+    /// let longer = CanFdFrame::new(id, &data).unwrap();
+    ///
+    /// assert_ne!(padded, longer);
+    /// assert!(padded.eq_ignoring_padding(&longer));
+    /// ```
+    pub fn eq_ignoring_padding(&self, other: &CanFdFrame) -> bool {
+        self.id == other.id
+            && self.bit_rate_switched == other.bit_rate_switched
+            && self.data_trimmed() == other.data_trimmed()
     }
 
     /// Returns whether or not this frame should be/was transmitted with the
@@ -225,3 +383,457 @@ impl CanFdFrame {
         self
     }
 }
+
+/// Errors which can arise while converting between [`Can2Frame`] and
+/// [`CanFdFrame`], for frames which have no equivalent of the other type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameConversionError {
+    #[error("{0} bytes of data don't fit into a CAN 2.0 frame")]
+    DataTooLong(usize),
+    #[error("CAN FD has no remote frames")]
+    RemoteFrame,
+}
+
+/// Converts a CAN 2.0 data frame into a CAN FD frame with the same ID and
+/// data, transmitted with bit rate switching like the frames created with
+/// [`CanFdFrame::new`]. Fails for remote frames, which CAN FD doesn't have.
+impl TryFrom<Can2Frame> for CanFdFrame {
+    type Error = FrameConversionError;
+
+    fn try_from(frame: Can2Frame) -> Result<Self, Self::Error> {
+        let data = frame.data().ok_or(FrameConversionError::RemoteFrame)?;
+        Ok(CanFdFrame::new(frame.id, data).expect("CAN 2.0 data is a valid CAN FD length"))
+    }
+}
+
+/// Converts a CAN FD frame with up to 8 bytes of data into a CAN 2.0 data
+/// frame with the same ID and data. Fails for longer frames instead of
+/// truncating their data.
+impl TryFrom<CanFdFrame> for Can2Frame {
+    type Error = FrameConversionError;
+
+    fn try_from(frame: CanFdFrame) -> Result<Self, Self::Error> {
+        Can2Frame::new_data(frame.id, frame.data())
+            .ok_or(FrameConversionError::DataTooLong(frame.len))
+    }
+}
+
+/// Represents a CAN XL frame which can store 1 to 2048 data bytes.
+///
+/// CAN XL frames are arbitrated with an 11 bit priority ID and carry a
+/// header describing their payload: the SDU (service data unit) type, a
+/// virtual CAN network ID and a 32 bit acceptance field which takes the role
+/// of the message ID for filtering.
+///
+/// SLCAN has no commands for CAN XL yet, so these frames can't be sent or
+/// received by the sockets. They can be read from and written to logs in the
+/// `candump` format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CanXlFrame {
+    priority: StandardId,
+    sdu_type: u8,
+    vcid: u8,
+    acceptance_field: u32,
+    simple_extended_content: bool,
+    data: Vec<u8>,
+}
+
+impl CanXlFrame {
+    /// The maximum number of data bytes of a CAN XL frame
+    pub const MAX_DATA_LENGTH: usize = 2048;
+
+    /// Creates a new CAN XL frame with all header fields set to 0. Will
+    /// return `None` if the data is empty or longer than 2048 bytes.
+    pub fn new(priority: StandardId, data: &[u8]) -> Option<Self> {
+        if data.is_empty() || data.len() > Self::MAX_DATA_LENGTH {
+            return None;
+        }
+
+        Some(Self {
+            priority,
+            sdu_type: 0,
+            vcid: 0,
+            acceptance_field: 0,
+            simple_extended_content: false,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Gets the priority ID of the frame
+    pub fn priority(&self) -> StandardId {
+        self.priority
+    }
+
+    /// Gets the priority ID of the frame as a message ID
+    pub fn id(&self) -> Id {
+        Id::Standard(self.priority)
+    }
+
+    /// Gets the data associated with the frame
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Gets the SDU type, which describes the kind of payload
+    pub fn sdu_type(&self) -> u8 {
+        self.sdu_type
+    }
+
+    /// Consumes self and returns a new self with the supplied SDU type
+    pub fn with_sdu_type(mut self, sdu_type: u8) -> Self {
+        self.sdu_type = sdu_type;
+        self
+    }
+
+    /// Gets the virtual CAN network ID
+    pub fn vcid(&self) -> u8 {
+        self.vcid
+    }
+
+    /// Consumes self and returns a new self with the supplied virtual CAN
+    /// network ID
+    pub fn with_vcid(mut self, vcid: u8) -> Self {
+        self.vcid = vcid;
+        self
+    }
+
+    /// Gets the acceptance field
+    pub fn acceptance_field(&self) -> u32 {
+        self.acceptance_field
+    }
+
+    /// Consumes self and returns a new self with the supplied acceptance
+    /// field
+    pub fn with_acceptance_field(mut self, acceptance_field: u32) -> Self {
+        self.acceptance_field = acceptance_field;
+        self
+    }
+
+    /// Returns whether the SEC (simple extended content) flag is set
+    pub fn is_simple_extended_content(&self) -> bool {
+        self.simple_extended_content
+    }
+
+    /// Consumes self and returns a new self with the supplied value for the
+    /// SEC (simple extended content) flag
+    pub fn with_simple_extended_content(mut self, simple_extended_content: bool) -> Self {
+        self.simple_extended_content = simple_extended_content;
+        self
+    }
+}
+
+/// Errors which can arise while parsing a frame from the text notation used
+/// by `cansend` and `candump`. See [`CanFrame`]'s `FromStr` implementation.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FrameParseError {
+    #[error("Expected a '#' between the ID and the data")]
+    MissingSeparator,
+    #[error("The ID must be 3 (standard) or 8 (extended) hex digits within range")]
+    InvalidId,
+    #[error("The data must be pairs of hex digits, optionally separated by '.'")]
+    InvalidData,
+    #[error("The data is longer than the frame type allows")]
+    DataTooLong,
+    #[error("The length of a remote frame must be a single digit in the range 0..=8")]
+    InvalidRemoteLength,
+    #[error("The flags of a CAN FD frame must be a single hex digit")]
+    InvalidFlags,
+    #[error("The header of a CAN XL frame must be <flags>:<sdt>:<af> with 2, 2 and 8 hex digits")]
+    InvalidXlHeader,
+    #[error("A CAN XL frame must carry at least one data byte")]
+    MissingData,
+}
+
+/// The BRS flag in the flags nibble of the `##` CAN FD notation
+const CANSEND_FD_BRS: u8 = 0x01;
+/// The XLF flag which is always set in the flags of the CAN XL notation
+const CANSEND_XL_XLF: u8 = 0x80;
+/// The SEC flag in the flags of the CAN XL notation
+const CANSEND_XL_SEC: u8 = 0x01;
+
+fn parse_cansend_id(text: &str) -> Result<Id, FrameParseError> {
+    let raw = u32::from_str_radix(text, 16).map_err(|_| FrameParseError::InvalidId)?;
+
+    match text.len() {
+        3 => StandardId::new(raw as u16)
+            .filter(|_| raw <= StandardId::MAX.as_raw() as u32)
+            .map(Id::Standard),
+        8 => ExtendedId::new(raw).map(Id::Extended),
+        _ => None,
+    }
+    .ok_or(FrameParseError::InvalidId)
+}
+
+/// Parses a CAN XL frame from its ID (`<vcid><prio>`) and the remainder after
+/// the first `#` (`<flags>:<sdt>:<af>#<data>`)
+fn parse_cansend_xl(id: &str, rest: &str) -> Result<CanXlFrame, FrameParseError> {
+    let hex = |text: Option<&str>, digits: usize| {
+        text.filter(|text| text.len() == digits)
+            .and_then(|text| u32::from_str_radix(text, 16).ok())
+    };
+
+    let vcid = hex(id.get(..2), 2).ok_or(FrameParseError::InvalidId)? as u8;
+    let priority = hex(id.get(2..), 3)
+        .and_then(|raw| StandardId::new(raw as u16).filter(|_| raw <= 0x7FF))
+        .ok_or(FrameParseError::InvalidId)?;
+
+    let (header, data) = rest
+        .split_once('#')
+        .ok_or(FrameParseError::MissingSeparator)?;
+    let mut fields = header.split(':');
+    let flags = hex(fields.next(), 2).ok_or(FrameParseError::InvalidXlHeader)? as u8;
+    let sdu_type = hex(fields.next(), 2).ok_or(FrameParseError::InvalidXlHeader)? as u8;
+    let acceptance_field = hex(fields.next(), 8).ok_or(FrameParseError::InvalidXlHeader)?;
+
+    if fields.next().is_some() {
+        return Err(FrameParseError::InvalidXlHeader);
+    }
+
+    let data = parse_cansend_data(data)?;
+    if data.is_empty() {
+        return Err(FrameParseError::MissingData);
+    }
+
+    Ok(CanXlFrame::new(priority, &data)
+        .ok_or(FrameParseError::DataTooLong)?
+        .with_sdu_type(sdu_type)
+        .with_vcid(vcid)
+        .with_acceptance_field(acceptance_field)
+        .with_simple_extended_content(flags & CANSEND_XL_SEC != 0))
+}
+
+fn parse_cansend_data(text: &str) -> Result<Vec<u8>, FrameParseError> {
+    let digits: Vec<u8> = text.bytes().filter(|b| *b != b'.').collect();
+
+    if digits.len() % 2 != 0 {
+        return Err(FrameParseError::InvalidData);
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or(FrameParseError::InvalidData)
+        })
+        .collect()
+}
+
+impl FromStr for CanFrame {
+    type Err = FrameParseError;
+
+    /// Parses a frame in the notation used by `cansend` from can-utils:
+    ///
+    /// - `123#DEADBEEF` - CAN 2.0 data frame with a standard ID (3 hex digits)
+    /// - `12345678#DE.AD.BE.EF` - CAN 2.0 data frame with an extended ID (8 hex
+    ///   digits), the data bytes may be separated by dots
+    /// - `123#R` or `123#R4` - CAN 2.0 remote frame with an optional DLC
+    /// - `123##1DEADBEEF` - CAN FD frame, where the hex digit after `##` holds
+    ///   the flags (`1` = BRS). Data is padded with zeros to the next valid
+    ///   CAN FD length.
+    /// - `45123#81:00:12345678#DEADBEEF` - CAN XL frame with the virtual CAN
+    ///   network ID (2 hex digits) in front of the priority ID (3 hex digits),
+    ///   followed by the flags (`01` = SEC), SDU type and acceptance field
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, rest) = s
+            .trim()
+            .split_once('#')
+            .ok_or(FrameParseError::MissingSeparator)?;
+
+        if id.len() == 5 {
+            return parse_cansend_xl(id, rest).map(Into::into);
+        }
+
+        let id = parse_cansend_id(id)?;
+
+        if let Some(rest) = rest.strip_prefix('#') {
+            let mut chars = rest.chars();
+            let flags = chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or(FrameParseError::InvalidFlags)? as u8;
+            let data = parse_cansend_data(chars.as_str())?;
+
+            return Ok(CanFdFrame::new_padded(id, &data)
+                .ok_or(FrameParseError::DataTooLong)?
+                .with_bit_rate_switched(flags & CANSEND_FD_BRS != 0)
+                .into());
+        }
+
+        if let Some(dlc) = rest.strip_prefix(['R', 'r']) {
+            let dlc = match dlc {
+                "" => 0,
+                dlc => dlc
+                    .parse()
+                    .map_err(|_| FrameParseError::InvalidRemoteLength)?,
+            };
+
+            return Ok(Can2Frame::new_remote(id, dlc)
+                .ok_or(FrameParseError::InvalidRemoteLength)?
+                .into());
+        }
+
+        let data = parse_cansend_data(rest)?;
+
+        Ok(Can2Frame::new_data(id, &data)
+            .ok_or(FrameParseError::DataTooLong)?
+            .into())
+    }
+}
+
+fn write_cansend_id(f: &mut fmt::Formatter<'_>, id: Id) -> fmt::Result {
+    match id {
+        Id::Standard(id) => write!(f, "{:03X}", id.as_raw()),
+        Id::Extended(id) => write!(f, "{:08X}", id.as_raw()),
+    }
+}
+
+/// Formats the frame in the notation used by `cansend` and `candump`, e.g.
+/// `123#DEADBEEF` or `123#R4`
+impl fmt::Display for Can2Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_cansend_id(f, self.id)?;
+
+        match self.data() {
+            Some(data) => {
+                write!(f, "#")?;
+                data.iter().try_for_each(|b| write!(f, "{:02X}", b))
+            }
+            None if self.dlc > 0 => write!(f, "#R{}", self.dlc),
+            None => write!(f, "#R"),
+        }
+    }
+}
+
+/// Formats the frame in the notation used by `cansend` and `candump`, e.g.
+/// `123##1DEADBEEF`
+impl fmt::Display for CanFdFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = if self.bit_rate_switched {
+            CANSEND_FD_BRS
+        } else {
+            0
+        };
+
+        write_cansend_id(f, self.id)?;
+        write!(f, "##{:X}", flags)?;
+        self.data().iter().try_for_each(|b| write!(f, "{:02X}", b))
+    }
+}
+
+/// Formats the frame in the notation used by `cansend` and `candump`, e.g.
+/// `45123#81:00:12345678#DEADBEEF`
+impl fmt::Display for CanXlFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = if self.simple_extended_content {
+            CANSEND_XL_XLF | CANSEND_XL_SEC
+        } else {
+            CANSEND_XL_XLF
+        };
+
+        write!(
+            f,
+            "{:02X}{:03X}#{:02X}:{:02X}:{:08X}#",
+            self.vcid,
+            self.priority.as_raw(),
+            flags,
+            self.sdu_type,
+            self.acceptance_field
+        )?;
+        self.data().iter().try_for_each(|b| write!(f, "{:02X}", b))
+    }
+}
+
+impl fmt::Display for CanFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanFrame::Can2(frame) => frame.fmt(f),
+            CanFrame::CanFd(frame) => frame.fmt(f),
+            CanFrame::CanXl(frame) => frame.fmt(f),
+        }
+    }
+}
+
+/// Writes a message ID in the same style as its `Debug` implementation, since
+/// [`Id`] itself does not implement [`defmt::Format`]
+#[cfg(feature = "defmt")]
+pub(crate) fn format_id(id: Id, f: defmt::Formatter) {
+    match id {
+        Id::Standard(id) => defmt::write!(f, "Standard({=u16:#05X})", id.as_raw()),
+        Id::Extended(id) => defmt::write!(f, "Extended({=u32:#010X})", id.as_raw()),
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Can2Frame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Can2Frame {{ id: ");
+        format_id(self.id, f);
+        defmt::write!(f, ", dlc: {=usize}, data: {} }}", self.dlc, self.data());
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanFdFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "CanFdFrame {{ id: ");
+        format_id(self.id, f);
+        defmt::write!(
+            f,
+            ", data: {=[u8]:02X}, bit_rate_switched: {=bool} }}",
+            self.data(),
+            self.bit_rate_switched
+        );
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CanXlFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "CanXlFrame {{ priority: {=u16:#05X}, sdu_type: {=u8:#04X}, vcid: {=u8:#04X}, acceptance_field: {=u32:#010X}, simple_extended_content: {=bool}, data: {=[u8]:02X} }}",
+            self.priority.as_raw(),
+            self.sdu_type,
+            self.vcid,
+            self.acceptance_field,
+            self.simple_extended_content,
+            self.data()
+        );
+    }
+}
+
+/// Whether a frame was received from the bus or transmitted by this host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameDirection {
+    /// The frame was received from another node on the bus
+    #[default]
+    Rx,
+    /// The frame was transmitted by this host
+    Tx,
+}
+
+/// A frame read from a socket along with its metadata. See
+/// `CanSocket::read_rx_frame`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxFrame {
+    /// The frame itself
+    pub frame: CanFrame,
+    /// The hardware timestamp of the frame, if timestamps are enabled. See
+    /// [`TimestampMode`](crate::TimestampMode).
+    pub timestamp: Option<Timestamp>,
+    /// Whether the frame was received or transmitted
+    pub direction: FrameDirection,
+    /// The index of the channel (i.e. gateway) the frame was read from
+    pub channel: usize,
+}
+
+impl From<RxFrame> for CanFrame {
+    fn from(frame: RxFrame) -> Self {
+        frame.frame
+    }
+}
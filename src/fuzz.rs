@@ -0,0 +1,196 @@
+//! [`Arbitrary`] implementations for fuzzing the parser and encoder.
+//!
+//! Besides the frame and configuration types, this module provides
+//! [`arbitrary_id`] (since [`Id`] is a foreign type) and [`RawLine`], which
+//! generates SLCAN lines that are mostly well formed so that fuzzers can get
+//! past the first byte of the parser.
+//!
+//! The `fuzz` directory of the repository has `cargo fuzz` targets built on
+//! these, e.g. `cargo +nightly fuzz run parse_frame` and
+//! `cargo +nightly fuzz run encode_round_trip`.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use crate::{
+    command::{
+        AutoRetransmissionMode, Command, DataBitRate, NominalBitRate, OperatingMode, SlcanFrame,
+        TimestampMode,
+    },
+    frame::{Can2Frame, CanFdFrame, CanFrame, CanXlFrame, FdDataLengthCode},
+};
+
+/// Generates an arbitrary standard or extended message ID
+pub fn arbitrary_id(u: &mut Unstructured<'_>) -> Result<Id> {
+    Ok(if u.arbitrary()? {
+        ExtendedId::new(u.int_in_range(0..=ExtendedId::MAX.as_raw())?)
+            .unwrap()
+            .into()
+    } else {
+        StandardId::new(u.int_in_range(0..=StandardId::MAX.as_raw())?)
+            .unwrap()
+            .into()
+    })
+}
+
+impl<'a> Arbitrary<'a> for Can2Frame {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let id = arbitrary_id(u)?;
+
+        Ok(if u.ratio(1, 8)? {
+            Can2Frame::new_remote(id, u.int_in_range(0..=8)?).unwrap()
+        } else {
+            let len = u.int_in_range(0..=8)?;
+            Can2Frame::new_data(id, u.bytes(len)?).unwrap()
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FdDataLengthCode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(FdDataLengthCode::try_from(u.int_in_range(0..=15)?).unwrap())
+    }
+}
+
+impl<'a> Arbitrary<'a> for CanFdFrame {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let id = arbitrary_id(u)?;
+        let dlc: FdDataLengthCode = u.arbitrary()?;
+        let data = u.bytes(dlc.get_num_bytes())?;
+
+        Ok(CanFdFrame::new(id, data)
+            .unwrap()
+            .with_bit_rate_switched(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for CanXlFrame {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let priority = StandardId::new(u.int_in_range(0..=StandardId::MAX.as_raw())?).unwrap();
+        let len = u.int_in_range(1..=CanXlFrame::MAX_DATA_LENGTH)?;
+
+        Ok(CanXlFrame::new(priority, u.bytes(len)?)
+            .unwrap()
+            .with_sdu_type(u.arbitrary()?)
+            .with_vcid(u.arbitrary()?)
+            .with_acceptance_field(u.arbitrary()?)
+            .with_simple_extended_content(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for CanFrame {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => CanFrame::CanXl(u.arbitrary()?),
+            1 | 2 => CanFrame::CanFd(u.arbitrary()?),
+            _ => CanFrame::Can2(u.arbitrary()?),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for SlcanFrame {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            SlcanFrame::from(u.arbitrary::<CanFdFrame>()?)
+        } else {
+            SlcanFrame::from(u.arbitrary::<Can2Frame>()?)
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for NominalBitRate {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&NominalBitRate::ALL)?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for DataBitRate {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&DataBitRate::ALL)?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for OperatingMode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            OperatingMode::Normal,
+            OperatingMode::Silent,
+            OperatingMode::Loopback,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for AutoRetransmissionMode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            AutoRetransmissionMode::Disabled,
+            AutoRetransmissionMode::Enabled,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for TimestampMode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            TimestampMode::Disabled,
+            TimestampMode::Milliseconds,
+            TimestampMode::Microseconds,
+        ])?)
+    }
+}
+
+/// A single SLCAN line as received from a gateway, without the terminating
+/// CR.
+///
+/// Most generated lines are the valid encoding of an arbitrary frame with a
+/// few bytes corrupted, truncated, appended or lowercased, while the rest are
+/// completely random bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawLine(pub Vec<u8>);
+
+impl<'a> Arbitrary<'a> for RawLine {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.ratio(1, 4)? {
+            let len = u.int_in_range(1..=crate::SLCAN_MTU)?;
+            let bytes = u.bytes(len)?;
+
+            return Ok(RawLine(
+                bytes
+                    .iter()
+                    .map(|b| if *b == b'\r' { b'0' } else { *b })
+                    .collect(),
+            ));
+        }
+
+        let mut line = Command::TransmitFrame(u.arbitrary()?).as_bytes();
+
+        match u.int_in_range(0..=4)? {
+            // Corrupt a single byte
+            0 => {
+                let index = u.choose_index(line.len())?;
+                line[index] = u.arbitrary()?;
+            }
+            // Truncate the line
+            1 => line.truncate(u.int_in_range(1..=line.len())?),
+            // Append some trailing hex digits (e.g. an unexpected timestamp)
+            2 => {
+                for _ in 0..u.int_in_range(1..=8)? {
+                    line.push(*u.choose(b"0123456789ABCDEFabcdef")?);
+                }
+            }
+            // Lowercase the hex digits after the specifier, as sent by some
+            // firmwares
+            3 => line[1..].make_ascii_lowercase(),
+            // Leave it valid
+            _ => {}
+        }
+
+        line.retain(|b| *b != b'\r');
+
+        if line.is_empty() {
+            line.push(b't');
+        }
+
+        Ok(RawLine(line))
+    }
+}
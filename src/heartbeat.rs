@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use crate::frame::CanFrame;
+
+/// The checksum written into a byte of a [Heartbeat] frame, computed over all
+/// other data bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// The sum of the bytes, truncated to 8 bits
+    Sum,
+    /// The XOR of the bytes
+    Xor,
+    /// The CRC-8 of SAE J1850 (polynomial 0x1D, initial value and final XOR
+    /// 0xFF), as used by AUTOSAR E2E profile 1
+    Crc8J1850,
+}
+
+impl Checksum {
+    fn compute(&self, bytes: impl Iterator<Item = u8>) -> u8 {
+        match self {
+            Checksum::Sum => bytes.fold(0, u8::wrapping_add),
+            Checksum::Xor => bytes.fold(0, |checksum, byte| checksum ^ byte),
+            Checksum::Crc8J1850 => {
+                let crc = bytes.fold(0xFF, |mut crc, byte| {
+                    crc ^= byte;
+                    for _ in 0..8 {
+                        crc = if crc & 0x80 != 0 {
+                            (crc << 1) ^ 0x1D
+                        } else {
+                            crc << 1
+                        };
+                    }
+                    crc
+                });
+
+                crc ^ 0xFF
+            }
+        }
+    }
+}
+
+/// A frame which is transmitted periodically, optionally with an alive
+/// counter and a checksum, e.g. for keeping ECUs on a test bench out of their
+/// fault states.
+///
+/// [`next_frame`](Heartbeat::next_frame) returns the frame to transmit next.
+/// With the `tokio` feature, `BackgroundReader::spawn_heartbeat` transmits it
+/// every period.
+///
+/// ```
+/// use slcan_fd::{Can2Frame, CanFrame, Checksum, Heartbeat, StandardId};
+/// use std::time::Duration;
+///
+/// let frame = Can2Frame::new_data(StandardId::new(0x321).unwrap(), &[0, 0, 0x55]).unwrap();
+///
+/// let mut heartbeat = Heartbeat::new(frame, Duration::from_millis(100))
+///     .with_counter(1, 0x0F)
+///     .with_checksum(0, Checksum::Xor);
+///
+/// assert_eq!(heartbeat.next_frame().data(), Some(&[0x55, 0x00, 0x55][..]));
+/// assert_eq!(heartbeat.next_frame().data(), Some(&[0x54, 0x01, 0x55][..]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heartbeat {
+    frame: CanFrame,
+    period: Duration,
+    counter: Option<(usize, u8)>,
+    checksum: Option<(usize, Checksum)>,
+    count: u8,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat which transmits `frame` unchanged every `period`
+    pub fn new(frame: impl Into<CanFrame>, period: Duration) -> Self {
+        Self {
+            frame: frame.into(),
+            period,
+            counter: None,
+            checksum: None,
+            count: 0,
+        }
+    }
+
+    /// Writes an alive counter into the bits of data byte `byte` selected by
+    /// `mask`, e.g. `0x0F` for a 4 bit counter in the low nibble. The counter
+    /// starts at 0, is incremented with every frame and wraps around once it
+    /// exceeds the width of the mask. Bytes past the data of the frame are
+    /// ignored.
+    pub fn with_counter(mut self, byte: usize, mask: u8) -> Self {
+        self.counter = Some((byte, mask));
+        self
+    }
+
+    /// Writes a checksum of all other data bytes into data byte `byte`, after
+    /// the alive counter was written. Bytes past the data of the frame are
+    /// ignored.
+    pub fn with_checksum(mut self, byte: usize, checksum: Checksum) -> Self {
+        self.checksum = Some((byte, checksum));
+        self
+    }
+
+    /// Returns the time between two frames
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the frame to transmit next and advances the alive counter
+    pub fn next_frame(&mut self) -> CanFrame {
+        let mut frame = self.frame.clone();
+        let Some(data) = frame.data_mut() else {
+            return frame;
+        };
+
+        if let Some((byte, mask)) = self.counter.filter(|&(_, mask)| mask != 0) {
+            let shift = mask.trailing_zeros();
+            let max = mask >> shift;
+
+            if let Some(byte) = data.get_mut(byte) {
+                *byte = (*byte & !mask) | ((self.count & max) << shift);
+            }
+
+            self.count = if self.count >= max { 0 } else { self.count + 1 };
+        }
+
+        if let Some((byte, checksum)) = self.checksum {
+            if byte < data.len() {
+                let others = data
+                    .iter()
+                    .enumerate()
+                    .filter(|&(index, _)| index != byte)
+                    .map(|(_, value)| *value);
+
+                data[byte] = checksum.compute(others);
+            }
+        }
+
+        frame
+    }
+}
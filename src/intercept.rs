@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::frame::{CanFrame, FrameDirection};
+
+/// What happens to a frame after it passed an [Interceptor]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Passes the frame on, possibly modified
+    Pass(CanFrame),
+    /// Drops the frame
+    Drop,
+    /// Passes the frame on after waiting for the given duration
+    Delay(CanFrame, Duration),
+    /// Replaces the frame with any number of frames, e.g. to duplicate it
+    Replace(Vec<CanFrame>),
+}
+
+/// A transform applied to every frame sent or received by a socket, for
+/// layering features like ID rewriting or fault injection on top of it.
+///
+/// Interceptors are registered with `CanSocket::add_interceptor` and run in
+/// the order they were added, each one receiving the frames produced by the
+/// previous one. Transmitted frames are intercepted by `CanSocket::send`
+/// before they are written to the gateway. Received frames are intercepted
+/// before they are returned by `CanSocket::read`, except for the frames
+/// matched by `CanSocket::request`.
+///
+/// Closures taking the frame and its direction implement this trait:
+///
+/// ```
+/// use slcan_fd::{CanFrame, FrameDirection, Id, Interceptor, StandardId, Verdict};
+///
+/// // Drops every received frame with ID 0x100
+/// let drop_0x100 = |frame: CanFrame, direction: FrameDirection| {
+///     if direction == FrameDirection::Rx && frame.id() == Id::Standard(StandardId::new(0x100).unwrap()) {
+///         Verdict::Drop
+///     } else {
+///         Verdict::Pass(frame)
+///     }
+/// };
+/// # fn is_interceptor(_: impl Interceptor) {}
+/// # is_interceptor(drop_0x100);
+/// ```
+pub trait Interceptor: Send {
+    /// Decides what happens to a frame travelling in the given direction
+    fn intercept(&mut self, frame: CanFrame, direction: FrameDirection) -> Verdict;
+}
+
+impl<F> Interceptor for F
+where
+    F: FnMut(CanFrame, FrameDirection) -> Verdict + Send,
+{
+    fn intercept(&mut self, frame: CanFrame, direction: FrameDirection) -> Verdict {
+        self(frame, direction)
+    }
+}
+
+/// The interceptors registered with a socket
+#[derive(Default)]
+pub(crate) struct InterceptorChain {
+    interceptors: Vec<Box<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    pub fn push(&mut self, interceptor: Box<dyn Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub fn clear(&mut self) {
+        self.interceptors.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+
+    /// Runs a frame through every interceptor in turn. Returns the resulting
+    /// frames in order, each with the time to wait before passing it on
+    /// (counted from now).
+    pub fn apply(
+        &mut self,
+        frame: CanFrame,
+        direction: FrameDirection,
+    ) -> Vec<(CanFrame, Duration)> {
+        let mut frames = vec![(frame, Duration::ZERO)];
+
+        for interceptor in &mut self.interceptors {
+            frames = frames
+                .into_iter()
+                .flat_map(
+                    |(frame, delay)| match interceptor.intercept(frame, direction) {
+                        Verdict::Pass(frame) => vec![(frame, delay)],
+                        Verdict::Drop => Vec::new(),
+                        Verdict::Delay(frame, extra) => vec![(frame, delay + extra)],
+                        Verdict::Replace(frames) => {
+                            frames.into_iter().map(|frame| (frame, delay)).collect()
+                        }
+                    },
+                )
+                .collect();
+        }
+
+        frames
+    }
+}
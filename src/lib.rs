@@ -4,6 +4,8 @@
 //! Since sending and receiving CAN FD frames over an SLCAN interface is not
 //! standardized, this crate looks to be compatible with the commands supported
 //! by the [CANable 2.0 Firmware](https://github.com/normaldotcom/canable2-fw).
+//! USBtin adapters, which stick to the original Lawicel command set, are
+//! supported as well with [`Dialect::UsbTin`].
 //!
 //! By default this crate is async and uses the
 //! [`tokio-serial`](https://github.com/berkowski/tokio-serial) crate, but it
@@ -11,10 +13,11 @@
 //!     
 //! ## Usage
 //!
-//! ```
+//! ```no_run
 //! use slcan_fd::{tokio::CanSocket, NominalBitRate, OperatingMode};
 //! use tokio_serial::SerialPortBuilderExt;
 //!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
 //! let mut port = tokio_serial::new("/dev/ttyUSB0", 115_200).open_native_async()?;
 //!
 //! #[cfg(unix)]
@@ -33,14 +36,68 @@
 //!         Err(e) => eprintln!("{:?}", e),
 //!     }
 //! }
+//! # }
 //! ```
 //!
 //! ## Feature Flags
 //!
 //! The `tokio` feature is enabled by default.
 //!
-//! - `tokio` - Implements the async API with the [`tokio-serial`](https://github.com/berkowski/tokio-serial) crate.
+//! - `tokio` - Implements the async API with the [`tokio-serial`](https://github.com/berkowski/tokio-serial) crate,
+//!   including the [multi] module for working with several buses at once,
+//!   the [blocking] module for using it from synchronous code and the [xcp]
+//!   module for talking to ECUs with XCP on CAN.
+//! - `serial2` - Adds `sync::CanSocket::open_serial2` for using the
+//!   [`serial2`](https://docs.rs/serial2) crate instead of `serialport`
+//!   (implies `sync`).
+//! - `serial2-tokio` - Adds `tokio::CanSocket::open_serial2` for using the
+//!   [`serial2-tokio`](https://docs.rs/serial2-tokio) crate instead of
+//!   `tokio-serial` (implies `tokio`).
+//! - `serialport` - Implements [ModemControl] for the ports of the
+//!   [`serialport`](https://docs.rs/serialport) crate, for resetting
+//!   adapters with `CanSocket::reset_adapter`.
+//! - `tokio-serial` - Implements [ModemControl] for
+//!   `tokio_serial::SerialStream` (implies `tokio`).
 //! - `sync` - Implements the synchronous API with the [`serialport`](https://github.com/serialport/serialport-rs) crate.
+//!   Together with `tokio`, the `unblock` module runs it behind an async
+//!   API.
+//! - `arbitrary` - Implements `arbitrary::Arbitrary` for the frame and
+//!   configuration types and adds the [fuzz] module for generating raw
+//!   SLCAN lines.
+//! - `background` - Adds the [background] module for sharing a socket
+//!   between tasks (implies `tokio`).
+//! - `can-dbc` - Converts DBC files parsed with the [`can-dbc`](https://docs.rs/can-dbc)
+//!   crate into a `dbc::Database` (implies `dbc`).
+//! - `color` - Adds `fmt::ColorFormatter` for printing traffic with
+//!   colored IDs and highlighted changes in terminals. See the [fmt] module.
+//! - `dbc` - Adds the [dbc] module for decoding signals described by DBC
+//!   files.
+//! - `defmt` - Implements `defmt::Format` for the frame, configuration and
+//!   error types.
+//! - `ffi` - Adds the [ffi] module, a C ABI for the synchronous API which is
+//!   exported when the crate is built with
+//!   `cargo rustc --features ffi --crate-type cdylib` (implies `sync`).
+//! - `gzip` - Adds support for gzip compressed logs to the [logging] module
+//!   (implies `logging`).
+//! - `logging` - Adds the [logging] module for reading, writing and replaying
+//!   logs of CAN traffic.
+//! - `metrics` - Emits frame, error and reconnect counters and a transmit
+//!   queue depth gauge through the [`metrics`](https://docs.rs/metrics)
+//!   facade. See the [metrics] module.
+//! - `mio` - Implements `mio::event::Source` for the synchronous
+//!   `CanSocket` so it can be driven by a readiness-based event loop (Unix only).
+//! - `sim` - Adds a deterministic virtual bus connecting any number of
+//!   sockets, for testing without hardware. See the [sim] module.
+//! - `socketcan` - Adds the `socketcan` module for mirroring an adapter
+//!   onto a Linux `vcan` interface, for use with can-utils and Wireshark
+//!   (Linux only, implies `tokio`).
+//! - `udp` - Adds the [udp] module for running the async `CanSocket` over
+//!   UDP, e.g. with WiFi-CAN bridges (implies `tokio`).
+//! - `webserial` - Adds the `web_serial` module for running the async
+//!   `CanSocket` in the browser over the Web Serial API (wasm32 only, implies
+//!   `tokio`).
+//! - `zstd` - Adds support for zstd compressed logs to the [logging] module
+//!   (implies `logging`).
 //!
 //! ## Credits
 //!
@@ -49,23 +106,330 @@
 
 pub use embedded_can::{ExtendedId, Id, StandardId};
 
+mod acceptance;
+#[cfg(feature = "background")]
+pub mod background;
+#[cfg(feature = "tokio")]
+pub mod blocking;
+pub mod bridge;
+pub mod busload;
+mod capabilities;
 mod command;
+#[cfg(feature = "dbc")]
+pub mod dbc;
+mod diagnostics;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+pub mod fmt;
+#[cfg(feature = "tokio")]
+mod forward;
 mod frame;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+mod heartbeat;
+mod intercept;
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod metrics;
+mod modem;
+#[cfg(feature = "tokio")]
+pub mod multi;
+mod observer;
 mod parser;
+mod responder;
+mod sequence;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(all(feature = "socketcan", target_os = "linux"))]
+pub mod socketcan;
+mod stats;
+mod status;
+mod stream_parser;
+mod timestamp;
+#[cfg(feature = "udp")]
+pub mod udp;
+#[cfg(all(feature = "sync", feature = "tokio"))]
+pub mod unblock;
+#[cfg(all(feature = "webserial", target_arch = "wasm32"))]
+pub mod web_serial;
+#[cfg(feature = "tokio")]
+pub mod xcp;
 
-pub use command::{AutoRetransmissionMode, DataBitRate, NominalBitRate, OperatingMode};
-pub use frame::{Can2Frame, CanFdFrame, CanFrame};
-pub use parser::{MessageKind, MessageParseError};
+pub use acceptance::AcceptanceFilter;
+pub use capabilities::Capabilities;
+pub use command::{
+    encode_frame, AutoRetransmissionMode, Command, CommandBuffer, DataBitRate, Dialect, FdCrcMode,
+    NominalBitRate, OperatingMode, SlcanFrame, TimestampMode, UnsupportedBitRate, UnsupportedFrame,
+};
+pub use diagnostics::{BusDiagnosis, BusFault};
+pub use filter::{FilterParseError, IdFilter};
+#[cfg(feature = "tokio")]
+pub use forward::{ForwardErrorPolicy, ForwardOptions, ForwardStats};
+pub use frame::{
+    Can2Frame, CanFdFrame, CanFrame, CanXlFrame, FrameConversionError, FrameDirection,
+    FrameParseError, RxFrame,
+};
+pub use heartbeat::{Checksum, Heartbeat};
+pub use intercept::{Interceptor, Verdict};
+pub use modem::ModemControl;
+pub use observer::BusObserver;
+pub use parser::{
+    parse_frame_from_bytes, parse_timestamped_frame_from_bytes, LineParseError, Message,
+    MessageKind, MessageParseError,
+};
+#[cfg(feature = "tokio")]
+pub use responder::ResponderError;
+pub use responder::{RemoteResponder, Responder, ResponseRule};
+pub use sequence::{SequenceChecker, SequenceGap, SequenceStats};
+pub use stats::{LinkStats, ParseErrorCounts};
+pub use status::{BusOffPolicy, BusState, BusStatus, CommandRetryPolicy, TxQueuePolicy};
+pub use stream_parser::{SlcanStreamParser, StreamParseError};
+pub use timestamp::{ClockSync, Timestamp};
 
-/// Maximum rx buffer len: (command + extended id + dlc + data + CR + 16 bytes extra)
+/// Maximum rx buffer len by default: (command + extended id + dlc + data + CR
+/// + 16 bytes extra). See `CanSocket::set_max_line_length`.
 const SLCAN_MTU: usize = (1 + 8 + 1 + 128) + 1 + 16;
 
+/// How long `CanSocket::reset_adapter` waits for the answer to each version
+/// query while the adapter is booting
+const READY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How long the sync socket waits before reading again when a port in
+/// non-blocking mode has no data while waiting for an answer
+#[cfg(feature = "sync")]
+const WOULD_BLOCK_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// A callback registered with `CanSocket::set_raw_rx_callback` or
+/// `CanSocket::set_raw_tx_callback`
+type RawLineCallback = Box<dyn FnMut(&[u8]) + Send>;
+
 #[derive(Debug, thiserror::Error)]
 pub enum ReadError {
     #[error("IO Error: {0}")]
     Io(#[from] std::io::Error),
     #[error("SLCAN message parsing error: {0}")]
-    Slcan(#[from] MessageParseError),
+    Slcan(#[from] LineParseError),
+    #[error("The CAN controller is bus-off")]
+    BusOff,
+    #[error("Received a line of {0} bytes which exceeds the maximum line length")]
+    LineTooLong(usize),
+    #[error("The gateway did not answer the keepalive query")]
+    DeviceUnresponsive,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ReadError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            ReadError::Io(e) => defmt::write!(f, "Io({})", defmt::Debug2Format(&e.kind())),
+            ReadError::Slcan(e) => defmt::write!(f, "Slcan({})", e),
+            ReadError::BusOff => defmt::write!(f, "BusOff"),
+            ReadError::LineTooLong(length) => defmt::write!(f, "LineTooLong({})", length),
+            ReadError::DeviceUnresponsive => defmt::write!(f, "DeviceUnresponsive"),
+        }
+    }
+}
+
+/// The outcome of a transmission which waited for the gateway to acknowledge
+/// it. See `CanSocket::send_and_confirm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransmitStatus {
+    /// The gateway queued the frame for transmission on the bus (`z`/`Z`)
+    Acknowledged,
+    /// The gateway refused the frame, e.g. because the channel is closed or
+    /// its transmit queue is full
+    Rejected,
+    /// The gateway did not respond within the timeout. The frame may or may
+    /// not have been sent.
+    TimedOut,
+}
+
+/// Errors which can arise while sending a frame, distinguishing failures which
+/// are worth retrying from ones where the frame should be dropped
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The channel was not opened with `CanSocket::open`, or it was closed
+    #[error("The channel is closed")]
+    ChannelClosed,
+    /// The gateway is in [`OperatingMode::Silent`] and can't transmit
+    #[error("The gateway is in listen only mode")]
+    ListenOnly,
+    /// The gateway refused the frame (NACK), e.g. because its transmit queue
+    /// is full. Only reported for sends which wait for the acknowledgement.
+    #[error("The gateway rejected the frame")]
+    Rejected,
+    /// The gateway did not acknowledge the frame in time. The frame may or
+    /// may not have been sent.
+    #[error("The gateway did not acknowledge the frame in time")]
+    TimedOut,
+    /// The frame can't be transmitted over SLCAN, e.g. a CAN XL frame, or a
+    /// CAN FD frame with [`Dialect::UsbTin`]
+    #[error("The frame type is not supported by SLCAN")]
+    Unsupported,
+    /// The ID given to one of the `send_*` helpers is out of range
+    #[error("0x{0:X} is not a valid CAN ID")]
+    InvalidId(u32),
+    /// The data given to one of the `send_*` helpers doesn't fit into the
+    /// frame
+    #[error("{0} bytes of data don't fit into the frame")]
+    InvalidLength(usize),
+    /// The transmit filter of the socket doesn't permit frames with this ID
+    /// (see `CanSocket::set_tx_filter`)
+    #[error("Frames with ID {} may not be transmitted", display_id(*.0))]
+    Blocked(Id),
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for SendError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            SendError::Io(e) => defmt::write!(f, "Io({})", defmt::Debug2Format(&e.kind())),
+            SendError::ChannelClosed => defmt::write!(f, "ChannelClosed"),
+            SendError::ListenOnly => defmt::write!(f, "ListenOnly"),
+            SendError::Rejected => defmt::write!(f, "Rejected"),
+            SendError::TimedOut => defmt::write!(f, "TimedOut"),
+            SendError::Unsupported => defmt::write!(f, "Unsupported"),
+            SendError::InvalidId(id) => defmt::write!(f, "InvalidId({=u32:#X})", id),
+            SendError::InvalidLength(length) => defmt::write!(f, "InvalidLength({})", length),
+            SendError::Blocked(id) => {
+                defmt::write!(f, "Blocked(");
+                frame::format_id(*id, f);
+                defmt::write!(f, ")");
+            }
+        }
+    }
+}
+
+impl SendError {
+    /// Returns true if the port could not accept the frame right now
+    /// (`WouldBlock` or `TimedOut`) or the gateway's transmit queue was full,
+    /// so sending the frame again later may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SendError::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ),
+            SendError::Rejected | SendError::TimedOut => true,
+            SendError::ChannelClosed
+            | SendError::ListenOnly
+            | SendError::Unsupported
+            | SendError::InvalidId(_)
+            | SendError::InvalidLength(_)
+            | SendError::Blocked(_) => false,
+        }
+    }
+}
+
+/// The error returned when the gateway rejected a configuration command, or
+/// didn't confirm it in time, on every attempt. See
+/// `CanSocket::set_command_retry_policy`.
+fn unconfirmed_command(command: &Command, rejected: bool) -> std::io::Error {
+    if rejected {
+        std::io::Error::other(format!("the gateway rejected the command {command:?}"))
+    } else {
+        std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("the gateway did not confirm the command {command:?}"),
+        )
+    }
+}
+
+/// The error returned when the adapter doesn't answer in time after
+/// `CanSocket::reset_adapter` reset it
+fn adapter_not_ready() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "the adapter did not answer after being reset",
+    )
+}
+
+/// The error returned when the gateway rejects the error counters command
+fn error_counters_rejected() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the gateway rejected the error counters command",
+    )
+}
+
+/// Converts the raw ID given to the `send_*` helpers, treating IDs up to
+/// 0x7FF as standard IDs and larger ones as extended IDs
+fn id_from_raw(raw: u32) -> Result<Id, SendError> {
+    match u16::try_from(raw).ok().and_then(StandardId::new) {
+        Some(id) => Ok(Id::Standard(id)),
+        None => ExtendedId::new(raw)
+            .map(Id::Extended)
+            .ok_or(SendError::InvalidId(raw)),
+    }
+}
+
+/// Formats an ID as hexadecimal with the number of digits of its kind
+fn display_id(id: Id) -> String {
+    match id {
+        Id::Standard(id) => format!("0x{:03X}", id.as_raw()),
+        Id::Extended(id) => format!("0x{:08X}", id.as_raw()),
+    }
+}
+
+impl TransmitStatus {
+    /// Converts the status into a result, turning `Rejected` and `TimedOut`
+    /// into the corresponding [SendError]
+    pub fn into_result(self) -> Result<(), SendError> {
+        match self {
+            TransmitStatus::Acknowledged => Ok(()),
+            TransmitStatus::Rejected => Err(SendError::Rejected),
+            TransmitStatus::TimedOut => Err(SendError::TimedOut),
+        }
+    }
+}
+
+impl ReadError {
+    /// Returns true if this error only indicates that no complete line was
+    /// available yet (`WouldBlock` or `TimedOut`) and the read can be retried
+    pub fn is_would_block(&self) -> bool {
+        matches!(
+            self,
+            ReadError::Io(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+        )
+    }
+}
+
+/// Generates methods of a socket wrapper which forward to the same method of
+/// its inner `CanSocket`, documented with a link to it.
+/// Methods declared `async fn` await the forwarded call.
+#[cfg(any(feature = "sync", feature = "tokio"))]
+macro_rules! forward_to_socket {
+    () => {};
+    (
+        $(#[$attr:meta])*
+        async fn $name:ident(&mut self $(, $arg:ident: $ty:ty)* $(,)?) $(-> $ret:ty)?;
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        #[doc = concat!("See [`CanSocket::", stringify!($name), "`]")]
+        pub async fn $name(&mut self $(, $arg: $ty)*) $(-> $ret)? {
+            self.socket.$name($($arg),*).await
+        }
+
+        forward_to_socket!($($rest)*);
+    };
+    (
+        $(#[$attr:meta])*
+        fn $name:ident(&mut self $(, $arg:ident: $ty:ty)* $(,)?) $(-> $ret:ty)?;
+        $($rest:tt)*
+    ) => {
+        $(#[$attr])*
+        #[doc = concat!("See [`CanSocket::", stringify!($name), "`]")]
+        pub fn $name(&mut self $(, $arg: $ty)*) $(-> $ret)? {
+            self.socket.$name($($arg),*)
+        }
+
+        forward_to_socket!($($rest)*);
+    };
 }
 
 #[cfg(feature = "sync")]
@@ -73,17 +437,45 @@ pub mod sync {
     //! The synchronous implementation of CanSocket for use with the
     //! [serialport] crate.
 
+    use std::collections::VecDeque;
     use std::io::{self, Read, Write};
+    use std::ops::Deref;
     #[cfg(target_family = "unix")]
     use std::os::unix::prelude::AsRawFd;
+    #[cfg(target_family = "windows")]
+    use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, RawHandle};
+    use std::time::{Duration, Instant};
 
     use crate::{
-        command::{AutoRetransmissionMode, Command, DataBitRate, OperatingMode},
-        frame::CanFrame,
-        parser::parse_frame_from_bytes,
-        NominalBitRate, ReadError, SLCAN_MTU,
+        acceptance::{AcceptanceFilter, AcceptanceFilters},
+        adapter_not_ready,
+        command::{
+            AutoRetransmissionMode, Command, DataBitRate, Dialect, FdCrcMode, OperatingMode,
+            SlcanFrame, TimestampMode,
+        },
+        diagnostics::{BusDiagnosis, DiagnosisSampler},
+        error_counters_rejected,
+        filter::IdFilter,
+        frame::{Can2Frame, CanFdFrame, CanFrame, FrameDirection, RxFrame},
+        id_from_raw,
+        intercept::{Interceptor, InterceptorChain},
+        metrics,
+        modem::ModemControl,
+        observer::{BusObserver, ObserverList},
+        parser::{parse_message_from_bytes, LineParseError, Message, NACK},
+        status::{
+            BusOffPolicy, BusState, BusStatus, BusStatusCallback, ChannelConfig,
+            CommandRetryPolicy, TxQueuePolicy,
+        },
+        stream_parser::{LineAccumulator, LineEvent},
+        unconfirmed_command, Capabilities, Id, LinkStats, NominalBitRate, RawLineCallback,
+        ReadError, RemoteResponder, SendError, Timestamp, TransmitStatus, READY_POLL_INTERVAL,
+        SLCAN_MTU, WOULD_BLOCK_RETRY_INTERVAL,
     };
 
+    #[cfg(feature = "logging")]
+    use crate::logging::Recorder;
+
     /// Represents an synchronous interface into a CAN FD network through a
     /// serial (USB) gateway device.
     ///
@@ -91,330 +483,4350 @@ pub mod sync {
     /// broadcasted on the bus by other nodes can be received through the
     /// gateway.
     pub struct CanSocket<P> {
-        port: Box<P>,
-        rx_buff: [u8; SLCAN_MTU],
-        rx_count: usize,
-        error: bool,
+        port: Option<Box<P>>,
+        lines: LineAccumulator,
+        line: Vec<u8>,
+        timestamp_mode: TimestampMode,
+        last_timestamp: Option<Timestamp>,
+        channel: usize,
+        tx_echo: bool,
+        rx_queue: VecDeque<RxFrame>,
+        interceptors: InterceptorChain,
+        observers: ObserverList,
+        intercepted: VecDeque<(RxFrame, Instant)>,
+        /// Frames which already passed the interceptors but didn't match a
+        /// [`request`](CanSocket::request), returned as they are by the next
+        /// reads
+        skipped: VecDeque<RxFrame>,
+        unacknowledged: usize,
+        transmitted: u64,
+        open: bool,
+        config: ChannelConfig,
+        bus_status: BusStatus,
+        stats: LinkStats,
+        keepalive: Option<Duration>,
+        keepalive_sent: Option<Instant>,
+        last_received: Instant,
+        version_pending: bool,
+        bus_off_policy: BusOffPolicy,
+        command_retry_policy: CommandRetryPolicy,
+        tx_queue_policy: TxQueuePolicy,
+        tx_filter: Option<IdFilter>,
+        on_bus_status: Option<BusStatusCallback>,
+        on_raw_rx: Option<RawLineCallback>,
+        on_raw_tx: Option<RawLineCallback>,
+        remote_responder: Option<RemoteResponder>,
+        #[cfg(feature = "logging")]
+        recorder: Option<Recorder>,
+        identify_command: Option<Vec<u8>>,
+        error_counters_command: Option<Vec<u8>>,
+        bootloader_command: Option<Vec<u8>>,
+        termination_commands: Option<(Vec<u8>, Vec<u8>)>,
+        fd_crc_commands: Option<(Vec<u8>, Vec<u8>)>,
+        acceptance: AcceptanceFilters,
+        dialect: Dialect,
+        close_on_drop: bool,
+        close_port: fn(&mut P),
+    }
+
+    impl<P> Drop for CanSocket<P> {
+        /// Closes the channel if it is still open, unless disabled with
+        /// [`set_close_on_drop`](CanSocket::set_close_on_drop)
+        fn drop(&mut self) {
+            if let Some(port) = self
+                .port
+                .as_mut()
+                .filter(|_| self.open && self.close_on_drop)
+            {
+                (self.close_port)(port);
+            }
+        }
+    }
+
+    /// Writes the close command to a port, ignoring errors since they can't
+    /// be reported from [Drop]
+    fn close_port<P: Write>(port: &mut P) {
+        let encoded = Command::Close.encode();
+
+        let _ = port.write_all(encoded.as_line()).and_then(|_| port.flush());
+    }
+
+    /// Waits before retrying a read which found no data, so the calls which
+    /// read until a deadline don't spin on a port in non-blocking mode.
+    /// Reads which timed out have waited already and are retried right away.
+    fn wait_for_data(error: &ReadError, deadline: Instant) {
+        if matches!(error, ReadError::Io(e) if e.kind() == io::ErrorKind::WouldBlock) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            std::thread::sleep(remaining.min(WOULD_BLOCK_RETRY_INTERVAL));
+        }
+    }
+
+    /// The port is only taken out of the socket by
+    /// [`into_inner`](CanSocket::into_inner), which consumes it
+    const PORT_TAKEN: &str = "the port is only taken when consuming the socket";
+
+    impl<P> CanSocket<P> {
+        /// Returns the underlying port
+        pub fn port(&self) -> &P {
+            self.port.as_deref().expect(PORT_TAKEN)
+        }
+
+        /// Returns the underlying port, e.g. for changing its baud rate,
+        /// timeouts or flow control at runtime. Bytes read from or written to
+        /// it directly bypass the socket, so this is best done while the
+        /// channel is closed.
+        pub fn port_mut(&mut self) -> &mut P {
+            self.port.as_deref_mut().expect(PORT_TAKEN)
+        }
+
+        /// Consumes the socket and returns the underlying port, e.g. for
+        /// talking to the firmware of the adapter directly. Unlike dropping
+        /// the socket, this leaves the channel as it is, so
+        /// [`close`](CanSocket::close) it first if needed. Frames and partial
+        /// lines which were received but not read yet are discarded.
+        pub fn into_inner(mut self) -> P {
+            *self.port.take().expect(PORT_TAKEN)
+        }
     }
 
     #[cfg(target_family = "unix")]
     impl<P: AsRawFd> AsRawFd for CanSocket<P> {
         fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-            self.port.as_raw_fd()
+            self.port().as_raw_fd()
         }
     }
 
-    impl<P: Read + Write> CanSocket<P> {
-        /// Constructs a new CanSocket from a generic serial port
-        pub fn new(port: P) -> Self {
-            CanSocket {
-                port: Box::new(port),
-                rx_buff: [0; SLCAN_MTU],
-                rx_count: 0,
-                error: false,
-            }
+    /// Exposes the handle of the underlying port, e.g. for registering it with
+    /// an I/O completion port. Note that the handle must have been opened
+    /// with `FILE_FLAG_OVERLAPPED` for overlapped I/O to be possible.
+    #[cfg(target_family = "windows")]
+    impl<P: AsRawHandle> AsRawHandle for CanSocket<P> {
+        fn as_raw_handle(&self) -> RawHandle {
+            self.port().as_raw_handle()
         }
+    }
 
-        /// Configures the device with the supplied bit timing and requests
-        /// the device to begin enable streaming of CAN frames
-        pub fn open(&mut self, nominal_bit_rate: NominalBitRate) -> io::Result<()> {
-            self.send_command(Command::SetNominalBitRate(nominal_bit_rate))?;
-            self.send_command(Command::Open)?;
-            Ok(())
+    #[cfg(target_family = "windows")]
+    impl<P: AsHandle> AsHandle for CanSocket<P> {
+        fn as_handle(&self) -> BorrowedHandle<'_> {
+            self.port().as_handle()
         }
+    }
 
-        /// Sends a close command to the gateway which instructs it to stop
-        /// sending and receiving CAN frames
-        pub fn close(&mut self) -> io::Result<()> {
-            self.send_command(Command::Close)?;
-            Ok(())
-        }
+    #[cfg(target_family = "unix")]
+    impl<P: AsRawFd> CanSocket<P> {
+        /// Moves the underlying file descriptor into or out of non-blocking
+        /// mode.
+        ///
+        /// In non-blocking mode [`read`](CanSocket::read) returns a
+        /// `WouldBlock` error as soon as the port has no more bytes
+        /// available. Any partial line received up to that point is kept
+        /// in the socket and completed by the next call, so it is always
+        /// safe to retry once the port is readable again.
+        ///
+        /// Calls which wait for an answer until a timeout, such as
+        /// [`request`](CanSocket::request), poll the port every millisecond
+        /// in this mode. A blocking port with a read timeout wakes them up as
+        /// soon as data arrives instead.
+        pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            let fd = self.port().as_raw_fd();
+
+            // SAFETY: `fd` is owned by the port for as long as `self` lives
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let flags = if nonblocking {
+                flags | libc::O_NONBLOCK
+            } else {
+                flags & !libc::O_NONBLOCK
+            };
+
+            // SAFETY: See above
+            if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
 
-        /// Sets the data bit rate (CAN FD frames only). See [DataBitRate].
-        pub fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()> {
-            self.send_command(Command::SetDataBitRate(rate))?;
             Ok(())
         }
+    }
 
-        /// Sets the operating mode of the gateway, either `Normal` or `Silent`
-        /// (a.k.a. "Listen Only" mode). See [OperatingMode].
-        pub fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()> {
-            self.send_command(Command::SetMode(mode))?;
-            Ok(())
+    /// Registers the underlying file descriptor with a [`mio::Poll`].
+    ///
+    /// The socket should be put into non-blocking mode first (see
+    /// [`CanSocket::set_nonblocking`]). Since mio readiness is edge
+    /// triggered, [`read`](CanSocket::read) must be called until it returns
+    /// an error for which [`ReadError::is_would_block`](crate::ReadError::is_would_block)
+    /// is true before waiting for the next event.
+    #[cfg(all(feature = "mio", target_family = "unix"))]
+    impl<P: AsRawFd> mio::event::Source for CanSocket<P> {
+        fn register(
+            &mut self,
+            registry: &mio::Registry,
+            token: mio::Token,
+            interests: mio::Interest,
+        ) -> io::Result<()> {
+            mio::unix::SourceFd(&self.port().as_raw_fd()).register(registry, token, interests)
         }
 
-        /// Sets the auto retransmission mode of the gateway, either `Enabled`
-        /// or `Disabled`. See [AutoRetransmissionMode].
-        pub fn set_auto_retransmission_mode(
+        fn reregister(
             &mut self,
-            mode: AutoRetransmissionMode,
+            registry: &mio::Registry,
+            token: mio::Token,
+            interests: mio::Interest,
         ) -> io::Result<()> {
-            self.send_command(Command::SetAutoRetransmission(mode))?;
-            Ok(())
+            mio::unix::SourceFd(&self.port().as_raw_fd()).reregister(registry, token, interests)
         }
 
-        /// Sends a CAN frame to the gateway to be broadcasted on the bus.
-        ///
-        /// If the frame fails to be sent, it may be retransmitted according to
-        /// the current [AutoRetransmissionMode].
-        pub fn send(&mut self, frame: impl Into<CanFrame>) -> io::Result<()> {
-            self.send_command(Command::TransmitFrame(frame.into()))?;
-            Ok(())
+        fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+            mio::unix::SourceFd(&self.port().as_raw_fd()).deregister(registry)
         }
+    }
 
-        /// Reads a line from the serial stream and attempts to parse it as a
-        /// valid CAN frame.
+    #[cfg(feature = "serial2")]
+    impl CanSocket<serial2::SerialPort> {
+        /// Opens the serial port at `path` with the [`serial2`] crate, as an
+        /// alternative to [`serialport`](https://docs.rs/serialport) on
+        /// platforms where it misbehaves. The CAN channel is not opened yet,
+        /// see [`open`](CanSocket::open).
+        pub fn open_serial2(path: impl AsRef<std::path::Path>, baud_rate: u32) -> io::Result<Self> {
+            Ok(Self::new(serial2::SerialPort::open(path, baud_rate)?))
+        }
+    }
+
+    impl<P: Read + Write + ModemControl> CanSocket<P> {
+        /// Resets the adapter by pulsing its DTR and RTS lines, for CANable
+        /// clones which are wired to reset when they are toggled, e.g. to
+        /// recover a wedged adapter without replugging it. The lines are
+        /// released for `pulse` and then asserted again.
         ///
-        /// # Errors
+        /// The firmware version is then queried until the adapter answers,
+        /// for up to `ready_timeout`, and the settings applied so far
+        /// (including the filter banks) are reapplied. The channel is reopened
+        /// if it was open. Partially received lines and acknowledgements still
+        /// outstanding are discarded.
         ///
-        /// An error will be returned if the operation would block or timed
-        /// out. In this case it is safe to call `read` again until a message
-        /// is received.
+        /// Adapters whose USB connection drops while resetting can't be
+        /// recovered this way, since their port has to be reopened.
         ///
-        /// An error will also be returned for any other kinds of I/O errors.
+        /// The port should be configured with a read timeout, otherwise this
+        /// call blocks until the adapter answers.
         ///
-        /// Finally, an error will be returned if the received line cannot be
-        /// parsed as a valid CAN frame for any number of reasons. See
-        /// [MessageParseError](crate::MessageParseError).
-        pub fn read(&mut self) -> Result<CanFrame, ReadError> {
-            Ok(parse_frame_from_bytes(&self.read_line()?)?)
-        }
-
-        /// Reads from the serial stream until a line of length 1..=SLCAN_MTU
-        /// is received with a terminating CR.
+        /// # Errors
         ///
-        /// Will return an Err if the operation would block and is safe to
-        /// call again in that case without losing any state.
-        fn read_line(&mut self) -> io::Result<Vec<u8>> {
-            let mut buf = [0u8; 1];
-
-            while self.port.read(&mut buf)? == 1 {
-                let b = buf[0];
-
-                if b == b'\r' {
-                    let valid = !self.error && self.rx_count > 0;
-                    let buffer = &self.rx_buff[..self.rx_count];
-
-                    self.error = false;
-                    self.rx_count = 0;
+        /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the adapter
+        /// doesn't answer within `ready_timeout`.
+        pub fn reset_adapter(
+            &mut self,
+            pulse: Duration,
+            ready_timeout: Duration,
+        ) -> io::Result<()> {
+            self.port_mut().set_dtr(false)?;
+            self.port_mut().set_rts(false)?;
+            std::thread::sleep(pulse);
+            self.port_mut().set_dtr(true)?;
+            self.port_mut().set_rts(true)?;
 
-                    // We detected an error, move on and read the next line instead
-                    if !valid {
-                        continue;
-                    }
+            let was_open = self.open;
+            self.lines.reset();
+            self.unacknowledged = 0;
+            metrics::record_tx_queue_depth(0);
+            if was_open {
+                self.open = false;
+                self.observers.channel_state(false);
+            }
 
-                    return Ok(buffer.to_vec());
+            let deadline = Instant::now() + ready_timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(adapter_not_ready());
                 }
 
-                // If we already detected an error, keep reading until we find a CR
-                if self.error {
-                    continue;
+                // The query is repeated since the adapter drops it while booting
+                match self.probe(None, true, remaining.min(READY_POLL_INTERVAL)) {
+                    Ok((Some(_), _)) => break,
+                    Ok((None, _)) => {}
+                    Err(ReadError::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::other(e)),
                 }
+            }
 
-                // If we encounter a line that is too long, set the error flag and
-                // keep reading until we find a CR
-                if self.rx_count >= SLCAN_MTU {
-                    self.error = true;
-                    continue;
-                }
+            self.reapply_config()?;
+            self.program_filter_banks(true)?;
+            if was_open {
+                self.open_channel()?;
+            }
+
+            metrics::record_reconnect("adapter_reset");
+            Ok(())
+        }
+    }
 
-                // If things are going normally, just store the byte
-                self.rx_buff[self.rx_count] = b;
-                self.rx_count += 1;
+    impl<P: Read + Write> CanSocket<P> {
+        /// Constructs a new CanSocket from a generic serial port
+        pub fn new(port: P) -> Self {
+            CanSocket {
+                port: Some(Box::new(port)),
+                lines: LineAccumulator::new(SLCAN_MTU),
+                line: Vec::with_capacity(SLCAN_MTU),
+                timestamp_mode: TimestampMode::Disabled,
+                last_timestamp: None,
+                channel: 0,
+                tx_echo: false,
+                rx_queue: VecDeque::new(),
+                interceptors: InterceptorChain::default(),
+                observers: ObserverList::default(),
+                intercepted: VecDeque::new(),
+                skipped: VecDeque::new(),
+                unacknowledged: 0,
+                transmitted: 0,
+                open: false,
+                config: ChannelConfig::default(),
+                bus_status: BusStatus::default(),
+                stats: LinkStats::default(),
+                keepalive: None,
+                keepalive_sent: None,
+                last_received: Instant::now(),
+                version_pending: false,
+                bus_off_policy: BusOffPolicy::default(),
+                command_retry_policy: CommandRetryPolicy::default(),
+                tx_queue_policy: TxQueuePolicy::default(),
+                tx_filter: None,
+                on_bus_status: None,
+                on_raw_rx: None,
+                on_raw_tx: None,
+                remote_responder: None,
+                #[cfg(feature = "logging")]
+                recorder: None,
+                identify_command: None,
+                error_counters_command: None,
+                bootloader_command: None,
+                termination_commands: None,
+                fd_crc_commands: None,
+                acceptance: AcceptanceFilters::default(),
+                dialect: Dialect::Standard,
+                close_on_drop: true,
+                close_port: close_port::<P>,
             }
+        }
 
-            Err(io::ErrorKind::WouldBlock.into())
+        /// Configures the device with the supplied bit timing and requests
+        /// the device to begin enable streaming of CAN frames
+        pub fn open(&mut self, nominal_bit_rate: NominalBitRate) -> io::Result<()> {
+            self.set_nominal_bit_rate(nominal_bit_rate)?;
+            self.open_channel()
         }
 
-        /// Serializes a command and sends it over the serial stream with a CR
-        /// line ending appended. Crucially, the entire command is sent in one
-        /// write operation which is important because the CANable does not
-        /// always correctly buffer input and will fail to parse our commands
-        /// if they are split into multiple USB packets.
-        fn send_command(&mut self, command: Command) -> io::Result<()> {
-            let mut buffer = command.as_bytes();
-            buffer.push(b'\r');
+        /// Requests the device to begin streaming CAN frames with the nominal
+        /// bit rate set earlier, e.g. for opening and closing the channel
+        /// repeatedly after setting the bit rate once. See
+        /// [`set_nominal_bit_rate`](CanSocket::set_nominal_bit_rate).
+        pub fn open_channel(&mut self) -> io::Result<()> {
+            let command = match (self.dialect, self.config.operating_mode) {
+                (Dialect::UsbTin, Some(OperatingMode::Silent)) => Command::OpenListenOnly,
+                (Dialect::UsbTin, Some(OperatingMode::Loopback)) => Command::OpenLoopback,
+                _ => Command::Open,
+            };
 
-            self.port.write_all(&buffer)?;
-            self.port.flush()?;
+            self.send_config_command(command)?;
+            self.open = true;
+            self.observers.channel_state(true);
+            metrics::record_channel_open();
             Ok(())
         }
-    }
-}
 
-#[cfg(feature = "tokio")]
-pub mod tokio {
-    //! The async implementation of CanSocket for use with the
-    //! [tokio_serial] crate.
+        /// Switches the channel to other bit rates, e.g. for scanning for the
+        /// bit rate of a bus: the channel is closed, the data bit rate is set
+        /// (if given) and the channel is reopened at the nominal bit rate. The
+        /// operating mode, auto retransmission and timestamp mode applied so
+        /// far are restored before reopening.
+        ///
+        /// If any step fails the channel may be left closed.
+        pub fn change_bit_rate(
+            &mut self,
+            nominal_bit_rate: NominalBitRate,
+            data_bit_rate: Option<DataBitRate>,
+        ) -> io::Result<()> {
+            self.close()?;
 
-    use std::io;
-    #[cfg(target_family = "unix")]
-    use std::os::unix::prelude::AsRawFd;
-    use std::pin::Pin;
+            self.config.nominal_bit_rate = Some(nominal_bit_rate);
+            if let Some(rate) = data_bit_rate {
+                self.config.data_bit_rate = Some(rate);
+            }
 
-    use tokio::io::AsyncRead;
-    use tokio::io::AsyncReadExt;
-    use tokio::io::AsyncWrite;
-    use tokio::io::AsyncWriteExt;
+            self.reopen()
+        }
 
-    use crate::parser::parse_frame_from_bytes;
-    use crate::{
-        command::{AutoRetransmissionMode, Command, DataBitRate, OperatingMode},
-        frame::CanFrame,
-        NominalBitRate, ReadError, SLCAN_MTU,
-    };
+        /// Sends a close command to the gateway which instructs it to stop
+        /// sending and receiving CAN frames
+        pub fn close(&mut self) -> io::Result<()> {
+            self.send_config_command(Command::Close)?;
+            self.open = false;
+            self.observers.channel_state(false);
+            Ok(())
+        }
 
-    /// Represents an asynchronous interface into a CAN FD network through a
-    /// serial (USB) gateway device.
-    ///
-    /// Messages can be sent over the bus through the gateway, and messages
-    /// broadcasted on the bus by other nodes can be received through the
-    /// gateway.
-    pub struct CanSocket<P> {
-        port: Pin<Box<P>>,
-        rx_buff: [u8; SLCAN_MTU],
-        rx_count: usize,
-        error: bool,
-    }
+        /// Switches the gateway to [`OperatingMode::Silent`] and returns a
+        /// socket which can only receive, so sending is ruled out when
+        /// compiling instead of being refused with [`SendError::ListenOnly`].
+        /// An open channel is closed while the mode is changed and reopened
+        /// afterwards.
+        pub fn into_silent(mut self) -> io::Result<SilentCanSocket<P>> {
+            let open = self.open;
 
-    #[cfg(target_family = "unix")]
-    impl<P: AsRawFd> AsRawFd for CanSocket<P> {
-        fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-            self.port.as_raw_fd()
+            if open {
+                self.close()?;
+            }
+            self.set_operating_mode(OperatingMode::Silent)?;
+            if open {
+                self.open_channel()?;
+            }
+
+            Ok(SilentCanSocket { socket: self })
         }
-    }
 
-    impl<P: AsyncRead + AsyncWrite> CanSocket<P> {
-        /// Constructs a new CanSocket from an async SerialStream
-        pub fn new(port: P) -> Self {
-            CanSocket {
-                port: Box::pin(port),
-                rx_buff: [0; SLCAN_MTU],
-                rx_count: 0,
-                error: false,
+        /// Closes the channel if it is open and returns a socket which only
+        /// offers the configuration methods, which take effect the next
+        /// time the channel is opened. Opening it returns an [OpenSocket],
+        /// which only offers reading and sending, so settings can't be
+        /// changed on an open channel where the gateway would ignore them.
+        pub fn into_closed(mut self) -> io::Result<ClosedSocket<P>> {
+            if self.open {
+                self.close()?;
             }
+
+            Ok(ClosedSocket { socket: self })
         }
 
-        /// Configures the device with the supplied bit timing and requests
-        /// the device to begin enable streaming of CAN frames
-        pub async fn open(&mut self, nominal_bitrate: NominalBitRate) -> io::Result<()> {
-            self.send_command(Command::SetNominalBitRate(nominal_bitrate))
-                .await?;
-            self.send_command(Command::Open).await?;
+        /// Sets whether the channel is closed when the socket is dropped
+        /// while it is open (enabled by default), so a program which panics
+        /// doesn't leave the gateway streaming frames. Closing on drop is best
+        /// effort, since errors can't be reported.
+        pub fn set_close_on_drop(&mut self, enabled: bool) {
+            self.close_on_drop = enabled;
+        }
 
-            Ok(())
+        /// Sets the variant of the SLCAN command set spoken by the gateway
+        /// (defaults to [`Dialect::Standard`]), e.g. [`Dialect::UsbTin`] for
+        /// USBtin adapters. Should be set before the channel is configured.
+        pub fn set_dialect(&mut self, dialect: Dialect) {
+            self.dialect = dialect;
         }
 
-        /// Sends a close command to the gateway which instructs it to stop
-        /// sending and receiving CAN frames
-        pub async fn close(&mut self) -> io::Result<()> {
-            self.send_command(Command::Close).await?;
+        /// Returns the variant of the SLCAN command set spoken by the gateway
+        pub fn dialect(&self) -> Dialect {
+            self.dialect
+        }
+
+        /// Sets the nominal bit rate, which takes effect the next time the
+        /// channel is opened. See [NominalBitRate].
+        pub fn set_nominal_bit_rate(&mut self, rate: NominalBitRate) -> io::Result<()> {
+            self.send_config_command(Command::SetNominalBitRate(rate))?;
+            self.config.nominal_bit_rate = Some(rate);
             Ok(())
         }
 
         /// Sets the data bit rate (CAN FD frames only). See [DataBitRate].
-        pub async fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()> {
-            self.send_command(Command::SetDataBitRate(rate)).await?;
+        pub fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()> {
+            self.check_dialect_supports("data bit rates")?;
+            self.send_config_command(Command::SetDataBitRate(rate))?;
+            self.config.data_bit_rate = Some(rate);
             Ok(())
         }
 
-        /// Sets the operating mode of the gateway, either `Normal` or `Silent`
-        /// (a.k.a. "Listen Only" mode). See [OperatingMode].
-        pub async fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()> {
-            self.send_command(Command::SetMode(mode)).await?;
+        /// Sets the operating mode of the gateway, e.g. `Silent` (a.k.a.
+        /// "Listen Only" mode). See [OperatingMode].
+        ///
+        /// With [`Dialect::UsbTin`] the mode is selected by the command which
+        /// opens the channel, so it takes effect the next time the channel is
+        /// opened.
+        pub fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()> {
+            if self.dialect == Dialect::Standard {
+                self.send_config_command(Command::SetMode(mode))?;
+            }
+            self.config.operating_mode = Some(mode);
             Ok(())
         }
 
         /// Sets the auto retransmission mode of the gateway, either `Enabled`
         /// or `Disabled`. See [AutoRetransmissionMode].
-        pub async fn set_auto_retransmission_mode(
+        pub fn set_auto_retransmission_mode(
             &mut self,
             mode: AutoRetransmissionMode,
         ) -> io::Result<()> {
-            self.send_command(Command::SetAutoRetransmission(mode))
-                .await?;
+            self.check_dialect_supports("auto retransmission")?;
+            self.send_config_command(Command::SetAutoRetransmission(mode))?;
+            self.config.auto_retransmission = Some(mode);
             Ok(())
         }
 
-        /// Sends a CAN frame to the gateway to be broadcasted on the bus.
+        /// Sets whether the gateway appends a timestamp to received frames,
+        /// and at which resolution. See [TimestampMode].
         ///
-        /// If the frame fails to be sent, it may be retransmitted according to
-        /// the current [AutoRetransmissionMode].
-        pub async fn send(&mut self, frame: impl Into<CanFrame>) -> io::Result<()> {
-            self.send_command(Command::TransmitFrame(frame.into()))
-                .await?;
+        /// The mode must match what the firmware actually sends, otherwise the
+        /// trailing digits will be interpreted as part of the frame (or vice
+        /// versa) and parsing will fail.
+        pub fn set_timestamp_mode(&mut self, mode: TimestampMode) -> io::Result<()> {
+            self.send_config_command(Command::SetTimestampMode(mode))?;
+            self.config.timestamp_mode = Some(mode);
+            self.timestamp_mode = mode;
             Ok(())
         }
 
-        /// Reads a line from the serial stream and attempts to parse it as a
-        /// valid CAN frame.
-        ///
-        /// # Errors
-        ///
-        /// An error will be returned for any kinds of I/O errors besides
-        /// WouldBlock or TimedOut.
-        ///
-        /// An error will also be returned if the received line cannot be
-        /// parsed as a valid CAN frame for any number of reasons. See
-        /// [MessageParseError](crate::MessageParseError).
+        /// Returns the hardware timestamp of the last frame returned by
+        /// [`read`](CanSocket::read), if timestamps are enabled. See
+        /// [`set_timestamp_mode`](CanSocket::set_timestamp_mode).
+        pub fn last_timestamp(&self) -> Option<Timestamp> {
+            self.last_timestamp
+        }
+
+        /// Sets the maximum length of a line received from the gateway
+        /// (excluding the CR), for firmwares which append extra fields to
+        /// frames. Longer lines are discarded and reported as
+        /// [`ReadError::LineTooLong`]. Defaults to 155 bytes, which fits a CAN
+        /// FD frame with 64 bytes of data and a timestamp.
         ///
-        /// # Cancel Safety
+        /// Any partially received line is discarded.
+        pub fn set_max_line_length(&mut self, length: usize) {
+            self.lines.set_max_line_length(length);
+        }
+
+        /// Returns the maximum length of a line received from the gateway.
+        /// See [`set_max_line_length`](CanSocket::set_max_line_length).
+        pub fn max_line_length(&self) -> usize {
+            self.lines.max_line_length()
+        }
+
+        /// Accepts LF and CRLF line endings in addition to CR (disabled by
+        /// default), for bridges and terminal programs which insert a LF.
+        /// Otherwise a stray LF becomes the first byte of the next line,
+        /// which then fails to parse.
+        pub fn set_lf_line_endings(&mut self, enabled: bool) {
+            self.lines.set_lf_line_endings(enabled);
+        }
+
+        /// Returns true if LF and CRLF line endings are accepted. See
+        /// [`set_lf_line_endings`](CanSocket::set_lf_line_endings).
+        pub fn lf_line_endings(&self) -> bool {
+            self.lines.lf_line_endings()
+        }
+
+        /// Sets the channel index reported in each [RxFrame], for telling
+        /// apart frames from multiple gateways (defaults to 0)
+        pub fn set_channel(&mut self, channel: usize) {
+            self.channel = channel;
+        }
+
+        /// Returns the channel index reported in each [RxFrame]
+        pub fn channel(&self) -> usize {
+            self.channel
+        }
+
+        /// Enables or disables the echo of transmitted frames.
+        ///
+        /// When enabled, every frame successfully handed to the gateway by
+        /// [`send`](CanSocket::send) is also returned by subsequent reads,
+        /// tagged with [`FrameDirection::Tx`] in [`read_rx_frame`](CanSocket::read_rx_frame).
+        /// This mirrors the loopback behavior of SocketCAN so that logs contain
+        /// both directions of the conversation.
+        pub fn set_tx_echo(&mut self, enabled: bool) {
+            self.tx_echo = enabled;
+
+            if !enabled {
+                self.rx_queue
+                    .retain(|frame| frame.direction != FrameDirection::Tx);
+                self.skipped
+                    .retain(|frame| frame.direction != FrameDirection::Tx);
+            }
+        }
+
+        /// Returns the last bus state reported by the gateway. See
+        /// [BusStatus].
+        pub fn bus_status(&self) -> BusStatus {
+            self.bus_status
+        }
+
+        /// Returns the number of received lines which were dropped because
+        /// they could not be parsed or were too long, and the number of
+        /// transmit acknowledgements. See [LinkStats].
+        pub fn stats(&self) -> &LinkStats {
+            &self.stats
+        }
+
+        /// Resets all counters returned by [`stats`](CanSocket::stats) to 0
+        pub fn reset_stats(&mut self) {
+            self.stats = LinkStats::default();
+        }
+
+        /// Returns the number of frames occupying the transmit queue of the
+        /// gateway, i.e. the frames sent to it which it hasn't acknowledged
+        /// (`z`/`Z`) or rejected yet. Sends are held back by this count with
+        /// [`TxQueuePolicy::Wait`]. A depth which keeps growing means the
+        /// gateway stopped accepting frames, e.g. because its transmit queue
+        /// is stuck while no other node acknowledges them.
+        pub fn tx_queue_len(&self) -> usize {
+            self.unacknowledged
+        }
+
+        /// Sets whether sends wait for room in the transmit queue of the
+        /// gateway (defaults to [`TxQueuePolicy::Unbounded`]). With
+        /// [`TxQueuePolicy::Wait`] a burst of frames is paced by the
+        /// acknowledgements of the gateway instead of overflowing its queue,
+        /// where the firmware would drop frames silently. Frames received
+        /// while waiting are buffered and returned by later reads.
+        pub fn set_tx_queue_policy(&mut self, policy: TxQueuePolicy) {
+            self.tx_queue_policy = policy;
+        }
+
+        /// Restricts the IDs which may be transmitted, as a safety net when
+        /// running test scripts against a live system. Frames whose ID the
+        /// filter doesn't match are refused with [`SendError::Blocked`]
+        /// without being written to the port. `None` (the default) permits
+        /// all IDs.
+        ///
+        /// An allowlist is a filter of the permitted IDs, a denylist one with
+        /// only exclusions, e.g. `"!7DF,!700-7FF".parse()`. The filter is
+        /// applied to the frames produced by the interceptors, so frames they
+        /// inject are checked as well.
+        pub fn set_tx_filter(&mut self, filter: Option<IdFilter>) {
+            self.tx_filter = filter;
+        }
+
+        /// Returns the filter restricting the IDs which may be transmitted
+        pub fn tx_filter(&self) -> Option<&IdFilter> {
+            self.tx_filter.as_ref()
+        }
+
+        /// Sets what happens when the gateway reports that the controller
+        /// went bus-off (defaults to [`BusOffPolicy::Report`]).
+        pub fn set_bus_off_policy(&mut self, policy: BusOffPolicy) {
+            self.bus_off_policy = policy;
+        }
+
+        /// Sets how configuration commands (bit rates, modes, opening and
+        /// closing the channel) are confirmed (defaults to
+        /// [`CommandRetryPolicy::Unconfirmed`]). With
+        /// [`CommandRetryPolicy::Retry`] a command which the gateway rejects or
+        /// doesn't confirm in time is sent again, since USB CDC adapters
+        /// occasionally drop the first command after enumeration.
+        ///
+        /// The port should be configured with a read timeout, otherwise a
+        /// gateway which never answers will block configuration commands
+        /// indefinitely.
+        pub fn set_command_retry_policy(&mut self, policy: CommandRetryPolicy) {
+            self.command_retry_policy = policy;
+        }
+
+        /// Sets the maximum time the gateway may stay silent before it is
+        /// queried for its firmware version (`V`) to check that it is still
+        /// alive. If the query isn't answered within the same time either,
+        /// reads fail with [`ReadError::DeviceUnresponsive`]. This catches
+        /// USB adapters which wedge while their port stays open. Disabled by
+        /// default.
+        ///
+        /// While a query is outstanding, a line starting with `V` or `v` is
+        /// taken as its answer. Other lines which can't be parsed still fail
+        /// the read like at any other time.
+        ///
+        /// The gateway's silence is only checked while reading, so reads
+        /// must be retried regularly (e.g. after the port's timeout).
+        pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+            self.keepalive = interval;
+            self.keepalive_sent = None;
+            self.last_received = Instant::now();
+        }
+
+        /// Registers a callback which is invoked with every bus state report
+        /// received from the gateway, including bus-off, before the
+        /// [BusOffPolicy] is applied
+        pub fn set_bus_status_callback(
+            &mut self,
+            callback: impl FnMut(&BusStatus) + Send + 'static,
+        ) {
+            self.on_bus_status = Some(Box::new(callback));
+        }
+
+        /// Registers a callback which is invoked with every line received
+        /// from the gateway (without the CR line ending), before it is parsed.
+        /// Together with [`set_raw_tx_callback`](CanSocket::set_raw_tx_callback)
+        /// this allows building a terminal-style view of the protocol.
+        ///
+        /// Lines which exceed the maximum length are discarded without being
+        /// passed to the callback. A NACK is passed as the BEL character
+        /// (0x07) on its own.
+        pub fn set_raw_rx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+            self.on_raw_rx = Some(Box::new(callback));
+        }
+
+        /// Registers a callback which is invoked with every line sent to the
+        /// gateway (without the CR line ending) once it was written
+        pub fn set_raw_tx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+            self.on_raw_tx = Some(Box::new(callback));
+        }
+
+        /// Adds an [Interceptor] which is run on every frame sent and
+        /// received through this socket, after the interceptors added before
+        /// it.
+        ///
+        /// Frames are recorded and counted as they are on the wire, i.e.
+        /// received frames before and transmitted frames after interception.
+        /// Delays are waited out within [`send`](CanSocket::send) and
+        /// [`read`](CanSocket::read), blocking them for that long.
+        pub fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static) {
+            self.interceptors.push(Box::new(interceptor));
+        }
+
+        /// Removes all interceptors. Frames which were already intercepted
+        /// are still delivered.
+        pub fn clear_interceptors(&mut self) {
+            self.interceptors.clear();
+        }
+
+        /// Adds a [BusObserver] which is notified of the traffic and events of
+        /// this socket, after the observers added before it
+        pub fn add_observer(&mut self, observer: impl BusObserver + 'static) {
+            self.observers.push(Box::new(observer));
+        }
+
+        /// Removes all observers
+        pub fn clear_observers(&mut self) {
+            self.observers.clear();
+        }
+
+        /// Attaches a [RemoteResponder] which answers remote frames received
+        /// through [`read`](CanSocket::read) and the methods built on it.
+        /// Returns the previously attached responder.
+        pub fn set_remote_responder(
+            &mut self,
+            responder: Option<RemoteResponder>,
+        ) -> Option<RemoteResponder> {
+            std::mem::replace(&mut self.remote_responder, responder)
+        }
+
+        /// Returns the attached [RemoteResponder], if any
+        pub fn remote_responder_mut(&mut self) -> Option<&mut RemoteResponder> {
+            self.remote_responder.as_mut()
+        }
+
+        /// Attaches a [Recorder] which is fed every frame received from the
+        /// bus and every transmitted frame (unless disabled with
+        /// [`Recorder::with_tx`]), tagged with its [FrameDirection]. Returns
+        /// the previously attached recorder, which can then be closed cleanly.
+        #[cfg(feature = "logging")]
+        pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+            std::mem::replace(&mut self.recorder, recorder)
+        }
+
+        /// Returns the attached [Recorder], if any
+        #[cfg(feature = "logging")]
+        pub fn recorder_mut(&mut self) -> Option<&mut Recorder> {
+            self.recorder.as_mut()
+        }
+
+        /// Sends a CAN frame to the gateway to be broadcasted on the bus.
+        ///
+        /// If the frame fails to be sent, it may be retransmitted according to
+        /// the current [AutoRetransmissionMode].
+        ///
+        /// # Errors
+        ///
+        /// Returns [`SendError::ChannelClosed`] if the channel isn't open and
+        /// [`SendError::ListenOnly`] if the gateway is in silent mode, without
+        /// writing anything to the port. I/O errors are returned as
+        /// [`SendError::Io`].
+        pub fn send(&mut self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+            self.check_can_send()?;
+
+            let frame = frame.into();
+            if self.interceptors.is_empty() {
+                return self.transmit(frame);
+            }
+
+            let start = Instant::now();
+            for (frame, delay) in self.interceptors.apply(frame, FrameDirection::Tx) {
+                std::thread::sleep((start + delay).saturating_duration_since(Instant::now()));
+                self.transmit(frame)?;
+            }
+
+            Ok(())
+        }
+
+        /// Sends a CAN 2.0 data frame with the given ID and data, see
+        /// [`send`](CanSocket::send). IDs up to 0x7FF are sent as standard
+        /// IDs and larger ones as extended IDs.
+        ///
+        /// Returns [`SendError::InvalidId`] if the ID is above 0x1FFFFFFF and
+        /// [`SendError::InvalidLength`] if there are more than 8 bytes of data.
+        pub fn send_data(&mut self, id: u32, data: &[u8]) -> Result<(), SendError> {
+            let frame = Can2Frame::new_data(id_from_raw(id)?, data)
+                .ok_or(SendError::InvalidLength(data.len()))?;
+            self.send(frame)
+        }
+
+        /// Sends a CAN 2.0 remote frame with the given ID and DLC, see
+        /// [`send_data`](CanSocket::send_data)
+        pub fn send_remote(&mut self, id: u32, dlc: usize) -> Result<(), SendError> {
+            let frame = Can2Frame::new_remote(id_from_raw(id)?, dlc)
+                .ok_or(SendError::InvalidLength(dlc))?;
+            self.send(frame)
+        }
+
+        /// Sends a CAN FD frame with bit rate switching, see
+        /// [`send_data`](CanSocket::send_data). The data is padded with zeros
+        /// to the next valid CAN FD length, and may be up to 64 bytes long.
+        pub fn send_fd(&mut self, id: u32, data: &[u8]) -> Result<(), SendError> {
+            let frame = CanFdFrame::new_padded(id_from_raw(id)?, data)
+                .ok_or(SendError::InvalidLength(data.len()))?;
+            self.send(frame)
+        }
+
+        /// Reads the answers of the gateway until there is room in its
+        /// transmit queue, according to the [TxQueuePolicy]
+        fn wait_for_tx_queue(&mut self) -> Result<(), SendError> {
+            let TxQueuePolicy::Wait { capacity, timeout } = self.tx_queue_policy else {
+                return Ok(());
+            };
+
+            let deadline = Instant::now() + timeout;
+            while self.unacknowledged >= capacity.max(1) {
+                if Instant::now() >= deadline {
+                    return Err(SendError::TimedOut);
+                }
+
+                match self.read_message() {
+                    Ok(Message::Frame(frame, timestamp)) => self.queue_received(frame, timestamp),
+                    Ok(_) => {}
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(_) => {}
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Writes a frame to the gateway, bypassing the interceptors
+        fn transmit(&mut self, frame: CanFrame) -> Result<(), SendError> {
+            let slcan_frame =
+                SlcanFrame::try_from(frame.clone()).map_err(|_| SendError::Unsupported)?;
+
+            if matches!(frame, CanFrame::CanFd(_)) && self.dialect == Dialect::UsbTin {
+                return Err(SendError::Unsupported);
+            }
+
+            if let Some(filter) = self.tx_filter.as_ref() {
+                if !filter.matches(frame.id()) {
+                    return Err(SendError::Blocked(frame.id()));
+                }
+            }
+
+            self.wait_for_tx_queue()?;
+            self.send_command(Command::TransmitFrame(slcan_frame))?;
+            metrics::record_frame_transmitted(&frame);
+            self.observers.tx_frame(&frame);
+            self.unacknowledged += 1;
+            metrics::record_tx_queue_depth(self.unacknowledged);
+            self.transmitted += 1;
+
+            #[cfg(feature = "logging")]
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record_frame(&frame, FrameDirection::Tx, self.channel);
+            }
+
+            if self.tx_echo {
+                self.rx_queue.push_back(RxFrame {
+                    frame,
+                    timestamp: None,
+                    direction: FrameDirection::Tx,
+                    channel: self.channel,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Sends a CAN frame and waits for the gateway to acknowledge that it
+        /// was queued for transmission, which distinguishes frames that were
+        /// accepted from ones silently dropped by the firmware.
+        ///
+        /// Frames received while waiting are buffered and returned by later
+        /// reads. Acknowledgements still outstanding for frames sent earlier
+        /// with [`send`](CanSocket::send) are accounted for, so the returned
+        /// status always refers to this frame.
+        /// If an [Interceptor] drops the frame, `TimedOut` is returned right
+        /// away. If it replaces the frame, the status of the last replacement
+        /// is returned.
+        ///
+        /// The port should be configured with a read timeout, otherwise a
+        /// gateway which never responds will block this call indefinitely.
+        pub fn send_and_confirm(
+            &mut self,
+            frame: impl Into<CanFrame>,
+            timeout: Duration,
+        ) -> Result<TransmitStatus, SendError> {
+            let deadline = Instant::now() + timeout;
+            let transmitted = self.transmitted;
+
+            self.send(frame)?;
+
+            // Interceptors may have dropped the frame or replaced it with
+            // several, in which case the status of the last one is returned.
+            // Earlier frames may have been acknowledged while waiting for
+            // room in the transmit queue, so only the ones still in flight
+            // are ahead of it.
+            if self.transmitted == transmitted {
+                return Ok(TransmitStatus::TimedOut);
+            }
+            let mut ahead = self.unacknowledged - 1;
+
+            while Instant::now() < deadline {
+                let status = match self.read_message() {
+                    Ok(Message::Frame(frame, timestamp)) => {
+                        self.queue_received(frame, timestamp);
+                        continue;
+                    }
+                    Ok(Message::TransmitAck) => TransmitStatus::Acknowledged,
+                    Ok(Message::Nack) => TransmitStatus::Rejected,
+                    Ok(Message::BusStatus(_) | Message::Version(_) | Message::ErrorRegister(_)) => {
+                        continue
+                    }
+                    Err(e) if e.is_would_block() => {
+                        wait_for_data(&e, deadline);
+                        continue;
+                    }
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(
+                        ReadError::Slcan(_)
+                        | ReadError::BusOff
+                        | ReadError::LineTooLong(_)
+                        | ReadError::DeviceUnresponsive,
+                    ) => continue,
+                };
+
+                if ahead == 0 {
+                    return Ok(status);
+                }
+
+                ahead -= 1;
+            }
+
+            Ok(TransmitStatus::TimedOut)
+        }
+
+        /// Sends a request frame and waits for the first received frame for
+        /// which `matches` returns true, e.g. one with the expected response
+        /// ID. Returns `None` if no matching frame arrives within `timeout`.
+        ///
+        /// Frames skipped by earlier requests are considered first. Other
+        /// frames received while waiting are buffered and returned by later
+        /// reads, so no traffic is lost. All frames pass the interceptors and
+        /// the [RemoteResponder] like with [`read`](CanSocket::read).
+        ///
+        /// The port should be configured with a read timeout, otherwise this
+        /// call may block past the timeout.
+        pub fn request(
+            &mut self,
+            frame: impl Into<CanFrame>,
+            mut matches: impl FnMut(&CanFrame) -> bool,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, SendError> {
+            let deadline = Instant::now() + timeout;
+
+            self.send(frame)?;
+
+            if let Some(frame) = self.take_skipped(&mut matches) {
+                return Ok(Some(frame));
+            }
+
+            while Instant::now() < deadline {
+                match self.read_next_rx_frame() {
+                    Ok(frame) if matches(&frame.frame) => return Ok(Some(frame.frame)),
+                    Ok(frame) => self.skipped.push_back(frame),
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(
+                        ReadError::Slcan(_)
+                        | ReadError::BusOff
+                        | ReadError::LineTooLong(_)
+                        | ReadError::DeviceUnresponsive,
+                    ) => {}
+                }
+            }
+
+            Ok(None)
+        }
+
+        /// Probes which features the gateway supports, so generic tools can
+        /// adapt to whatever adapter is plugged in. The firmware version is
+        /// queried and the result is refined with trial commands, waiting up
+        /// to `timeout` for the answer to each.
+        ///
+        /// Every [DataBitRate] is tried in turn. The trial commands then
+        /// reapply the data bit rate, timestamp mode and auto retransmission
+        /// mode configured so far (or the defaults), so this should be called
+        /// while the channel is closed. Frames received
+        /// while probing are buffered and returned by later reads.
+        pub fn capabilities(&mut self, timeout: Duration) -> Result<Capabilities, ReadError> {
+            let (version, _) = self.probe(None, true, timeout)?;
+            let query_version = version.is_some();
+            let mut capabilities = Capabilities::from_version(version.as_deref());
+
+            // The configured rate is probed last so that it stays applied
+            let data_bit_rate = self.config.data_bit_rate.unwrap_or_default();
+            let rates = DataBitRate::ALL
+                .into_iter()
+                .filter(|&rate| rate != data_bit_rate)
+                .chain([data_bit_rate]);
+            for rate in rates {
+                let command = Command::SetDataBitRate(rate);
+                if self.probe(Some(command), query_version, timeout)?.1 == 0 {
+                    capabilities.data_bit_rates.push(rate);
+                }
+            }
+            capabilities.data_bit_rates.sort();
+            capabilities.brs = !capabilities.data_bit_rates.is_empty();
+            capabilities.fd |= capabilities.brs;
+
+            let command = Command::SetTimestampMode(self.timestamp_mode);
+            capabilities.timestamps = self.probe(Some(command), query_version, timeout)?.1 == 0;
+
+            let mode = self.config.auto_retransmission.unwrap_or_default();
+            let command = Command::SetAutoRetransmission(mode);
+            capabilities.auto_retransmission =
+                self.probe(Some(command), query_version, timeout)?.1 == 0;
+
+            Ok(capabilities)
+        }
+
+        /// Cycles through the nominal bit rates in silent mode until frames
+        /// are received, and returns the bit rate they were received at, e.g.
+        /// for plugging into a bus whose bit rate is unknown. Each rate is
+        /// listened to for up to `dwell`, or until the gateway reports bus
+        /// errors, either on its own or in answer to an error register query
+        /// (`E`) sent halfway through. Returns `None` if no frames were
+        /// received at any rate, e.g. because the bus is idle.
+        ///
+        /// The channel is left closed with the detected bit rate set, so it
+        /// can be opened with [`open_channel`](CanSocket::open_channel). The
+        /// operating mode configured before is restored, as is the bit rate if
+        /// none was detected. The first frame
+        /// received at the detected rate is buffered and returned by a later
+        /// read.
+        ///
+        /// The port should be configured with a read timeout, otherwise this
+        /// call may block past the dwell time.
+        pub fn autodetect_bit_rate(
+            &mut self,
+            dwell: Duration,
+        ) -> Result<Option<NominalBitRate>, ReadError> {
+            if self.open {
+                self.close()?;
+            }
+
+            let operating_mode = self.config.operating_mode;
+            let nominal_bit_rate = self.config.nominal_bit_rate;
+            self.set_operating_mode(OperatingMode::Silent)?;
+
+            let mut detected = None;
+            for rate in NominalBitRate::ALL {
+                self.set_nominal_bit_rate(rate)?;
+                self.open_channel()?;
+                let received = self.listen(dwell)?;
+                self.close()?;
+
+                if received {
+                    detected = Some(rate);
+                    break;
+                }
+            }
+
+            self.set_operating_mode(operating_mode.unwrap_or_default())?;
+            self.config.operating_mode = operating_mode;
+            if detected.is_none() {
+                match nominal_bit_rate {
+                    Some(rate) => self.set_nominal_bit_rate(rate)?,
+                    None => self.config.nominal_bit_rate = None,
+                }
+            }
+            self.bus_status = BusStatus::default();
+
+            Ok(detected)
+        }
+
+        /// Samples the traffic and the bus state reports of the gateway for
+        /// `window` and returns a [BusDiagnosis] with the bus load, the trend
+        /// of the error counters and the fault the bus is suspected to have,
+        /// e.g. for troubleshooting an installation in the field.
+        ///
+        /// The channel should be open. Frames received while sampling are
+        /// consumed by the diagnosis and not returned by reads. The bus load
+        /// is only estimated if the nominal bit rate was set through the
+        /// socket.
+        ///
+        /// The port should be configured with a read timeout, otherwise this
+        /// call may block past the window.
+        pub fn diagnose(&mut self, window: Duration) -> Result<BusDiagnosis, ReadError> {
+            let mut sampler = DiagnosisSampler::new(
+                self.bus_status,
+                self.config.nominal_bit_rate,
+                self.config.data_bit_rate.unwrap_or_default(),
+            );
+            let deadline = Instant::now() + window;
+
+            while Instant::now() < deadline {
+                match self.read_message() {
+                    Ok(Message::Frame(frame, _)) => sampler.record_frame(&frame),
+                    Ok(Message::BusStatus(status)) => sampler.record_status(status),
+                    Ok(_) => {}
+                    Err(ReadError::Slcan(_)) => sampler.record_parse_error(),
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(_) => {}
+                }
+            }
+
+            Ok(sampler.finish(window))
+        }
+
+        /// Reads the next line exactly as the gateway sent it, without the CR
+        /// line ending and without parsing it, e.g. for showing the traffic
+        /// in a protocol debugging tool even when it can't be parsed.
+        ///
+        /// Lines read this way bypass the typed API: frames aren't recorded or
+        /// answered, acknowledgements aren't counted and bus state reports
+        /// aren't tracked. Frames already buffered for [`read`](CanSocket::read)
+        /// aren't returned. A NACK is returned as a line containing only the
+        /// BEL character (0x07).
+        pub fn read_raw_line(&mut self) -> Result<Vec<u8>, ReadError> {
+            self.read_line()?;
+            Ok(self.line.clone())
+        }
+
+        /// Sends a line to the gateway exactly as given, with a CR line ending
+        /// appended, e.g. for issuing commands this crate has no typed API
+        /// for. The line is written in a single write.
+        pub fn send_raw_line(&mut self, line: &[u8]) -> io::Result<()> {
+            let mut buffer = Vec::with_capacity(line.len() + 1);
+            buffer.extend_from_slice(line);
+            buffer.push(b'\r');
+
+            self.port_mut().write_all(&buffer)?;
+            self.port_mut().flush()?;
+
+            if let Some(callback) = self.on_raw_tx.as_mut() {
+                callback(line);
+            }
+
+            Ok(())
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway identify itself, e.g. by blinking its LED, for firmwares
+        /// which have such a command. SLCAN doesn't define one, so it has to
+        /// be taken from the documentation of the firmware. See
+        /// [`identify`](CanSocket::identify).
+        pub fn set_identify_command(&mut self, command: Option<&[u8]>) {
+            self.identify_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Makes the gateway identify itself with the command set with
+        /// [`set_identify_command`](CanSocket::set_identify_command), e.g. for
+        /// finding out which adapter of a rig is connected to which port.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// identify command was set.
+        pub fn identify(&mut self) -> io::Result<()> {
+            let Some(command) = self.identify_command.clone() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no identify command was set for this gateway",
+                ));
+            };
+
+            self.send_raw_line(&command)
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway report the state of the bus along with its error counters
+        /// (TEC/REC), for firmwares which have such a command. It has to be
+        /// answered with a bus state report like `sa005000`. SLCAN doesn't
+        /// define one, so it has to be taken from the documentation of the
+        /// firmware. See [`get_error_counters`](CanSocket::get_error_counters).
+        pub fn set_error_counters_command(&mut self, command: Option<&[u8]>) {
+            self.error_counters_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Queries the error counters of the CAN controller with the command
+        /// set with
+        /// [`set_error_counters_command`](CanSocket::set_error_counters_command)
+        /// and waits up to `timeout` for the answer. Returns `None` if the
+        /// gateway didn't answer in time.
+        ///
+        /// The answer is handled like any other bus state report, so it also
+        /// updates [`bus_status`](CanSocket::bus_status) and is passed to the
+        /// observers and the bus status callback. See
+        /// [`BusStatus::derived_state`] for the state implied by the counters.
+        /// Frames received while waiting are buffered and returned by later
+        /// reads.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no error
+        /// counters command was set or the gateway rejected it.
+        pub fn get_error_counters(
+            &mut self,
+            timeout: Duration,
+        ) -> Result<Option<BusStatus>, ReadError> {
+            let command = self.error_counters_command()?;
+            self.send_raw_line(&command)?;
+
+            let deadline = Instant::now() + timeout;
+
+            while Instant::now() < deadline {
+                match self.read_message() {
+                    Ok(Message::Frame(frame, timestamp)) => self.queue_received(frame, timestamp),
+                    Ok(Message::BusStatus(status)) => return Ok(Some(status)),
+                    Ok(Message::Nack) => return Err(error_counters_rejected().into()),
+                    Ok(Message::TransmitAck | Message::Version(_) | Message::ErrorRegister(_)) => {}
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(
+                        ReadError::Slcan(_)
+                        | ReadError::BusOff
+                        | ReadError::LineTooLong(_)
+                        | ReadError::DeviceUnresponsive,
+                    ) => {}
+                }
+            }
+
+            Ok(None)
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway reboot into its bootloader (DFU) for a firmware update, for
+        /// firmwares which have such a command. SLCAN doesn't define one, so
+        /// it has to be taken from the documentation of the firmware. See
+        /// [`enter_bootloader`](CanSocket::enter_bootloader).
+        pub fn set_bootloader_command(&mut self, command: Option<&[u8]>) {
+            self.bootloader_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Makes the gateway reboot into its bootloader with the command set
+        /// with [`set_bootloader_command`](CanSocket::set_bootloader_command),
+        /// so its firmware can be updated.
+        ///
+        /// The gateway disconnects while rebooting, so the socket can't be
+        /// used afterwards. The channel is considered closed, so dropping the
+        /// socket doesn't send a close command.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// bootloader command was set.
+        pub fn enter_bootloader(&mut self) -> io::Result<()> {
+            let Some(command) = self.bootloader_command.clone() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no bootloader command was set for this gateway",
+                ));
+            };
+
+            self.send_raw_line(&command)?;
+            self.open = false;
+            self.observers.channel_state(false);
+            Ok(())
+        }
+
+        /// Sets the commands (without the CR line ending) which switch the
+        /// 120Ω termination resistor of the gateway on and off, for adapters
+        /// with switchable termination. SLCAN doesn't define such commands, so
+        /// they have to be taken from the documentation of the firmware. See
+        /// [`set_termination`](CanSocket::set_termination).
+        pub fn set_termination_commands(&mut self, commands: Option<(&[u8], &[u8])>) {
+            self.termination_commands = commands.map(|(on, off)| (on.to_vec(), off.to_vec()));
+        }
+
+        /// Switches the termination resistor of the gateway on or off with the
+        /// commands set with
+        /// [`set_termination_commands`](CanSocket::set_termination_commands),
+        /// so bench setups can be reconfigured without touching the hardware.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// termination commands were set.
+        pub fn set_termination(&mut self, enabled: bool) -> io::Result<()> {
+            let command = match &self.termination_commands {
+                Some((on, _)) if enabled => on.clone(),
+                Some((_, off)) => off.clone(),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "no termination commands were set for this gateway",
+                    ))
+                }
+            };
+
+            self.send_raw_line(&command)
+        }
+
+        /// Sets the commands (without the CR line ending) which select the
+        /// ISO and the non-ISO CAN FD CRC, for firmwares which support both.
+        /// SLCAN doesn't define such commands, so they have to be taken from
+        /// the documentation of the firmware. See
+        /// [`set_fd_crc_mode`](CanSocket::set_fd_crc_mode).
+        pub fn set_fd_crc_commands(&mut self, commands: Option<(&[u8], &[u8])>) {
+            self.fd_crc_commands = commands.map(|(iso, non_iso)| (iso.to_vec(), non_iso.to_vec()));
+        }
+
+        /// Selects the CRC of CAN FD frames with the commands set with
+        /// [`set_fd_crc_commands`](CanSocket::set_fd_crc_commands). Like the
+        /// bit rates, this should be set while the channel is closed. The mode
+        /// is applied again when the channel is reopened.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no FD
+        /// CRC commands were set.
+        pub fn set_fd_crc_mode(&mut self, mode: FdCrcMode) -> io::Result<()> {
+            let command = match (&self.fd_crc_commands, mode) {
+                (Some((iso, _)), FdCrcMode::Iso) => iso.clone(),
+                (Some((_, non_iso)), FdCrcMode::NonIso) => non_iso.clone(),
+                (None, _) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "no FD CRC commands were set for this gateway",
+                    ))
+                }
+            };
+
+            self.send_raw_line(&command)?;
+            self.config.fd_crc_mode = Some(mode);
+            Ok(())
+        }
+
+        /// Declares that the gateway has `count` hardware filter banks and
+        /// how to program them: `encode` returns the command (without the CR
+        /// line ending) which sets the bank with the given index to a filter,
+        /// or clears it for `None`. SLCAN doesn't define filter commands, so
+        /// they have to be taken from the documentation of the firmware. Some
+        /// firmwares only accept them while the channel is closed.
+        ///
+        /// All banks are programmed right away for the current filters. See
+        /// [`add_filter`](CanSocket::add_filter).
+        pub fn set_filter_banks(
+            &mut self,
+            count: usize,
+            encode: impl Fn(usize, Option<&AcceptanceFilter>) -> Vec<u8> + Send + 'static,
+        ) -> io::Result<()> {
+            self.acceptance.set_banks(count, Some(Box::new(encode)));
+            self.program_filter_banks(true)
+        }
+
+        /// Adds an acceptance filter. Once there are any filters, only frames
+        /// passing at least one of them are received.
+        ///
+        /// The filters are programmed into the filter banks of the gateway
+        /// (see [`set_filter_banks`](CanSocket::set_filter_banks)) to take load
+        /// off the serial link. If there are more filters than banks, filters
+        /// are merged into wider ones which pass a superset of the frames. All
+        /// frames are filtered in software as well, so the result is exact
+        /// either way.
+        pub fn add_filter(&mut self, filter: AcceptanceFilter) -> io::Result<()> {
+            self.acceptance.push(filter);
+            self.program_filter_banks(false)
+        }
+
+        /// Removes an acceptance filter added with
+        /// [`add_filter`](CanSocket::add_filter). Returns false if there was no
+        /// such filter.
+        pub fn remove_filter(&mut self, filter: &AcceptanceFilter) -> io::Result<bool> {
+            if !self.acceptance.remove(filter) {
+                return Ok(false);
+            }
+
+            self.program_filter_banks(false)?;
+            Ok(true)
+        }
+
+        /// Removes all acceptance filters, so all frames are received again
+        pub fn clear_filters(&mut self) -> io::Result<()> {
+            self.acceptance.clear();
+            self.program_filter_banks(false)
+        }
+
+        /// Returns the acceptance filters added with
+        /// [`add_filter`](CanSocket::add_filter)
+        pub fn filters(&self) -> &[AcceptanceFilter] {
+            self.acceptance.filters()
+        }
+
+        /// Returns the filter programmed into each filter bank of the gateway,
+        /// or `None` for empty banks
+        pub fn filter_banks(&self) -> &[Option<AcceptanceFilter>] {
+            self.acceptance.banks()
+        }
+
+        /// Reads a line from the serial stream and attempts to parse it as a
+        /// valid CAN frame.
+        ///
+        /// # Errors
+        ///
+        /// An error will be returned if the operation would block or timed
+        /// out. In this case it is safe to call `read` again until a message
+        /// is received: bytes of a partially received line are buffered
+        /// internally and are never lost. See [`ReadError::is_would_block`].
+        ///
+        /// An error will also be returned for any other kinds of I/O errors.
+        ///
+        /// Finally, an error will be returned if the received line cannot be
+        /// parsed as a valid CAN frame for any number of reasons. See
+        /// [MessageParseError](crate::MessageParseError).
+        pub fn read(&mut self) -> Result<CanFrame, ReadError> {
+            Ok(self.read_rx_frame()?.frame)
+        }
+
+        /// Reads frames until one with any of the given IDs is received,
+        /// discarding all others. See [`read`](CanSocket::read).
+        pub fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError> {
+            self.read_matching(|frame| ids.contains(&frame.id()))
+        }
+
+        /// Reads frames until one for which `matches` returns true is
+        /// received, discarding all others. See [`read`](CanSocket::read).
+        ///
+        /// Errors are returned as soon as they occur, so in non-blocking mode
+        /// (or when the port times out) this returns before a matching frame
+        /// arrives and has to be called again.
+        pub fn read_matching(
+            &mut self,
+            mut matches: impl FnMut(&CanFrame) -> bool,
+        ) -> Result<CanFrame, ReadError> {
+            loop {
+                let frame = self.read()?;
+
+                if matches(&frame) {
+                    return Ok(frame);
+                }
+            }
+        }
+
+        /// Waits for the next frame with the given ID, discarding all others.
+        /// Returns `None` if no such frame arrives within `timeout`.
+        ///
+        /// The port should be configured with a read timeout, otherwise this
+        /// call may block past the timeout.
+        pub fn await_frame(
+            &mut self,
+            id: impl Into<Id>,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, ReadError> {
+            let id = id.into();
+            self.await_matching(|frame| frame.id() == id, timeout)
+        }
+
+        /// Waits for the next frame for which `matches` returns true,
+        /// discarding all others. Returns `None` if no such frame arrives
+        /// within `timeout`.
+        ///
+        /// The port should be configured with a read timeout, otherwise this
+        /// call may block past the timeout.
+        pub fn await_matching(
+            &mut self,
+            mut matches: impl FnMut(&CanFrame) -> bool,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, ReadError> {
+            let deadline = Instant::now() + timeout;
+
+            while Instant::now() < deadline {
+                match self.read() {
+                    Ok(frame) if matches(&frame) => return Ok(Some(frame)),
+                    Ok(_) => {}
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(None)
+        }
+
+        /// Same as [`read`](CanSocket::read) but also returns the metadata
+        /// of the frame, such as its hardware timestamp. See [RxFrame].
+        pub fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError> {
+            if let Some(frame) = self.skipped.pop_front() {
+                self.last_timestamp = frame.timestamp;
+                return Ok(frame);
+            }
+
+            self.read_next_rx_frame()
+        }
+
+        /// Removes and returns the first frame skipped by an earlier
+        /// [`request`](CanSocket::request) for which `matches` returns true
+        fn take_skipped(&mut self, mut matches: impl FnMut(&CanFrame) -> bool) -> Option<CanFrame> {
+            let index = self
+                .skipped
+                .iter()
+                .position(|frame| matches(&frame.frame))?;
+            let frame = self.skipped.remove(index)?;
+
+            self.last_timestamp = frame.timestamp;
+            Some(frame.frame)
+        }
+
+        /// Reads the next frame which wasn't skipped by a
+        /// [`request`](CanSocket::request), applying the interceptors
+        fn read_next_rx_frame(&mut self) -> Result<RxFrame, ReadError> {
+            loop {
+                if let Some(&(_, due)) = self.intercepted.front() {
+                    std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                    let (frame, _) = self.intercepted.pop_front().expect("checked above");
+                    return self.deliver(frame);
+                }
+
+                let frame = match self.rx_queue.pop_front() {
+                    Some(frame) => frame,
+                    None => self.read_port_frame()?,
+                };
+
+                if frame.direction == FrameDirection::Rx && !self.interceptors.is_empty() {
+                    let now = Instant::now();
+                    let frames = self
+                        .interceptors
+                        .apply(frame.frame.clone(), FrameDirection::Rx);
+
+                    for (intercepted, delay) in frames {
+                        let intercepted = RxFrame {
+                            frame: intercepted,
+                            ..frame.clone()
+                        };
+                        self.intercepted.push_back((intercepted, now + delay));
+                    }
+
+                    continue;
+                }
+
+                return self.deliver(frame);
+            }
+        }
+
+        /// Hands a frame to the caller of [`read_rx_frame`](CanSocket::read_rx_frame)
+        fn deliver(&mut self, frame: RxFrame) -> Result<RxFrame, ReadError> {
+            self.last_timestamp = frame.timestamp;
+            self.answer_remote_request(&frame)?;
+            Ok(frame)
+        }
+
+        /// Reads messages from the gateway until a frame is received
+        fn read_port_frame(&mut self) -> Result<RxFrame, ReadError> {
+            loop {
+                match self.read_message()? {
+                    Message::Frame(frame, timestamp) => {
+                        return Ok(RxFrame {
+                            frame,
+                            timestamp,
+                            direction: FrameDirection::Rx,
+                            channel: self.channel,
+                        });
+                    }
+                    Message::BusStatus(status) if status.state == BusState::BusOff => {
+                        self.handle_bus_off()?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        /// Transmits the response registered with the [RemoteResponder] if
+        /// `frame` is a received remote request
+        fn answer_remote_request(&mut self, frame: &RxFrame) -> io::Result<()> {
+            let response = match (&frame.frame, self.remote_responder.as_mut()) {
+                (CanFrame::Can2(request), Some(responder))
+                    if frame.direction == FrameDirection::Rx =>
+                {
+                    responder.respond(request)
+                }
+                _ => None,
+            };
+
+            let result = match response {
+                Some(response) => self.send(response),
+                None => Ok(()),
+            };
+
+            // Requests can't be answered while the channel is closed or
+            // silent, which isn't an error for the read
+            match result {
+                Err(SendError::Io(e)) => Err(e),
+                _ => Ok(()),
+            }
+        }
+
+        /// Sends `command` (if any) followed by a version query (if
+        /// `query_version` is set) and reads the answers until the query is
+        /// answered or `timeout` elapses. Returns the answer to the query
+        /// along with the number of NACKs received before it, since the
+        /// gateway answers commands in order.
+        fn probe(
+            &mut self,
+            command: Option<Command>,
+            query_version: bool,
+            timeout: Duration,
+        ) -> Result<(Option<Vec<u8>>, usize), ReadError> {
+            let deadline = Instant::now() + timeout;
+
+            if let Some(command) = command {
+                self.send_command(command)?;
+            }
+            if query_version {
+                self.send_command(Command::GetFirmwareVersion)?;
+                self.version_pending = true;
+            }
+
+            let mut nacks = 0;
+
+            while Instant::now() < deadline {
+                match self.read_message() {
+                    Ok(Message::Version(answer)) if query_version => {
+                        return Ok((Some(answer), nacks));
+                    }
+                    Ok(Message::Nack) => nacks += 1,
+                    Ok(Message::Frame(frame, timestamp)) => self.queue_received(frame, timestamp),
+                    Ok(_) => {}
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(_) => {}
+                }
+            }
+
+            self.version_pending = false;
+            Ok((None, nacks))
+        }
+
+        /// Reads from the open channel for up to `dwell`. Returns true once a
+        /// frame is received, or false if the gateway reports bus errors.
+        /// Halfway through, the error register is queried for firmwares which
+        /// don't report the bus state on their own.
+        fn listen(&mut self, dwell: Duration) -> Result<bool, ReadError> {
+            let halfway = Instant::now() + dwell / 2;
+            if let Some(received) = self.watch_bus(halfway)? {
+                return Ok(received);
+            }
+
+            self.send_command(Command::GetErrorRegister)?;
+            Ok(self
+                .watch_bus(halfway + (dwell - dwell / 2))?
+                .unwrap_or(false))
+        }
+
+        /// Reads until `deadline`. Returns true once a frame is received,
+        /// false once the gateway reports bus errors, or `None` if neither
+        /// happened.
+        fn watch_bus(&mut self, deadline: Instant) -> Result<Option<bool>, ReadError> {
+            while Instant::now() < deadline {
+                match self.read_message() {
+                    Ok(Message::Frame(frame, timestamp)) => {
+                        self.queue_received(frame, timestamp);
+                        return Ok(Some(true));
+                    }
+                    Ok(Message::BusStatus(status)) if status.has_errors() => {
+                        return Ok(Some(false))
+                    }
+                    Ok(Message::ErrorRegister(flags)) if flags != 0 => return Ok(Some(false)),
+                    Ok(_) => {}
+                    Err(e) if e.is_would_block() => wait_for_data(&e, deadline),
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                    Err(_) => {}
+                }
+            }
+
+            Ok(None)
+        }
+
+        /// Applies the [BusOffPolicy] after the gateway reported bus-off
+        fn handle_bus_off(&mut self) -> Result<(), ReadError> {
+            let BusOffPolicy::Recover { delay } = self.bus_off_policy else {
+                return Err(ReadError::BusOff);
+            };
+
+            self.close()?;
+            std::thread::sleep(delay);
+            self.reopen()?;
+            metrics::record_reconnect("bus_off");
+
+            self.bus_status = BusStatus::default();
+            Ok(())
+        }
+
+        /// Reapplies the settings applied so far and opens the channel
+        fn reopen(&mut self) -> io::Result<()> {
+            self.reapply_config()?;
+            self.open_channel()
+        }
+
+        /// Reapplies the settings applied so far, without opening the channel
+        fn reapply_config(&mut self) -> io::Result<()> {
+            let config = self.config;
+            if let Some(rate) = config.data_bit_rate {
+                self.set_data_bit_rate(rate)?;
+            }
+            if let Some(mode) = config.operating_mode {
+                self.set_operating_mode(mode)?;
+            }
+            if let Some(mode) = config.auto_retransmission {
+                self.set_auto_retransmission_mode(mode)?;
+            }
+            if let Some(mode) = config.timestamp_mode {
+                self.set_timestamp_mode(mode)?;
+            }
+            if let Some(mode) = config.fd_crc_mode {
+                self.set_fd_crc_mode(mode)?;
+            }
+            if let Some(rate) = config.nominal_bit_rate {
+                self.set_nominal_bit_rate(rate)?;
+            }
+            Ok(())
+        }
+
+        /// Sends the commands for the filter banks whose contents changed, or
+        /// for all banks if `all` is set
+        fn program_filter_banks(&mut self, all: bool) -> io::Result<()> {
+            for command in self.acceptance.update_banks(all) {
+                self.send_raw_line(&command)?;
+            }
+
+            Ok(())
+        }
+
+        /// Returns the command set with `set_error_counters_command`, or an
+        /// error of kind [`io::ErrorKind::Unsupported`] if there is none
+        fn error_counters_command(&self) -> io::Result<Vec<u8>> {
+            self.error_counters_command.clone().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no error counters command was set for this gateway",
+                )
+            })
+        }
+
+        /// Returns an error if the channel is in a state where frames can't
+        /// be sent
+        fn check_can_send(&self) -> Result<(), SendError> {
+            if !self.open {
+                return Err(SendError::ChannelClosed);
+            }
+
+            if self.config.operating_mode == Some(OperatingMode::Silent) {
+                return Err(SendError::ListenOnly);
+            }
+
+            Ok(())
+        }
+
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if the
+        /// dialect of the gateway has no command for `feature`
+        fn check_dialect_supports(&self, feature: &str) -> io::Result<()> {
+            if self.dialect == Dialect::UsbTin {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("USBtin adapters don't support {feature}"),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Counts the answer of the gateway to a frame in flight and reports
+        /// it to the observers
+        fn record_transmit_status(&mut self, status: TransmitStatus) {
+            self.stats.record_transmit_status(status);
+            metrics::record_tx_queue_depth(self.unacknowledged);
+            self.observers.transmit_status(status, self.unacknowledged);
+        }
+
+        /// Buffers a received frame to be returned by a later read
+        fn queue_received(&mut self, frame: CanFrame, timestamp: Option<Timestamp>) {
+            self.rx_queue.push_back(RxFrame {
+                frame,
+                timestamp,
+                direction: FrameDirection::Rx,
+                channel: self.channel,
+            });
+        }
+
+        /// Reads and parses the next message from the gateway, keeping track
+        /// of transmit acknowledgements
+        fn read_message(&mut self) -> Result<Message, ReadError> {
+            loop {
+                if let Some(message) = self.read_next_message()? {
+                    return Ok(message);
+                }
+            }
+        }
+
+        /// Reads and handles a single message. Returns `None` for frames which
+        /// don't pass the acceptance filters, which are dropped before anything
+        /// else gets to see them, as if the gateway had filtered them.
+        fn read_next_message(&mut self) -> Result<Option<Message>, ReadError> {
+            if let Err(e) = self.read_line() {
+                self.observers.error(&e);
+                return Err(e);
+            }
+
+            let message = match parse_message_from_bytes(&self.line, self.timestamp_mode) {
+                Ok(message) => message,
+                // Firmwares answer the version query in different formats
+                Err(_)
+                    if self.version_pending && matches!(self.line.first(), Some(b'V' | b'v')) =>
+                {
+                    Message::Version(self.line.clone())
+                }
+                Err(error) => {
+                    metrics::record_parse_error(&error);
+                    self.stats.record_parse_error(&error, self.line.len());
+                    let line = self.line.clone();
+                    let error = LineParseError { line, error }.into();
+                    self.observers.error(&error);
+                    return Err(error);
+                }
+            };
+
+            match &message {
+                Message::Frame(frame, _) if !self.acceptance.accepts(frame.id()) => {
+                    return Ok(None);
+                }
+                Message::Frame(frame, timestamp) => {
+                    metrics::record_frame_received(frame);
+                    self.observers.rx_frame(frame, *timestamp);
+
+                    #[cfg(feature = "logging")]
+                    if let Some(recorder) = self.recorder.as_mut() {
+                        recorder.record_frame(frame, FrameDirection::Rx, self.channel);
+                    }
+                }
+                Message::TransmitAck => {
+                    self.unacknowledged = self.unacknowledged.saturating_sub(1);
+                    self.record_transmit_status(TransmitStatus::Acknowledged);
+                }
+                // A NACK only answers a frame while one is in flight, otherwise
+                // it refers to some other command
+                Message::Nack if self.unacknowledged > 0 => {
+                    self.unacknowledged -= 1;
+                    self.record_transmit_status(TransmitStatus::Rejected);
+                }
+                Message::Nack => {}
+                Message::Version(_) => self.version_pending = false,
+                Message::ErrorRegister(_) => {}
+                Message::BusStatus(status) => {
+                    self.bus_status = *status;
+                    self.observers.bus_status(status);
+
+                    if let Some(callback) = self.on_bus_status.as_mut() {
+                        callback(status);
+                    }
+                }
+            }
+
+            Ok(Some(message))
+        }
+
+        /// Reads from the serial stream until a line of length
+        /// 1..=max_line_length is received with a terminating CR, and stores
+        /// it in `line`. Longer lines are discarded and reported as
+        /// [`ReadError::LineTooLong`]. The allocation of `line` is reused, so
+        /// reading frames doesn't allocate once the socket is running.
+        ///
+        /// Will return an Err if the operation would block and is safe to
+        /// call again in that case without losing any state.
+        fn read_line(&mut self) -> Result<(), ReadError> {
+            let mut buf = [0u8; 1];
+
+            while self.read_byte(&mut buf)? == 1 {
+                match self.lines.push(buf[0]) {
+                    None => {}
+                    Some(LineEvent::Nack) => {
+                        if let Some(callback) = self.on_raw_rx.as_mut() {
+                            callback(&[NACK]);
+                        }
+
+                        self.line.clear();
+                        self.line.push(NACK);
+                        return Ok(());
+                    }
+                    Some(LineEvent::TooLong(length)) => {
+                        metrics::record_oversized_line();
+                        self.stats.record_oversized_line(length);
+                        return Err(ReadError::LineTooLong(length));
+                    }
+                    Some(LineEvent::Line(line)) => {
+                        self.line.clear();
+                        self.line.extend_from_slice(line);
+
+                        if let Some(callback) = self.on_raw_rx.as_mut() {
+                            callback(&self.line);
+                        }
+
+                        return Ok(());
+                    }
+                }
+            }
+
+            Err(io::Error::from(io::ErrorKind::WouldBlock).into())
+        }
+
+        /// Reads a single byte from the port, checking the keepalive while
+        /// the gateway is silent
+        fn read_byte(&mut self, buf: &mut [u8; 1]) -> Result<usize, ReadError> {
+            match self.port_mut().read(buf) {
+                Ok(0) => {
+                    self.check_keepalive()?;
+                    Ok(0)
+                }
+                Ok(count) => {
+                    self.last_received = Instant::now();
+                    self.keepalive_sent = None;
+                    Ok(count)
+                }
+                Err(e) => {
+                    self.check_keepalive()?;
+                    Err(e.into())
+                }
+            }
+        }
+
+        /// Queries the firmware version once the gateway was silent for the
+        /// keepalive interval, and fails if the query isn't answered in time
+        fn check_keepalive(&mut self) -> Result<(), ReadError> {
+            let Some(interval) = self.keepalive else {
+                return Ok(());
+            };
+
+            match self.keepalive_sent {
+                Some(sent) if sent.elapsed() >= interval => {
+                    self.keepalive_sent = None;
+                    self.last_received = Instant::now();
+                    Err(ReadError::DeviceUnresponsive)
+                }
+                None if self.last_received.elapsed() >= interval => {
+                    self.send_command(Command::GetFirmwareVersion)?;
+                    self.keepalive_sent = Some(Instant::now());
+                    self.version_pending = true;
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        /// Sends a configuration command, confirming it and retrying it
+        /// according to the [CommandRetryPolicy]
+        fn send_config_command(&mut self, command: Command) -> io::Result<()> {
+            let CommandRetryPolicy::Retry {
+                attempts,
+                timeout,
+                backoff,
+            } = self.command_retry_policy
+            else {
+                return self.send_command(command);
+            };
+
+            let mut rejected = false;
+
+            for attempt in 0..=attempts {
+                if attempt > 0 {
+                    std::thread::sleep(backoff);
+                }
+
+                let (answer, nacks) = match self.probe(Some(command.clone()), true, timeout) {
+                    Ok(result) => result,
+                    Err(ReadError::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::other(e)),
+                };
+
+                if answer.is_some() && nacks == 0 {
+                    return Ok(());
+                }
+
+                rejected = nacks > 0;
+            }
+
+            Err(unconfirmed_command(&command, rejected))
+        }
+
+        /// Serializes a command and sends it over the serial stream with a CR
+        /// line ending appended. Crucially, the entire command is sent in one
+        /// write operation which is important because the CANable does not
+        /// always correctly buffer input and will fail to parse our commands
+        /// if they are split into multiple USB packets.
+        fn send_command(&mut self, command: Command) -> io::Result<()> {
+            let encoded = command.encode();
+
+            self.port_mut().write_all(encoded.as_line())?;
+            self.port_mut().flush()?;
+
+            if let Some(callback) = self.on_raw_tx.as_mut() {
+                callback(&encoded);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// A [CanSocket] whose gateway is in [`OperatingMode::Silent`], created
+    /// with [`CanSocket::into_silent`]. Only the methods which receive frames
+    /// or open and close the channel are available, so a program which must
+    /// never disturb the bus can't send frames by accident. The getters of
+    /// the socket are available through `Deref`.
+    pub struct SilentCanSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    impl<P> Deref for SilentCanSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> SilentCanSocket<P> {
+        /// Returns the socket, with all of its methods. The gateway stays in
+        /// silent mode until another operating mode is set.
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P: Read + Write> SilentCanSocket<P> {
+        forward_to_socket! {
+            fn open(&mut self, nominal_bit_rate: NominalBitRate) -> io::Result<()>;
+            fn open_channel(&mut self) -> io::Result<()>;
+            fn close(&mut self) -> io::Result<()>;
+            fn read(&mut self) -> Result<CanFrame, ReadError>;
+            fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError>;
+            fn read_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+            ) -> Result<CanFrame, ReadError>;
+            fn await_frame(
+                &mut self,
+                id: impl Into<Id>,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            fn await_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError>;
+        }
+    }
+
+    /// A [CanSocket] whose channel is closed, created with
+    /// [`CanSocket::into_closed`]. Only the methods which configure the
+    /// channel are available, and [`open`](ClosedSocket::open) turns it into
+    /// an [OpenSocket]. The getters of the socket are available through
+    /// `Deref`.
+    pub struct ClosedSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    /// A [CanSocket] whose channel is open, created with
+    /// [`ClosedSocket::open`]. Only the methods which receive and send frames
+    /// are available, and [`close`](OpenSocket::close) turns it back into a
+    /// [ClosedSocket] for changing settings. The getters of the socket are
+    /// available through `Deref`.
+    pub struct OpenSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    impl<P> Deref for ClosedSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> Deref for OpenSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> ClosedSocket<P> {
+        /// Returns the socket, with all of its methods
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P> OpenSocket<P> {
+        /// Returns the socket, with all of its methods
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P: Read + Write> ClosedSocket<P> {
+        /// Sets the nominal bit rate and opens the channel. See
+        /// [`CanSocket::open`].
+        pub fn open(mut self, nominal_bit_rate: NominalBitRate) -> io::Result<OpenSocket<P>> {
+            self.socket.open(nominal_bit_rate)?;
+            Ok(OpenSocket {
+                socket: self.socket,
+            })
+        }
+
+        /// Opens the channel with the nominal bit rate set earlier. See
+        /// [`CanSocket::open_channel`].
+        pub fn open_channel(mut self) -> io::Result<OpenSocket<P>> {
+            self.socket.open_channel()?;
+            Ok(OpenSocket {
+                socket: self.socket,
+            })
+        }
+
+        forward_to_socket! {
+            fn set_dialect(&mut self, dialect: Dialect);
+            fn set_nominal_bit_rate(&mut self, rate: NominalBitRate) -> io::Result<()>;
+            fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()>;
+            fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()>;
+            fn set_auto_retransmission_mode(
+                &mut self,
+                mode: AutoRetransmissionMode,
+            ) -> io::Result<()>;
+            fn set_timestamp_mode(&mut self, mode: TimestampMode) -> io::Result<()>;
+            fn set_close_on_drop(&mut self, enabled: bool);
+            fn set_max_line_length(&mut self, length: usize);
+            fn set_lf_line_endings(&mut self, enabled: bool);
+            fn set_channel(&mut self, channel: usize);
+            fn set_tx_echo(&mut self, enabled: bool);
+            fn set_tx_queue_policy(&mut self, policy: TxQueuePolicy);
+            fn set_tx_filter(&mut self, filter: Option<IdFilter>);
+            fn set_bus_off_policy(&mut self, policy: BusOffPolicy);
+            fn set_command_retry_policy(&mut self, policy: CommandRetryPolicy);
+            fn set_keepalive(&mut self, interval: Option<Duration>);
+            fn set_bus_status_callback(
+                &mut self,
+                callback: impl FnMut(&BusStatus) + Send + 'static,
+            );
+            fn set_raw_rx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static);
+            fn set_raw_tx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static);
+            fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static);
+            fn clear_interceptors(&mut self);
+            fn add_observer(&mut self, observer: impl BusObserver + 'static);
+            fn clear_observers(&mut self);
+            fn set_remote_responder(
+                &mut self,
+                responder: Option<RemoteResponder>,
+            ) -> Option<RemoteResponder>;
+            #[cfg(feature = "logging")]
+            fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder>;
+            fn set_identify_command(&mut self, command: Option<&[u8]>);
+            fn set_error_counters_command(&mut self, command: Option<&[u8]>);
+            fn set_bootloader_command(&mut self, command: Option<&[u8]>);
+            fn set_termination_commands(&mut self, commands: Option<(&[u8], &[u8])>);
+            fn set_termination(&mut self, enabled: bool) -> io::Result<()>;
+            fn set_fd_crc_commands(&mut self, commands: Option<(&[u8], &[u8])>);
+            fn set_fd_crc_mode(&mut self, mode: FdCrcMode) -> io::Result<()>;
+            fn set_filter_banks(
+                &mut self,
+                count: usize,
+                encode: impl Fn(usize, Option<&AcceptanceFilter>) -> Vec<u8> + Send + 'static,
+            ) -> io::Result<()>;
+            fn add_filter(&mut self, filter: AcceptanceFilter) -> io::Result<()>;
+            fn remove_filter(&mut self, filter: &AcceptanceFilter) -> io::Result<bool>;
+            fn clear_filters(&mut self) -> io::Result<()>;
+        }
+    }
+
+    impl<P: Read + Write> OpenSocket<P> {
+        /// Closes the channel. See [`CanSocket::close`].
+        pub fn close(mut self) -> io::Result<ClosedSocket<P>> {
+            self.socket.close()?;
+            Ok(ClosedSocket {
+                socket: self.socket,
+            })
+        }
+
+        forward_to_socket! {
+            fn send(&mut self, frame: impl Into<CanFrame>) -> Result<(), SendError>;
+            fn send_data(&mut self, id: u32, data: &[u8]) -> Result<(), SendError>;
+            fn send_remote(&mut self, id: u32, dlc: usize) -> Result<(), SendError>;
+            fn send_fd(&mut self, id: u32, data: &[u8]) -> Result<(), SendError>;
+            fn send_and_confirm(
+                &mut self,
+                frame: impl Into<CanFrame>,
+                timeout: Duration,
+            ) -> Result<TransmitStatus, SendError>;
+            fn read(&mut self) -> Result<CanFrame, ReadError>;
+            fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError>;
+            fn read_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+            ) -> Result<CanFrame, ReadError>;
+            fn await_frame(
+                &mut self,
+                id: impl Into<Id>,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            fn await_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError>;
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub mod tokio {
+    //! The async implementation of CanSocket for use with the
+    //! [tokio_serial] crate.
+
+    use std::collections::VecDeque;
+    use std::future::{poll_fn, Future};
+    use std::io;
+    use std::ops::Deref;
+    #[cfg(target_family = "unix")]
+    use std::os::unix::prelude::AsRawFd;
+    #[cfg(target_family = "windows")]
+    use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, RawHandle};
+    use std::pin::{pin, Pin};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::time::{Duration, Instant};
+
+    use futures_core::Stream;
+    use tokio::io::AsyncRead;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWrite;
+    use tokio::io::AsyncWriteExt;
+    use tokio::time;
+
+    use crate::parser::{parse_message_from_bytes, LineParseError, Message, NACK};
+    use crate::{
+        acceptance::{AcceptanceFilter, AcceptanceFilters},
+        adapter_not_ready,
+        command::{
+            AutoRetransmissionMode, Command, DataBitRate, Dialect, FdCrcMode, OperatingMode,
+            SlcanFrame, TimestampMode,
+        },
+        diagnostics::{BusDiagnosis, DiagnosisSampler},
+        error_counters_rejected,
+        filter::IdFilter,
+        forward::{ForwardErrorPolicy, ForwardOptions, ForwardStats},
+        frame::{Can2Frame, CanFdFrame, CanFrame, FrameDirection, RxFrame},
+        id_from_raw,
+        intercept::{Interceptor, InterceptorChain},
+        metrics,
+        modem::ModemControl,
+        observer::{BusObserver, ObserverList},
+        status::{
+            BusOffPolicy, BusState, BusStatus, BusStatusCallback, ChannelConfig,
+            CommandRetryPolicy, TxQueuePolicy,
+        },
+        stream_parser::{LineAccumulator, LineEvent},
+        unconfirmed_command, Capabilities, Id, LinkStats, NominalBitRate, RawLineCallback,
+        ReadError, RemoteResponder, SendError, Timestamp, TransmitStatus, READY_POLL_INTERVAL,
+        SLCAN_MTU,
+    };
+
+    #[cfg(feature = "logging")]
+    use crate::logging::Recorder;
+
+    /// Represents an asynchronous interface into a CAN FD network through a
+    /// serial (USB) gateway device.
+    ///
+    /// Messages can be sent over the bus through the gateway, and messages
+    /// broadcasted on the bus by other nodes can be received through the
+    /// gateway.
+    pub struct CanSocket<P> {
+        port: Option<Pin<Box<P>>>,
+        lines: LineAccumulator,
+        line: Vec<u8>,
+        timestamp_mode: TimestampMode,
+        last_timestamp: Option<Timestamp>,
+        channel: usize,
+        tx_echo: bool,
+        rx_queue: VecDeque<RxFrame>,
+        interceptors: InterceptorChain,
+        observers: ObserverList,
+        intercepted: VecDeque<(RxFrame, Instant)>,
+        /// Frames which already passed the interceptors but didn't match a
+        /// [`request`](CanSocket::request), returned as they are by the next
+        /// reads
+        skipped: VecDeque<RxFrame>,
+        unacknowledged: usize,
+        transmitted: u64,
+        open: bool,
+        config: ChannelConfig,
+        bus_status: BusStatus,
+        stats: LinkStats,
+        keepalive: Option<Duration>,
+        keepalive_sent: bool,
+        /// The part of a keepalive query which wasn't written yet because the
+        /// read which sent it was cancelled
+        pending_tx: Vec<u8>,
+        version_pending: bool,
+        bus_off_policy: BusOffPolicy,
+        command_retry_policy: CommandRetryPolicy,
+        tx_queue_policy: TxQueuePolicy,
+        tx_filter: Option<IdFilter>,
+        on_bus_status: Option<BusStatusCallback>,
+        on_raw_rx: Option<RawLineCallback>,
+        on_raw_tx: Option<RawLineCallback>,
+        remote_responder: Option<RemoteResponder>,
+        #[cfg(feature = "logging")]
+        recorder: Option<Recorder>,
+        identify_command: Option<Vec<u8>>,
+        error_counters_command: Option<Vec<u8>>,
+        bootloader_command: Option<Vec<u8>>,
+        termination_commands: Option<(Vec<u8>, Vec<u8>)>,
+        fd_crc_commands: Option<(Vec<u8>, Vec<u8>)>,
+        acceptance: AcceptanceFilters,
+        dialect: Dialect,
+        close_on_drop: bool,
+        close_port: fn(Pin<&mut P>),
+    }
+
+    impl<P> Drop for CanSocket<P> {
+        /// Starts closing the channel if it is still open, unless disabled
+        /// with [`set_close_on_drop`](CanSocket::set_close_on_drop)
+        fn drop(&mut self) {
+            if let Some(port) = self
+                .port
+                .as_mut()
+                .filter(|_| self.open && self.close_on_drop)
+            {
+                (self.close_port)(port.as_mut());
+            }
+        }
+    }
+
+    /// Writes the close command to a port if it is ready right away, since
+    /// [Drop] can't wait for the write. Errors are ignored as they can't be
+    /// reported either.
+    fn close_port<P: AsyncWrite>(mut port: Pin<&mut P>) {
+        let encoded = Command::Close.encode();
+
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        if let Poll::Ready(Ok(_)) = port.as_mut().poll_write(&mut cx, encoded.as_line()) {
+            let _ = port.poll_flush(&mut cx);
+        }
+    }
+
+    /// A waker which does nothing, for polling a port once without waiting
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// The port is only taken out of the socket by
+    /// [`into_inner`](CanSocket::into_inner), which consumes it
+    const PORT_TAKEN: &str = "the port is only taken when consuming the socket";
+
+    impl<P> CanSocket<P> {
+        /// Returns the underlying port
+        pub fn port(&self) -> &P {
+            self.port.as_deref().expect(PORT_TAKEN)
+        }
+
+        /// Returns the pinned underlying port, for ports which aren't
+        /// [Unpin]. See [`port_mut`](CanSocket::port_mut).
+        pub fn port_pin_mut(&mut self) -> Pin<&mut P> {
+            self.port.as_mut().expect(PORT_TAKEN).as_mut()
+        }
+    }
+
+    impl<P: Unpin> CanSocket<P> {
+        /// Returns the underlying port, e.g. for changing its baud rate or
+        /// flow control at runtime. Bytes read from or written to it directly
+        /// bypass the socket, so this is best done while the channel is
+        /// closed.
+        pub fn port_mut(&mut self) -> &mut P {
+            self.port_pin_mut().get_mut()
+        }
+
+        /// Consumes the socket and returns the underlying port, e.g. for
+        /// talking to the firmware of the adapter directly. Unlike dropping
+        /// the socket, this leaves the channel as it is, so
+        /// [`close`](CanSocket::close) it first if needed. Frames and partial
+        /// lines which were received but not read yet are discarded.
+        pub fn into_inner(mut self) -> P {
+            *Pin::into_inner(self.port.take().expect(PORT_TAKEN))
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    impl<P: AsRawFd> AsRawFd for CanSocket<P> {
+        fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
+            self.port().as_raw_fd()
+        }
+    }
+
+    /// Exposes the handle of the underlying port, e.g. for registering it with
+    /// an I/O completion port. Note that the handle must have been opened
+    /// with `FILE_FLAG_OVERLAPPED` for overlapped I/O to be possible.
+    #[cfg(target_family = "windows")]
+    impl<P: AsRawHandle> AsRawHandle for CanSocket<P> {
+        fn as_raw_handle(&self) -> RawHandle {
+            self.port().as_raw_handle()
+        }
+    }
+
+    #[cfg(target_family = "windows")]
+    impl<P: AsHandle> AsHandle for CanSocket<P> {
+        fn as_handle(&self) -> BorrowedHandle<'_> {
+            self.port().as_handle()
+        }
+    }
+
+    #[cfg(feature = "serial2-tokio")]
+    impl CanSocket<serial2_tokio::SerialPort> {
+        /// Opens the serial port at `path` with the
+        /// [`serial2-tokio`](serial2_tokio) crate, as an alternative to
+        /// [`tokio-serial`](https://docs.rs/tokio-serial) on platforms where
+        /// it misbehaves. The CAN channel is not opened yet, see
+        /// [`open`](CanSocket::open).
+        ///
+        /// # Panics
+        ///
+        /// Panics if called outside of a tokio runtime.
+        pub fn open_serial2(path: impl AsRef<std::path::Path>, baud_rate: u32) -> io::Result<Self> {
+            Ok(Self::new(serial2_tokio::SerialPort::open(path, baud_rate)?))
+        }
+    }
+
+    impl<P: AsyncRead + AsyncWrite + ModemControl + Unpin> CanSocket<P> {
+        /// Resets the adapter by pulsing its DTR and RTS lines, for CANable
+        /// clones which are wired to reset when they are toggled, e.g. to
+        /// recover a wedged adapter without replugging it. The lines are
+        /// released for `pulse` and then asserted again.
+        ///
+        /// The firmware version is then queried until the adapter answers,
+        /// for up to `ready_timeout`, and the settings applied so far
+        /// (including the filter banks) are reapplied. The channel is reopened
+        /// if it was open. Partially received lines and acknowledgements still
+        /// outstanding are discarded.
+        ///
+        /// Adapters whose USB connection drops while resetting can't be
+        /// recovered this way, since their port has to be reopened.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the adapter
+        /// doesn't answer within `ready_timeout`.
+        pub async fn reset_adapter(
+            &mut self,
+            pulse: Duration,
+            ready_timeout: Duration,
+        ) -> io::Result<()> {
+            let port = self.port_mut();
+            port.set_dtr(false)?;
+            port.set_rts(false)?;
+            time::sleep(pulse).await;
+            let port = self.port_mut();
+            port.set_dtr(true)?;
+            port.set_rts(true)?;
+
+            let was_open = self.open;
+            self.lines.reset();
+            self.unacknowledged = 0;
+            metrics::record_tx_queue_depth(0);
+            if was_open {
+                self.open = false;
+                self.observers.channel_state(false);
+            }
+
+            let deadline = Instant::now() + ready_timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(adapter_not_ready());
+                }
+
+                // The query is repeated since the adapter drops it while booting
+                match self
+                    .probe(None, true, remaining.min(READY_POLL_INTERVAL))
+                    .await
+                {
+                    Ok((Some(_), _)) => break,
+                    Ok((None, _)) => {}
+                    Err(ReadError::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::other(e)),
+                }
+            }
+
+            self.reapply_config().await?;
+            self.program_filter_banks(true).await?;
+            if was_open {
+                self.open_channel().await?;
+            }
+
+            metrics::record_reconnect("adapter_reset");
+            Ok(())
+        }
+    }
+
+    impl<P: AsyncRead + AsyncWrite> CanSocket<P> {
+        /// Constructs a new CanSocket from an async SerialStream
+        pub fn new(port: P) -> Self {
+            CanSocket {
+                port: Some(Box::pin(port)),
+                lines: LineAccumulator::new(SLCAN_MTU),
+                line: Vec::with_capacity(SLCAN_MTU),
+                timestamp_mode: TimestampMode::Disabled,
+                last_timestamp: None,
+                channel: 0,
+                tx_echo: false,
+                rx_queue: VecDeque::new(),
+                interceptors: InterceptorChain::default(),
+                observers: ObserverList::default(),
+                intercepted: VecDeque::new(),
+                skipped: VecDeque::new(),
+                unacknowledged: 0,
+                transmitted: 0,
+                open: false,
+                config: ChannelConfig::default(),
+                bus_status: BusStatus::default(),
+                stats: LinkStats::default(),
+                keepalive: None,
+                keepalive_sent: false,
+                pending_tx: Vec::new(),
+                version_pending: false,
+                bus_off_policy: BusOffPolicy::default(),
+                command_retry_policy: CommandRetryPolicy::default(),
+                tx_queue_policy: TxQueuePolicy::default(),
+                tx_filter: None,
+                on_bus_status: None,
+                on_raw_rx: None,
+                on_raw_tx: None,
+                remote_responder: None,
+                #[cfg(feature = "logging")]
+                recorder: None,
+                identify_command: None,
+                error_counters_command: None,
+                bootloader_command: None,
+                termination_commands: None,
+                fd_crc_commands: None,
+                acceptance: AcceptanceFilters::default(),
+                dialect: Dialect::Standard,
+                close_on_drop: true,
+                close_port: close_port::<P>,
+            }
+        }
+
+        /// Configures the device with the supplied bit timing and requests
+        /// the device to begin enable streaming of CAN frames
+        pub async fn open(&mut self, nominal_bitrate: NominalBitRate) -> io::Result<()> {
+            self.set_nominal_bit_rate(nominal_bitrate).await?;
+            self.open_channel().await
+        }
+
+        /// Requests the device to begin streaming CAN frames with the nominal
+        /// bit rate set earlier, e.g. for opening and closing the channel
+        /// repeatedly after setting the bit rate once. See
+        /// [`set_nominal_bit_rate`](CanSocket::set_nominal_bit_rate).
+        pub async fn open_channel(&mut self) -> io::Result<()> {
+            let command = match (self.dialect, self.config.operating_mode) {
+                (Dialect::UsbTin, Some(OperatingMode::Silent)) => Command::OpenListenOnly,
+                (Dialect::UsbTin, Some(OperatingMode::Loopback)) => Command::OpenLoopback,
+                _ => Command::Open,
+            };
+
+            self.send_config_command(command).await?;
+            self.open = true;
+            self.observers.channel_state(true);
+            metrics::record_channel_open();
+
+            Ok(())
+        }
+
+        /// Switches the channel to other bit rates, e.g. for scanning for the
+        /// bit rate of a bus: the channel is closed, the data bit rate is set
+        /// (if given) and the channel is reopened at the nominal bit rate. The
+        /// operating mode, auto retransmission and timestamp mode applied so
+        /// far are restored before reopening.
+        ///
+        /// If any step fails the channel may be left closed.
+        pub async fn change_bit_rate(
+            &mut self,
+            nominal_bit_rate: NominalBitRate,
+            data_bit_rate: Option<DataBitRate>,
+        ) -> io::Result<()> {
+            self.close().await?;
+
+            self.config.nominal_bit_rate = Some(nominal_bit_rate);
+            if let Some(rate) = data_bit_rate {
+                self.config.data_bit_rate = Some(rate);
+            }
+
+            self.reopen().await
+        }
+
+        /// Sends a close command to the gateway which instructs it to stop
+        /// sending and receiving CAN frames
+        pub async fn close(&mut self) -> io::Result<()> {
+            self.send_config_command(Command::Close).await?;
+            self.open = false;
+            self.observers.channel_state(false);
+            Ok(())
+        }
+
+        /// Switches the gateway to [`OperatingMode::Silent`] and returns a
+        /// socket which can only receive, so sending is ruled out when
+        /// compiling instead of being refused with [`SendError::ListenOnly`].
+        /// An open channel is closed while the mode is changed and reopened
+        /// afterwards.
+        pub async fn into_silent(mut self) -> io::Result<SilentCanSocket<P>> {
+            let open = self.open;
+
+            if open {
+                self.close().await?;
+            }
+            self.set_operating_mode(OperatingMode::Silent).await?;
+            if open {
+                self.open_channel().await?;
+            }
+
+            Ok(SilentCanSocket { socket: self })
+        }
+
+        /// Closes the channel if it is open and returns a socket which only
+        /// offers the configuration methods, which take effect the next
+        /// time the channel is opened. Opening it returns an [OpenSocket],
+        /// which only offers reading and sending, so settings can't be
+        /// changed on an open channel where the gateway would ignore them.
+        pub async fn into_closed(mut self) -> io::Result<ClosedSocket<P>> {
+            if self.open {
+                self.close().await?;
+            }
+
+            Ok(ClosedSocket { socket: self })
+        }
+
+        /// Closes the channel if it is open and shuts down the port. Call
+        /// this before dropping the socket, since closing on drop can't wait
+        /// for the port and only succeeds if it can be written right away.
+        pub async fn shutdown(&mut self) -> io::Result<()> {
+            if self.open {
+                self.close().await?;
+            }
+
+            self.port_pin_mut().shutdown().await
+        }
+
+        /// Sets whether the channel is closed when the socket is dropped
+        /// while it is open (enabled by default), so a program which panics
+        /// doesn't leave the gateway streaming frames. Closing on drop is best
+        /// effort: the close command is only written if the port accepts it
+        /// without waiting. Use [`shutdown`](CanSocket::shutdown) to close
+        /// the channel reliably.
+        pub fn set_close_on_drop(&mut self, enabled: bool) {
+            self.close_on_drop = enabled;
+        }
+
+        /// Sets the variant of the SLCAN command set spoken by the gateway
+        /// (defaults to [`Dialect::Standard`]), e.g. [`Dialect::UsbTin`] for
+        /// USBtin adapters. Should be set before the channel is configured.
+        pub fn set_dialect(&mut self, dialect: Dialect) {
+            self.dialect = dialect;
+        }
+
+        /// Returns the variant of the SLCAN command set spoken by the gateway
+        pub fn dialect(&self) -> Dialect {
+            self.dialect
+        }
+
+        /// Sets the nominal bit rate, which takes effect the next time the
+        /// channel is opened. See [NominalBitRate].
+        pub async fn set_nominal_bit_rate(&mut self, rate: NominalBitRate) -> io::Result<()> {
+            self.send_config_command(Command::SetNominalBitRate(rate))
+                .await?;
+            self.config.nominal_bit_rate = Some(rate);
+            Ok(())
+        }
+
+        /// Sets the data bit rate (CAN FD frames only). See [DataBitRate].
+        pub async fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()> {
+            self.check_dialect_supports("data bit rates")?;
+            self.send_config_command(Command::SetDataBitRate(rate))
+                .await?;
+            self.config.data_bit_rate = Some(rate);
+            Ok(())
+        }
+
+        /// Sets the operating mode of the gateway, e.g. `Silent` (a.k.a.
+        /// "Listen Only" mode). See [OperatingMode].
+        ///
+        /// With [`Dialect::UsbTin`] the mode is selected by the command which
+        /// opens the channel, so it takes effect the next time the channel is
+        /// opened.
+        pub async fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()> {
+            if self.dialect == Dialect::Standard {
+                self.send_config_command(Command::SetMode(mode)).await?;
+            }
+            self.config.operating_mode = Some(mode);
+            Ok(())
+        }
+
+        /// Sets the auto retransmission mode of the gateway, either `Enabled`
+        /// or `Disabled`. See [AutoRetransmissionMode].
+        pub async fn set_auto_retransmission_mode(
+            &mut self,
+            mode: AutoRetransmissionMode,
+        ) -> io::Result<()> {
+            self.check_dialect_supports("auto retransmission")?;
+            self.send_config_command(Command::SetAutoRetransmission(mode))
+                .await?;
+            self.config.auto_retransmission = Some(mode);
+            Ok(())
+        }
+
+        /// Sets whether the gateway appends a timestamp to received frames,
+        /// and at which resolution. See [TimestampMode].
+        ///
+        /// The mode must match what the firmware actually sends, otherwise the
+        /// trailing digits will be interpreted as part of the frame (or vice
+        /// versa) and parsing will fail.
+        pub async fn set_timestamp_mode(&mut self, mode: TimestampMode) -> io::Result<()> {
+            self.send_config_command(Command::SetTimestampMode(mode))
+                .await?;
+            self.config.timestamp_mode = Some(mode);
+            self.timestamp_mode = mode;
+            Ok(())
+        }
+
+        /// Returns the hardware timestamp of the last frame returned by
+        /// [`read`](CanSocket::read), if timestamps are enabled. See
+        /// [`set_timestamp_mode`](CanSocket::set_timestamp_mode).
+        pub fn last_timestamp(&self) -> Option<Timestamp> {
+            self.last_timestamp
+        }
+
+        /// Sets the maximum length of a line received from the gateway
+        /// (excluding the CR), for firmwares which append extra fields to
+        /// frames. Longer lines are discarded and reported as
+        /// [`ReadError::LineTooLong`]. Defaults to 155 bytes, which fits a CAN
+        /// FD frame with 64 bytes of data and a timestamp.
+        ///
+        /// Any partially received line is discarded.
+        pub fn set_max_line_length(&mut self, length: usize) {
+            self.lines.set_max_line_length(length);
+        }
+
+        /// Returns the maximum length of a line received from the gateway.
+        /// See [`set_max_line_length`](CanSocket::set_max_line_length).
+        pub fn max_line_length(&self) -> usize {
+            self.lines.max_line_length()
+        }
+
+        /// Accepts LF and CRLF line endings in addition to CR (disabled by
+        /// default), for bridges and terminal programs which insert a LF.
+        /// Otherwise a stray LF becomes the first byte of the next line,
+        /// which then fails to parse.
+        pub fn set_lf_line_endings(&mut self, enabled: bool) {
+            self.lines.set_lf_line_endings(enabled);
+        }
+
+        /// Returns true if LF and CRLF line endings are accepted. See
+        /// [`set_lf_line_endings`](CanSocket::set_lf_line_endings).
+        pub fn lf_line_endings(&self) -> bool {
+            self.lines.lf_line_endings()
+        }
+
+        /// Sets the channel index reported in each [RxFrame], for telling
+        /// apart frames from multiple gateways (defaults to 0)
+        pub fn set_channel(&mut self, channel: usize) {
+            self.channel = channel;
+        }
+
+        /// Returns the channel index reported in each [RxFrame]
+        pub fn channel(&self) -> usize {
+            self.channel
+        }
+
+        /// Enables or disables the echo of transmitted frames.
+        ///
+        /// When enabled, every frame successfully handed to the gateway by
+        /// [`send`](CanSocket::send) is also returned by subsequent reads,
+        /// tagged with [`FrameDirection::Tx`] in [`read_rx_frame`](CanSocket::read_rx_frame).
+        /// This mirrors the loopback behavior of SocketCAN so that logs contain
+        /// both directions of the conversation.
+        pub fn set_tx_echo(&mut self, enabled: bool) {
+            self.tx_echo = enabled;
+
+            if !enabled {
+                self.rx_queue
+                    .retain(|frame| frame.direction != FrameDirection::Tx);
+                self.skipped
+                    .retain(|frame| frame.direction != FrameDirection::Tx);
+            }
+        }
+
+        /// Returns the last bus state reported by the gateway. See
+        /// [BusStatus].
+        pub fn bus_status(&self) -> BusStatus {
+            self.bus_status
+        }
+
+        /// Returns the number of received lines which were dropped because
+        /// they could not be parsed or were too long, and the number of
+        /// transmit acknowledgements. See [LinkStats].
+        pub fn stats(&self) -> &LinkStats {
+            &self.stats
+        }
+
+        /// Resets all counters returned by [`stats`](CanSocket::stats) to 0
+        pub fn reset_stats(&mut self) {
+            self.stats = LinkStats::default();
+        }
+
+        /// Returns the number of frames occupying the transmit queue of the
+        /// gateway, i.e. the frames sent to it which it hasn't acknowledged
+        /// (`z`/`Z`) or rejected yet. Sends are held back by this count with
+        /// [`TxQueuePolicy::Wait`]. A depth which keeps growing means the
+        /// gateway stopped accepting frames, e.g. because its transmit queue
+        /// is stuck while no other node acknowledges them.
+        pub fn tx_queue_len(&self) -> usize {
+            self.unacknowledged
+        }
+
+        /// Sets whether sends wait for room in the transmit queue of the
+        /// gateway (defaults to [`TxQueuePolicy::Unbounded`]). With
+        /// [`TxQueuePolicy::Wait`] a burst of frames is paced by the
+        /// acknowledgements of the gateway instead of overflowing its queue,
+        /// where the firmware would drop frames silently. Frames received
+        /// while waiting are buffered and returned by later reads.
+        pub fn set_tx_queue_policy(&mut self, policy: TxQueuePolicy) {
+            self.tx_queue_policy = policy;
+        }
+
+        /// Restricts the IDs which may be transmitted, as a safety net when
+        /// running test scripts against a live system. Frames whose ID the
+        /// filter doesn't match are refused with [`SendError::Blocked`]
+        /// without being written to the port. `None` (the default) permits
+        /// all IDs.
+        ///
+        /// An allowlist is a filter of the permitted IDs, a denylist one with
+        /// only exclusions, e.g. `"!7DF,!700-7FF".parse()`. The filter is
+        /// applied to the frames produced by the interceptors, so frames they
+        /// inject are checked as well.
+        pub fn set_tx_filter(&mut self, filter: Option<IdFilter>) {
+            self.tx_filter = filter;
+        }
+
+        /// Returns the filter restricting the IDs which may be transmitted
+        pub fn tx_filter(&self) -> Option<&IdFilter> {
+            self.tx_filter.as_ref()
+        }
+
+        /// Sets what happens when the gateway reports that the controller
+        /// went bus-off (defaults to [`BusOffPolicy::Report`]).
+        ///
+        /// Recovery happens within [`read`](CanSocket::read), so if the read
+        /// is cancelled while waiting for the delay the channel stays closed
+        /// and has to be reopened manually.
+        pub fn set_bus_off_policy(&mut self, policy: BusOffPolicy) {
+            self.bus_off_policy = policy;
+        }
+
+        /// Sets how configuration commands (bit rates, modes, opening and
+        /// closing the channel) are confirmed (defaults to
+        /// [`CommandRetryPolicy::Unconfirmed`]). With
+        /// [`CommandRetryPolicy::Retry`] a command which the gateway rejects or
+        /// doesn't confirm in time is sent again, since USB CDC adapters
+        /// occasionally drop the first command after enumeration.
+        pub fn set_command_retry_policy(&mut self, policy: CommandRetryPolicy) {
+            self.command_retry_policy = policy;
+        }
+
+        /// Sets the maximum time the gateway may stay silent before it is
+        /// queried for its firmware version (`V`) to check that it is still
+        /// alive. If the query isn't answered within the same time either,
+        /// reads fail with [`ReadError::DeviceUnresponsive`]. This catches
+        /// USB adapters which wedge while their port stays open. Disabled by
+        /// default.
+        ///
+        /// While a query is outstanding, a line starting with `V` or `v` is
+        /// taken as its answer. Other lines which can't be parsed still fail
+        /// the read like at any other time.
+        pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+            self.keepalive = interval;
+            self.keepalive_sent = false;
+        }
+
+        /// Registers a callback which is invoked with every bus state report
+        /// received from the gateway, including bus-off, before the
+        /// [BusOffPolicy] is applied
+        pub fn set_bus_status_callback(
+            &mut self,
+            callback: impl FnMut(&BusStatus) + Send + 'static,
+        ) {
+            self.on_bus_status = Some(Box::new(callback));
+        }
+
+        /// Registers a callback which is invoked with every line received
+        /// from the gateway (without the CR line ending), before it is parsed.
+        /// Together with [`set_raw_tx_callback`](CanSocket::set_raw_tx_callback)
+        /// this allows building a terminal-style view of the protocol.
+        ///
+        /// Lines which exceed the maximum length are discarded without being
+        /// passed to the callback. A NACK is passed as the BEL character
+        /// (0x07) on its own.
+        pub fn set_raw_rx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+            self.on_raw_rx = Some(Box::new(callback));
+        }
+
+        /// Registers a callback which is invoked with every line sent to the
+        /// gateway (without the CR line ending) once it was written
+        pub fn set_raw_tx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+            self.on_raw_tx = Some(Box::new(callback));
+        }
+
+        /// Adds an [Interceptor] which is run on every frame sent and
+        /// received through this socket, after the interceptors added before
+        /// it.
+        ///
+        /// Frames are recorded and counted as they are on the wire, i.e.
+        /// received frames before and transmitted frames after interception.
+        /// Delays are waited out within [`send`](CanSocket::send) and
+        /// [`read`](CanSocket::read), blocking them for that long.
+        pub fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static) {
+            self.interceptors.push(Box::new(interceptor));
+        }
+
+        /// Removes all interceptors. Frames which were already intercepted
+        /// are still delivered.
+        pub fn clear_interceptors(&mut self) {
+            self.interceptors.clear();
+        }
+
+        /// Adds a [BusObserver] which is notified of the traffic and events of
+        /// this socket, after the observers added before it
+        pub fn add_observer(&mut self, observer: impl BusObserver + 'static) {
+            self.observers.push(Box::new(observer));
+        }
+
+        /// Removes all observers
+        pub fn clear_observers(&mut self) {
+            self.observers.clear();
+        }
+
+        /// Attaches a [RemoteResponder] which answers remote frames received
+        /// through [`read`](CanSocket::read) and the methods built on it.
+        /// Returns the previously attached responder.
+        pub fn set_remote_responder(
+            &mut self,
+            responder: Option<RemoteResponder>,
+        ) -> Option<RemoteResponder> {
+            std::mem::replace(&mut self.remote_responder, responder)
+        }
+
+        /// Returns the attached [RemoteResponder], if any
+        pub fn remote_responder_mut(&mut self) -> Option<&mut RemoteResponder> {
+            self.remote_responder.as_mut()
+        }
+
+        /// Attaches a [Recorder] which is fed every frame received from the
+        /// bus and every transmitted frame (unless disabled with
+        /// [`Recorder::with_tx`]), tagged with its [FrameDirection]. Returns
+        /// the previously attached recorder, which can then be closed cleanly.
+        #[cfg(feature = "logging")]
+        pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+            std::mem::replace(&mut self.recorder, recorder)
+        }
+
+        /// Returns the attached [Recorder], if any
+        #[cfg(feature = "logging")]
+        pub fn recorder_mut(&mut self) -> Option<&mut Recorder> {
+            self.recorder.as_mut()
+        }
+
+        /// Sends a CAN frame to the gateway to be broadcasted on the bus.
+        ///
+        /// If the frame fails to be sent, it may be retransmitted according to
+        /// the current [AutoRetransmissionMode].
+        pub async fn send(&mut self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+            self.check_can_send()?;
+
+            let frame = frame.into();
+            if self.interceptors.is_empty() {
+                return self.transmit(frame).await;
+            }
+
+            let start = Instant::now();
+            for (frame, delay) in self.interceptors.apply(frame, FrameDirection::Tx) {
+                time::sleep_until((start + delay).into()).await;
+                self.transmit(frame).await?;
+            }
+
+            Ok(())
+        }
+
+        /// Sends a CAN 2.0 data frame with the given ID and data, see
+        /// [`send`](CanSocket::send). IDs up to 0x7FF are sent as standard
+        /// IDs and larger ones as extended IDs.
+        ///
+        /// Returns [`SendError::InvalidId`] if the ID is above 0x1FFFFFFF and
+        /// [`SendError::InvalidLength`] if there are more than 8 bytes of data.
+        pub async fn send_data(&mut self, id: u32, data: &[u8]) -> Result<(), SendError> {
+            let frame = Can2Frame::new_data(id_from_raw(id)?, data)
+                .ok_or(SendError::InvalidLength(data.len()))?;
+            self.send(frame).await
+        }
+
+        /// Sends a CAN 2.0 remote frame with the given ID and DLC, see
+        /// [`send_data`](CanSocket::send_data)
+        pub async fn send_remote(&mut self, id: u32, dlc: usize) -> Result<(), SendError> {
+            let frame = Can2Frame::new_remote(id_from_raw(id)?, dlc)
+                .ok_or(SendError::InvalidLength(dlc))?;
+            self.send(frame).await
+        }
+
+        /// Sends a CAN FD frame with bit rate switching, see
+        /// [`send_data`](CanSocket::send_data). The data is padded with zeros
+        /// to the next valid CAN FD length, and may be up to 64 bytes long.
+        pub async fn send_fd(&mut self, id: u32, data: &[u8]) -> Result<(), SendError> {
+            let frame = CanFdFrame::new_padded(id_from_raw(id)?, data)
+                .ok_or(SendError::InvalidLength(data.len()))?;
+            self.send(frame).await
+        }
+
+        /// Sends every frame yielded by `frames` until the stream ends, e.g.
+        /// for piping a generator, a replay or the frames of another socket
+        /// into the gateway. See [ForwardOptions] for pacing the frames and
+        /// handling frames which fail to send.
+        ///
+        /// # Cancel Safety
+        ///
+        /// Cancelling loses a frame which was taken from the stream but not
+        /// sent yet.
+        pub async fn forward<S>(
+            &mut self,
+            frames: S,
+            options: &ForwardOptions,
+        ) -> Result<ForwardStats, SendError>
+        where
+            S: Stream,
+            S::Item: Into<CanFrame>,
+        {
+            let mut frames = pin!(frames);
+            let mut stats = ForwardStats::default();
+            let mut next_due = Instant::now();
+
+            while let Some(frame) = poll_fn(|cx| frames.as_mut().poll_next(cx)).await {
+                let frame = frame.into();
+                let mut attempts = 0;
+
+                time::sleep_until(next_due.into()).await;
+
+                loop {
+                    let error = match self.send(frame.clone()).await {
+                        Ok(()) => {
+                            stats.sent += 1;
+                            break;
+                        }
+                        Err(e) => e,
+                    };
+
+                    match options.error_policy() {
+                        ForwardErrorPolicy::Skip
+                            if error.is_retryable() || !matches!(error, SendError::Io(_)) =>
+                        {
+                            stats.skipped += 1;
+                            break;
+                        }
+                        ForwardErrorPolicy::Retry {
+                            attempts: max_attempts,
+                            backoff,
+                        } if error.is_retryable() && attempts < max_attempts => {
+                            attempts += 1;
+                            time::sleep(backoff).await;
+                        }
+                        _ => return Err(error),
+                    }
+                }
+
+                next_due = Instant::now() + options.interval();
+            }
+
+            Ok(stats)
+        }
+
+        /// Reads the answers of the gateway until there is room in its
+        /// transmit queue, according to the [TxQueuePolicy]
+        async fn wait_for_tx_queue(&mut self) -> Result<(), SendError> {
+            let TxQueuePolicy::Wait { capacity, timeout } = self.tx_queue_policy else {
+                return Ok(());
+            };
+
+            let wait = async {
+                while self.unacknowledged >= capacity.max(1) {
+                    match self.read_message().await {
+                        Ok(Message::Frame(frame, timestamp)) => {
+                            self.queue_received(frame, timestamp)
+                        }
+                        Ok(_) => {}
+                        Err(ReadError::Io(e)) => return Err(e.into()),
+                        Err(_) => {}
+                    }
+                }
+
+                Ok(())
+            };
+
+            match time::timeout(timeout, wait).await {
+                Ok(result) => result,
+                Err(_) => Err(SendError::TimedOut),
+            }
+        }
+
+        /// Writes a frame to the gateway, bypassing the interceptors
+        async fn transmit(&mut self, frame: CanFrame) -> Result<(), SendError> {
+            let slcan_frame =
+                SlcanFrame::try_from(frame.clone()).map_err(|_| SendError::Unsupported)?;
+
+            if matches!(frame, CanFrame::CanFd(_)) && self.dialect == Dialect::UsbTin {
+                return Err(SendError::Unsupported);
+            }
+
+            if let Some(filter) = self.tx_filter.as_ref() {
+                if !filter.matches(frame.id()) {
+                    return Err(SendError::Blocked(frame.id()));
+                }
+            }
+
+            self.wait_for_tx_queue().await?;
+            self.send_command(Command::TransmitFrame(slcan_frame))
+                .await?;
+            metrics::record_frame_transmitted(&frame);
+            self.observers.tx_frame(&frame);
+            self.unacknowledged += 1;
+            metrics::record_tx_queue_depth(self.unacknowledged);
+            self.transmitted += 1;
+
+            #[cfg(feature = "logging")]
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record_frame(&frame, FrameDirection::Tx, self.channel);
+            }
+
+            if self.tx_echo {
+                self.rx_queue.push_back(RxFrame {
+                    frame,
+                    timestamp: None,
+                    direction: FrameDirection::Tx,
+                    channel: self.channel,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Sends a CAN frame and waits for the gateway to acknowledge that it
+        /// was queued for transmission, which distinguishes frames that were
+        /// accepted from ones silently dropped by the firmware.
+        ///
+        /// Frames received while waiting are buffered and returned by later
+        /// reads. Acknowledgements still outstanding for frames sent earlier
+        /// with [`send`](CanSocket::send) are accounted for, so the returned
+        /// status always refers to this frame.
+        /// If an [Interceptor] drops the frame, `TimedOut` is returned right
+        /// away. If it replaces the frame, the status of the last replacement
+        /// is returned.
+        pub async fn send_and_confirm(
+            &mut self,
+            frame: impl Into<CanFrame>,
+            timeout: Duration,
+        ) -> Result<TransmitStatus, SendError> {
+            let transmitted = self.transmitted;
+
+            self.send(frame).await?;
+
+            // Interceptors may have dropped the frame or replaced it with
+            // several, in which case the status of the last one is returned.
+            // Earlier frames may have been acknowledged while waiting for
+            // room in the transmit queue, so only the ones still in flight
+            // are ahead of it.
+            if self.transmitted == transmitted {
+                return Ok(TransmitStatus::TimedOut);
+            }
+            let mut ahead = self.unacknowledged - 1;
+
+            let wait = async {
+                loop {
+                    let status = match self.read_message().await {
+                        Ok(Message::Frame(frame, timestamp)) => {
+                            self.queue_received(frame, timestamp);
+                            continue;
+                        }
+                        Ok(Message::TransmitAck) => TransmitStatus::Acknowledged,
+                        Ok(Message::Nack) => TransmitStatus::Rejected,
+                        Ok(
+                            Message::BusStatus(_) | Message::Version(_) | Message::ErrorRegister(_),
+                        ) => continue,
+                        Err(ReadError::Io(e)) => return Err(e.into()),
+                        Err(
+                            ReadError::Slcan(_)
+                            | ReadError::BusOff
+                            | ReadError::LineTooLong(_)
+                            | ReadError::DeviceUnresponsive,
+                        ) => continue,
+                    };
+
+                    if ahead == 0 {
+                        return Ok(status);
+                    }
+
+                    ahead -= 1;
+                }
+            };
+
+            match time::timeout(timeout, wait).await {
+                Ok(result) => result,
+                Err(_) => Ok(TransmitStatus::TimedOut),
+            }
+        }
+
+        /// Sends a request frame and waits for the first received frame for
+        /// which `matches` returns true, e.g. one with the expected response
+        /// ID. Returns `None` if no matching frame arrives within `timeout`.
+        ///
+        /// Frames skipped by earlier requests are considered first. Other
+        /// frames received while waiting are buffered and returned by later
+        /// reads, so no traffic is lost. All frames pass the interceptors and
+        /// the [RemoteResponder] like with [`read`](CanSocket::read).
+        pub async fn request(
+            &mut self,
+            frame: impl Into<CanFrame>,
+            mut matches: impl FnMut(&CanFrame) -> bool,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, SendError> {
+            self.send(frame).await?;
+
+            if let Some(frame) = self.take_skipped(&mut matches) {
+                return Ok(Some(frame));
+            }
+
+            let wait = async {
+                loop {
+                    match self.read_next_rx_frame().await {
+                        Ok(frame) if matches(&frame.frame) => return Ok(frame.frame),
+                        Ok(frame) => self.skipped.push_back(frame),
+                        Err(ReadError::Io(e)) => return Err(e.into()),
+                        Err(
+                            ReadError::Slcan(_)
+                            | ReadError::BusOff
+                            | ReadError::LineTooLong(_)
+                            | ReadError::DeviceUnresponsive,
+                        ) => {}
+                    }
+                }
+            };
+
+            match time::timeout(timeout, wait).await {
+                Ok(result) => result.map(Some),
+                Err(_) => Ok(None),
+            }
+        }
+
+        /// Probes which features the gateway supports, so generic tools can
+        /// adapt to whatever adapter is plugged in. The firmware version is
+        /// queried and the result is refined with trial commands, waiting up
+        /// to `timeout` for the answer to each.
+        ///
+        /// Every [DataBitRate] is tried in turn. The trial commands then
+        /// reapply the data bit rate, timestamp mode and auto retransmission
+        /// mode configured so far (or the defaults), so this should be called
+        /// while the channel is closed. Frames received
+        /// while probing are buffered and returned by later reads.
+        pub async fn capabilities(&mut self, timeout: Duration) -> Result<Capabilities, ReadError> {
+            let (version, _) = self.probe(None, true, timeout).await?;
+            let query_version = version.is_some();
+            let mut capabilities = Capabilities::from_version(version.as_deref());
+
+            // The configured rate is probed last so that it stays applied
+            let data_bit_rate = self.config.data_bit_rate.unwrap_or_default();
+            let rates = DataBitRate::ALL
+                .into_iter()
+                .filter(|&rate| rate != data_bit_rate)
+                .chain([data_bit_rate]);
+            for rate in rates {
+                let command = Command::SetDataBitRate(rate);
+                if self.probe(Some(command), query_version, timeout).await?.1 == 0 {
+                    capabilities.data_bit_rates.push(rate);
+                }
+            }
+            capabilities.data_bit_rates.sort();
+            capabilities.brs = !capabilities.data_bit_rates.is_empty();
+            capabilities.fd |= capabilities.brs;
+
+            let command = Command::SetTimestampMode(self.timestamp_mode);
+            capabilities.timestamps =
+                self.probe(Some(command), query_version, timeout).await?.1 == 0;
+
+            let mode = self.config.auto_retransmission.unwrap_or_default();
+            let command = Command::SetAutoRetransmission(mode);
+            capabilities.auto_retransmission =
+                self.probe(Some(command), query_version, timeout).await?.1 == 0;
+
+            Ok(capabilities)
+        }
+
+        /// Cycles through the nominal bit rates in silent mode until frames
+        /// are received, and returns the bit rate they were received at, e.g.
+        /// for plugging into a bus whose bit rate is unknown. Each rate is
+        /// listened to for up to `dwell`, or until the gateway reports bus
+        /// errors, either on its own or in answer to an error register query
+        /// (`E`) sent halfway through. Returns `None` if no frames were
+        /// received at any rate, e.g. because the bus is idle.
+        ///
+        /// The channel is left closed with the detected bit rate set, so it
+        /// can be opened with [`open_channel`](CanSocket::open_channel). The
+        /// operating mode configured before is restored, as is the bit rate if
+        /// none was detected. The first frame
+        /// received at the detected rate is buffered and returned by a later
+        /// read.
+        pub async fn autodetect_bit_rate(
+            &mut self,
+            dwell: Duration,
+        ) -> Result<Option<NominalBitRate>, ReadError> {
+            if self.open {
+                self.close().await?;
+            }
+
+            let operating_mode = self.config.operating_mode;
+            let nominal_bit_rate = self.config.nominal_bit_rate;
+            self.set_operating_mode(OperatingMode::Silent).await?;
+
+            let mut detected = None;
+            for rate in NominalBitRate::ALL {
+                self.set_nominal_bit_rate(rate).await?;
+                self.open_channel().await?;
+                let received = self.listen(dwell).await?;
+                self.close().await?;
+
+                if received {
+                    detected = Some(rate);
+                    break;
+                }
+            }
+
+            self.set_operating_mode(operating_mode.unwrap_or_default())
+                .await?;
+            self.config.operating_mode = operating_mode;
+            if detected.is_none() {
+                match nominal_bit_rate {
+                    Some(rate) => self.set_nominal_bit_rate(rate).await?,
+                    None => self.config.nominal_bit_rate = None,
+                }
+            }
+            self.bus_status = BusStatus::default();
+
+            Ok(detected)
+        }
+
+        /// Samples the traffic and the bus state reports of the gateway for
+        /// `window` and returns a [BusDiagnosis] with the bus load, the trend
+        /// of the error counters and the fault the bus is suspected to have,
+        /// e.g. for troubleshooting an installation in the field.
+        ///
+        /// The channel should be open. Frames received while sampling are
+        /// consumed by the diagnosis and not returned by reads. The bus load
+        /// is only estimated if the nominal bit rate was set through the
+        /// socket.
+        pub async fn diagnose(&mut self, window: Duration) -> Result<BusDiagnosis, ReadError> {
+            let mut sampler = DiagnosisSampler::new(
+                self.bus_status,
+                self.config.nominal_bit_rate,
+                self.config.data_bit_rate.unwrap_or_default(),
+            );
+
+            let sample = async {
+                loop {
+                    match self.read_message().await {
+                        Ok(Message::Frame(frame, _)) => sampler.record_frame(&frame),
+                        Ok(Message::BusStatus(status)) => sampler.record_status(status),
+                        Ok(_) => {}
+                        Err(ReadError::Slcan(_)) => sampler.record_parse_error(),
+                        Err(ReadError::Io(e)) => return e,
+                        Err(_) => {}
+                    }
+                }
+            };
+
+            if let Ok(e) = time::timeout(window, sample).await {
+                return Err(e.into());
+            }
+
+            Ok(sampler.finish(window))
+        }
+
+        /// Reads the next line exactly as the gateway sent it, without the CR
+        /// line ending and without parsing it, e.g. for showing the traffic
+        /// in a protocol debugging tool even when it can't be parsed.
+        ///
+        /// Lines read this way bypass the typed API: frames aren't recorded or
+        /// answered, acknowledgements aren't counted and bus state reports
+        /// aren't tracked. Frames already buffered for [`read`](CanSocket::read)
+        /// aren't returned. A NACK is returned as a line containing only the
+        /// BEL character (0x07).
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read).
+        pub async fn read_raw_line(&mut self) -> Result<Vec<u8>, ReadError> {
+            self.read_line().await?;
+            Ok(self.line.clone())
+        }
+
+        /// Sends a line to the gateway exactly as given, with a CR line ending
+        /// appended, e.g. for issuing commands this crate has no typed API
+        /// for. The line is written in a single write.
+        pub async fn send_raw_line(&mut self, line: &[u8]) -> io::Result<()> {
+            let mut buffer = Vec::with_capacity(line.len() + 1);
+            buffer.extend_from_slice(line);
+            buffer.push(b'\r');
+
+            self.port_pin_mut().write_all(&buffer).await?;
+            self.port_pin_mut().flush().await?;
+
+            if let Some(callback) = self.on_raw_tx.as_mut() {
+                callback(line);
+            }
+
+            Ok(())
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway identify itself, e.g. by blinking its LED, for firmwares
+        /// which have such a command. SLCAN doesn't define one, so it has to
+        /// be taken from the documentation of the firmware. See
+        /// [`identify`](CanSocket::identify).
+        pub fn set_identify_command(&mut self, command: Option<&[u8]>) {
+            self.identify_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Makes the gateway identify itself with the command set with
+        /// [`set_identify_command`](CanSocket::set_identify_command), e.g. for
+        /// finding out which adapter of a rig is connected to which port.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// identify command was set.
+        pub async fn identify(&mut self) -> io::Result<()> {
+            let Some(command) = self.identify_command.clone() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no identify command was set for this gateway",
+                ));
+            };
+
+            self.send_raw_line(&command).await
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway report the state of the bus along with its error counters
+        /// (TEC/REC), for firmwares which have such a command. It has to be
+        /// answered with a bus state report like `sa005000`. SLCAN doesn't
+        /// define one, so it has to be taken from the documentation of the
+        /// firmware. See [`get_error_counters`](CanSocket::get_error_counters).
+        pub fn set_error_counters_command(&mut self, command: Option<&[u8]>) {
+            self.error_counters_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Queries the error counters of the CAN controller with the command
+        /// set with
+        /// [`set_error_counters_command`](CanSocket::set_error_counters_command)
+        /// and waits up to `timeout` for the answer. Returns `None` if the
+        /// gateway didn't answer in time.
+        ///
+        /// The answer is handled like any other bus state report, so it also
+        /// updates [`bus_status`](CanSocket::bus_status) and is passed to the
+        /// observers and the bus status callback. See
+        /// [`BusStatus::derived_state`] for the state implied by the counters.
+        /// Frames received while waiting are buffered and returned by later
+        /// reads.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no error
+        /// counters command was set or the gateway rejected it.
+        pub async fn get_error_counters(
+            &mut self,
+            timeout: Duration,
+        ) -> Result<Option<BusStatus>, ReadError> {
+            let command = self.error_counters_command()?;
+            self.send_raw_line(&command).await?;
+
+            let wait = async {
+                loop {
+                    match self.read_message().await {
+                        Ok(Message::Frame(frame, timestamp)) => {
+                            self.queue_received(frame, timestamp)
+                        }
+                        Ok(Message::BusStatus(status)) => return Ok(status),
+                        Ok(Message::Nack) => return Err(error_counters_rejected().into()),
+                        Ok(
+                            Message::TransmitAck | Message::Version(_) | Message::ErrorRegister(_),
+                        ) => {}
+                        Err(ReadError::Io(e)) => return Err(e.into()),
+                        Err(
+                            ReadError::Slcan(_)
+                            | ReadError::BusOff
+                            | ReadError::LineTooLong(_)
+                            | ReadError::DeviceUnresponsive,
+                        ) => {}
+                    }
+                }
+            };
+
+            match time::timeout(timeout, wait).await {
+                Ok(result) => result.map(Some),
+                Err(_) => Ok(None),
+            }
+        }
+
+        /// Sets the command (without the CR line ending) which makes the
+        /// gateway reboot into its bootloader (DFU) for a firmware update, for
+        /// firmwares which have such a command. SLCAN doesn't define one, so
+        /// it has to be taken from the documentation of the firmware. See
+        /// [`enter_bootloader`](CanSocket::enter_bootloader).
+        pub fn set_bootloader_command(&mut self, command: Option<&[u8]>) {
+            self.bootloader_command = command.map(<[u8]>::to_vec);
+        }
+
+        /// Makes the gateway reboot into its bootloader with the command set
+        /// with [`set_bootloader_command`](CanSocket::set_bootloader_command),
+        /// so its firmware can be updated.
+        ///
+        /// The gateway disconnects while rebooting, so the socket can't be
+        /// used afterwards. The channel is considered closed, so dropping the
+        /// socket doesn't send a close command.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// bootloader command was set.
+        pub async fn enter_bootloader(&mut self) -> io::Result<()> {
+            let Some(command) = self.bootloader_command.clone() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no bootloader command was set for this gateway",
+                ));
+            };
+
+            self.send_raw_line(&command).await?;
+            self.open = false;
+            self.observers.channel_state(false);
+            Ok(())
+        }
+
+        /// Sets the commands (without the CR line ending) which switch the
+        /// 120Ω termination resistor of the gateway on and off, for adapters
+        /// with switchable termination. SLCAN doesn't define such commands, so
+        /// they have to be taken from the documentation of the firmware. See
+        /// [`set_termination`](CanSocket::set_termination).
+        pub fn set_termination_commands(&mut self, commands: Option<(&[u8], &[u8])>) {
+            self.termination_commands = commands.map(|(on, off)| (on.to_vec(), off.to_vec()));
+        }
+
+        /// Switches the termination resistor of the gateway on or off with the
+        /// commands set with
+        /// [`set_termination_commands`](CanSocket::set_termination_commands),
+        /// so bench setups can be reconfigured without touching the hardware.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no
+        /// termination commands were set.
+        pub async fn set_termination(&mut self, enabled: bool) -> io::Result<()> {
+            let command = match &self.termination_commands {
+                Some((on, _)) if enabled => on.clone(),
+                Some((_, off)) => off.clone(),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "no termination commands were set for this gateway",
+                    ))
+                }
+            };
+
+            self.send_raw_line(&command).await
+        }
+
+        /// Sets the commands (without the CR line ending) which select the
+        /// ISO and the non-ISO CAN FD CRC, for firmwares which support both.
+        /// SLCAN doesn't define such commands, so they have to be taken from
+        /// the documentation of the firmware. See
+        /// [`set_fd_crc_mode`](CanSocket::set_fd_crc_mode).
+        pub fn set_fd_crc_commands(&mut self, commands: Option<(&[u8], &[u8])>) {
+            self.fd_crc_commands = commands.map(|(iso, non_iso)| (iso.to_vec(), non_iso.to_vec()));
+        }
+
+        /// Selects the CRC of CAN FD frames with the commands set with
+        /// [`set_fd_crc_commands`](CanSocket::set_fd_crc_commands). Like the
+        /// bit rates, this should be set while the channel is closed. The mode
+        /// is applied again when the channel is reopened.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if no FD
+        /// CRC commands were set.
+        pub async fn set_fd_crc_mode(&mut self, mode: FdCrcMode) -> io::Result<()> {
+            let command = match (&self.fd_crc_commands, mode) {
+                (Some((iso, _)), FdCrcMode::Iso) => iso.clone(),
+                (Some((_, non_iso)), FdCrcMode::NonIso) => non_iso.clone(),
+                (None, _) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "no FD CRC commands were set for this gateway",
+                    ))
+                }
+            };
+
+            self.send_raw_line(&command).await?;
+            self.config.fd_crc_mode = Some(mode);
+            Ok(())
+        }
+
+        /// Declares that the gateway has `count` hardware filter banks and
+        /// how to program them: `encode` returns the command (without the CR
+        /// line ending) which sets the bank with the given index to a filter,
+        /// or clears it for `None`. SLCAN doesn't define filter commands, so
+        /// they have to be taken from the documentation of the firmware. Some
+        /// firmwares only accept them while the channel is closed.
+        ///
+        /// All banks are programmed right away for the current filters. See
+        /// [`add_filter`](CanSocket::add_filter).
+        pub async fn set_filter_banks(
+            &mut self,
+            count: usize,
+            encode: impl Fn(usize, Option<&AcceptanceFilter>) -> Vec<u8> + Send + 'static,
+        ) -> io::Result<()> {
+            self.acceptance.set_banks(count, Some(Box::new(encode)));
+            self.program_filter_banks(true).await
+        }
+
+        /// Adds an acceptance filter. Once there are any filters, only frames
+        /// passing at least one of them are received.
+        ///
+        /// The filters are programmed into the filter banks of the gateway
+        /// (see [`set_filter_banks`](CanSocket::set_filter_banks)) to take load
+        /// off the serial link. If there are more filters than banks, filters
+        /// are merged into wider ones which pass a superset of the frames. All
+        /// frames are filtered in software as well, so the result is exact
+        /// either way.
+        pub async fn add_filter(&mut self, filter: AcceptanceFilter) -> io::Result<()> {
+            self.acceptance.push(filter);
+            self.program_filter_banks(false).await
+        }
+
+        /// Removes an acceptance filter added with
+        /// [`add_filter`](CanSocket::add_filter). Returns false if there was no
+        /// such filter.
+        pub async fn remove_filter(&mut self, filter: &AcceptanceFilter) -> io::Result<bool> {
+            if !self.acceptance.remove(filter) {
+                return Ok(false);
+            }
+
+            self.program_filter_banks(false).await?;
+            Ok(true)
+        }
+
+        /// Removes all acceptance filters, so all frames are received again
+        pub async fn clear_filters(&mut self) -> io::Result<()> {
+            self.acceptance.clear();
+            self.program_filter_banks(false).await
+        }
+
+        /// Returns the acceptance filters added with
+        /// [`add_filter`](CanSocket::add_filter)
+        pub fn filters(&self) -> &[AcceptanceFilter] {
+            self.acceptance.filters()
+        }
+
+        /// Returns the filter programmed into each filter bank of the gateway,
+        /// or `None` for empty banks
+        pub fn filter_banks(&self) -> &[Option<AcceptanceFilter>] {
+            self.acceptance.banks()
+        }
+
+        /// Reads a line from the serial stream and attempts to parse it as a
+        /// valid CAN frame.
+        ///
+        /// # Errors
+        ///
+        /// An error will be returned for any kinds of I/O errors besides
+        /// WouldBlock or TimedOut.
+        ///
+        /// An error will also be returned if the received line cannot be
+        /// parsed as a valid CAN frame for any number of reasons. See
+        /// [MessageParseError](crate::MessageParseError).
+        ///
+        /// # Cancel Safety
         ///
         /// This method is cancel safe. If you use it as the event in a
         /// [`tokio::select`] statement and some other branch completes first,
         /// then it is guaranteed that either no data was read, or any read
         /// data was stored appropriately. Future calls to `read` will use this
-        /// buffered data to continue construction of the next frame.
+        /// buffered data to continue construction of the next frame. The one
+        /// exception is the response of an attached [RemoteResponder], which
+        /// may be lost if the read is cancelled while it is transmitted. A
+        /// [keepalive](CanSocket::set_keepalive) query which was cut off is
+        /// completed by the next read or command.
         pub async fn read(&mut self) -> Result<CanFrame, ReadError> {
-            Ok(parse_frame_from_bytes(&self.read_line().await?)?)
+            Ok(self.read_rx_frame().await?.frame)
+        }
+
+        /// Reads frames until one with any of the given IDs is received,
+        /// discarding all others. See [`read`](CanSocket::read).
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read), non-matching frames which were already
+        /// discarded are not recovered.
+        pub async fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError> {
+            self.read_matching(|frame| ids.contains(&frame.id())).await
+        }
+
+        /// Reads frames until one for which `matches` returns true is
+        /// received, discarding all others. See [`read`](CanSocket::read).
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read), non-matching frames which were already
+        /// discarded are not recovered.
+        pub async fn read_matching(
+            &mut self,
+            mut matches: impl FnMut(&CanFrame) -> bool,
+        ) -> Result<CanFrame, ReadError> {
+            loop {
+                let frame = self.read().await?;
+
+                if matches(&frame) {
+                    return Ok(frame);
+                }
+            }
+        }
+
+        /// Waits for the next frame with the given ID, discarding all others.
+        /// Returns `None` if no such frame arrives within `timeout`.
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read).
+        pub async fn await_frame(
+            &mut self,
+            id: impl Into<Id>,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, ReadError> {
+            let id = id.into();
+            self.await_matching(|frame| frame.id() == id, timeout).await
+        }
+
+        /// Waits for the next frame for which `matches` returns true,
+        /// discarding all others. Returns `None` if no such frame arrives
+        /// within `timeout`.
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read).
+        pub async fn await_matching(
+            &mut self,
+            matches: impl FnMut(&CanFrame) -> bool,
+            timeout: Duration,
+        ) -> Result<Option<CanFrame>, ReadError> {
+            match time::timeout(timeout, self.read_matching(matches)).await {
+                Ok(result) => result.map(Some),
+                Err(_) => Ok(None),
+            }
+        }
+
+        /// Reads the next frame like [`read`](CanSocket::read), but returns
+        /// `None` as soon as `cancelled` completes instead, e.g. for ending a
+        /// read loop as part of a graceful shutdown. Any future signalling
+        /// the shutdown can be used, such as
+        /// `tokio_util::sync::CancellationToken::cancelled` or
+        /// `tokio::signal::ctrl_c`. If both are ready at once, the
+        /// cancellation wins.
+        ///
+        /// ```no_run
+        /// # async fn run(
+        /// #     mut socket: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>,
+        /// #     mut shutdown: tokio::sync::watch::Receiver<bool>,
+        /// # ) -> Result<(), slcan_fd::ReadError> {
+        /// while let Some(frame) = socket
+        ///     .read_until_cancelled(shutdown.wait_for(|&stop| stop))
+        ///     .await?
+        /// {
+        ///     println!("{:?}", frame);
+        /// }
+        ///
+        /// socket.shutdown().await?;
+        /// # Ok(())
+        /// # }
+        /// ```
+        ///
+        /// # Cancel Safety
+        ///
+        /// No data is lost when cancelled, in the same way as
+        /// [`read`](CanSocket::read).
+        pub async fn read_until_cancelled(
+            &mut self,
+            cancelled: impl Future,
+        ) -> Result<Option<CanFrame>, ReadError> {
+            tokio::select! {
+                biased;
+                _ = cancelled => Ok(None),
+                result = self.read() => result.map(Some),
+            }
+        }
+
+        /// Same as [`read`](CanSocket::read) but also returns the metadata
+        /// of the frame, such as its hardware timestamp. See [RxFrame].
+        ///
+        /// # Cancel Safety
+        ///
+        /// This method is cancel safe in the same way as
+        /// [`read`](CanSocket::read).
+        pub async fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError> {
+            if let Some(frame) = self.skipped.pop_front() {
+                self.last_timestamp = frame.timestamp;
+                return Ok(frame);
+            }
+
+            self.read_next_rx_frame().await
+        }
+
+        /// Removes and returns the first frame skipped by an earlier
+        /// [`request`](CanSocket::request) for which `matches` returns true
+        fn take_skipped(&mut self, mut matches: impl FnMut(&CanFrame) -> bool) -> Option<CanFrame> {
+            let index = self
+                .skipped
+                .iter()
+                .position(|frame| matches(&frame.frame))?;
+            let frame = self.skipped.remove(index)?;
+
+            self.last_timestamp = frame.timestamp;
+            Some(frame.frame)
+        }
+
+        /// Reads the next frame which wasn't skipped by a
+        /// [`request`](CanSocket::request), applying the interceptors
+        async fn read_next_rx_frame(&mut self) -> Result<RxFrame, ReadError> {
+            loop {
+                if let Some(&(_, due)) = self.intercepted.front() {
+                    time::sleep_until(due.into()).await;
+                    let (frame, _) = self.intercepted.pop_front().expect("checked above");
+                    return self.deliver(frame).await;
+                }
+
+                let frame = match self.rx_queue.pop_front() {
+                    Some(frame) => frame,
+                    None => self.read_port_frame().await?,
+                };
+
+                if frame.direction == FrameDirection::Rx && !self.interceptors.is_empty() {
+                    let now = Instant::now();
+                    let frames = self
+                        .interceptors
+                        .apply(frame.frame.clone(), FrameDirection::Rx);
+
+                    for (intercepted, delay) in frames {
+                        let intercepted = RxFrame {
+                            frame: intercepted,
+                            ..frame.clone()
+                        };
+                        self.intercepted.push_back((intercepted, now + delay));
+                    }
+
+                    continue;
+                }
+
+                return self.deliver(frame).await;
+            }
+        }
+
+        /// Hands a frame to the caller of [`read_rx_frame`](CanSocket::read_rx_frame)
+        async fn deliver(&mut self, frame: RxFrame) -> Result<RxFrame, ReadError> {
+            self.last_timestamp = frame.timestamp;
+            self.answer_remote_request(&frame).await?;
+            Ok(frame)
+        }
+
+        /// Reads messages from the gateway until a frame is received
+        async fn read_port_frame(&mut self) -> Result<RxFrame, ReadError> {
+            loop {
+                match self.read_message().await? {
+                    Message::Frame(frame, timestamp) => {
+                        return Ok(RxFrame {
+                            frame,
+                            timestamp,
+                            direction: FrameDirection::Rx,
+                            channel: self.channel,
+                        });
+                    }
+                    Message::BusStatus(status) if status.state == BusState::BusOff => {
+                        self.handle_bus_off().await?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        /// Transmits the response registered with the [RemoteResponder] if
+        /// `frame` is a received remote request
+        async fn answer_remote_request(&mut self, frame: &RxFrame) -> io::Result<()> {
+            let response = match (&frame.frame, self.remote_responder.as_mut()) {
+                (CanFrame::Can2(request), Some(responder))
+                    if frame.direction == FrameDirection::Rx =>
+                {
+                    responder.respond(request)
+                }
+                _ => None,
+            };
+
+            let result = match response {
+                Some(response) => self.send(response).await,
+                None => Ok(()),
+            };
+
+            // Requests can't be answered while the channel is closed or
+            // silent, which isn't an error for the read
+            match result {
+                Err(SendError::Io(e)) => Err(e),
+                _ => Ok(()),
+            }
+        }
+
+        /// Sends `command` (if any) followed by a version query (if
+        /// `query_version` is set) and reads the answers until the query is
+        /// answered or `timeout` elapses. Returns the answer to the query
+        /// along with the number of NACKs received before it, since the
+        /// gateway answers commands in order.
+        async fn probe(
+            &mut self,
+            command: Option<Command>,
+            query_version: bool,
+            timeout: Duration,
+        ) -> Result<(Option<Vec<u8>>, usize), ReadError> {
+            if let Some(command) = command {
+                self.send_command(command).await?;
+            }
+            if query_version {
+                self.send_command(Command::GetFirmwareVersion).await?;
+                self.version_pending = true;
+            }
+
+            let mut nacks = 0;
+
+            let wait = async {
+                loop {
+                    match self.read_message().await {
+                        Ok(Message::Version(answer)) if query_version => return Ok(answer),
+                        Ok(Message::Nack) => nacks += 1,
+                        Ok(Message::Frame(frame, timestamp)) => {
+                            self.queue_received(frame, timestamp)
+                        }
+                        Ok(_) => {}
+                        Err(ReadError::Io(e)) => return Err(e),
+                        Err(_) => {}
+                    }
+                }
+            };
+
+            let answer = match time::timeout(timeout, wait).await {
+                Ok(answer) => Some(answer?),
+                Err(_) => {
+                    self.version_pending = false;
+                    None
+                }
+            };
+
+            Ok((answer, nacks))
+        }
+
+        /// Reads from the open channel for up to `dwell`. Returns true once a
+        /// frame is received, or false if the gateway reports bus errors.
+        /// Halfway through, the error register is queried for firmwares which
+        /// don't report the bus state on their own.
+        async fn listen(&mut self, dwell: Duration) -> Result<bool, ReadError> {
+            if let Ok(received) = time::timeout(dwell / 2, self.watch_bus()).await {
+                return Ok(received?);
+            }
+
+            self.send_command(Command::GetErrorRegister).await?;
+            let received = time::timeout(dwell - dwell / 2, self.watch_bus()).await;
+            Ok(received.unwrap_or(Ok(false))?)
+        }
+
+        /// Reads until a frame is received (returning true) or the gateway
+        /// reports bus errors (returning false)
+        async fn watch_bus(&mut self) -> io::Result<bool> {
+            loop {
+                match self.read_message().await {
+                    Ok(Message::Frame(frame, timestamp)) => {
+                        self.queue_received(frame, timestamp);
+                        return Ok(true);
+                    }
+                    Ok(Message::BusStatus(status)) if status.has_errors() => return Ok(false),
+                    Ok(Message::ErrorRegister(flags)) if flags != 0 => return Ok(false),
+                    Ok(_) => {}
+                    Err(ReadError::Io(e)) => return Err(e),
+                    Err(_) => {}
+                }
+            }
+        }
+
+        /// Applies the [BusOffPolicy] after the gateway reported bus-off
+        async fn handle_bus_off(&mut self) -> Result<(), ReadError> {
+            let BusOffPolicy::Recover { delay } = self.bus_off_policy else {
+                return Err(ReadError::BusOff);
+            };
+
+            self.close().await?;
+            time::sleep(delay).await;
+            self.reopen().await?;
+            metrics::record_reconnect("bus_off");
+
+            self.bus_status = BusStatus::default();
+            Ok(())
+        }
+
+        /// Reapplies the settings applied so far and opens the channel
+        async fn reopen(&mut self) -> io::Result<()> {
+            self.reapply_config().await?;
+            self.open_channel().await
+        }
+
+        /// Reapplies the settings applied so far, without opening the channel
+        async fn reapply_config(&mut self) -> io::Result<()> {
+            let config = self.config;
+            if let Some(rate) = config.data_bit_rate {
+                self.set_data_bit_rate(rate).await?;
+            }
+            if let Some(mode) = config.operating_mode {
+                self.set_operating_mode(mode).await?;
+            }
+            if let Some(mode) = config.auto_retransmission {
+                self.set_auto_retransmission_mode(mode).await?;
+            }
+            if let Some(mode) = config.timestamp_mode {
+                self.set_timestamp_mode(mode).await?;
+            }
+            if let Some(mode) = config.fd_crc_mode {
+                self.set_fd_crc_mode(mode).await?;
+            }
+            if let Some(rate) = config.nominal_bit_rate {
+                self.set_nominal_bit_rate(rate).await?;
+            }
+            Ok(())
+        }
+
+        /// Sends the commands for the filter banks whose contents changed, or
+        /// for all banks if `all` is set
+        async fn program_filter_banks(&mut self, all: bool) -> io::Result<()> {
+            for command in self.acceptance.update_banks(all) {
+                self.send_raw_line(&command).await?;
+            }
+
+            Ok(())
+        }
+
+        /// Returns the command set with `set_error_counters_command`, or an
+        /// error of kind [`io::ErrorKind::Unsupported`] if there is none
+        fn error_counters_command(&self) -> io::Result<Vec<u8>> {
+            self.error_counters_command.clone().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "no error counters command was set for this gateway",
+                )
+            })
+        }
+
+        /// Returns an error if the channel is in a state where frames can't
+        /// be sent
+        fn check_can_send(&self) -> Result<(), SendError> {
+            if !self.open {
+                return Err(SendError::ChannelClosed);
+            }
+
+            if self.config.operating_mode == Some(OperatingMode::Silent) {
+                return Err(SendError::ListenOnly);
+            }
+
+            Ok(())
+        }
+
+        /// Returns an error of kind [`io::ErrorKind::Unsupported`] if the
+        /// dialect of the gateway has no command for `feature`
+        fn check_dialect_supports(&self, feature: &str) -> io::Result<()> {
+            if self.dialect == Dialect::UsbTin {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("USBtin adapters don't support {feature}"),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Counts the answer of the gateway to a frame in flight and reports
+        /// it to the observers
+        fn record_transmit_status(&mut self, status: TransmitStatus) {
+            self.stats.record_transmit_status(status);
+            metrics::record_tx_queue_depth(self.unacknowledged);
+            self.observers.transmit_status(status, self.unacknowledged);
+        }
+
+        /// Buffers a received frame to be returned by a later read
+        fn queue_received(&mut self, frame: CanFrame, timestamp: Option<Timestamp>) {
+            self.rx_queue.push_back(RxFrame {
+                frame,
+                timestamp,
+                direction: FrameDirection::Rx,
+                channel: self.channel,
+            });
+        }
+
+        /// Reads and parses the next message from the gateway, keeping track
+        /// of transmit acknowledgements
+        async fn read_message(&mut self) -> Result<Message, ReadError> {
+            loop {
+                if let Some(message) = self.read_next_message().await? {
+                    return Ok(message);
+                }
+            }
+        }
+
+        /// Reads and handles a single message. Returns `None` for frames which
+        /// don't pass the acceptance filters, which are dropped before anything
+        /// else gets to see them, as if the gateway had filtered them.
+        async fn read_next_message(&mut self) -> Result<Option<Message>, ReadError> {
+            if let Err(e) = self.read_line().await {
+                self.observers.error(&e);
+                return Err(e);
+            }
+
+            let message = match parse_message_from_bytes(&self.line, self.timestamp_mode) {
+                Ok(message) => message,
+                // Firmwares answer the version query in different formats
+                Err(_)
+                    if self.version_pending && matches!(self.line.first(), Some(b'V' | b'v')) =>
+                {
+                    Message::Version(self.line.clone())
+                }
+                Err(error) => {
+                    metrics::record_parse_error(&error);
+                    self.stats.record_parse_error(&error, self.line.len());
+                    let line = self.line.clone();
+                    let error = LineParseError { line, error }.into();
+                    self.observers.error(&error);
+                    return Err(error);
+                }
+            };
+
+            match &message {
+                Message::Frame(frame, _) if !self.acceptance.accepts(frame.id()) => {
+                    return Ok(None);
+                }
+                Message::Frame(frame, timestamp) => {
+                    metrics::record_frame_received(frame);
+                    self.observers.rx_frame(frame, *timestamp);
+
+                    #[cfg(feature = "logging")]
+                    if let Some(recorder) = self.recorder.as_mut() {
+                        recorder.record_frame(frame, FrameDirection::Rx, self.channel);
+                    }
+                }
+                Message::TransmitAck => {
+                    self.unacknowledged = self.unacknowledged.saturating_sub(1);
+                    self.record_transmit_status(TransmitStatus::Acknowledged);
+                }
+                // A NACK only answers a frame while one is in flight, otherwise
+                // it refers to some other command
+                Message::Nack if self.unacknowledged > 0 => {
+                    self.unacknowledged -= 1;
+                    self.record_transmit_status(TransmitStatus::Rejected);
+                }
+                Message::Nack => {}
+                Message::Version(_) => self.version_pending = false,
+                Message::ErrorRegister(_) => {}
+                Message::BusStatus(status) => {
+                    self.bus_status = *status;
+                    self.observers.bus_status(status);
+
+                    if let Some(callback) = self.on_bus_status.as_mut() {
+                        callback(status);
+                    }
+                }
+            }
+
+            Ok(Some(message))
         }
 
-        /// Reads from the serial stream until a line of length 1..=SLCAN_MTU
-        /// is received with a terminating CR.
+        /// Reads from the serial stream until a line of length
+        /// 1..=max_line_length is received with a terminating CR, and stores
+        /// it in `line`. Longer lines are discarded and reported as
+        /// [`ReadError::LineTooLong`]. The allocation of `line` is reused, so
+        /// reading frames doesn't allocate once the socket is running.
         ///
         /// Will wait until data is available to produce a line and will not
         /// return until one is received.
-        async fn read_line(&mut self) -> Result<Vec<u8>, ReadError> {
+        async fn read_line(&mut self) -> Result<(), ReadError> {
+            self.write_pending().await?;
+
             loop {
                 let mut buf = [0u8; 1];
 
-                if self.port.read(&mut buf).await? != 1 {
+                let count = match self.keepalive {
+                    Some(interval) => {
+                        match time::timeout(interval, self.port_pin_mut().read(&mut buf)).await {
+                            Ok(count) => count?,
+                            Err(_) => {
+                                self.keepalive_expired().await?;
+                                continue;
+                            }
+                        }
+                    }
+                    None => self.port_pin_mut().read(&mut buf).await?,
+                };
+
+                if count != 1 {
                     continue;
                 }
 
-                let b = buf[0];
+                self.keepalive_sent = false;
 
-                if b == b'\r' {
-                    let valid = !self.error && self.rx_count > 0;
-                    let buffer = &self.rx_buff[..self.rx_count];
+                match self.lines.push(buf[0]) {
+                    None => {}
+                    Some(LineEvent::Nack) => {
+                        if let Some(callback) = self.on_raw_rx.as_mut() {
+                            callback(&[NACK]);
+                        }
 
-                    self.error = false;
-                    self.rx_count = 0;
+                        self.line.clear();
+                        self.line.push(NACK);
+                        return Ok(());
+                    }
+                    Some(LineEvent::TooLong(length)) => {
+                        metrics::record_oversized_line();
+                        self.stats.record_oversized_line(length);
+                        return Err(ReadError::LineTooLong(length));
+                    }
+                    Some(LineEvent::Line(line)) => {
+                        self.line.clear();
+                        self.line.extend_from_slice(line);
 
-                    // We detected an error, move on and read the next line instead
-                    if !valid {
-                        continue;
+                        if let Some(callback) = self.on_raw_rx.as_mut() {
+                            callback(&self.line);
+                        }
+
+                        return Ok(());
                     }
+                }
+            }
+        }
+
+        /// Sends a configuration command, confirming it and retrying it
+        /// according to the [CommandRetryPolicy]
+        async fn send_config_command(&mut self, command: Command) -> io::Result<()> {
+            let CommandRetryPolicy::Retry {
+                attempts,
+                timeout,
+                backoff,
+            } = self.command_retry_policy
+            else {
+                return self.send_command(command).await;
+            };
 
-                    return Ok(buffer.to_vec());
+            let mut rejected = false;
+
+            for attempt in 0..=attempts {
+                if attempt > 0 {
+                    time::sleep(backoff).await;
                 }
 
-                // If we already detected an error, keep reading until we find a CR
-                if self.error {
-                    continue;
+                let (answer, nacks) = match self.probe(Some(command.clone()), true, timeout).await {
+                    Ok(result) => result,
+                    Err(ReadError::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::other(e)),
+                };
+
+                if answer.is_some() && nacks == 0 {
+                    return Ok(());
                 }
 
-                // If we encounter a line that is too long, set the error flag and
-                // keep reading until we find a CR
-                if self.rx_count >= SLCAN_MTU {
-                    self.error = true;
-                    continue;
+                rejected = nacks > 0;
+            }
+
+            Err(unconfirmed_command(&command, rejected))
+        }
+
+        /// Called when the gateway was silent for the keepalive interval.
+        /// Queries the firmware version, or fails if the previous query
+        /// wasn't answered.
+        ///
+        /// The query is written from within reads, so it is queued in
+        /// `pending_tx` first. If the read is cancelled during the write, the
+        /// rest of the query is written by the next read or command instead
+        /// of leaving a partial command on the wire.
+        async fn keepalive_expired(&mut self) -> Result<(), ReadError> {
+            if self.keepalive_sent {
+                self.keepalive_sent = false;
+                return Err(ReadError::DeviceUnresponsive);
+            }
+
+            let encoded = Command::GetFirmwareVersion.encode();
+            self.pending_tx.extend_from_slice(encoded.as_line());
+            self.keepalive_sent = true;
+            self.version_pending = true;
+
+            if let Some(callback) = self.on_raw_tx.as_mut() {
+                callback(&encoded);
+            }
+
+            self.write_pending().await?;
+            Ok(())
+        }
+
+        /// Writes the rest of a keepalive query whose read was cancelled.
+        /// Cancel safe, since the written bytes are removed after every
+        /// write.
+        async fn write_pending(&mut self) -> io::Result<()> {
+            if self.pending_tx.is_empty() {
+                return Ok(());
+            }
+
+            while !self.pending_tx.is_empty() {
+                let mut port = self.port.as_mut().expect(PORT_TAKEN).as_mut();
+                let count = port.write(&self.pending_tx).await?;
+
+                if count == 0 {
+                    return Err(io::ErrorKind::WriteZero.into());
                 }
 
-                // If things are going normally, just store the byte
-                self.rx_buff[self.rx_count] = b;
-                self.rx_count += 1;
+                self.pending_tx.drain(..count);
             }
+
+            self.port_pin_mut().flush().await
         }
 
         /// Serializes a command and sends it over the serial stream with a CR
@@ -423,12 +4835,236 @@ pub mod tokio {
         /// always correctly buffer input and will fail to parse our commands
         /// if they are split into multiple USB packets.
         async fn send_command(&mut self, command: Command) -> io::Result<()> {
-            let mut buffer = command.as_bytes();
-            buffer.push(b'\r');
+            self.write_pending().await?;
+
+            let encoded = command.encode();
+
+            self.port_pin_mut().write_all(encoded.as_line()).await?;
+            self.port_pin_mut().flush().await?;
+
+            if let Some(callback) = self.on_raw_tx.as_mut() {
+                callback(&encoded);
+            }
 
-            self.port.write_all(&buffer).await?;
-            self.port.flush().await?;
             Ok(())
         }
     }
+
+    /// A [CanSocket] whose gateway is in [`OperatingMode::Silent`], created
+    /// with [`CanSocket::into_silent`]. Only the methods which receive frames
+    /// or open and close the channel are available, so a program which must
+    /// never disturb the bus can't send frames by accident. The getters of
+    /// the socket are available through `Deref`.
+    pub struct SilentCanSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    impl<P> Deref for SilentCanSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> SilentCanSocket<P> {
+        /// Returns the socket, with all of its methods. The gateway stays in
+        /// silent mode until another operating mode is set.
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P: AsyncRead + AsyncWrite> SilentCanSocket<P> {
+        forward_to_socket! {
+            async fn open(&mut self, nominal_bit_rate: NominalBitRate) -> io::Result<()>;
+            async fn open_channel(&mut self) -> io::Result<()>;
+            async fn close(&mut self) -> io::Result<()>;
+            async fn read(&mut self) -> Result<CanFrame, ReadError>;
+            async fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError>;
+            async fn read_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+            ) -> Result<CanFrame, ReadError>;
+            async fn await_frame(
+                &mut self,
+                id: impl Into<Id>,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn await_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn read_until_cancelled(
+                &mut self,
+                cancelled: impl Future,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError>;
+        }
+    }
+
+    /// A [CanSocket] whose channel is closed, created with
+    /// [`CanSocket::into_closed`]. Only the methods which configure the
+    /// channel are available, and [`open`](ClosedSocket::open) turns it into
+    /// an [OpenSocket]. The getters of the socket are available through
+    /// `Deref`.
+    pub struct ClosedSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    /// A [CanSocket] whose channel is open, created with
+    /// [`ClosedSocket::open`]. Only the methods which receive and send frames
+    /// are available, and [`close`](OpenSocket::close) turns it back into a
+    /// [ClosedSocket] for changing settings. The getters of the socket are
+    /// available through `Deref`.
+    pub struct OpenSocket<P> {
+        socket: CanSocket<P>,
+    }
+
+    impl<P> Deref for ClosedSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> Deref for OpenSocket<P> {
+        type Target = CanSocket<P>;
+
+        fn deref(&self) -> &CanSocket<P> {
+            &self.socket
+        }
+    }
+
+    impl<P> ClosedSocket<P> {
+        /// Returns the socket, with all of its methods
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P> OpenSocket<P> {
+        /// Returns the socket, with all of its methods
+        pub fn into_socket(self) -> CanSocket<P> {
+            self.socket
+        }
+    }
+
+    impl<P: AsyncRead + AsyncWrite> ClosedSocket<P> {
+        /// Sets the nominal bit rate and opens the channel. See
+        /// [`CanSocket::open`].
+        pub async fn open(mut self, nominal_bit_rate: NominalBitRate) -> io::Result<OpenSocket<P>> {
+            self.socket.open(nominal_bit_rate).await?;
+            Ok(OpenSocket {
+                socket: self.socket,
+            })
+        }
+
+        /// Opens the channel with the nominal bit rate set earlier. See
+        /// [`CanSocket::open_channel`].
+        pub async fn open_channel(mut self) -> io::Result<OpenSocket<P>> {
+            self.socket.open_channel().await?;
+            Ok(OpenSocket {
+                socket: self.socket,
+            })
+        }
+
+        forward_to_socket! {
+            fn set_dialect(&mut self, dialect: Dialect);
+            async fn set_nominal_bit_rate(&mut self, rate: NominalBitRate) -> io::Result<()>;
+            async fn set_data_bit_rate(&mut self, rate: DataBitRate) -> io::Result<()>;
+            async fn set_operating_mode(&mut self, mode: OperatingMode) -> io::Result<()>;
+            async fn set_auto_retransmission_mode(
+                &mut self,
+                mode: AutoRetransmissionMode,
+            ) -> io::Result<()>;
+            async fn set_timestamp_mode(&mut self, mode: TimestampMode) -> io::Result<()>;
+            fn set_close_on_drop(&mut self, enabled: bool);
+            fn set_max_line_length(&mut self, length: usize);
+            fn set_lf_line_endings(&mut self, enabled: bool);
+            fn set_channel(&mut self, channel: usize);
+            fn set_tx_echo(&mut self, enabled: bool);
+            fn set_tx_queue_policy(&mut self, policy: TxQueuePolicy);
+            fn set_tx_filter(&mut self, filter: Option<IdFilter>);
+            fn set_bus_off_policy(&mut self, policy: BusOffPolicy);
+            fn set_command_retry_policy(&mut self, policy: CommandRetryPolicy);
+            fn set_keepalive(&mut self, interval: Option<Duration>);
+            fn set_bus_status_callback(
+                &mut self,
+                callback: impl FnMut(&BusStatus) + Send + 'static,
+            );
+            fn set_raw_rx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static);
+            fn set_raw_tx_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static);
+            fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static);
+            fn clear_interceptors(&mut self);
+            fn add_observer(&mut self, observer: impl BusObserver + 'static);
+            fn clear_observers(&mut self);
+            fn set_remote_responder(
+                &mut self,
+                responder: Option<RemoteResponder>,
+            ) -> Option<RemoteResponder>;
+            #[cfg(feature = "logging")]
+            fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder>;
+            fn set_identify_command(&mut self, command: Option<&[u8]>);
+            fn set_error_counters_command(&mut self, command: Option<&[u8]>);
+            fn set_bootloader_command(&mut self, command: Option<&[u8]>);
+            fn set_termination_commands(&mut self, commands: Option<(&[u8], &[u8])>);
+            async fn set_termination(&mut self, enabled: bool) -> io::Result<()>;
+            fn set_fd_crc_commands(&mut self, commands: Option<(&[u8], &[u8])>);
+            async fn set_fd_crc_mode(&mut self, mode: FdCrcMode) -> io::Result<()>;
+            async fn set_filter_banks(
+                &mut self,
+                count: usize,
+                encode: impl Fn(usize, Option<&AcceptanceFilter>) -> Vec<u8> + Send + 'static,
+            ) -> io::Result<()>;
+            async fn add_filter(&mut self, filter: AcceptanceFilter) -> io::Result<()>;
+            async fn remove_filter(&mut self, filter: &AcceptanceFilter) -> io::Result<bool>;
+            async fn clear_filters(&mut self) -> io::Result<()>;
+        }
+    }
+
+    impl<P: AsyncRead + AsyncWrite> OpenSocket<P> {
+        /// Closes the channel. See [`CanSocket::close`].
+        pub async fn close(mut self) -> io::Result<ClosedSocket<P>> {
+            self.socket.close().await?;
+            Ok(ClosedSocket {
+                socket: self.socket,
+            })
+        }
+
+        forward_to_socket! {
+            async fn send(&mut self, frame: impl Into<CanFrame>) -> Result<(), SendError>;
+            async fn send_data(&mut self, id: u32, data: &[u8]) -> Result<(), SendError>;
+            async fn send_remote(&mut self, id: u32, dlc: usize) -> Result<(), SendError>;
+            async fn send_fd(&mut self, id: u32, data: &[u8]) -> Result<(), SendError>;
+            async fn send_and_confirm(
+                &mut self,
+                frame: impl Into<CanFrame>,
+                timeout: Duration,
+            ) -> Result<TransmitStatus, SendError>;
+            async fn read(&mut self) -> Result<CanFrame, ReadError>;
+            async fn read_filtered(&mut self, ids: &[Id]) -> Result<CanFrame, ReadError>;
+            async fn read_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+            ) -> Result<CanFrame, ReadError>;
+            async fn await_frame(
+                &mut self,
+                id: impl Into<Id>,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn await_matching(
+                &mut self,
+                matches: impl FnMut(&CanFrame) -> bool,
+                timeout: Duration,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn read_until_cancelled(
+                &mut self,
+                cancelled: impl Future,
+            ) -> Result<Option<CanFrame>, ReadError>;
+            async fn read_rx_frame(&mut self) -> Result<RxFrame, ReadError>;
+        }
+    }
 }
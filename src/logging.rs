@@ -0,0 +1,87 @@
+//! Reading, writing and replaying logs of CAN traffic.
+//!
+//! Every log format provides a reader which is an iterator of
+//! [`LogRecord`]s and a writer implementing [`LogWriter`], so formats can be
+//! converted into one another and any of them can be fed to the [`Replayer`].
+//! Long running captures can be written with a [`Recorder`], which rotates
+//! through multiple files.
+//!
+//! Supported formats:
+//!
+//! - [`candump`] - The text format written by `candump -l` from can-utils
+//! - [`csv`] - Comma separated values for analysis in other tools, optionally
+//!   with decoded signals (write only)
+//! - [`blf`] - Vector's binary logging format
+//! - [`trc`] - The trace format of PEAK-System's PCAN-View
+//!
+//! Any of them can be gzip or zstd compressed (with the `gzip` and `zstd`
+//! features respectively). Use [`open_log`] and [`create_log`] to pick the
+//! compression from the file, or wrap existing readers and writers with
+//! [`decompress`] and [`CompressedWriter`].
+
+use std::{io, time::Duration};
+
+use crate::frame::{CanFrame, FrameDirection};
+
+pub mod blf;
+pub mod candump;
+mod compression;
+pub mod csv;
+mod recorder;
+mod replay;
+pub mod trc;
+
+pub use compression::{create_log, decompress, open_log, CompressedWriter, Compression};
+pub use recorder::{LogFormat, Recorder};
+pub use replay::Replayer;
+
+/// A single frame stored in a log file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// The time at which the frame was on the bus. Usually relative to the
+    /// UNIX epoch, but some formats only store the time relative to the
+    /// start of the log.
+    pub time: Duration,
+    /// The index of the channel (i.e. bus) the frame was logged from
+    pub channel: usize,
+    /// Whether the frame was received or transmitted by the logging host
+    pub direction: FrameDirection,
+    /// The frame itself
+    pub frame: CanFrame,
+}
+
+/// Errors which can arise while reading or writing logs
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Malformed record on line {line}: {reason}")]
+    Malformed { line: usize, reason: &'static str },
+    #[error("Invalid file header: {reason}")]
+    InvalidHeader { reason: &'static str },
+    #[error("Malformed object {index}: {reason}")]
+    MalformedObject { index: usize, reason: &'static str },
+    #[error("Failed to send a replayed frame: {0}")]
+    Send(#[from] crate::SendError),
+    #[error("The log format can't store CAN XL frames")]
+    UnsupportedFrame,
+}
+
+/// A destination for [`LogRecord`]s in a particular log format
+pub trait LogWriter {
+    /// Appends a record to the log
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError>;
+
+    /// Flushes any buffered records to the underlying writer
+    fn flush(&mut self) -> Result<(), LogError>;
+}
+
+impl<W: LogWriter + ?Sized> LogWriter for Box<W> {
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        (**self).write_record(record)
+    }
+
+    fn flush(&mut self) -> Result<(), LogError> {
+        (**self).flush()
+    }
+}
@@ -0,0 +1,971 @@
+//! Vector's Binary Logging Format (BLF), as written by CANoe, CANalyzer and
+//! most other Vector tools.
+//!
+//! A BLF file starts with a file header followed by a sequence of objects,
+//! most of which are containers holding a zlib compressed batch of further
+//! objects. Only the CAN objects are read (`CAN_MESSAGE`, `CAN_MESSAGE2`,
+//! `CAN_FD_MESSAGE` and `CAN_FD_MESSAGE_64`), everything else is skipped.
+//!
+//! Reading compressed containers (which includes every file written by
+//! Vector tools) requires the `gzip` feature. [`BlfWriter`] compresses its
+//! containers when the feature is enabled and stores them uncompressed
+//! otherwise.
+//!
+//! BLF channels are numbered from 1, so channel 1 maps to
+//! [`LogRecord::channel`] 0.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use super::{LogError, LogRecord, LogWriter};
+use crate::frame::{Can2Frame, CanFdFrame, CanFrame, FdDataLengthCode, FrameDirection};
+
+const FILE_SIGNATURE: &[u8] = b"LOGG";
+const FILE_HEADER_SIZE: usize = 144;
+const OBJECT_SIGNATURE: &[u8] = b"LOBJ";
+const OBJECT_HEADER_BASE_SIZE: usize = 16;
+const OBJECT_HEADER_V1_SIZE: usize = 32;
+const CONTAINER_HEADER_SIZE: usize = 16;
+const MAX_CONTAINER_SIZE: usize = 128 * 1024;
+/// Objects and unpacked containers larger than this are rejected instead of
+/// trusting a corrupted size field with the allocation. Vector tools never
+/// write containers larger than [`MAX_CONTAINER_SIZE`].
+const MAX_OBJECT_SIZE: usize = MAX_CONTAINER_SIZE + 64 * 1024;
+
+const CAN_MESSAGE: u32 = 1;
+const LOG_CONTAINER: u32 = 10;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+
+const TIME_TEN_MICS: u32 = 0x01;
+const TIME_ONE_NANS: u32 = 0x02;
+
+const EXTENDED_ID: u32 = 0x8000_0000;
+const DIRECTION_TX: u8 = 0x01;
+const REMOTE_FRAME: u8 = 0x80;
+const FD_EDL: u8 = 0x01;
+const FD_BRS: u8 = 0x02;
+const FD64_REMOTE_FRAME: u32 = 0x0010;
+const FD64_EDL: u32 = 0x1000;
+const FD64_BRS: u32 = 0x2000;
+
+const CAN_MESSAGE_SIZE: usize = 16;
+const CAN_FD_MESSAGE_SIZE: usize = 84;
+
+/// Reads [`LogRecord`]s from a BLF file
+pub struct BlfReader<R> {
+    reader: R,
+    start_time: Duration,
+    buffer: Vec<u8>,
+    position: usize,
+    index: usize,
+}
+
+impl<R: Read> BlfReader<R> {
+    /// Reads the file header from `reader` and creates a reader over the
+    /// objects which follow it
+    pub fn new(mut reader: R) -> Result<Self, LogError> {
+        let mut header = [0; FILE_HEADER_SIZE];
+        reader.read_exact(&mut header[..8])?;
+
+        if &header[..4] != FILE_SIGNATURE {
+            return Err(LogError::InvalidHeader {
+                reason: "missing LOGG signature",
+            });
+        }
+
+        let header_size = u32_at(&header, 4) as usize;
+        if header_size < 72 {
+            return Err(LogError::InvalidHeader {
+                reason: "file header too small",
+            });
+        }
+
+        let read = header_size.min(FILE_HEADER_SIZE);
+        reader.read_exact(&mut header[8..read])?;
+        skip(&mut reader, (header_size - read) as u64)?;
+
+        Ok(Self {
+            reader,
+            start_time: system_time_to_duration(&header[40..56]),
+            buffer: Vec::new(),
+            position: 0,
+            index: 0,
+        })
+    }
+
+    /// The time at which the measurement was started, relative to the UNIX
+    /// epoch
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// Reads the next object from the file into the buffer. Containers are
+    /// unpacked, everything else is copied as is. Returns false at the end
+    /// of the file.
+    fn fill_buffer(&mut self) -> Result<bool, LogError> {
+        let mut header = [0; OBJECT_HEADER_BASE_SIZE];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        if &header[..4] != OBJECT_SIGNATURE {
+            return Err(self.malformed("missing LOBJ signature"));
+        }
+
+        let object_size = u32_at(&header, 8) as usize;
+        let object_type = u32_at(&header, 12);
+
+        if object_size < OBJECT_HEADER_BASE_SIZE {
+            return Err(self.malformed("object size too small"));
+        }
+        if object_size > MAX_OBJECT_SIZE {
+            return Err(self.malformed("object size too large"));
+        }
+
+        let mut body = vec![0; object_size - OBJECT_HEADER_BASE_SIZE];
+        self.reader
+            .read_exact(&mut body)
+            .map_err(|_| self.malformed("truncated object"))?;
+
+        // Objects are followed by padding, which may be missing at the end
+        // of the file
+        let _ = skip(&mut self.reader, (object_size % 4) as u64);
+
+        // Drop everything which has already been parsed
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        if object_type != LOG_CONTAINER {
+            self.buffer.extend_from_slice(&header);
+            self.buffer.extend_from_slice(&body);
+            return Ok(true);
+        }
+
+        if body.len() < CONTAINER_HEADER_SIZE {
+            return Err(self.malformed("truncated container"));
+        }
+
+        let method = u16_at(&body, 0);
+        let data = &body[CONTAINER_HEADER_SIZE..];
+
+        match method {
+            NO_COMPRESSION => self.buffer.extend_from_slice(data),
+            #[cfg(feature = "gzip")]
+            ZLIB_DEFLATE => {
+                let unpacked = flate2::read::ZlibDecoder::new(data)
+                    .take(MAX_OBJECT_SIZE as u64 + 1)
+                    .read_to_end(&mut self.buffer)
+                    .map_err(|_| self.malformed("invalid zlib data in container"))?;
+                if unpacked > MAX_OBJECT_SIZE {
+                    return Err(self.malformed("unpacked container too large"));
+                }
+            }
+            #[cfg(not(feature = "gzip"))]
+            ZLIB_DEFLATE => {
+                return Err(self.malformed("compressed containers require the `gzip` feature"))
+            }
+            _ => return Err(self.malformed("unknown container compression")),
+        }
+
+        Ok(true)
+    }
+
+    /// Parses the next CAN object from the buffer, skipping other objects.
+    /// Returns `None` if the buffer does not contain a complete object.
+    fn parse_buffered(&mut self) -> Option<Result<LogRecord, LogError>> {
+        loop {
+            let remaining = &self.buffer[self.position..];
+
+            // Skip any padding in front of the object
+            let padding = remaining
+                .windows(OBJECT_SIGNATURE.len())
+                .take(8)
+                .position(|w| w == OBJECT_SIGNATURE);
+            let padding = match padding {
+                Some(padding) => padding,
+                None if remaining.len() < 8 + OBJECT_SIGNATURE.len() => return None,
+                None => return Some(Err(self.discard("missing LOBJ signature"))),
+            };
+
+            let object = &remaining[padding..];
+            if object.len() < OBJECT_HEADER_BASE_SIZE {
+                return None;
+            }
+
+            let header_size = u16_at(object, 4) as usize;
+            let object_size = u32_at(object, 8) as usize;
+            let object_type = u32_at(object, 12);
+
+            if object_size < OBJECT_HEADER_BASE_SIZE || header_size > object_size {
+                return Some(Err(self.discard("invalid object size")));
+            }
+            if object.len() < object_size {
+                return None;
+            }
+
+            self.position += padding + object_size;
+            self.index += 1;
+
+            if !matches!(
+                object_type,
+                CAN_MESSAGE | CAN_MESSAGE2 | CAN_FD_MESSAGE | CAN_FD_MESSAGE_64
+            ) {
+                continue;
+            }
+
+            if header_size < OBJECT_HEADER_V1_SIZE {
+                return Some(Err(self.malformed("object header too small")));
+            }
+
+            let flags = u32_at(object, 16);
+            let timestamp = u64_at(object, 24);
+            let offset = if flags & TIME_ONE_NANS != 0 {
+                Duration::from_nanos(timestamp)
+            } else if flags & TIME_TEN_MICS != 0 {
+                Duration::from_micros(timestamp.saturating_mul(10))
+            } else {
+                Duration::from_nanos(timestamp)
+            };
+
+            let body = &object[header_size..object_size];
+            let parsed = match object_type {
+                CAN_MESSAGE | CAN_MESSAGE2 => parse_can_message(body),
+                CAN_FD_MESSAGE => parse_can_fd_message(body),
+                _ => parse_can_fd_message_64(body),
+            };
+
+            return Some(match parsed {
+                Ok((channel, direction, frame)) => Ok(LogRecord {
+                    time: self.start_time + offset,
+                    channel,
+                    direction,
+                    frame,
+                }),
+                Err(reason) => Err(self.malformed(reason)),
+            });
+        }
+    }
+
+    /// Drops the rest of the buffer since the start of the next object can't
+    /// be found
+    fn discard(&mut self, reason: &'static str) -> LogError {
+        self.position = self.buffer.len();
+        self.malformed(reason)
+    }
+
+    fn malformed(&self, reason: &'static str) -> LogError {
+        LogError::MalformedObject {
+            index: self.index,
+            reason,
+        }
+    }
+}
+
+impl<R: Read> Iterator for BlfReader<R> {
+    type Item = Result<LogRecord, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.parse_buffered() {
+                return Some(record);
+            }
+
+            match self.fill_buffer() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+type ParsedFrame = (usize, FrameDirection, CanFrame);
+
+fn parse_can_message(body: &[u8]) -> Result<ParsedFrame, &'static str> {
+    if body.len() < CAN_MESSAGE_SIZE {
+        return Err("truncated CAN message");
+    }
+
+    let channel = u16_at(body, 0) as usize;
+    let flags = body[2];
+    let dlc = (body[3] as usize).min(8);
+    let id = parse_id(u32_at(body, 4))?;
+
+    let frame = if flags & REMOTE_FRAME != 0 {
+        Can2Frame::new_remote(id, dlc)
+    } else {
+        Can2Frame::new_data(id, &body[8..8 + dlc])
+    }
+    .ok_or("invalid CAN message")?;
+
+    Ok((
+        channel.saturating_sub(1),
+        direction(flags & DIRECTION_TX != 0),
+        frame.into(),
+    ))
+}
+
+fn parse_can_fd_message(body: &[u8]) -> Result<ParsedFrame, &'static str> {
+    if body.len() < 20 {
+        return Err("truncated CAN FD message");
+    }
+
+    let channel = u16_at(body, 0) as usize;
+    let flags = body[2];
+    let dlc = body[3];
+    let id = parse_id(u32_at(body, 4))?;
+    let fd_flags = body[13];
+    let valid_bytes = body[14] as usize;
+    let data = &body[20..];
+
+    let frame = if fd_flags & FD_EDL != 0 {
+        fd_frame(id, dlc, data, valid_bytes, fd_flags & FD_BRS != 0)?
+    } else if flags & REMOTE_FRAME != 0 {
+        Can2Frame::new_remote(id, (dlc as usize).min(8))
+            .ok_or("invalid CAN message")?
+            .into()
+    } else {
+        classic_frame(id, dlc, data)?
+    };
+
+    Ok((
+        channel.saturating_sub(1),
+        direction(flags & DIRECTION_TX != 0),
+        frame,
+    ))
+}
+
+fn parse_can_fd_message_64(body: &[u8]) -> Result<ParsedFrame, &'static str> {
+    if body.len() < 40 {
+        return Err("truncated CAN FD message");
+    }
+
+    let channel = body[0] as usize;
+    let dlc = body[1];
+    let valid_bytes = body[2] as usize;
+    let id = parse_id(u32_at(body, 4))?;
+    let flags = u32_at(body, 12);
+    let data = &body[40..];
+
+    let frame = if flags & FD64_EDL != 0 {
+        fd_frame(id, dlc, data, valid_bytes, flags & FD64_BRS != 0)?
+    } else if flags & FD64_REMOTE_FRAME != 0 {
+        Can2Frame::new_remote(id, (dlc as usize).min(8))
+            .ok_or("invalid CAN message")?
+            .into()
+    } else {
+        classic_frame(id, dlc, data)?
+    };
+
+    Ok((channel.saturating_sub(1), direction(body[34] != 0), frame))
+}
+
+fn fd_frame(
+    id: Id,
+    dlc: u8,
+    data: &[u8],
+    valid_bytes: usize,
+    bit_rate_switched: bool,
+) -> Result<CanFrame, &'static str> {
+    let length = FdDataLengthCode::try_from(dlc)
+        .map_err(|_| "invalid CAN FD data length code")?
+        .get_num_bytes()
+        .min(valid_bytes)
+        .min(data.len());
+
+    Ok(CanFdFrame::new_padded(id, &data[..length])
+        .ok_or("invalid CAN FD message")?
+        .with_bit_rate_switched(bit_rate_switched)
+        .into())
+}
+
+fn classic_frame(id: Id, dlc: u8, data: &[u8]) -> Result<CanFrame, &'static str> {
+    let length = (dlc as usize).min(8).min(data.len());
+
+    Ok(Can2Frame::new_data(id, &data[..length])
+        .ok_or("invalid CAN message")?
+        .into())
+}
+
+fn parse_id(raw: u32) -> Result<Id, &'static str> {
+    if raw & EXTENDED_ID != 0 {
+        ExtendedId::new(raw & !EXTENDED_ID)
+            .map(Id::from)
+            .ok_or("extended ID out of range")
+    } else {
+        u16::try_from(raw)
+            .ok()
+            .and_then(StandardId::new)
+            .map(Id::from)
+            .ok_or("standard ID out of range")
+    }
+}
+
+fn direction(is_tx: bool) -> FrameDirection {
+    if is_tx {
+        FrameDirection::Tx
+    } else {
+        FrameDirection::Rx
+    }
+}
+
+/// Writes [`LogRecord`]s as a BLF file.
+///
+/// Records are collected into containers of up to 128 KiB, which are only
+/// written on [`flush`](LogWriter::flush) or once they are full. Flushing
+/// also updates the file header, so the file is valid after every flush.
+/// Remember to flush (or call [`into_inner`](BlfWriter::into_inner)) before
+/// dropping the writer.
+pub struct BlfWriter<W> {
+    writer: W,
+    header_position: u64,
+    container: Vec<u8>,
+    start_time: Option<Duration>,
+    stop_time: Duration,
+    object_count: u32,
+    uncompressed_size: u64,
+}
+
+impl<W: Write + Seek> BlfWriter<W> {
+    /// Creates a writer which writes a BLF file starting at the current
+    /// position of `writer`
+    pub fn new(mut writer: W) -> Result<Self, LogError> {
+        let header_position = writer.stream_position()?;
+
+        let mut this = Self {
+            writer,
+            header_position,
+            container: Vec::new(),
+            start_time: None,
+            stop_time: Duration::ZERO,
+            object_count: 0,
+            uncompressed_size: FILE_HEADER_SIZE as u64,
+        };
+        let header = this.file_header(FILE_HEADER_SIZE as u64);
+        this.writer.write_all(&header)?;
+
+        Ok(this)
+    }
+
+    /// Flushes all records and returns the underlying writer
+    pub fn into_inner(mut self) -> Result<W, LogError> {
+        LogWriter::flush(&mut self)?;
+        Ok(self.writer)
+    }
+
+    fn write_container(&mut self) -> Result<(), LogError> {
+        if self.container.is_empty() {
+            return Ok(());
+        }
+
+        #[cfg(feature = "gzip")]
+        let compressed = {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&self.container)?;
+            encoder.finish()?
+        };
+        #[cfg(feature = "gzip")]
+        let (method, data) = (ZLIB_DEFLATE, compressed.as_slice());
+        #[cfg(not(feature = "gzip"))]
+        let (method, data) = (NO_COMPRESSION, self.container.as_slice());
+
+        let object_size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + data.len();
+
+        let mut header = Vec::with_capacity(OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE);
+        header.extend_from_slice(OBJECT_SIGNATURE);
+        header.extend_from_slice(&(OBJECT_HEADER_BASE_SIZE as u16).to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&(object_size as u32).to_le_bytes());
+        header.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&[0; 6]);
+        header.extend_from_slice(&(self.container.len() as u32).to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&[0; 3][..object_size % 4])?;
+
+        self.uncompressed_size += (header.len() + self.container.len()) as u64;
+        self.container.clear();
+
+        Ok(())
+    }
+
+    fn file_header(&self, file_size: u64) -> [u8; FILE_HEADER_SIZE] {
+        let mut header = [0; FILE_HEADER_SIZE];
+        header[..4].copy_from_slice(FILE_SIGNATURE);
+        header[4..8].copy_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // Application ID and version are left at 0, followed by the BLF
+        // version
+        header[12..16].copy_from_slice(&[2, 6, 8, 1]);
+        header[16..24].copy_from_slice(&file_size.to_le_bytes());
+        header[24..32].copy_from_slice(&self.uncompressed_size.to_le_bytes());
+        header[32..36].copy_from_slice(&self.object_count.to_le_bytes());
+        header[40..56].copy_from_slice(&duration_to_system_time(
+            self.start_time.unwrap_or_default(),
+        ));
+        header[56..72].copy_from_slice(&duration_to_system_time(self.stop_time));
+        header
+    }
+}
+
+impl<W: Write + Seek> LogWriter for BlfWriter<W> {
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        // SYSTEMTIME only has millisecond resolution
+        let start_time = *self
+            .start_time
+            .get_or_insert(Duration::from_millis(record.time.as_millis() as u64));
+        let timestamp = record.time.saturating_sub(start_time).as_nanos() as u64;
+        self.stop_time = self.stop_time.max(record.time);
+
+        let channel = (record.channel + 1) as u16;
+        let direction = match record.direction {
+            FrameDirection::Rx => 0,
+            FrameDirection::Tx => DIRECTION_TX,
+        };
+
+        let (object_type, body) = match &record.frame {
+            CanFrame::Can2(frame) => {
+                let mut body = Vec::with_capacity(CAN_MESSAGE_SIZE);
+                let remote = if frame.is_remote() { REMOTE_FRAME } else { 0 };
+
+                body.extend_from_slice(&channel.to_le_bytes());
+                body.push(direction | remote);
+                body.push(frame.dlc() as u8);
+                body.extend_from_slice(&raw_id(frame.id()).to_le_bytes());
+                body.extend_from_slice(frame.data().unwrap_or_default());
+                body.resize(CAN_MESSAGE_SIZE, 0);
+
+                (CAN_MESSAGE, body)
+            }
+            CanFrame::CanFd(frame) => {
+                let mut body = Vec::with_capacity(CAN_FD_MESSAGE_SIZE);
+                let brs = if frame.is_bit_rate_switched() {
+                    FD_BRS
+                } else {
+                    0
+                };
+
+                body.extend_from_slice(&channel.to_le_bytes());
+                body.push(direction);
+                body.push(frame.dlc().into());
+                body.extend_from_slice(&raw_id(frame.id()).to_le_bytes());
+                // Frame length and arbitration bit count are unknown
+                body.extend_from_slice(&[0; 5]);
+                body.push(FD_EDL | brs);
+                body.push(frame.data().len() as u8);
+                body.extend_from_slice(&[0; 5]);
+                body.extend_from_slice(frame.data());
+                body.resize(CAN_FD_MESSAGE_SIZE, 0);
+
+                (CAN_FD_MESSAGE, body)
+            }
+            CanFrame::CanXl(_) => return Err(LogError::UnsupportedFrame),
+        };
+
+        push_object(&mut self.container, object_type, timestamp, &body);
+        self.object_count += 1;
+
+        if self.container.len() >= MAX_CONTAINER_SIZE {
+            self.write_container()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), LogError> {
+        self.write_container()?;
+
+        let end = self.writer.stream_position()?;
+        let header = self.file_header(end - self.header_position);
+
+        self.writer.seek(SeekFrom::Start(self.header_position))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Appends an object with a version 1 header and a timestamp in nanoseconds
+fn push_object(buffer: &mut Vec<u8>, object_type: u32, timestamp: u64, body: &[u8]) {
+    let object_size = OBJECT_HEADER_V1_SIZE + body.len();
+
+    buffer.extend_from_slice(OBJECT_SIGNATURE);
+    buffer.extend_from_slice(&(OBJECT_HEADER_V1_SIZE as u16).to_le_bytes());
+    buffer.extend_from_slice(&1u16.to_le_bytes());
+    buffer.extend_from_slice(&(object_size as u32).to_le_bytes());
+    buffer.extend_from_slice(&object_type.to_le_bytes());
+    buffer.extend_from_slice(&TIME_ONE_NANS.to_le_bytes());
+    // Client index and object version
+    buffer.extend_from_slice(&[0; 4]);
+    buffer.extend_from_slice(&timestamp.to_le_bytes());
+    buffer.extend_from_slice(body);
+}
+
+fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | EXTENDED_ID,
+    }
+}
+
+fn skip(reader: &mut impl Read, bytes: u64) -> io::Result<()> {
+    io::copy(&mut reader.take(bytes), &mut io::sink())?;
+    Ok(())
+}
+
+fn u16_at(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buffer[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buffer: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap())
+}
+
+/// Converts a Windows `SYSTEMTIME` (year, month, day of week, day, hour,
+/// minute, second, millisecond) into the time since the UNIX epoch. Unset or
+/// earlier times map to zero.
+fn system_time_to_duration(raw: &[u8]) -> Duration {
+    let field = |index: usize| u16_at(raw, index * 2) as i64;
+    let (year, month, day) = (field(0), field(1), field(3));
+
+    if year < 1970 || !(1..=12).contains(&month) {
+        return Duration::ZERO;
+    }
+
+    // Days since the epoch of the proleptic Gregorian calendar date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = days * 86_400 + field(4) * 3600 + field(5) * 60 + field(6);
+
+    Duration::from_secs(secs.max(0) as u64) + Duration::from_millis(field(7) as u64)
+}
+
+/// The inverse of [`system_time_to_duration`]
+fn duration_to_system_time(time: Duration) -> [u8; 16] {
+    if time.is_zero() {
+        return [0; 16];
+    }
+
+    let secs = time.as_secs() as i64;
+    let days = secs.div_euclid(86_400);
+    let secs_of_day = secs.rem_euclid(86_400);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    let fields = [
+        year,
+        month,
+        // 1970-01-01 was a Thursday, and Sunday is 0
+        (days + 4).rem_euclid(7),
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        time.subsec_millis() as i64,
+    ];
+
+    let mut raw = [0; 16];
+    for (chunk, field) in raw.chunks_exact_mut(2).zip(fields) {
+        chunk.copy_from_slice(&(field as u16).to_le_bytes());
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[cfg(feature = "gzip")]
+    const METHODS: &[u16] = &[NO_COMPRESSION, ZLIB_DEFLATE];
+    #[cfg(not(feature = "gzip"))]
+    const METHODS: &[u16] = &[NO_COMPRESSION];
+
+    fn record(
+        micros: u64,
+        channel: usize,
+        direction: FrameDirection,
+        frame: CanFrame,
+    ) -> LogRecord {
+        LogRecord {
+            time: Duration::from_millis(1_700_000_000_000) + Duration::from_micros(micros),
+            channel,
+            direction,
+            frame,
+        }
+    }
+
+    fn read(file: &[u8]) -> Vec<LogRecord> {
+        BlfReader::new(file)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn compress(objects: &[u8], method: u16) -> Vec<u8> {
+        match method {
+            #[cfg(feature = "gzip")]
+            ZLIB_DEFLATE => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(objects).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => objects.to_vec(),
+        }
+    }
+
+    fn decompress(data: &[u8], method: u16) -> Vec<u8> {
+        match method {
+            #[cfg(feature = "gzip")]
+            ZLIB_DEFLATE => {
+                let mut objects = Vec::new();
+                flate2::read::ZlibDecoder::new(data)
+                    .read_to_end(&mut objects)
+                    .unwrap();
+                objects
+            }
+            _ => data.to_vec(),
+        }
+    }
+
+    /// Builds a file with the given file header and a single container
+    /// holding `objects`
+    fn container_file(header: &[u8], objects: &[u8], method: u16) -> Vec<u8> {
+        let data = compress(objects, method);
+        let object_size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + data.len();
+
+        let mut file = header.to_vec();
+        file.extend_from_slice(OBJECT_SIGNATURE);
+        file.extend_from_slice(&(OBJECT_HEADER_BASE_SIZE as u16).to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&(object_size as u32).to_le_bytes());
+        file.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        file.extend_from_slice(&method.to_le_bytes());
+        file.extend_from_slice(&[0; 6]);
+        file.extend_from_slice(&(objects.len() as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&data);
+        file
+    }
+
+    fn empty_file() -> Vec<u8> {
+        BlfWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner()
+            .unwrap()
+            .into_inner()
+    }
+
+    /// Writes `records` with [`BlfWriter`] and checks that they are read back
+    /// unchanged, from the written file as well as from copies of its
+    /// container repacked with every supported compression method
+    fn round_trip(records: &[LogRecord], object_type: u32) {
+        let mut writer = BlfWriter::new(Cursor::new(Vec::new())).unwrap();
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        let file = writer.into_inner().unwrap().into_inner();
+
+        assert_eq!(read(&file), records);
+
+        let container = &file[FILE_HEADER_SIZE..];
+        assert_eq!(u32_at(container, 12), LOG_CONTAINER);
+
+        let object_size = u32_at(container, 8) as usize;
+        let method = u16_at(container, OBJECT_HEADER_BASE_SIZE);
+        let data = &container[OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE..object_size];
+        let objects = decompress(data, method);
+        assert_eq!(u32_at(&objects, 12), object_type);
+
+        for &method in METHODS {
+            let repacked = container_file(&file[..FILE_HEADER_SIZE], &objects, method);
+            assert_eq!(read(&repacked), records);
+        }
+    }
+
+    #[test]
+    fn round_trips_can_messages() {
+        let standard = StandardId::new(0x123).unwrap();
+        let extended = ExtendedId::new(0x1234_5678).unwrap();
+
+        round_trip(
+            &[
+                record(0, 0, FrameDirection::Rx, {
+                    Can2Frame::new_data(standard, &[1, 2, 3]).unwrap().into()
+                }),
+                record(150, 1, FrameDirection::Tx, {
+                    Can2Frame::new_data(extended, &[0xFF; 8]).unwrap().into()
+                }),
+                record(2_000_250, 0, FrameDirection::Rx, {
+                    Can2Frame::new_remote(standard, 4).unwrap().into()
+                }),
+                record(2_000_500, 3, FrameDirection::Rx, {
+                    Can2Frame::new_data(extended, &[]).unwrap().into()
+                }),
+            ],
+            CAN_MESSAGE,
+        );
+    }
+
+    #[test]
+    fn round_trips_can_fd_messages() {
+        let standard = StandardId::new(0x7FF).unwrap();
+        let extended = ExtendedId::new(0x1FFF_FFFF).unwrap();
+        let data: Vec<u8> = (0..64).collect();
+
+        round_trip(
+            &[
+                record(10, 0, FrameDirection::Rx, {
+                    CanFdFrame::new(standard, &data).unwrap().into()
+                }),
+                record(20, 1, FrameDirection::Tx, {
+                    CanFdFrame::new(extended, &data[..12])
+                        .unwrap()
+                        .with_bit_rate_switched(false)
+                        .into()
+                }),
+                record(30, 0, FrameDirection::Rx, {
+                    CanFdFrame::new(standard, &data[..3]).unwrap().into()
+                }),
+            ],
+            CAN_FD_MESSAGE,
+        );
+    }
+
+    fn can_fd_message_64(timestamp: u64, channel: u8, flags: u32, dlc: u8, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![0; 40];
+        body[0] = channel;
+        body[1] = dlc;
+        body[2] = data.len() as u8;
+        body[4..8].copy_from_slice(&(0x0ABC_DEF0 | EXTENDED_ID).to_le_bytes());
+        body[12..16].copy_from_slice(&flags.to_le_bytes());
+        body[34] = (channel == 2) as u8;
+        body.extend_from_slice(data);
+
+        let mut object = Vec::new();
+        push_object(&mut object, CAN_FD_MESSAGE_64, timestamp, &body);
+        object
+    }
+
+    #[test]
+    fn reads_can_fd_message_64() {
+        let id = ExtendedId::new(0x0ABC_DEF0).unwrap();
+        let data: Vec<u8> = (0..64).rev().collect();
+
+        let objects = [
+            can_fd_message_64(1_000, 1, FD64_EDL | FD64_BRS, 15, &data),
+            can_fd_message_64(2_000, 2, FD64_EDL, 9, &data[..12]),
+            can_fd_message_64(3_000, 1, 0, 8, &data[..8]),
+            can_fd_message_64(4_000, 1, FD64_REMOTE_FRAME, 2, &[]),
+        ]
+        .concat();
+
+        let expected = [
+            LogRecord {
+                time: Duration::from_micros(1),
+                channel: 0,
+                direction: FrameDirection::Rx,
+                frame: CanFdFrame::new(id, &data)
+                    .unwrap()
+                    .with_bit_rate_switched(true)
+                    .into(),
+            },
+            LogRecord {
+                time: Duration::from_micros(2),
+                channel: 1,
+                direction: FrameDirection::Tx,
+                frame: CanFdFrame::new(id, &data[..12])
+                    .unwrap()
+                    .with_bit_rate_switched(false)
+                    .into(),
+            },
+            LogRecord {
+                time: Duration::from_micros(3),
+                channel: 0,
+                direction: FrameDirection::Rx,
+                frame: Can2Frame::new_data(id, &data[..8]).unwrap().into(),
+            },
+            LogRecord {
+                time: Duration::from_micros(4),
+                channel: 0,
+                direction: FrameDirection::Rx,
+                frame: Can2Frame::new_remote(id, 2).unwrap().into(),
+            },
+        ];
+
+        for &method in METHODS {
+            let file = container_file(&empty_file(), &objects, method);
+            assert_eq!(read(&file), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_objects() {
+        let mut file = empty_file();
+        file.extend_from_slice(OBJECT_SIGNATURE);
+        file.extend_from_slice(&(OBJECT_HEADER_BASE_SIZE as u16).to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        file.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+
+        let mut reader = BlfReader::new(file.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(LogError::MalformedObject {
+                reason: "object size too large",
+                ..
+            }))
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn rejects_oversized_compressed_containers() {
+        let file = container_file(&empty_file(), &[0; 2 * MAX_OBJECT_SIZE], ZLIB_DEFLATE);
+
+        let mut reader = BlfReader::new(file.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(LogError::MalformedObject {
+                reason: "unpacked container too large",
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! The log format written by `candump -l` and read by `canplayer` from
+//! can-utils, with one frame per line:
+//!
+//! ```text
+//! (1436509052.249713) can0 123#DEADBEEF
+//! (1436509052.250001) can0 12345678##1DEADBEEF
+//! ```
+//!
+//! Frames use the same notation as `cansend` (see [`CanFrame`]'s `FromStr`
+//! implementation). The channel index is taken from the trailing digits of
+//! the interface name, and a trailing `T` marks transmitted frames.
+
+use std::{
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+use super::{LogError, LogRecord, LogWriter};
+use crate::frame::{CanFrame, FrameDirection};
+
+/// Reads [`LogRecord`]s from a candump log
+pub struct CandumpReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    /// Creates a reader over the lines of `reader`
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+
+    fn parse_line(&self) -> Result<LogRecord, LogError> {
+        let malformed = |reason| LogError::Malformed {
+            line: self.line_number,
+            reason,
+        };
+
+        let mut fields = self.line.split_whitespace();
+
+        let time = fields
+            .next()
+            .and_then(|t| t.strip_prefix('('))
+            .and_then(|t| t.strip_suffix(')'))
+            .ok_or(malformed("expected a timestamp in parentheses"))?;
+        let (secs, fraction) = time.split_once('.').unwrap_or((time, "0"));
+        let secs: u64 = secs.parse().map_err(|_| malformed("invalid timestamp"))?;
+        let micros: u32 = format!("{:0<6.6}", fraction)
+            .parse()
+            .map_err(|_| malformed("invalid timestamp"))?;
+
+        let interface = fields.next().ok_or(malformed("missing interface name"))?;
+        let digits = interface.trim_start_matches(|c: char| !c.is_ascii_digit());
+        let channel = digits.parse().unwrap_or(0);
+
+        let frame: CanFrame = fields
+            .next()
+            .ok_or(malformed("missing frame"))?
+            .parse()
+            .map_err(|_| malformed("invalid frame"))?;
+
+        let direction = match fields.next() {
+            Some("T") => FrameDirection::Tx,
+            _ => FrameDirection::Rx,
+        };
+
+        Ok(LogRecord {
+            time: Duration::new(secs, micros * 1000),
+            channel,
+            direction,
+            frame,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Result<LogRecord, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => return Some(self.parse_line()),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+/// Writes [`LogRecord`]s as a candump log. Channels are named `can0`,
+/// `can1`, etc.
+pub struct CandumpWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> CandumpWriter<W> {
+    /// Creates a writer which appends to `writer`
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consumes the writer, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> LogWriter for CandumpWriter<W> {
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        write!(
+            self.writer,
+            "({}.{:06}) can{} {}",
+            record.time.as_secs(),
+            record.time.subsec_micros(),
+            record.channel,
+            record.frame
+        )?;
+
+        if record.direction == FrameDirection::Tx {
+            write!(self.writer, " T")?;
+        }
+
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), LogError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
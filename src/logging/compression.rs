@@ -0,0 +1,194 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The compression applied to a log file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Plain, uncompressed text
+    #[default]
+    None,
+    /// gzip, as written by `gzip` (requires the `gzip` feature)
+    Gzip,
+    /// Zstandard, as written by `zstd` (requires the `zstd` feature)
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression from a file extension (`.gz` or `.zst`)
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Detects the compression from the magic bytes at the start of a file
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The extension appended to the name of a compressed file, without the
+    /// leading dot
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    fn unsupported(&self) -> io::Error {
+        let feature = match self {
+            Compression::None => "",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        };
+
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{:?} compression requires the `{}` feature", self, feature),
+        )
+    }
+}
+
+/// Wraps `reader` in a decompressor if it starts with gzip or zstd magic
+/// bytes, so it can be passed to any log reader
+pub fn decompress<R: BufRead + Send + 'static>(
+    mut reader: R,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let compression = Compression::from_magic(reader.fill_buf()?);
+
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)?,
+        ))),
+        #[allow(unreachable_patterns)]
+        _ => Err(compression.unsupported()),
+    }
+}
+
+/// Opens a log file for reading, transparently decompressing it if needed
+pub fn open_log(path: impl AsRef<Path>) -> io::Result<Box<dyn BufRead + Send>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Creates a log file for writing, compressing it according to its
+/// extension (see [`Compression::from_path`])
+pub fn create_log(path: impl AsRef<Path>) -> io::Result<CompressedWriter<BufWriter<File>>> {
+    let compression = Compression::from_path(&path);
+    CompressedWriter::new(BufWriter::new(File::create(path)?), compression)
+}
+
+/// A writer which compresses everything written to it, to be passed to any
+/// log writer.
+///
+/// Compressed streams have to be terminated, which happens when the writer is
+/// dropped. Call [`finish`](CompressedWriter::finish) instead to find out
+/// whether that succeeded.
+pub struct CompressedWriter<W: Write> {
+    inner: Option<Encoder<W>>,
+}
+
+enum Encoder<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Wraps `writer` in the given compression, which fails if support for it
+    /// was not enabled
+    pub fn new(writer: W, compression: Compression) -> io::Result<Self> {
+        let encoder = match compression {
+            Compression::None => Encoder::None(writer),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+            #[allow(unreachable_patterns)]
+            _ => return Err(compression.unsupported()),
+        };
+
+        Ok(Self {
+            inner: Some(encoder),
+        })
+    }
+
+    /// Terminates the compressed stream and returns the underlying writer
+    #[allow(clippy::infallible_destructuring_match)]
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut writer = match self.inner.take().expect("only taken by finish") {
+            Encoder::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    fn encoder(&mut self) -> &mut dyn Write {
+        match self.inner.as_mut().expect("only taken by finish") {
+            Encoder::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder,
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder,
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder().flush()
+    }
+}
+
+impl<W: Write> Drop for CompressedWriter<W> {
+    fn drop(&mut self) {
+        match self.inner.as_mut() {
+            None => {}
+            Some(Encoder::None(writer)) => {
+                let _ = writer.flush();
+            }
+            #[cfg(feature = "gzip")]
+            Some(Encoder::Gzip(encoder)) => {
+                let _ = encoder.try_finish();
+            }
+            #[cfg(feature = "zstd")]
+            Some(Encoder::Zstd(encoder)) => {
+                let _ = encoder.do_finish();
+            }
+        }
+    }
+}
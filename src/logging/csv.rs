@@ -0,0 +1,172 @@
+//! Comma separated values for analysis in spreadsheets or pandas, with one
+//! frame per row:
+//!
+//! ```text
+//! time,channel,direction,id,flags,dlc,data
+//! 1436509052.249713,0,Rx,123,,4,DEADBEEF
+//! 1436509052.250001,0,Tx,12345678,XFB,9,00112233445566778899AABB
+//! ```
+//!
+//! The `flags` column contains `X` for extended IDs, `R` for remote frames,
+//! `F` for CAN FD frames, `B` for CAN FD frames with bit rate switching and
+//! `L` for CAN XL frames. The `dlc` column contains the data length code
+//! rather than the number of bytes, which differs for CAN FD frames longer
+//! than 8 bytes and for CAN XL frames (whose DLC is the length minus one).
+//!
+//! With the `dbc` feature, [`CsvWriter::with_database`] adds a column for
+//! every signal of the database (named `<message>.<signal>`), which holds the
+//! physical value of the signal for rows of that message and is left empty
+//! otherwise. CSV files are write only.
+
+use std::io::Write;
+
+use embedded_can::Id;
+
+use super::{LogError, LogRecord, LogWriter};
+use crate::frame::{CanFrame, FrameDirection};
+
+#[cfg(feature = "dbc")]
+use crate::dbc::Database;
+
+/// Writes [`LogRecord`]s as CSV rows
+pub struct CsvWriter<W> {
+    writer: W,
+    header_written: bool,
+    #[cfg(feature = "dbc")]
+    database: Option<Database>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Creates a writer which appends to `writer`. The header row is written
+    /// along with the first record.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: false,
+            #[cfg(feature = "dbc")]
+            database: None,
+        }
+    }
+
+    /// Adds a column for the physical value of every signal in `database`
+    #[cfg(feature = "dbc")]
+    pub fn with_database(mut self, database: Database) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Consumes the writer, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self) -> Result<(), LogError> {
+        write!(self.writer, "time,channel,direction,id,flags,dlc,data")?;
+
+        #[cfg(feature = "dbc")]
+        if let Some(database) = &self.database {
+            for message in database.messages() {
+                for signal in &message.signals {
+                    write!(self.writer, ",{}.{}", message.name, signal.name)?;
+                }
+            }
+        }
+
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "dbc")]
+    fn write_signals(&mut self, frame: &CanFrame) -> Result<(), LogError> {
+        let Some(database) = &self.database else {
+            return Ok(());
+        };
+        let decoded = database.decode(frame);
+
+        for message in database.messages() {
+            let decoded = decoded
+                .as_ref()
+                .filter(|decoded| std::ptr::eq(decoded.definition, message));
+
+            for signal in &message.signals {
+                write!(self.writer, ",")?;
+
+                if let Some(value) = decoded
+                    .and_then(|decoded| decoded.signal(&signal.name))
+                    .map(|decoded| decoded.value)
+                {
+                    write!(self.writer, "{}", value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> LogWriter for CsvWriter<W> {
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        if !self.header_written {
+            self.write_header()?;
+            self.header_written = true;
+        }
+
+        let (id, mut flags) = match record.frame.id() {
+            Id::Standard(id) => (format!("{:03X}", id.as_raw()), String::new()),
+            Id::Extended(id) => (format!("{:08X}", id.as_raw()), String::from("X")),
+        };
+        let (dlc, data): (usize, &[u8]) = match &record.frame {
+            CanFrame::Can2(frame) => {
+                if frame.is_remote() {
+                    flags.push('R');
+                }
+
+                (frame.dlc(), frame.data().unwrap_or_default())
+            }
+            CanFrame::CanFd(frame) => {
+                flags.push('F');
+                if frame.is_bit_rate_switched() {
+                    flags.push('B');
+                }
+
+                (u8::from(frame.dlc()).into(), frame.data())
+            }
+            CanFrame::CanXl(frame) => {
+                flags.push('L');
+
+                (frame.data().len() - 1, frame.data())
+            }
+        };
+        let direction = match record.direction {
+            FrameDirection::Rx => "Rx",
+            FrameDirection::Tx => "Tx",
+        };
+
+        write!(
+            self.writer,
+            "{}.{:06},{},{},{},{},{},",
+            record.time.as_secs(),
+            record.time.subsec_micros(),
+            record.channel,
+            direction,
+            id,
+            flags,
+            dlc
+        )?;
+
+        for byte in data {
+            write!(self.writer, "{:02X}", byte)?;
+        }
+
+        #[cfg(feature = "dbc")]
+        self.write_signals(&record.frame)?;
+
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), LogError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    candump::CandumpWriter, csv::CsvWriter, trc::TrcWriter, CompressedWriter, Compression,
+    LogError, LogRecord, LogWriter,
+};
+use crate::frame::{CanFrame, FrameDirection};
+
+/// The file format written by a [`Recorder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// See [`candump`](super::candump)
+    #[default]
+    Candump,
+    /// See [`csv`](super::csv)
+    Csv,
+    /// See [`trc`](super::trc)
+    Trc,
+}
+
+impl LogFormat {
+    /// The file extension used for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Candump => "log",
+            LogFormat::Csv => "csv",
+            LogFormat::Trc => "trc",
+        }
+    }
+
+    fn writer(&self, file: CompressedWriter<CountingWriter>) -> Box<dyn LogWriter + Send> {
+        match self {
+            LogFormat::Candump => Box::new(CandumpWriter::new(file)),
+            LogFormat::Csv => Box::new(CsvWriter::new(file)),
+            LogFormat::Trc => Box::new(TrcWriter::new(file)),
+        }
+    }
+}
+
+/// Writes frames to a series of log files in a directory, starting a new file
+/// whenever the current one grows too large or too old.
+///
+/// Files are named `<prefix>-<unix time>-<sequence>.<extension>`, with a
+/// further `.gz` or `.zst` extension if they are compressed. A recorder
+/// is usually attached to a socket with `CanSocket::set_recorder`, which
+/// records every received and transmitted frame along with its direction.
+///
+/// Errors while writing never interrupt the socket. Instead the first error
+/// is kept and can be retrieved with [`take_error`](Recorder::take_error).
+/// Call [`close`](Recorder::close) to flush the current file and find out
+/// whether recording succeeded; dropping the recorder flushes on a best-effort
+/// basis.
+pub struct Recorder {
+    directory: PathBuf,
+    prefix: String,
+    format: LogFormat,
+    compression: Compression,
+    max_file_size: Option<u64>,
+    max_file_age: Option<std::time::Duration>,
+    record_tx: bool,
+    sequence: u32,
+    current: Option<CurrentFile>,
+    error: Option<LogError>,
+}
+
+struct CurrentFile {
+    writer: Box<dyn LogWriter + Send>,
+    written: SharedCounter,
+    opened_at: Instant,
+}
+
+impl Recorder {
+    /// Creates a recorder which writes candump logs into `directory`
+    pub fn new(directory: impl AsRef<Path>, prefix: impl Into<String>) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            prefix: prefix.into(),
+            format: LogFormat::default(),
+            compression: Compression::default(),
+            max_file_size: None,
+            max_file_age: None,
+            record_tx: true,
+            sequence: 0,
+            current: None,
+            error: None,
+        }
+    }
+
+    /// Sets the format of the log files
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the compression of the log files (defaults to none)
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Starts a new file once the current one reaches `bytes` in size (after
+    /// compression)
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Starts a new file once the current one has been open for `age`
+    pub fn with_max_file_age(mut self, age: std::time::Duration) -> Self {
+        self.max_file_age = Some(age);
+        self
+    }
+
+    /// Sets whether transmitted frames are recorded as well as received ones
+    /// (defaults to true, so the log shows both sides of the conversation)
+    pub fn with_tx(mut self, record_tx: bool) -> Self {
+        self.record_tx = record_tx;
+        self
+    }
+
+    /// Returns true if frames in the given direction should be recorded
+    pub fn records(&self, direction: FrameDirection) -> bool {
+        direction == FrameDirection::Rx || self.record_tx
+    }
+
+    /// Records a frame which was on the bus just now
+    pub fn record_frame(&mut self, frame: &CanFrame, direction: FrameDirection, channel: usize) {
+        if !self.records(direction) {
+            return;
+        }
+
+        let record = LogRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            channel,
+            direction,
+            frame: frame.clone(),
+        };
+
+        if let Err(e) = self.record(&record) {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    /// Writes a record to the current file, rotating files as needed
+    pub fn record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+
+        self.current
+            .as_mut()
+            .expect("a file was opened by rotate")
+            .writer
+            .write_record(record)
+    }
+
+    /// Takes the first error which occurred while recording frames through
+    /// [`record_frame`](Recorder::record_frame)
+    pub fn take_error(&mut self) -> Option<LogError> {
+        self.error.take()
+    }
+
+    /// Flushes and closes the current file, returning the first error which
+    /// occurred while recording (if any)
+    pub fn close(mut self) -> Result<(), LogError> {
+        let result = self.finish_file();
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => result,
+        }
+    }
+
+    fn should_rotate(&self) -> bool {
+        let Some(current) = &self.current else {
+            return true;
+        };
+
+        let too_large = self
+            .max_file_size
+            .is_some_and(|max| current.written.get() >= max);
+        let too_old = self
+            .max_file_age
+            .is_some_and(|max| current.opened_at.elapsed() >= max);
+
+        too_large || too_old
+    }
+
+    fn rotate(&mut self) -> Result<(), LogError> {
+        self.finish_file()?;
+
+        fs::create_dir_all(&self.directory)?;
+
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut name = format!(
+            "{}-{}-{:04}.{}",
+            self.prefix,
+            secs,
+            self.sequence,
+            self.format.extension()
+        );
+        if let Some(extension) = self.compression.extension() {
+            name = format!("{}.{}", name, extension);
+        }
+        let path = self.directory.join(name);
+        self.sequence += 1;
+
+        let written = SharedCounter::default();
+        let file = CompressedWriter::new(
+            CountingWriter {
+                inner: BufWriter::new(File::create(path)?),
+                written: written.clone(),
+            },
+            self.compression,
+        )?;
+
+        self.current = Some(CurrentFile {
+            writer: self.format.writer(file),
+            written,
+            opened_at: Instant::now(),
+        });
+
+        Ok(())
+    }
+
+    fn finish_file(&mut self) -> Result<(), LogError> {
+        match self.current.take() {
+            Some(mut current) => current.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.finish_file();
+    }
+}
+
+/// A byte count shared between a [`Recorder`] and the writer of its current
+/// file, since the writer is boxed behind [`LogWriter`]
+#[derive(Clone, Default)]
+struct SharedCounter(std::sync::Arc<std::sync::atomic::AtomicU64>);
+
+impl SharedCounter {
+    fn get(&self) -> u64 {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn add(&self, bytes: u64) {
+        self.0
+            .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Counts the bytes written to a log file for size based rotation
+struct CountingWriter {
+    inner: BufWriter<File>,
+    written: SharedCounter,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written.add(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
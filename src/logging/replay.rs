@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use embedded_can::Id;
+
+use super::{LogError, LogRecord};
+
+/// Retransmits logged frames with their original timing.
+///
+/// ```no_run
+/// # async fn run(mut can: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>) -> Result<(), slcan_fd::logging::LogError> {
+/// use std::{fs::File, io::BufReader};
+///
+/// use slcan_fd::logging::{candump::CandumpReader, Replayer};
+///
+/// let log = CandumpReader::new(BufReader::new(File::open("capture.log")?));
+///
+/// Replayer::new(log).with_speed(2.0).run(&mut can).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Replayer<I> {
+    records: I,
+    speed: f64,
+    ids: Option<Vec<Id>>,
+}
+
+impl<I: Iterator<Item = Result<LogRecord, LogError>>> Replayer<I> {
+    /// Creates a replayer for the records of any log reader
+    pub fn new(records: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            records: records.into_iter(),
+            speed: 1.0,
+            ids: None,
+        }
+    }
+
+    /// Sets a multiplier for the replay speed, e.g. `2.0` replays twice as
+    /// fast as the frames were logged. `f64::INFINITY` sends all frames
+    /// back-to-back without waiting.
+    pub fn with_speed(mut self, multiplier: f64) -> Self {
+        self.speed = multiplier;
+        self
+    }
+
+    /// Only replays frames with one of the given IDs
+    pub fn with_id_filter(mut self, ids: impl IntoIterator<Item = impl Into<Id>>) -> Self {
+        self.ids = Some(ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Returns the next record which passes the ID filter
+    fn next_record(&mut self) -> Option<Result<LogRecord, LogError>> {
+        self.records
+            .by_ref()
+            .find(|record| match (record, &self.ids) {
+                (Ok(record), Some(ids)) => ids.contains(&record.frame.id()),
+                _ => true,
+            })
+    }
+
+    /// Works out when a record is due relative to the start of the replay
+    fn due(&self, start: Instant, first: Duration, time: Duration) -> Instant {
+        let offset = time.saturating_sub(first).as_secs_f64() / self.speed;
+
+        if offset.is_finite() && offset > 0.0 {
+            start + Duration::from_secs_f64(offset)
+        } else {
+            start
+        }
+    }
+
+    /// Replays all records through a synchronous socket, blocking the current
+    /// thread in between frames. Returns the number of frames sent.
+    #[cfg(feature = "sync")]
+    pub fn run_blocking<P: std::io::Read + std::io::Write>(
+        mut self,
+        socket: &mut crate::sync::CanSocket<P>,
+    ) -> Result<usize, LogError> {
+        let start = Instant::now();
+        let mut first = None;
+        let mut count = 0;
+
+        while let Some(record) = self.next_record() {
+            let record = record?;
+            let first = *first.get_or_insert(record.time);
+            let due = self.due(start, first, record.time);
+
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+
+            socket.send(record.frame)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Replays all records through an asynchronous socket. Returns the number
+    /// of frames sent.
+    #[cfg(feature = "tokio")]
+    pub async fn run<P: tokio::io::AsyncRead + tokio::io::AsyncWrite>(
+        mut self,
+        socket: &mut crate::tokio::CanSocket<P>,
+    ) -> Result<usize, LogError> {
+        let start = Instant::now();
+        let mut first = None;
+        let mut count = 0;
+
+        while let Some(record) = self.next_record() {
+            let record = record?;
+            let first = *first.get_or_insert(record.time);
+            let due = self.due(start, first, record.time);
+
+            tokio::time::sleep_until(due.into()).await;
+
+            socket.send(record.frame).await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,727 @@
+//! The `.trc` trace format written by PEAK-System's PCAN-View and PCAN-Basic,
+//! with one frame per line:
+//!
+//! ```text
+//! ;$FILEVERSION=2.1
+//! ;$STARTTIME=45000.5000000000
+//! ;$COLUMNS=N,O,T,B,I,d,R,L,D
+//!       1         0.000 DT  1     0123 Rx -  4    DE AD BE EF
+//!       2        12.345 FB  1 12345678 Tx -  9    00 11 22 33 44 55 66 77 88 99 AA BB
+//! ```
+//!
+//! Versions 1.0, 1.1, 1.3, 2.0 and 2.1 can be read, while files are always
+//! written as version 2.1 (which PCAN-View 4 and later can open). Only data
+//! and remote frames are read, status and error lines are skipped.
+//!
+//! PCAN buses are numbered from 1, so bus 1 maps to [`LogRecord::channel`] 0.
+//! Files without a bus column are read as channel 0.
+
+use std::{
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use super::{LogError, LogRecord, LogWriter};
+use crate::frame::{Can2Frame, CanFdFrame, CanFrame, FdDataLengthCode, FrameDirection};
+
+/// Days between the epoch used by `$STARTTIME` (1899-12-30) and the UNIX
+/// epoch
+const UNIX_EPOCH_DAYS: f64 = 25569.0;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// The versions of the trace format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    V1_0,
+    V1_1,
+    V1_3,
+    V2_0,
+    V2_1,
+}
+
+/// Reads [`LogRecord`]s from a PCAN trace file
+pub struct TrcReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    version: Version,
+    start_time: Duration,
+    columns: Vec<char>,
+}
+
+impl<R: BufRead> TrcReader<R> {
+    /// Creates a reader over the lines of `reader`
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            version: Version::V1_1,
+            start_time: Duration::ZERO,
+            columns: "NOTIdlD".chars().collect(),
+        }
+    }
+
+    /// The time at which the trace was started, relative to the UNIX epoch.
+    /// Only valid once the header has been read, i.e. after the first record.
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    fn parse_header(&mut self) -> Result<(), LogError> {
+        let malformed = |reason| LogError::Malformed {
+            line: self.line_number,
+            reason,
+        };
+
+        let Some((key, value)) = self
+            .line
+            .trim()
+            .trim_start_matches(';')
+            .strip_prefix('$')
+            .and_then(|l| l.split_once('='))
+        else {
+            return Ok(());
+        };
+
+        match key {
+            "FILEVERSION" => {
+                self.version = match value.trim() {
+                    "1.0" => Version::V1_0,
+                    "1.1" => Version::V1_1,
+                    "1.2" | "1.3" => Version::V1_3,
+                    "2.0" => Version::V2_0,
+                    "2.1" => Version::V2_1,
+                    _ => return Err(malformed("unsupported file version")),
+                };
+            }
+            "STARTTIME" => {
+                let days: f64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| malformed("invalid start time"))?;
+                let millis = ((days - UNIX_EPOCH_DAYS) * SECONDS_PER_DAY * 1000.0).round();
+
+                // The start time is stored with millisecond precision
+                self.start_time = Duration::from_millis(millis.max(0.0) as u64);
+            }
+            "COLUMNS" => {
+                self.columns = value
+                    .trim()
+                    .split(',')
+                    .filter_map(|c| c.trim().chars().next())
+                    .collect();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Parses a frame line, returning `None` for lines which are not data or
+    /// remote frames
+    fn parse_line(&self) -> Result<Option<LogRecord>, LogError> {
+        let malformed = |reason| LogError::Malformed {
+            line: self.line_number,
+            reason,
+        };
+
+        let fields: Vec<&str> = self.line.split_whitespace().collect();
+        let field = |index: usize| fields.get(index).copied().ok_or(malformed("missing field"));
+
+        if matches!(self.version, Version::V2_0 | Version::V2_1) {
+            return self.parse_columns(&fields).map_err(malformed);
+        }
+
+        // Version 1 files have fixed columns: the message number, time offset,
+        // bus (1.3 only), direction (1.1 and 1.3), ID, a reserved column (1.3
+        // only), data length and data
+        let (bus, direction, id, length) = match self.version {
+            Version::V1_0 => (None, None, 2, 3),
+            Version::V1_1 => (None, Some(2), 3, 4),
+            _ => (Some(2), Some(3), 4, 6),
+        };
+
+        let channel = match bus {
+            Some(bus) => parse_bus(field(bus)?).ok_or(malformed("invalid bus"))?,
+            None => 0,
+        };
+        let direction = match direction.map(field).transpose()? {
+            None | Some("Rx") => FrameDirection::Rx,
+            Some("Tx") => FrameDirection::Tx,
+            // Warnings and errors
+            Some(_) => return Ok(None),
+        };
+        let data = fields.get(length + 1..);
+        let kind = match data.and_then(|d| d.first()) {
+            Some(&"RTR") => "RR",
+            _ => "DT",
+        };
+        let length = field(length)?
+            .parse()
+            .map_err(|_| malformed("invalid data length"))?;
+
+        self.build_record(
+            field(1)?,
+            field(id)?,
+            kind,
+            length,
+            data,
+            channel,
+            direction,
+        )
+        .map_err(malformed)
+    }
+
+    /// Parses a frame line of a version 2 file, whose columns are listed in
+    /// the header
+    fn parse_columns(&self, fields: &[&str]) -> Result<Option<LogRecord>, &'static str> {
+        let column = |name: char| self.columns.iter().position(|c| *c == name);
+        let field = |name: char| {
+            column(name)
+                .and_then(|index| fields.get(index).copied())
+                .ok_or("missing field")
+        };
+
+        let kind = field('T')?;
+        if !matches!(kind, "DT" | "RR" | "FD" | "FB" | "FE" | "BI") {
+            return Ok(None);
+        }
+
+        let channel = match column('B') {
+            Some(_) => parse_bus(field('B')?).ok_or("invalid bus")?,
+            None => 0,
+        };
+        let direction = match field('d')? {
+            "Tx" => FrameDirection::Tx,
+            _ => FrameDirection::Rx,
+        };
+
+        // Data lengths are given either in bytes ('l') or as a DLC ('L')
+        let length = match column('l') {
+            Some(_) => field('l')?.parse().map_err(|_| "invalid data length")?,
+            None => {
+                let dlc: u8 = field('L')?.parse().map_err(|_| "invalid DLC")?;
+
+                if matches!(kind, "FD" | "FB" | "FE" | "BI") {
+                    FdDataLengthCode::try_from(dlc)
+                        .map_err(|_| "invalid DLC")?
+                        .get_num_bytes()
+                } else {
+                    dlc as usize
+                }
+            }
+        };
+        let data = column('D').and_then(|index| fields.get(index..));
+
+        self.build_record(
+            field('O')?,
+            field('I')?,
+            kind,
+            length,
+            data,
+            channel,
+            direction,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_record(
+        &self,
+        time: &str,
+        id: &str,
+        kind: &str,
+        length: usize,
+        data: Option<&[&str]>,
+        channel: usize,
+        direction: FrameDirection,
+    ) -> Result<Option<LogRecord>, &'static str> {
+        let id = parse_id(id)?;
+
+        let mut bytes = Vec::new();
+        if kind != "RR" {
+            if length > 64 {
+                return Err("too many data bytes");
+            }
+
+            for byte in data.unwrap_or_default().iter().take(length) {
+                let byte = u8::from_str_radix(byte, 16).map_err(|_| "invalid data byte")?;
+                bytes.push(byte);
+            }
+
+            if bytes.len() < length {
+                return Err("missing data bytes");
+            }
+        }
+
+        let frame: CanFrame = match kind {
+            "DT" => Can2Frame::new_data(id, &bytes)
+                .ok_or("too many data bytes")?
+                .into(),
+            "RR" => Can2Frame::new_remote(id, length)
+                .ok_or("invalid remote frame length")?
+                .into(),
+            "FD" | "FE" => CanFdFrame::new(id, &bytes)
+                .ok_or("invalid CAN FD data length")?
+                .with_bit_rate_switched(false)
+                .into(),
+            "FB" | "BI" => CanFdFrame::new(id, &bytes)
+                .ok_or("invalid CAN FD data length")?
+                .into(),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(LogRecord {
+            time: self.start_time + parse_offset(time)?,
+            channel,
+            direction,
+            frame,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for TrcReader<R> {
+    type Item = Result<LogRecord, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) if self.line.starts_with(';') => {
+                    if let Err(e) = self.parse_header() {
+                        return Some(Err(e));
+                    }
+                }
+                Ok(_) => match self.parse_line() {
+                    Ok(Some(record)) => return Some(Ok(record)),
+                    Ok(None) => continue,
+                    Err(e) => return Some(Err(e)),
+                },
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+/// Parses a time offset in milliseconds, keeping microsecond precision
+fn parse_offset(offset: &str) -> Result<Duration, &'static str> {
+    let (millis, fraction) = offset.split_once('.').unwrap_or((offset, "0"));
+    let millis: u64 = millis.parse().map_err(|_| "invalid time offset")?;
+    let micros: u64 = format!("{:0<3.3}", fraction)
+        .parse()
+        .map_err(|_| "invalid time offset")?;
+
+    millis
+        .checked_mul(1000)
+        .and_then(|millis| millis.checked_add(micros))
+        .map(Duration::from_micros)
+        .ok_or("invalid time offset")
+}
+
+/// Parses a hex ID, which is extended if it has more than 4 digits
+fn parse_id(id: &str) -> Result<Id, &'static str> {
+    let raw = u32::from_str_radix(id, 16).map_err(|_| "invalid ID")?;
+
+    if id.len() > 4 {
+        ExtendedId::new(raw)
+            .map(Id::from)
+            .ok_or("extended ID out of range")
+    } else {
+        StandardId::new(raw as u16)
+            .map(Id::from)
+            .ok_or("standard ID out of range")
+    }
+}
+
+fn parse_bus(bus: &str) -> Option<usize> {
+    bus.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// Writes [`LogRecord`]s as a version 2.1 PCAN trace file.
+///
+/// The header is written along with the first record, since it contains the
+/// start time of the trace. Offsets are relative to the first record.
+pub struct TrcWriter<W> {
+    writer: W,
+    start_time: Option<Duration>,
+    message_number: usize,
+}
+
+impl<W: Write> TrcWriter<W> {
+    /// Creates a writer which appends to `writer`
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start_time: None,
+            message_number: 0,
+        }
+    }
+
+    /// Consumes the writer, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self, start_time: Duration) -> Result<(), LogError> {
+        let days = UNIX_EPOCH_DAYS + start_time.as_secs_f64() / SECONDS_PER_DAY;
+
+        writeln!(self.writer, ";$FILEVERSION=2.1")?;
+        writeln!(self.writer, ";$STARTTIME={:.10}", days)?;
+        writeln!(self.writer, ";$COLUMNS=N,O,T,B,I,d,R,L,D")?;
+        writeln!(self.writer, ";")?;
+        writeln!(self.writer, ";   Generated by slcan_fd")?;
+        writeln!(
+            self.writer,
+            ";-------------------------------------------------------------------------------"
+        )?;
+        writeln!(self.writer, ";   Message   Time    Type    ID     Rx/Tx")?;
+        writeln!(
+            self.writer,
+            ";   Number    Offset  |  Bus  [hex]  |  Reserved"
+        )?;
+        writeln!(
+            self.writer,
+            ";   |         [ms]    |  |    |      |  |  Data Length Code"
+        )?;
+        writeln!(
+            self.writer,
+            ";   |         |       |  |    |      |  |  |    Data [hex] ..."
+        )?;
+        writeln!(
+            self.writer,
+            ";   |         |       |  |    |      |  |  |    |"
+        )?;
+        writeln!(
+            self.writer,
+            ";---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --"
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> LogWriter for TrcWriter<W> {
+    fn write_record(&mut self, record: &LogRecord) -> Result<(), LogError> {
+        let start_time = match self.start_time {
+            Some(start_time) => start_time,
+            None => {
+                // The start time is stored with millisecond precision
+                let start_time = Duration::from_millis(record.time.as_millis() as u64);
+                self.write_header(start_time)?;
+                *self.start_time.insert(start_time)
+            }
+        };
+
+        self.message_number += 1;
+
+        let offset = record.time.saturating_sub(start_time);
+        let (kind, dlc, data): (_, u8, &[u8]) = match &record.frame {
+            CanFrame::Can2(frame) if frame.is_remote() => ("RR", frame.dlc() as u8, &[]),
+            CanFrame::Can2(frame) => ("DT", frame.dlc() as u8, frame.data().unwrap_or_default()),
+            CanFrame::CanFd(frame) => (
+                if frame.is_bit_rate_switched() {
+                    "FB"
+                } else {
+                    "FD"
+                },
+                frame.dlc().into(),
+                frame.data(),
+            ),
+            CanFrame::CanXl(_) => return Err(LogError::UnsupportedFrame),
+        };
+        let id = match record.frame.id() {
+            Id::Standard(id) => format!("{:04X}", id.as_raw()),
+            Id::Extended(id) => format!("{:08X}", id.as_raw()),
+        };
+        let direction = match record.direction {
+            FrameDirection::Rx => "Rx",
+            FrameDirection::Tx => "Tx",
+        };
+
+        let mut line = format!(
+            "{:>7} {:>13} {} {:>2} {:>8} {} -  {:<4}",
+            self.message_number,
+            format!(
+                "{}.{:03}",
+                offset.as_millis(),
+                offset.subsec_micros() % 1000
+            ),
+            kind,
+            record.channel + 1,
+            id,
+            direction,
+            dlc
+        );
+
+        for byte in data {
+            line.push_str(&format!(" {:02X}", byte));
+        }
+
+        writeln!(self.writer, "{}", line.trim_end())?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), LogError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `$STARTTIME` of the hand-written traces, 43000.5 days after
+    /// 1899-12-30
+    const START_TIME: Duration = Duration::from_secs(1_506_081_600);
+
+    fn read(trace: &str) -> Vec<LogRecord> {
+        TrcReader::new(trace.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn write(records: &[LogRecord]) -> String {
+        let mut writer = TrcWriter::new(Vec::new());
+        for record in records {
+            writer.write_record(record).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    /// Reads `trace`, writes its records as a version 2.1 trace and checks
+    /// that they are read back unchanged
+    fn round_trip(trace: &str) -> Vec<LogRecord> {
+        let records = read(trace);
+        let written = write(&records);
+
+        assert!(written.starts_with(";$FILEVERSION=2.1\n"));
+        assert_eq!(read(&written), records);
+
+        records
+    }
+
+    fn record(
+        millis: u64,
+        channel: usize,
+        direction: FrameDirection,
+        frame: CanFrame,
+    ) -> LogRecord {
+        LogRecord {
+            time: START_TIME + Duration::from_micros(millis * 1000),
+            channel,
+            direction,
+            frame,
+        }
+    }
+
+    fn standard(id: u16) -> StandardId {
+        StandardId::new(id).unwrap()
+    }
+
+    fn extended(id: u32) -> ExtendedId {
+        ExtendedId::new(id).unwrap()
+    }
+
+    #[test]
+    fn round_trips_version_1_0() {
+        let records = round_trip(
+            ";$FILEVERSION=1.0\n\
+             ;$STARTTIME=43000.5\n\
+             \x20     1)      1059.9  0300  8  00 11 22 33 44 55 66 77\n\
+             \x20     2)      1283.2  0100  0\n",
+        );
+
+        assert_eq!(
+            records,
+            [
+                LogRecord {
+                    time: START_TIME + Duration::from_micros(1_059_900),
+                    ..record(0, 0, FrameDirection::Rx, {
+                        Can2Frame::new_data(
+                            standard(0x300),
+                            &[0, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77],
+                        )
+                        .unwrap()
+                        .into()
+                    })
+                },
+                LogRecord {
+                    time: START_TIME + Duration::from_micros(1_283_200),
+                    ..record(0, 0, FrameDirection::Rx, {
+                        Can2Frame::new_data(standard(0x100), &[]).unwrap().into()
+                    })
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_version_1_1() {
+        let records = round_trip(
+            ";$FILEVERSION=1.1\n\
+             ;$STARTTIME=43000.5\n\
+             \x20     1)        10.0  Rx         0300  2  AA BB\n\
+             \x20     2)        20.5  Tx     18EFC8F1  1  01\n\
+             \x20     3)        30.0  Warng  FFFFFFFF  4  00 00 00 08  BUSHEAVY\n\
+             \x20     4)        40.0  Rx         0123  4  RTR\n",
+        );
+
+        assert_eq!(
+            records,
+            [
+                record(10, 0, FrameDirection::Rx, {
+                    Can2Frame::new_data(standard(0x300), &[0xAA, 0xBB])
+                        .unwrap()
+                        .into()
+                }),
+                LogRecord {
+                    time: START_TIME + Duration::from_micros(20_500),
+                    ..record(0, 0, FrameDirection::Tx, {
+                        Can2Frame::new_data(extended(0x18EF_C8F1), &[1])
+                            .unwrap()
+                            .into()
+                    })
+                },
+                record(40, 0, FrameDirection::Rx, {
+                    Can2Frame::new_remote(standard(0x123), 4).unwrap().into()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_version_1_3() {
+        let records = round_trip(
+            ";$FILEVERSION=1.3\n\
+             ;$STARTTIME=43000.5\n\
+             \x20     1)        10.000 1  Rx         0300 -  2    AA BB\n\
+             \x20     2)        20.123 2  Tx     18EFC8F1 -  1    01\n\
+             \x20     3)        30.000 1  Rx         0123 -  4    RTR\n",
+        );
+
+        assert_eq!(
+            records,
+            [
+                record(10, 0, FrameDirection::Rx, {
+                    Can2Frame::new_data(standard(0x300), &[0xAA, 0xBB])
+                        .unwrap()
+                        .into()
+                }),
+                LogRecord {
+                    time: START_TIME + Duration::from_micros(20_123),
+                    ..record(0, 1, FrameDirection::Tx, {
+                        Can2Frame::new_data(extended(0x18EF_C8F1), &[1])
+                            .unwrap()
+                            .into()
+                    })
+                },
+                record(30, 0, FrameDirection::Rx, {
+                    Can2Frame::new_remote(standard(0x123), 4).unwrap().into()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_version_2_0() {
+        // Version 2.0 gives the data length in bytes rather than as a DLC
+        let records = round_trip(
+            ";$FILEVERSION=2.0\n\
+             ;$STARTTIME=43000.5\n\
+             ;$COLUMNS=N,O,T,I,d,l,D\n\
+             \x20     1        10.000 DT     0300 Rx 2    AA BB\n\
+             \x20     2        20.000 ST          Rx    00 00 00 04\n\
+             \x20     3        30.000 FD 18EFC8F1 Tx 12   00 01 02 03 04 05 06 07 08 09 0A 0B\n\
+             \x20     4        40.000 RR     0123 Rx 4\n",
+        );
+
+        assert_eq!(
+            records,
+            [
+                record(10, 0, FrameDirection::Rx, {
+                    Can2Frame::new_data(standard(0x300), &[0xAA, 0xBB])
+                        .unwrap()
+                        .into()
+                }),
+                record(30, 0, FrameDirection::Tx, {
+                    CanFdFrame::new(
+                        extended(0x18EF_C8F1),
+                        &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+                    )
+                    .unwrap()
+                    .with_bit_rate_switched(false)
+                    .into()
+                }),
+                record(40, 0, FrameDirection::Rx, {
+                    Can2Frame::new_remote(standard(0x123), 4).unwrap().into()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_version_2_1() {
+        let data: Vec<u8> = (0..64).collect();
+        let records = [
+            record(0, 0, FrameDirection::Rx, {
+                Can2Frame::new_data(standard(0x7FF), &data[..8])
+                    .unwrap()
+                    .into()
+            }),
+            record(5, 1, FrameDirection::Tx, {
+                Can2Frame::new_remote(extended(0x1FFF_FFFF), 3)
+                    .unwrap()
+                    .into()
+            }),
+            record(12_345, 0, FrameDirection::Rx, {
+                CanFdFrame::new(standard(0x123), &data).unwrap().into()
+            }),
+            LogRecord {
+                time: START_TIME + Duration::from_micros(12_345_678),
+                ..record(0, 3, FrameDirection::Tx, {
+                    CanFdFrame::new(extended(0x0123_4567), &data[..12])
+                        .unwrap()
+                        .with_bit_rate_switched(false)
+                        .into()
+                })
+            },
+        ];
+
+        let trace = write(&records);
+        assert_eq!(
+            trace.lines().last(),
+            Some("      4     12345.678 FD  4 01234567 Tx -  9    00 01 02 03 04 05 06 07 08 09 0A 0B")
+        );
+        assert_eq!(round_trip(&trace), records);
+    }
+
+    #[test]
+    fn rejects_overflowing_time_offsets() {
+        assert_eq!(
+            parse_offset("18446744073709551615.000"),
+            Err("invalid time offset")
+        );
+        assert_eq!(
+            parse_offset("18446744073709551.615"),
+            Ok(Duration::from_micros(u64::MAX))
+        );
+
+        let trace = ";$FILEVERSION=2.1\n\
+                     ;$COLUMNS=N,O,T,B,I,d,R,L,D\n\
+                     \x20     1 18446744073709551615.000 DT  1     0123 Rx -  0\n";
+        assert!(matches!(
+            TrcReader::new(trace.as_bytes()).next(),
+            Some(Err(LogError::Malformed {
+                line: 3,
+                reason: "invalid time offset",
+            }))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+//! Counters and gauges emitted through the [`metrics`](https://docs.rs/metrics)
+//! facade when the `metrics` feature is enabled.
+//!
+//! Install any `metrics` compatible recorder (e.g.
+//! `metrics-exporter-prometheus`) to collect them. Without the feature all of
+//! the recording functions compile down to nothing.
+
+use crate::{frame::CanFrame, parser::MessageParseError};
+
+/// Counter of frames received from the bus, labelled with `kind`
+/// (`can2`, `canfd` or `canxl`)
+pub const FRAMES_RECEIVED: &str = "slcan_frames_received_total";
+/// Counter of frames sent to the gateway, labelled with `kind` (`can2`,
+/// `canfd` or `canxl`)
+pub const FRAMES_TRANSMITTED: &str = "slcan_frames_transmitted_total";
+/// Counter of received lines which could not be parsed, labelled with `error`
+pub const PARSE_ERRORS: &str = "slcan_parse_errors_total";
+/// Counter of received lines which were dropped for exceeding the MTU
+pub const OVERSIZED_LINES: &str = "slcan_oversized_lines_total";
+/// Counter of times the CAN channel was (re)opened
+pub const CHANNEL_OPENS: &str = "slcan_channel_opens_total";
+/// Counter of times a socket recovered its link to the bus, labelled with
+/// `reason` (`adapter_reset` after `CanSocket::reset_adapter` or `bus_off`
+/// after recovering from bus-off according to the `BusOffPolicy`)
+pub const RECONNECTS: &str = "slcan_reconnects_total";
+/// Gauge of the frames occupying the transmit queue of the gateway (see
+/// `CanSocket::tx_queue_len`), as last updated by any socket
+pub const TX_QUEUE_DEPTH: &str = "slcan_tx_queue_depth";
+
+#[cfg(feature = "metrics")]
+fn frame_kind(frame: &CanFrame) -> &'static str {
+    match frame {
+        CanFrame::Can2(_) => "can2",
+        CanFrame::CanFd(_) => "canfd",
+        CanFrame::CanXl(_) => "canxl",
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn error_kind(error: &MessageParseError) -> &'static str {
+    match error {
+        MessageParseError::EmptyMessage => "empty_message",
+        MessageParseError::UnrecognizedMessage(_) => "unrecognized_message",
+        MessageParseError::NotEnoughBytes(..) => "not_enough_bytes",
+        MessageParseError::TooManyBytes(..) => "too_many_bytes",
+        MessageParseError::IllegalHexDigit(_) => "illegal_hex_digit",
+        MessageParseError::IllegalDecimalDigit(_) => "illegal_decimal_digit",
+        MessageParseError::StandardIdOutOfRange(_) => "standard_id_out_of_range",
+        MessageParseError::ExtendedIdOutOfRange(_) => "extended_id_out_of_range",
+        MessageParseError::InvalidDataLength(_) => "invalid_data_length",
+        MessageParseError::MismatchedDataLength(..) => "mismatched_data_length",
+        MessageParseError::IllegalBusState(_) => "illegal_bus_state",
+    }
+}
+
+pub(crate) fn record_frame_received(frame: &CanFrame) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(FRAMES_RECEIVED, "kind" => frame_kind(frame)).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = frame;
+}
+
+pub(crate) fn record_frame_transmitted(frame: &CanFrame) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(FRAMES_TRANSMITTED, "kind" => frame_kind(frame)).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = frame;
+}
+
+pub(crate) fn record_parse_error(error: &MessageParseError) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(PARSE_ERRORS, "error" => error_kind(error)).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = error;
+}
+
+pub(crate) fn record_oversized_line() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(OVERSIZED_LINES).increment(1);
+}
+
+pub(crate) fn record_channel_open() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(CHANNEL_OPENS).increment(1);
+}
+
+pub(crate) fn record_reconnect(reason: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(RECONNECTS, "reason" => reason).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = reason;
+}
+
+pub(crate) fn record_tx_queue_depth(depth: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!(TX_QUEUE_DEPTH).set(depth as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = depth;
+}
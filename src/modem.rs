@@ -0,0 +1,89 @@
+use std::io;
+
+/// Control over the modem control lines of a serial port, used by
+/// `CanSocket::reset_adapter` to reset adapters which are wired to reset
+/// when DTR or RTS is toggled.
+///
+/// Implemented for the ports of the serial crates this crate integrates
+/// with, when the matching feature is enabled.
+pub trait ModemControl {
+    /// Drives the Data Terminal Ready line, `true` being asserted
+    fn set_dtr(&mut self, level: bool) -> io::Result<()>;
+
+    /// Drives the Request To Send line, `true` being asserted
+    fn set_rts(&mut self, level: bool) -> io::Result<()>;
+}
+
+#[cfg(feature = "serialport")]
+impl ModemControl for Box<dyn serialport::SerialPort> {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(self.write_data_terminal_ready(level)?)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(self.write_request_to_send(level)?)
+    }
+}
+
+#[cfg(all(feature = "serialport", unix))]
+impl ModemControl for serialport::TTYPort {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(serialport::SerialPort::write_data_terminal_ready(
+            self, level,
+        )?)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(serialport::SerialPort::write_request_to_send(self, level)?)
+    }
+}
+
+#[cfg(all(feature = "serialport", windows))]
+impl ModemControl for serialport::COMPort {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(serialport::SerialPort::write_data_terminal_ready(
+            self, level,
+        )?)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(serialport::SerialPort::write_request_to_send(self, level)?)
+    }
+}
+
+#[cfg(feature = "tokio-serial")]
+impl ModemControl for tokio_serial::SerialStream {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(tokio_serial::SerialPort::write_data_terminal_ready(
+            self, level,
+        )?)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(tokio_serial::SerialPort::write_request_to_send(
+            self, level,
+        )?)
+    }
+}
+
+#[cfg(feature = "serial2")]
+impl ModemControl for serial2::SerialPort {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        serial2::SerialPort::set_dtr(self, level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        serial2::SerialPort::set_rts(self, level)
+    }
+}
+
+#[cfg(feature = "serial2-tokio")]
+impl ModemControl for serial2_tokio::SerialPort {
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        serial2_tokio::SerialPort::set_dtr(self, level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        serial2_tokio::SerialPort::set_rts(self, level)
+    }
+}
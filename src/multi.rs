@@ -0,0 +1,259 @@
+//! Working with several gateways (i.e. several buses) at once, e.g. on test
+//! benches with more than one bus.
+//!
+//! A [`BusManager`] owns a socket per bus, tags every received frame with the
+//! index of the bus it came from (see [`RxFrame::channel`]) and merges the
+//! frames of all buses into a single stream. [`FrameMux`] does the same for
+//! sockets which are owned elsewhere.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     first: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>,
+//! #     second: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use slcan_fd::{multi::BusManager, Can2Frame, StandardId};
+//!
+//! let mut buses = BusManager::new();
+//! let powertrain = buses.add(first);
+//! let body = buses.add(second);
+//!
+//! let frame = Can2Frame::new_data(StandardId::new(0x123).unwrap(), &[0x01]).unwrap();
+//! buses.send(body, frame).await?;
+//!
+//! loop {
+//!     let frame = buses.read().await?;
+//!     println!("{}: {:?}", frame.channel, frame.frame);
+//! }
+//! # }
+//! ```
+
+use std::{
+    future::{pending, poll_fn, Future},
+    io,
+    task::Poll,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{frame::CanFrame, tokio::CanSocket, ReadError, RxFrame, SendError};
+
+/// An error which occurred while reading from one of several sockets
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to read from channel {channel}: {error}")]
+pub struct ChannelError {
+    /// The index of the socket which failed
+    pub channel: usize,
+    /// The error itself
+    pub error: ReadError,
+}
+
+/// Owns the sockets of several buses and merges the frames they receive. See
+/// the [module documentation](self).
+pub struct BusManager<P> {
+    sockets: Vec<CanSocket<P>>,
+    next: usize,
+}
+
+impl<P> Default for BusManager<P> {
+    fn default() -> Self {
+        Self {
+            sockets: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite> BusManager<P> {
+    /// Creates a manager without any buses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a socket and returns its channel index, which is also set as the
+    /// channel of the socket (see `CanSocket::set_channel`). Channels are
+    /// numbered from 0 in the order they were added.
+    pub fn add(&mut self, mut socket: CanSocket<P>) -> usize {
+        let channel = self.sockets.len();
+        socket.set_channel(channel);
+        self.sockets.push(socket);
+        channel
+    }
+
+    /// Returns the number of buses
+    pub fn len(&self) -> usize {
+        self.sockets.len()
+    }
+
+    /// Returns true if no buses were added
+    pub fn is_empty(&self) -> bool {
+        self.sockets.is_empty()
+    }
+
+    /// Returns the socket of a channel, e.g. for configuring it
+    pub fn socket_mut(&mut self, channel: usize) -> Option<&mut CanSocket<P>> {
+        self.sockets.get_mut(channel)
+    }
+
+    /// Sends a frame on the given channel. See `CanSocket::send`.
+    pub async fn send(
+        &mut self,
+        channel: usize,
+        frame: impl Into<CanFrame>,
+    ) -> Result<(), SendError> {
+        let socket = self.sockets.get_mut(channel).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no socket for this channel")
+        })?;
+
+        socket.send(frame).await
+    }
+
+    /// Reads the next frame received on any channel. Frames carry the index
+    /// of their channel in [`RxFrame::channel`]. Never completes if no buses
+    /// were added.
+    ///
+    /// Channels are polled in turns so a busy bus can't starve the others.
+    /// An error only concerns the channel it names, reading can continue.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe in the same way as `CanSocket::read`.
+    pub async fn read(&mut self) -> Result<RxFrame, ChannelError> {
+        let start = self.next;
+        self.next = self.next.wrapping_add(1);
+
+        let Some((channel, result)) = read_any(self.sockets.iter_mut().enumerate(), start).await
+        else {
+            return pending().await;
+        };
+
+        result.map_err(|error| ChannelError { channel, error })
+    }
+
+    /// Consumes the manager, returning the sockets in channel order
+    pub fn into_sockets(self) -> Vec<CanSocket<P>> {
+        self.sockets
+    }
+}
+
+/// Merges the frames received by a set of borrowed sockets, yielding them in
+/// the order they arrive along with the index of their socket.
+///
+/// Each socket is handled independently: an I/O error is returned once along
+/// with the index of the failed socket, which is then no longer read from
+/// while the others continue. Other errors, such as lines which can't be
+/// parsed, don't stop the socket.
+///
+/// ```no_run
+/// # async fn run(mut sockets: Vec<slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>>) {
+/// use slcan_fd::multi::FrameMux;
+///
+/// let mut mux = FrameMux::new(&mut sockets);
+///
+/// while let Some(result) = mux.next().await {
+///     match result {
+///         Ok((channel, frame)) => println!("{}: {:?}", channel, frame),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// # }
+/// ```
+pub struct FrameMux<'a, P> {
+    sockets: &'a mut [CanSocket<P>],
+    failed: Vec<bool>,
+    next: usize,
+}
+
+impl<'a, P: AsyncRead + AsyncWrite> FrameMux<'a, P> {
+    /// Creates a mux over `sockets`, which are identified by their index in
+    /// the slice
+    pub fn new(sockets: &'a mut [CanSocket<P>]) -> Self {
+        Self {
+            failed: vec![false; sockets.len()],
+            sockets,
+            next: 0,
+        }
+    }
+
+    /// Waits for the next frame from any socket which hasn't failed. Returns
+    /// `None` once all sockets have failed (or if there are none).
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe in the same way as `CanSocket::read`.
+    pub async fn next(&mut self) -> Option<Result<(usize, CanFrame), ChannelError>> {
+        let start = self.next;
+        self.next = self.next.wrapping_add(1);
+
+        let failed = &self.failed;
+        let sockets = self
+            .sockets
+            .iter_mut()
+            .enumerate()
+            .filter(|(channel, _)| !failed[*channel]);
+
+        let (channel, result) = read_any(sockets, start).await?;
+
+        Some(match result {
+            Ok(frame) => Ok((channel, frame.frame)),
+            Err(error) => {
+                if matches!(error, ReadError::Io(_)) {
+                    self.failed[channel] = true;
+                }
+
+                Err(ChannelError { channel, error })
+            }
+        })
+    }
+
+    /// Returns true if reading from the socket with the given index failed
+    pub fn is_failed(&self, channel: usize) -> bool {
+        self.failed.get(channel).copied().unwrap_or(true)
+    }
+
+    /// Returns the socket with the given index, e.g. for sending frames or
+    /// reopening it after it failed
+    pub fn socket_mut(&mut self, channel: usize) -> Option<&mut CanSocket<P>> {
+        self.sockets.get_mut(channel)
+    }
+
+    /// Resumes reading from a socket which failed
+    pub fn reset(&mut self, channel: usize) {
+        if let Some(failed) = self.failed.get_mut(channel) {
+            *failed = false;
+        }
+    }
+}
+
+/// Reads the next frame from any of `sockets`, which are given along with
+/// their channel index. The search starts at the socket in position `start`
+/// (modulo their number). Returns the channel of the socket along with the
+/// result of its read, or `None` if there are no sockets.
+async fn read_any<'a, P: AsyncRead + AsyncWrite + 'a>(
+    sockets: impl IntoIterator<Item = (usize, &'a mut CanSocket<P>)>,
+    start: usize,
+) -> Option<(usize, Result<RxFrame, ReadError>)> {
+    let mut reads: Vec<_> = sockets
+        .into_iter()
+        .map(|(channel, socket)| (channel, Box::pin(socket.read_rx_frame())))
+        .collect();
+    let count = reads.len();
+
+    if count == 0 {
+        return None;
+    }
+
+    let result = poll_fn(|cx| {
+        for offset in 0..count {
+            let (channel, read) = &mut reads[(start + offset) % count];
+
+            if let Poll::Ready(result) = read.as_mut().poll(cx) {
+                return Poll::Ready((*channel, result));
+            }
+        }
+
+        Poll::Pending
+    })
+    .await;
+
+    Some(result)
+}
@@ -0,0 +1,104 @@
+use crate::{frame::CanFrame, status::BusStatus, timestamp::Timestamp, ReadError, TransmitStatus};
+
+/// Hooks which are called for the traffic and events of a socket, for
+/// plugging in loggers, metrics or user interfaces without wrapping every
+/// call site.
+///
+/// Observers are registered with `CanSocket::add_observer` and are called in
+/// the order they were added. All hooks do nothing by default, so only the
+/// interesting ones need to be implemented:
+///
+/// ```
+/// use slcan_fd::{BusObserver, CanFrame, Timestamp};
+///
+/// /// Counts the received frames
+/// #[derive(Default)]
+/// struct FrameCounter(u64);
+///
+/// impl BusObserver for FrameCounter {
+///     fn on_rx_frame(&mut self, _frame: &CanFrame, _timestamp: Option<Timestamp>) {
+///         self.0 += 1;
+///     }
+/// }
+/// ```
+pub trait BusObserver: Send {
+    /// Called for every frame received from the bus, before it passes the
+    /// interceptors of the socket
+    fn on_rx_frame(&mut self, _frame: &CanFrame, _timestamp: Option<Timestamp>) {}
+
+    /// Called for every frame handed to the gateway for transmission, after
+    /// it passed the interceptors of the socket
+    fn on_tx_frame(&mut self, _frame: &CanFrame) {}
+
+    /// Called for every error while reading from the gateway, except for
+    /// reads which would block (see [`ReadError::is_would_block`])
+    fn on_error(&mut self, _error: &ReadError) {}
+
+    /// Called whenever the gateway acknowledges (`z`/`Z`) or rejects a frame
+    /// which was sent to it, along with the number of frames still in flight
+    /// (see `CanSocket::tx_queue_len`). Acknowledgements are only seen while the
+    /// socket is being read.
+    fn on_transmit_status(&mut self, _status: TransmitStatus, _in_flight: usize) {}
+
+    /// Called for every bus state report of the gateway
+    fn on_bus_status(&mut self, _status: &BusStatus) {}
+
+    /// Called whenever the channel is opened or closed
+    fn on_channel_state(&mut self, _open: bool) {}
+}
+
+/// The observers registered with a socket
+#[derive(Default)]
+pub(crate) struct ObserverList {
+    observers: Vec<Box<dyn BusObserver>>,
+}
+
+impl ObserverList {
+    pub fn push(&mut self, observer: Box<dyn BusObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear(&mut self) {
+        self.observers.clear();
+    }
+
+    pub fn rx_frame(&mut self, frame: &CanFrame, timestamp: Option<Timestamp>) {
+        for observer in &mut self.observers {
+            observer.on_rx_frame(frame, timestamp);
+        }
+    }
+
+    pub fn tx_frame(&mut self, frame: &CanFrame) {
+        for observer in &mut self.observers {
+            observer.on_tx_frame(frame);
+        }
+    }
+
+    pub fn error(&mut self, error: &ReadError) {
+        if error.is_would_block() {
+            return;
+        }
+
+        for observer in &mut self.observers {
+            observer.on_error(error);
+        }
+    }
+
+    pub fn transmit_status(&mut self, status: TransmitStatus, in_flight: usize) {
+        for observer in &mut self.observers {
+            observer.on_transmit_status(status, in_flight);
+        }
+    }
+
+    pub fn bus_status(&mut self, status: &BusStatus) {
+        for observer in &mut self.observers {
+            observer.on_bus_status(status);
+        }
+    }
+
+    pub fn channel_state(&mut self, open: bool) {
+        for observer in &mut self.observers {
+            observer.on_channel_state(open);
+        }
+    }
+}
@@ -2,7 +2,10 @@ use embedded_can::{ExtendedId, StandardId};
 use num_enum::TryFromPrimitive;
 
 use crate::{
+    command::TimestampMode,
     frame::{CanFdFrame, CanFrame, FdDataLengthCode},
+    status::{BusState, BusStatus},
+    timestamp::Timestamp,
     Can2Frame,
 };
 
@@ -10,8 +13,11 @@ const MAX_DATA_LENGTH: usize = 64;
 
 /// Various errors which can arise while parsing an SLCAN message
 #[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MessageParseError {
     /* Generic message parsing */
+    #[error("Received an empty message")]
+    EmptyMessage,
     #[error("Received a message with an unrecognized specifier ({0:?})")]
     UnrecognizedMessage(u8),
     #[error("Received a message ({0:?}) but less bytes than is required to parse it ({1:?})")]
@@ -28,14 +34,45 @@ pub enum MessageParseError {
     StandardIdOutOfRange(u16),
     #[error("Received a CAN Extended ID ({0:?}) that was out of the valid range (0..=0x1FFFFFFF)")]
     ExtendedIdOutOfRange(u32),
-    #[error("Received encoded data with a length ({0:?}) that was not a multiple of 2")]
+    /// The encoded data has an odd number of hex digits, or the DLC of a CAN
+    /// 2.0 frame exceeds 8
+    #[error("Received a data length ({0:?}) that is invalid for the message")]
     InvalidDataLength(u8),
     #[error("Received a message with expected length ({0:?}) but ({1:?}) bytes of data")]
     MismatchedDataLength(u8, usize),
+
+    /* Status Parsing */
+    #[error("Received a bus state ({0:?}) that was not recognized")]
+    IllegalBusState(u8),
+}
+
+/// A [`MessageParseError`] along with the line (without the CR line ending)
+/// which could not be parsed, so corrupted data can be inspected
+#[derive(Debug, thiserror::Error)]
+#[error("{error} in line \"{}\"", .line.escape_ascii())]
+pub struct LineParseError {
+    /// The raw line exactly as it was received
+    pub line: Vec<u8>,
+    /// Why the line could not be parsed
+    #[source]
+    pub error: MessageParseError,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for LineParseError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "LineParseError {{ line: {=[u8]:a}, error: {} }}",
+            self.line.as_slice(),
+            self.error
+        );
+    }
 }
 
 /// Represents a message received from the CAN gateway
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[num_enum(error_type(name = MessageParseError, constructor = MessageParseError::UnrecognizedMessage))]
 #[repr(u8)]
 pub enum MessageKind {
@@ -56,6 +93,11 @@ pub enum MessageKind {
     ReceivedStandardFdFrameWithBrs = b'b',
     /// Received an extended (29bit) CAN FD frame at the increased data bit rate
     ReceivedExtendedFdFrameWithBrs = b'B',
+
+    /// Received a report of the bus state along with the error counters
+    BusStatus = b's',
+    /// Received the value of the error register
+    ErrorRegister = b'E',
 }
 
 impl MessageKind {
@@ -69,6 +111,8 @@ impl MessageKind {
             MessageKind::ReceivedExtendedFdFrameNoBrs => 8 + 1, // (extended id + dlc)
             MessageKind::ReceivedStandardFdFrameWithBrs => 3 + 1, // (standard id + dlc)
             MessageKind::ReceivedExtendedFdFrameWithBrs => 8 + 1, // (extended id + dlc)
+            MessageKind::BusStatus => 1 + 3 + 3,             // (state + rx errors + tx errors)
+            MessageKind::ErrorRegister => 1,                 // (register)
         }
     }
 
@@ -82,17 +126,26 @@ impl MessageKind {
             MessageKind::ReceivedExtendedFdFrameNoBrs => 8 + 1 + 128, // (extended id + dlc + data)
             MessageKind::ReceivedStandardFdFrameWithBrs => 3 + 1 + 128, // (standard id + dlc + data)
             MessageKind::ReceivedExtendedFdFrameWithBrs => 8 + 1 + 128, // (extended id + dlc + data)
+            MessageKind::BusStatus => 1 + 3 + 3, // (state + rx errors + tx errors)
+            MessageKind::ErrorRegister => 8,     // (register)
         }
     }
 }
 
+/// Parses a line received from the gateway (without the CR line ending) as a
+/// CAN frame, e.g. `t1231AB`. This is the protocol layer of the sockets, for
+/// users with their own transport. See also
+/// [`encode_frame`](crate::encode_frame).
+///
+/// Hex digits (in IDs, FD data length codes, data and timestamps) are
+/// accepted in either case, since some firmwares send lowercase hex. Only the
+/// specifier is case sensitive, as its case selects the ID length.
 pub fn parse_frame_from_bytes(buffer: &[u8]) -> Result<CanFrame, MessageParseError> {
-    assert!(
-        buffer.len() > 1,
-        "Tried to parse message from empty buffer!"
-    );
+    let Some(&specifier) = buffer.first() else {
+        return Err(MessageParseError::EmptyMessage);
+    };
 
-    let kind: MessageKind = buffer[0].try_into()?;
+    let kind: MessageKind = specifier.try_into()?;
     let message_data = &buffer[1..];
 
     /* Validate data length */
@@ -114,7 +167,7 @@ pub fn parse_frame_from_bytes(buffer: &[u8]) -> Result<CanFrame, MessageParseErr
             let data_bytes = &message_data[4..];
 
             let id = standard_id_from_hex(id_bytes.try_into().unwrap())?;
-            let dlc = dec_digit_to_u8(dlc_byte)?;
+            let dlc = can2_dlc(dlc_byte)?;
             let data = unpack_data_bytes(data_bytes, dlc)?;
 
             Can2Frame::new_data(id, &data[..dlc as usize])
@@ -127,7 +180,7 @@ pub fn parse_frame_from_bytes(buffer: &[u8]) -> Result<CanFrame, MessageParseErr
             let data_bytes = &message_data[9..];
 
             let id = extended_id_from_hex(id_bytes.try_into().unwrap())?;
-            let dlc = dec_digit_to_u8(dlc_byte)?;
+            let dlc = can2_dlc(dlc_byte)?;
             let data = unpack_data_bytes(data_bytes, dlc)?;
 
             Can2Frame::new_data(id, &data[..dlc as usize])
@@ -139,18 +192,22 @@ pub fn parse_frame_from_bytes(buffer: &[u8]) -> Result<CanFrame, MessageParseErr
             let dlc_byte = message_data[3];
 
             let id = standard_id_from_hex(id_bytes.try_into().unwrap())?;
-            let dlc = dec_digit_to_u8(dlc_byte)?;
+            let dlc = can2_dlc(dlc_byte)?;
 
-            Can2Frame::new_remote(id, dlc as usize).unwrap().into()
+            Can2Frame::new_remote(id, dlc as usize)
+                .ok_or(MessageParseError::InvalidDataLength(dlc))?
+                .into()
         }
         MessageKind::ReceivedExtendedRemoteFrame => {
             let id_bytes = &message_data[..8];
             let dlc_byte = message_data[8];
 
             let id = extended_id_from_hex(id_bytes.try_into().unwrap())?;
-            let dlc = dec_digit_to_u8(dlc_byte)?;
+            let dlc = can2_dlc(dlc_byte)?;
 
-            Can2Frame::new_remote(id, dlc as usize).unwrap().into()
+            Can2Frame::new_remote(id, dlc as usize)
+                .ok_or(MessageParseError::InvalidDataLength(dlc))?
+                .into()
         }
         MessageKind::ReceivedStandardFdFrameNoBrs => {
             let id_bytes = &message_data[..3];
@@ -206,9 +263,153 @@ pub fn parse_frame_from_bytes(buffer: &[u8]) -> Result<CanFrame, MessageParseErr
                 .unwrap()
                 .into()
         }
+        MessageKind::BusStatus | MessageKind::ErrorRegister => {
+            return Err(MessageParseError::UnrecognizedMessage(kind as u8));
+        }
+    })
+}
+
+/// Parses a bus state report such as `sb256256`: the state (`a`ctive,
+/// `w`arning, `p`assive or `b`us-off) followed by the receive and transmit
+/// error counters as 3 decimal digits each
+pub fn parse_bus_status_from_bytes(buffer: &[u8]) -> Result<BusStatus, MessageParseError> {
+    let kind = MessageKind::BusStatus;
+    let message_data = &buffer[1..];
+
+    if message_data.len() < kind.get_min_data_length() {
+        return Err(MessageParseError::NotEnoughBytes(kind, buffer.len()));
+    }
+
+    if message_data.len() > kind.get_max_data_length() {
+        return Err(MessageParseError::TooManyBytes(kind, buffer.len()));
+    }
+
+    let state = match message_data[0] {
+        b'a' => BusState::ErrorActive,
+        b'w' => BusState::ErrorWarning,
+        b'p' => BusState::ErrorPassive,
+        b'b' => BusState::BusOff,
+        other => return Err(MessageParseError::IllegalBusState(other)),
+    };
+
+    let counter = |digits: &[u8]| -> Result<u16, MessageParseError> {
+        digits.iter().try_fold(0u16, |value, digit| {
+            Ok(value * 10 + dec_digit_to_u8(*digit)? as u16)
+        })
+    };
+
+    Ok(BusStatus {
+        state,
+        rx_error_count: counter(&message_data[1..4])?,
+        tx_error_count: counter(&message_data[4..7])?,
+    })
+}
+
+/// Parses the answer to an error register query such as `E08`: the value of
+/// the register as up to 8 hex digits, where every set bit is an error flag
+/// of the CAN controller
+pub fn parse_error_register_from_bytes(buffer: &[u8]) -> Result<u32, MessageParseError> {
+    let kind = MessageKind::ErrorRegister;
+    let message_data = &buffer[1..];
+
+    if message_data.len() < kind.get_min_data_length() {
+        return Err(MessageParseError::NotEnoughBytes(kind, buffer.len()));
+    }
+
+    if message_data.len() > kind.get_max_data_length() {
+        return Err(MessageParseError::TooManyBytes(kind, buffer.len()));
+    }
+
+    message_data.iter().try_fold(0u32, |value, digit| {
+        Ok(value << 4 | hex_digit_to_u8(*digit)? as u32)
+    })
+}
+
+/// The byte sent by the gateway (without a trailing CR) when it fails to
+/// execute a command
+pub const NACK: u8 = 0x07;
+
+/// A single message received from the gateway
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A frame received from the bus along with its hardware timestamp
+    Frame(CanFrame, Option<Timestamp>),
+    /// The gateway queued a frame for transmission (`z` for standard and `Z`
+    /// for extended IDs)
+    TransmitAck,
+    /// The gateway failed to execute the previous command
+    Nack,
+    /// The gateway reported the state of the bus
+    BusStatus(BusStatus),
+    /// The gateway answered a query for the firmware version. The format of
+    /// the answer differs between firmwares, so it isn't parsed.
+    Version(Vec<u8>),
+    /// The gateway answered a query for its error register, where every set
+    /// bit is an error flag of the CAN controller
+    ErrorRegister(u32),
+}
+
+/// Parses any message the gateway can send, where frames may be followed by
+/// a hardware timestamp as described in [`parse_timestamped_frame_from_bytes`]
+pub fn parse_message_from_bytes(
+    buffer: &[u8],
+    timestamp_mode: TimestampMode,
+) -> Result<Message, MessageParseError> {
+    Ok(match buffer {
+        [b'z'] | [b'Z'] => Message::TransmitAck,
+        [NACK] => Message::Nack,
+        [b's', ..] => Message::BusStatus(parse_bus_status_from_bytes(buffer)?),
+        [b'E', ..] => Message::ErrorRegister(parse_error_register_from_bytes(buffer)?),
+        _ => {
+            let (frame, timestamp) = parse_timestamped_frame_from_bytes(buffer, timestamp_mode)?;
+            Message::Frame(frame, timestamp)
+        }
     })
 }
 
+/// Parses a frame followed by a hardware timestamp with the number of digits
+/// implied by `mode`. With [`TimestampMode::Disabled`] this is equivalent to
+/// [`parse_frame_from_bytes`].
+pub fn parse_timestamped_frame_from_bytes(
+    buffer: &[u8],
+    mode: TimestampMode,
+) -> Result<(CanFrame, Option<Timestamp>), MessageParseError> {
+    let num_digits = mode.num_digits();
+
+    if num_digits == 0 {
+        return Ok((parse_frame_from_bytes(buffer)?, None));
+    }
+
+    let Some(&specifier) = buffer.first() else {
+        return Err(MessageParseError::EmptyMessage);
+    };
+    let kind: MessageKind = specifier.try_into()?;
+
+    if buffer.len() < 1 + kind.get_min_data_length() + num_digits {
+        return Err(MessageParseError::NotEnoughBytes(kind, buffer.len()));
+    }
+
+    let (frame_bytes, timestamp_bytes) = buffer.split_at(buffer.len() - num_digits);
+    let frame = parse_frame_from_bytes(frame_bytes)?;
+
+    let mut value = 0u32;
+
+    for nibble in timestamp_bytes.iter() {
+        value <<= 4;
+        value |= hex_digit_to_u8(*nibble)? as u32;
+    }
+
+    let timestamp = match mode {
+        TimestampMode::Disabled => unreachable!(),
+        TimestampMode::Milliseconds => Timestamp::Milliseconds(value as u16),
+        TimestampMode::Microseconds => Timestamp::Microseconds(value),
+    };
+
+    Ok((frame, Some(timestamp)))
+}
+
+/// Decodes a hex digit of either case. All hex fields are decoded with this so
+/// lowercase digits are accepted everywhere.
 fn hex_digit_to_u8(byte: u8) -> Result<u8, MessageParseError> {
     Ok(match byte {
         b'0'..=b'9' => byte - b'0',
@@ -225,6 +426,17 @@ fn dec_digit_to_u8(byte: u8) -> Result<u8, MessageParseError> {
     })
 }
 
+/// Decodes the DLC of a CAN 2.0 frame, which is a decimal digit up to 8
+fn can2_dlc(byte: u8) -> Result<u8, MessageParseError> {
+    let dlc = dec_digit_to_u8(byte)?;
+
+    if dlc > 8 {
+        return Err(MessageParseError::InvalidDataLength(dlc));
+    }
+
+    Ok(dlc)
+}
+
 fn u8_from_hex(hex_nibbles: &[u8; 2]) -> Result<u8, MessageParseError> {
     let msn = hex_digit_to_u8(hex_nibbles[0])?;
     let lsn = hex_digit_to_u8(hex_nibbles[1])?;
@@ -282,3 +494,53 @@ fn unpack_data_bytes(
 
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fd_frames_with_hex_dlc() {
+        let line = [b"b123F".as_slice(), &[b'A'; 128]].concat();
+        let frame = parse_frame_from_bytes(&line).unwrap();
+        assert_eq!(frame.data().unwrap(), &[0xAA; 64]);
+
+        let line = [b"D000012349".as_slice(), &[b'0'; 24]].concat();
+        let frame = parse_frame_from_bytes(&line).unwrap();
+        assert_eq!(frame.data().unwrap(), &[0; 12]);
+
+        let line = [b"b123A".as_slice(), &[b'1'; 32], b"12AB"].concat();
+        let (frame, timestamp) =
+            parse_timestamped_frame_from_bytes(&line, TimestampMode::Milliseconds).unwrap();
+        assert_eq!(frame.data().unwrap(), &[0x11; 16]);
+        assert_eq!(timestamp, Some(Timestamp::Milliseconds(0x12AB)));
+    }
+
+    #[test]
+    fn rejects_can2_dlc_above_8() {
+        for line in [b"r1239".as_slice(), b"R123456789"] {
+            assert!(matches!(
+                parse_frame_from_bytes(line),
+                Err(MessageParseError::InvalidDataLength(9))
+            ));
+        }
+
+        assert!(parse_frame_from_bytes(b"t1239000000000000000000").is_err());
+    }
+
+    #[test]
+    fn parses_error_register_answers() {
+        assert_eq!(
+            parse_message_from_bytes(b"E08", TimestampMode::Disabled).unwrap(),
+            Message::ErrorRegister(0x08)
+        );
+        assert_eq!(
+            parse_message_from_bytes(b"E0000a0fF", TimestampMode::Disabled).unwrap(),
+            Message::ErrorRegister(0xA0FF)
+        );
+
+        for line in [b"E".as_slice(), b"E123456789", b"E0G"] {
+            assert!(parse_message_from_bytes(line, TimestampMode::Disabled).is_err());
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::{collections::HashMap, time::Duration};
+
+use embedded_can::Id;
+
+use crate::{
+    filter::IdFilter,
+    frame::{Can2Frame, CanFrame},
+};
+
+type ResponseCallback = Box<dyn FnMut(&Can2Frame) -> Option<Can2Frame> + Send>;
+
+enum Response {
+    Frame(Can2Frame),
+    Callback(ResponseCallback),
+}
+
+/// A table of responses to remote (RTR) frames, for emulating ECUs which must
+/// answer remote requests.
+///
+/// Attach it to a socket with `CanSocket::set_remote_responder`. Whenever a
+/// remote frame with a registered ID is read, the socket transmits the
+/// response before returning the remote frame to the caller.
+///
+/// ```
+/// use slcan_fd::{Can2Frame, RemoteResponder, StandardId};
+///
+/// let id = StandardId::new(0x123).unwrap();
+///
+/// let mut responder = RemoteResponder::new();
+/// responder.insert_frame(Can2Frame::new_data(id, &[0xDE, 0xAD]).unwrap());
+///
+/// let request = Can2Frame::new_remote(id, 2).unwrap();
+/// assert_eq!(responder.respond(&request).unwrap().data(), Some(&[0xDE, 0xAD][..]));
+/// ```
+#[derive(Default)]
+pub struct RemoteResponder {
+    responses: HashMap<Id, Response>,
+}
+
+impl RemoteResponder {
+    /// Creates an empty responder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers remote requests for the ID of `frame` with `frame`, replacing
+    /// any previous response for that ID
+    pub fn insert_frame(&mut self, frame: Can2Frame) {
+        self.responses.insert(frame.id(), Response::Frame(frame));
+    }
+
+    /// Answers remote requests for `id` with the frame returned by
+    /// `callback`, which receives the request (e.g. to look at its DLC). No
+    /// response is sent if the callback returns `None`.
+    pub fn insert_callback(
+        &mut self,
+        id: impl Into<Id>,
+        callback: impl FnMut(&Can2Frame) -> Option<Can2Frame> + Send + 'static,
+    ) {
+        self.responses
+            .insert(id.into(), Response::Callback(Box::new(callback)));
+    }
+
+    /// Stops answering remote requests for `id`. Returns false if no response
+    /// was registered.
+    pub fn remove(&mut self, id: impl Into<Id>) -> bool {
+        self.responses.remove(&id.into()).is_some()
+    }
+
+    /// Returns true if a response is registered for `id`
+    pub fn contains(&self, id: impl Into<Id>) -> bool {
+        self.responses.contains_key(&id.into())
+    }
+
+    /// Returns the response to `request`, or `None` if it isn't a remote
+    /// frame or no response is registered for its ID
+    pub fn respond(&mut self, request: &Can2Frame) -> Option<Can2Frame> {
+        if !request.is_remote() {
+            return None;
+        }
+
+        match self.responses.get_mut(&request.id())? {
+            Response::Frame(frame) => Some(frame.clone()),
+            Response::Callback(callback) => callback(request),
+        }
+    }
+}
+
+type RuleCallback = Box<dyn FnMut(&CanFrame) -> Option<CanFrame> + Send>;
+
+enum RuleResponse {
+    Frame(CanFrame),
+    Callback(RuleCallback),
+}
+
+/// A rule of a [Responder], pairing the frames it matches with the response
+/// to transmit for them
+pub struct ResponseRule {
+    filter: IdFilter,
+    data: Vec<u8>,
+    mask: Vec<u8>,
+    delay: Duration,
+    response: RuleResponse,
+}
+
+impl ResponseRule {
+    /// Answers the frames passing `filter` with `response`
+    pub fn frame(filter: IdFilter, response: impl Into<CanFrame>) -> Self {
+        Self::new(filter, RuleResponse::Frame(response.into()))
+    }
+
+    /// Answers the frames passing `filter` with the frame returned by
+    /// `callback`, which receives the matched frame. No response is sent if
+    /// the callback returns `None`.
+    pub fn callback(
+        filter: IdFilter,
+        callback: impl FnMut(&CanFrame) -> Option<CanFrame> + Send + 'static,
+    ) -> Self {
+        Self::new(filter, RuleResponse::Callback(Box::new(callback)))
+    }
+
+    fn new(filter: IdFilter, response: RuleResponse) -> Self {
+        Self {
+            filter,
+            data: Vec::new(),
+            mask: Vec::new(),
+            delay: Duration::ZERO,
+            response,
+        }
+    }
+
+    /// Only matches frames whose data starts with `data`
+    pub fn with_data(self, data: &[u8]) -> Self {
+        let mask = vec![0xFF; data.len()];
+        self.with_masked_data(data, &mask)
+    }
+
+    /// Only matches frames whose data starts with bytes whose bits selected
+    /// by `mask` equal those of `data`. Bytes of `data` without a
+    /// corresponding byte in `mask` must match exactly.
+    pub fn with_masked_data(mut self, data: &[u8], mask: &[u8]) -> Self {
+        self.data = data.to_vec();
+        self.mask = (0..data.len())
+            .map(|i| mask.get(i).copied().unwrap_or(0xFF))
+            .collect();
+        self
+    }
+
+    /// Transmits the response `delay` after the matched frame was received,
+    /// instead of right away
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Returns the delay after which the response is transmitted
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns true if `frame` passes the filter and data pattern of the rule.
+    /// Remote frames only match rules without a data pattern.
+    pub fn matches(&self, frame: &CanFrame) -> bool {
+        if !self.filter.matches(frame.id()) {
+            return false;
+        }
+
+        if self.data.is_empty() {
+            return true;
+        }
+
+        match frame.data() {
+            Some(data) if data.len() >= self.data.len() => self
+                .data
+                .iter()
+                .zip(&self.mask)
+                .zip(data)
+                .all(|((expected, mask), actual)| (expected ^ actual) & mask == 0),
+            _ => false,
+        }
+    }
+
+    /// Returns the response to `frame`, or `None` if the rule doesn't match
+    /// it or its callback declined to answer
+    pub fn respond(&mut self, frame: &CanFrame) -> Option<CanFrame> {
+        if !self.matches(frame) {
+            return None;
+        }
+
+        match &mut self.response {
+            RuleResponse::Frame(response) => Some(response.clone()),
+            RuleResponse::Callback(callback) => callback(frame),
+        }
+    }
+}
+
+/// A set of [ResponseRule]s answering received frames, for simulating simple
+/// ECUs on a test rig.
+///
+/// Every rule which matches a received frame transmits its response, in the
+/// order the rules were added. With the `tokio` feature, `Responder::run`
+/// drives the responder from a socket, including the delays of the rules.
+///
+/// ```
+/// use slcan_fd::{CanFdFrame, CanFrame, IdFilter, Responder, ResponseRule, StandardId};
+/// use std::time::Duration;
+///
+/// let request_id = StandardId::new(0x7E0).unwrap();
+/// let response_id = StandardId::new(0x7E8).unwrap();
+///
+/// let mut responder = Responder::new()
+///     // Answer the OBD-II request for the engine speed
+///     .with_rule(
+///         ResponseRule::frame(
+///             IdFilter::new().with_id(request_id),
+///             CanFdFrame::new(response_id, &[0x04, 0x41, 0x0C, 0x1A, 0xF8]).unwrap(),
+///         )
+///         .with_data(&[0x02, 0x01, 0x0C])
+///         .with_delay(Duration::from_millis(5)),
+///     )
+///     // Echo everything sent to 0x100 back on 0x101
+///     .with_rule(ResponseRule::callback(
+///         "100".parse().unwrap(),
+///         |frame| Some(frame.clone().with_id(StandardId::new(0x101).unwrap())),
+///     ));
+///
+/// let request = CanFrame::CanFd(CanFdFrame::new(request_id, &[0x02, 0x01, 0x0C]).unwrap());
+/// let responses: Vec<_> = responder.respond(&request).collect();
+///
+/// assert_eq!(responses.len(), 1);
+/// assert_eq!(responses[0].0, Duration::from_millis(5));
+/// ```
+#[derive(Default)]
+pub struct Responder {
+    rules: Vec<ResponseRule>,
+}
+
+impl Responder {
+    /// Creates a responder without any rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consumes self and returns a new self with `rule` added
+    pub fn with_rule(mut self, rule: ResponseRule) -> Self {
+        self.add_rule(rule);
+        self
+    }
+
+    /// Adds `rule` after all previously added rules
+    pub fn add_rule(&mut self, rule: ResponseRule) {
+        self.rules.push(rule);
+    }
+
+    /// Removes all rules
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Returns the responses of all rules to `frame`, along with the delay
+    /// after which each should be transmitted
+    pub fn respond<'a>(
+        &'a mut self,
+        frame: &'a CanFrame,
+    ) -> impl Iterator<Item = (Duration, CanFrame)> + 'a {
+        self.rules
+            .iter_mut()
+            .filter_map(move |rule| Some((rule.delay, rule.respond(frame)?)))
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::run::ResponderError;
+
+#[cfg(feature = "tokio")]
+mod run {
+    use tokio::{
+        io::{AsyncRead, AsyncWrite},
+        time::Instant,
+    };
+
+    use super::Responder;
+    use crate::{
+        frame::{CanFrame, FrameDirection},
+        tokio::CanSocket,
+        ReadError, RxFrame, SendError,
+    };
+
+    /// Errors which stop `Responder::run`
+    #[derive(Debug, thiserror::Error)]
+    pub enum ResponderError {
+        #[error("Failed to read a frame: {0}")]
+        Read(ReadError),
+        #[error("Failed to send a response: {0}")]
+        Send(SendError),
+    }
+
+    impl Responder {
+        /// Answers the frames received by `socket` until reading from or
+        /// sending to it fails. The socket must already be open.
+        ///
+        /// Only frames received from the bus are answered, echoes of
+        /// transmitted frames are not. Lines which can't be parsed are
+        /// skipped. Delayed responses are held by this method, so they are
+        /// only transmitted while it is running.
+        ///
+        /// # Cancel Safety
+        ///
+        /// Cancelling drops all responses which are still waiting for their
+        /// delay to pass.
+        pub async fn run<P>(&mut self, socket: &mut CanSocket<P>) -> Result<(), ResponderError>
+        where
+            P: AsyncRead + AsyncWrite,
+        {
+            let mut pending: Vec<(Instant, CanFrame)> = Vec::new();
+
+            loop {
+                // The earliest deadline comes first, ties are sent in the
+                // order they were scheduled
+                let next = pending
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, (deadline, _))| *deadline)
+                    .map(|(index, (deadline, _))| (index, *deadline));
+
+                let result = tokio::select! {
+                    result = socket.read_rx_frame() => result,
+                    _ = tokio::time::sleep_until(next.map_or_else(Instant::now, |(_, deadline)| deadline)),
+                        if next.is_some() =>
+                    {
+                        let (index, _) = next.expect("checked by the guard");
+                        let (_, response) = pending.remove(index);
+                        socket.send(response).await.map_err(ResponderError::Send)?;
+
+                        continue;
+                    }
+                };
+
+                let frame = match result {
+                    Ok(RxFrame {
+                        frame,
+                        direction: FrameDirection::Rx,
+                        ..
+                    }) => frame,
+                    Ok(_) | Err(ReadError::Slcan(_) | ReadError::LineTooLong(_)) => continue,
+                    Err(e) => return Err(ResponderError::Read(e)),
+                };
+
+                let now = Instant::now();
+                for (delay, response) in self.respond(&frame) {
+                    if delay.is_zero() {
+                        socket.send(response).await.map_err(ResponderError::Send)?;
+                    } else {
+                        pending.push((now + delay, response));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use embedded_can::Id;
+
+use crate::frame::CanFrame;
+
+/// A jump of the alive counter watched by a [SequenceChecker]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SequenceGap {
+    /// The counter value which was expected next
+    pub expected: u8,
+    /// The counter value which was received instead
+    pub received: u8,
+    /// The number of frames which are missing between the two, or 0 if the
+    /// previous counter value was repeated
+    pub missed: u8,
+}
+
+/// The frames seen by a [SequenceChecker] since it was created or reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SequenceStats {
+    /// Frames with the watched ID which carried a counter
+    pub frames: u64,
+    /// Frames which are missing according to the counter
+    pub missed: u64,
+    /// Times the counter skipped at least one value
+    pub gaps: u64,
+    /// Frames which repeated the previous counter value
+    pub repeated: u64,
+}
+
+impl SequenceStats {
+    /// Returns the fraction of the frames which were lost (0 to 1), or 0 if
+    /// no frame was expected yet
+    pub fn loss_ratio(&self) -> f64 {
+        let expected = self.frames + self.missed;
+
+        if expected == 0 {
+            0.0
+        } else {
+            self.missed as f64 / expected as f64
+        }
+    }
+}
+
+/// Watches the alive counter of the frames with one ID and reports the
+/// values it skips, for quantifying how many frames are lost on their way
+/// through the gateway and the serial link, e.g. during stress tests.
+///
+/// The counter is read from the bits of a data byte selected by a mask and
+/// is expected to increment with every frame and wrap around like the one
+/// written by [`Heartbeat`](crate::Heartbeat). A gap which is a multiple of
+/// the period of the counter can't be told apart from no gap at all, so
+/// the counter should be as wide as possible.
+///
+/// ```
+/// use slcan_fd::{Can2Frame, Heartbeat, SequenceChecker, StandardId};
+/// use std::time::Duration;
+///
+/// let id = StandardId::new(0x321).unwrap();
+/// let frame = Can2Frame::new_data(id, &[0, 0]).unwrap();
+/// let mut heartbeat = Heartbeat::new(frame, Duration::from_millis(10)).with_counter(1, 0xFF);
+/// let mut checker = SequenceChecker::new(id).with_counter(1, 0xFF);
+///
+/// for i in 0..10 {
+///     let frame = heartbeat.next_frame();
+///
+///     // Lose two frames on the way
+///     if i != 4 && i != 5 {
+///         checker.check(&frame);
+///     }
+/// }
+///
+/// assert_eq!(checker.stats().missed, 2);
+/// assert_eq!(checker.stats().gaps, 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceChecker {
+    id: Id,
+    byte: usize,
+    mask: u8,
+    last: Option<u8>,
+    stats: SequenceStats,
+}
+
+impl SequenceChecker {
+    /// Creates a checker for the frames with `id`, which reads the counter
+    /// from the whole first data byte
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            id: id.into(),
+            byte: 0,
+            mask: 0xFF,
+            last: None,
+            stats: SequenceStats::default(),
+        }
+    }
+
+    /// Reads the counter from the bits of data byte `byte` selected by
+    /// `mask`, e.g. `0x0F` for a 4 bit counter in the low nibble. See
+    /// [`Heartbeat::with_counter`](crate::Heartbeat::with_counter).
+    pub fn with_counter(mut self, byte: usize, mask: u8) -> Self {
+        self.byte = byte;
+        self.mask = mask;
+        self
+    }
+
+    /// Returns the ID of the frames which are checked
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns the counter value received last, if any
+    pub fn last(&self) -> Option<u8> {
+        self.last
+    }
+
+    /// Returns the frames seen so far
+    pub fn stats(&self) -> SequenceStats {
+        self.stats
+    }
+
+    /// Forgets the counter value received last and clears the statistics,
+    /// e.g. after the sender was restarted
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.stats = SequenceStats::default();
+    }
+
+    /// Checks the counter of a received frame and returns the gap it
+    /// reveals, if any.
+    ///
+    /// Frames with other IDs, remote frames and frames whose data ends
+    /// before the counter byte are ignored. The first frame only sets the
+    /// value the next one is expected to follow.
+    pub fn check(&mut self, frame: &CanFrame) -> Option<SequenceGap> {
+        if frame.id() != self.id || self.mask == 0 {
+            return None;
+        }
+
+        let shift = self.mask.trailing_zeros();
+        let max = self.mask >> shift;
+        let received = (frame.data()?.get(self.byte)? & self.mask) >> shift;
+
+        self.stats.frames += 1;
+        let last = self.last.replace(received)?;
+        let expected = if last >= max { 0 } else { last + 1 };
+
+        if received == expected {
+            return None;
+        }
+
+        // The number of increments from the previous value, modulo the period
+        // of the counter
+        let period = max as u16 + 1;
+        let increments = (received as u16 + period - last as u16) % period;
+
+        let missed = if increments == 0 {
+            self.stats.repeated += 1;
+            0
+        } else {
+            self.stats.gaps += 1;
+            self.stats.missed += increments as u64 - 1;
+            (increments - 1) as u8
+        };
+
+        Some(SequenceGap {
+            expected,
+            received,
+            missed,
+        })
+    }
+}
@@ -0,0 +1,680 @@
+//! A deterministic virtual CAN bus, for testing protocol stacks built on this
+//! crate without hardware.
+//!
+//! [`VirtualBus::add_node`] returns a [`VirtualPort`] which emulates a
+//! gateway speaking SLCAN, so a regular `CanSocket` (sync or async) can be
+//! constructed on top of it. The frames sent by the nodes are arbitrated like
+//! on a real bus and occupy it for as long as their bits take at the bit
+//! rates of the bus (see [`busload`](crate::busload)). Every other node with
+//! an open channel receives them.
+//!
+//! Time only passes when the bus is advanced with [`VirtualBus::advance`] or
+//! [`VirtualBus::run_until_idle`], so tests behave the same on every run.
+//! Reading a node which has nothing to receive returns `WouldBlock` from the
+//! sync API and waits for the bus to be advanced with the async one.
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use slcan_fd::{sim::VirtualBus, tokio::CanSocket, Can2Frame, NominalBitRate, StandardId};
+//!
+//! let bus = VirtualBus::new();
+//! let mut sender = CanSocket::new(bus.add_node());
+//! let mut receiver = CanSocket::new(bus.add_node());
+//!
+//! sender.open(NominalBitRate::Rate500Kbit).await?;
+//! receiver.open(NominalBitRate::Rate500Kbit).await?;
+//!
+//! let frame = Can2Frame::new_data(StandardId::new(0x123).unwrap(), &[1, 2, 3]).unwrap();
+//! sender.send(frame.clone()).await?;
+//!
+//! bus.run_until_idle();
+//! assert_eq!(receiver.read().await?, frame.into());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+    task::Waker,
+    time::Duration,
+};
+
+use embedded_can::Id;
+
+use crate::{
+    busload::{frame_bits, Stuffing},
+    command::{encode_frame, DataBitRate, NominalBitRate, OperatingMode, TimestampMode},
+    frame::CanFrame,
+    parser::{parse_frame_from_bytes, NACK},
+};
+
+/// Decides whether a transmission is destroyed by an error frame, given the
+/// index of the sending node and the frame
+type ErrorInjector = Box<dyn FnMut(usize, &CanFrame) -> bool + Send>;
+
+/// The bits of the error flag and the error delimiter which follow a frame
+/// destroyed by an error
+const ERROR_FRAME_BITS: u32 = 6 + 8;
+
+/// The version the emulated gateways answer `V` with
+const FIRMWARE_VERSION: &[u8] = b"V1013\r";
+
+/// A virtual CAN bus connecting any number of nodes. See the
+/// [module documentation](self).
+///
+/// The bus is a cheap handle which can be cloned, e.g. to advance it from
+/// another task.
+#[derive(Clone)]
+pub struct VirtualBus {
+    shared: Arc<Mutex<Bus>>,
+}
+
+impl Default for VirtualBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualBus {
+    /// Creates a bus running at 500 Kbit/s with a data bit rate of 2 Mbit/s,
+    /// which delivers frames as soon as they were transmitted
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Bus {
+                nominal_bit_rate: NominalBitRate::Rate500Kbit,
+                data_bit_rate: DataBitRate::Rate2Mbit,
+                latency: Duration::ZERO,
+                error_injector: None,
+                now: Duration::ZERO,
+                busy_until: Duration::ZERO,
+                nodes: Vec::new(),
+                deliveries: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Sets the bit rates of the bus, which determine how long frames occupy
+    /// it. The bit rates the nodes are configured with are acknowledged but
+    /// have no effect.
+    pub fn with_bit_rates(
+        self,
+        nominal_bit_rate: NominalBitRate,
+        data_bit_rate: DataBitRate,
+    ) -> Self {
+        {
+            let mut bus = self.lock();
+            bus.nominal_bit_rate = nominal_bit_rate;
+            bus.data_bit_rate = data_bit_rate;
+        }
+        self
+    }
+
+    /// Delays the delivery of every frame to the receiving nodes by
+    /// `latency` after it was transmitted, e.g. to model the latency of USB
+    /// adapters
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.lock().latency = latency;
+        self
+    }
+
+    /// Calls `injector` with the index of the sending node (see
+    /// [`VirtualPort::node`]) and the frame for every transmission. If it
+    /// returns true, the transmission is destroyed by an error frame and
+    /// retransmitted after arbitrating again, unless the sender disabled auto
+    /// retransmission.
+    ///
+    /// A deterministic injector, e.g. one failing every 10th frame or driven
+    /// by a seeded random number generator, keeps the bus deterministic.
+    pub fn with_error_injector(
+        self,
+        injector: impl FnMut(usize, &CanFrame) -> bool + Send + 'static,
+    ) -> Self {
+        self.lock().error_injector = Some(Box::new(injector));
+        self
+    }
+
+    /// Connects a new node to the bus and returns the port of its emulated
+    /// gateway. The node leaves the bus when the port is dropped.
+    pub fn add_node(&self) -> VirtualPort {
+        let mut bus = self.lock();
+        bus.nodes.push(Node::new());
+
+        VirtualPort {
+            shared: self.shared.clone(),
+            node: bus.nodes.len() - 1,
+        }
+    }
+
+    /// Returns the time which passed on the bus since it was created
+    pub fn now(&self) -> Duration {
+        self.lock().now
+    }
+
+    /// Returns true if no frame is waiting to be transmitted or delivered
+    pub fn is_idle(&self) -> bool {
+        self.lock().next_event().is_none()
+    }
+
+    /// Lets `duration` pass on the bus, transmitting and delivering the frames
+    /// which are due in the meantime
+    pub fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut bus = self.lock();
+            let until = bus.now + duration;
+            bus.advance_to(until);
+            bus.take_wakers()
+        };
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Lets time pass on the bus until all frames sent so far were
+    /// transmitted and delivered, and returns the time afterwards.
+    ///
+    /// Never returns if an error injector keeps destroying a frame which is
+    /// retransmitted automatically.
+    pub fn run_until_idle(&self) -> Duration {
+        let (now, wakers) = {
+            let mut bus = self.lock();
+            while let Some(at) = bus.next_event() {
+                bus.advance_to(at);
+            }
+            (bus.now, bus.take_wakers())
+        };
+
+        wakers.into_iter().for_each(Waker::wake);
+        now
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        self.shared.lock().unwrap()
+    }
+}
+
+/// The state of a [`VirtualBus`], shared with its ports
+struct Bus {
+    nominal_bit_rate: NominalBitRate,
+    data_bit_rate: DataBitRate,
+    latency: Duration,
+    error_injector: Option<ErrorInjector>,
+    now: Duration,
+    /// When the frame which was transmitted last ends
+    busy_until: Duration,
+    nodes: Vec<Node>,
+    /// Frames which were transmitted, ordered by when they are due to be
+    /// delivered
+    deliveries: VecDeque<Delivery>,
+}
+
+struct Delivery {
+    due: Duration,
+    sender: usize,
+    frame: CanFrame,
+    /// Whether the frame is only delivered back to the sender, which is in
+    /// loopback mode
+    loopback: bool,
+}
+
+/// The emulated gateway of a node
+struct Node {
+    attached: bool,
+    open: bool,
+    mode: OperatingMode,
+    auto_retransmission: bool,
+    timestamp_mode: TimestampMode,
+    /// The command line being written by the socket
+    line: Vec<u8>,
+    /// The bytes waiting to be read by the socket
+    output: VecDeque<u8>,
+    /// The frames waiting for transmission, along with when they were sent
+    tx_queue: VecDeque<(Duration, CanFrame)>,
+    waker: Option<Waker>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            attached: true,
+            open: false,
+            mode: OperatingMode::Normal,
+            auto_retransmission: true,
+            timestamp_mode: TimestampMode::Disabled,
+            line: Vec::new(),
+            output: VecDeque::new(),
+            tx_queue: VecDeque::new(),
+            waker: None,
+        }
+    }
+}
+
+impl Bus {
+    fn frame_duration(&self, frame: &CanFrame) -> Duration {
+        frame_bits(frame, Stuffing::Exact).duration(self.nominal_bit_rate, self.data_bit_rate)
+    }
+
+    /// Returns when the bus carries the next frame: once the last frame
+    /// ended, and not before the earliest waiting frame was sent
+    fn next_start(&self) -> Option<Duration> {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.tx_queue.front().map(|&(sent, _)| sent))
+            .min()
+            .map(|sent| sent.max(self.busy_until))
+    }
+
+    /// Returns when the next frame starts being transmitted or is delivered
+    fn next_event(&self) -> Option<Duration> {
+        let due = self.deliveries.front().map(|delivery| delivery.due);
+        self.next_start().into_iter().chain(due).min()
+    }
+
+    fn advance_to(&mut self, until: Duration) {
+        while let Some(start) = self.next_start().filter(|&start| start <= until) {
+            // The frame which wins arbitration among the ones waiting by then
+            let sender = (0..self.nodes.len())
+                .filter(|&i| matches!(self.nodes[i].tx_queue.front(), Some(&(sent, _)) if sent <= start))
+                .min_by(|&a, &b| {
+                    let (_, a) = &self.nodes[a].tx_queue[0];
+                    let (_, b) = &self.nodes[b].tx_queue[0];
+                    a.arbitration_cmp(b)
+                })
+                .expect("at least one frame is waiting");
+
+            let frame = self.nodes[sender].tx_queue[0].1.clone();
+            let duration = self.frame_duration(&frame);
+
+            let destroyed = match self.error_injector.as_mut() {
+                Some(injector) => injector(sender, &frame),
+                None => false,
+            };
+
+            if destroyed {
+                let error_frame = Duration::from_secs_f64(
+                    ERROR_FRAME_BITS as f64 / self.nominal_bit_rate.bits_per_second() as f64,
+                );
+                self.busy_until = start + duration + error_frame;
+
+                if !self.nodes[sender].auto_retransmission {
+                    self.nodes[sender].tx_queue.pop_front();
+                }
+                continue;
+            }
+
+            self.nodes[sender].tx_queue.pop_front();
+            self.busy_until = start + duration;
+            self.schedule(Delivery {
+                due: self.busy_until + self.latency,
+                sender,
+                frame,
+                loopback: false,
+            });
+        }
+
+        while let Some(delivery) = self.deliveries.front() {
+            if delivery.due > until {
+                break;
+            }
+
+            let delivery = self.deliveries.pop_front().expect("checked above");
+            self.deliver(&delivery);
+        }
+
+        self.now = self.now.max(until);
+    }
+
+    fn schedule(&mut self, delivery: Delivery) {
+        let index = self
+            .deliveries
+            .partition_point(|other| other.due <= delivery.due);
+        self.deliveries.insert(index, delivery);
+    }
+
+    fn deliver(&mut self, delivery: &Delivery) {
+        // The frames were parsed from SLCAN commands, so they can always be
+        // encoded again
+        let Ok(encoded) = encode_frame(&delivery.frame) else {
+            return;
+        };
+
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            let receives = if delivery.loopback {
+                i == delivery.sender
+            } else {
+                i != delivery.sender
+            };
+            if !receives || !node.attached || !node.open {
+                continue;
+            }
+
+            node.output.extend(&encoded);
+            match node.timestamp_mode {
+                TimestampMode::Disabled => {}
+                TimestampMode::Milliseconds => {
+                    let ms = (delivery.due.as_millis() % 60_000) as u16;
+                    node.output.extend(format!("{ms:04X}").bytes());
+                }
+                TimestampMode::Microseconds => {
+                    let us = delivery.due.as_micros() as u32;
+                    node.output.extend(format!("{us:08X}").bytes());
+                }
+            }
+            node.output.push_back(b'\r');
+        }
+    }
+
+    /// Returns the wakers of the nodes which have bytes to be read
+    fn take_wakers(&mut self) -> Vec<Waker> {
+        self.nodes
+            .iter_mut()
+            .filter(|node| !node.output.is_empty())
+            .filter_map(|node| node.waker.take())
+            .collect()
+    }
+
+    /// Executes a command written to the gateway of a node and answers it
+    fn execute(&mut self, index: usize, line: &[u8]) {
+        let now = self.now;
+        let node = &mut self.nodes[index];
+
+        let accepted = match line {
+            [] => return,
+            [b'O'] => {
+                node.open = true;
+                true
+            }
+            [b'L'] => {
+                node.mode = OperatingMode::Silent;
+                node.open = true;
+                true
+            }
+            [b'l'] => {
+                node.mode = OperatingMode::Loopback;
+                node.open = true;
+                true
+            }
+            [b'C'] => {
+                node.open = false;
+                node.tx_queue.clear();
+                true
+            }
+            [b'M', mode] => match mode {
+                b'0' => set(&mut node.mode, OperatingMode::Normal),
+                b'1' => set(&mut node.mode, OperatingMode::Silent),
+                b'2' => set(&mut node.mode, OperatingMode::Loopback),
+                _ => false,
+            },
+            [b'A', mode] => match mode {
+                b'0' => set(&mut node.auto_retransmission, false),
+                b'1' => set(&mut node.auto_retransmission, true),
+                _ => false,
+            },
+            [b'Z', mode] => match mode {
+                b'0' => set(&mut node.timestamp_mode, TimestampMode::Disabled),
+                b'1' => set(&mut node.timestamp_mode, TimestampMode::Milliseconds),
+                b'2' => set(&mut node.timestamp_mode, TimestampMode::Microseconds),
+                _ => false,
+            },
+            [b'S' | b'Y', _] => true,
+            [b'V' | b'v'] => {
+                node.output.extend(FIRMWARE_VERSION);
+                return;
+            }
+            [b't' | b'T' | b'r' | b'R' | b'd' | b'D' | b'b' | b'B', ..] => {
+                match parse_frame_from_bytes(line) {
+                    Ok(frame) if node.open && node.mode != OperatingMode::Silent => {
+                        let ack = match frame.id() {
+                            Id::Standard(_) => b'z',
+                            Id::Extended(_) => b'Z',
+                        };
+                        node.output.extend([ack, b'\r']);
+
+                        if node.mode == OperatingMode::Loopback {
+                            let due = now + self.frame_duration(&frame) + self.latency;
+                            self.schedule(Delivery {
+                                due,
+                                sender: index,
+                                frame,
+                                loopback: true,
+                            });
+                        } else {
+                            node.tx_queue.push_back((now, frame));
+                        }
+                        return;
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        };
+
+        let node = &mut self.nodes[index];
+        node.output.push_back(if accepted { b'\r' } else { NACK });
+    }
+}
+
+/// Assigns a setting of an emulated gateway, accepting the command
+fn set<T>(setting: &mut T, value: T) -> bool {
+    *setting = value;
+    true
+}
+
+/// The port of a node on a [`VirtualBus`], which emulates a gateway speaking
+/// SLCAN. See the [module documentation](self).
+pub struct VirtualPort {
+    shared: Arc<Mutex<Bus>>,
+    node: usize,
+}
+
+impl VirtualPort {
+    /// Returns the index of the node on the bus, in the order the nodes were
+    /// added, as passed to the error injector
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        self.shared.lock().unwrap()
+    }
+
+    /// Moves the bytes waiting for the socket into `buf`
+    fn take_output(&self, buf: &mut [u8]) -> usize {
+        let mut bus = self.lock();
+        let output = &mut bus.nodes[self.node].output;
+
+        let count = buf.len().min(output.len());
+        for (byte, received) in buf.iter_mut().zip(output.drain(..count)) {
+            *byte = received;
+        }
+        count
+    }
+
+    fn write_bytes(&self, buf: &[u8]) -> Vec<Waker> {
+        let mut bus = self.lock();
+
+        for &byte in buf {
+            if byte == b'\r' {
+                let line = std::mem::take(&mut bus.nodes[self.node].line);
+                bus.execute(self.node, &line);
+            } else {
+                bus.nodes[self.node].line.push(byte);
+            }
+        }
+
+        bus.take_wakers()
+    }
+}
+
+impl Drop for VirtualPort {
+    fn drop(&mut self) {
+        let mut bus = self.lock();
+        let node = &mut bus.nodes[self.node];
+        node.attached = false;
+        node.open = false;
+        node.tx_queue.clear();
+    }
+}
+
+impl Read for VirtualPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.take_output(buf) {
+            0 if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            count => Ok(count),
+        }
+    }
+}
+
+impl Write for VirtualPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_bytes(buf).into_iter().for_each(Waker::wake);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for VirtualPort {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let mut bus = self.lock();
+        let node = &mut bus.nodes[self.node];
+
+        if node.output.is_empty() {
+            node.waker = Some(cx.waker().clone());
+            return std::task::Poll::Pending;
+        }
+
+        let count = buf.remaining().min(node.output.len());
+        let (front, back) = node.output.as_slices();
+        let from_front = count.min(front.len());
+        buf.put_slice(&front[..from_front]);
+        buf.put_slice(&back[..count - from_front]);
+        node.output.drain(..count);
+
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for VirtualPort {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.write_bytes(buf).into_iter().for_each(Waker::wake);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::busload::frame_bits;
+
+    /// Adds a node to the bus and opens its channel after running `setup`
+    fn open_node(bus: &VirtualBus, setup: &[u8]) -> VirtualPort {
+        let mut port = bus.add_node();
+        port.write_all(setup).unwrap();
+        port.write_all(b"O\r").unwrap();
+        received(&mut port);
+        port
+    }
+
+    /// Returns the bytes waiting to be read from a node
+    fn received(port: &mut VirtualPort) -> Vec<u8> {
+        let mut buf = [0; 256];
+        match port.read(&mut buf) {
+            Ok(count) => buf[..count].to_vec(),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Vec::new(),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn lowest_id_wins_arbitration() {
+        let bus = VirtualBus::new();
+        let mut nodes: Vec<_> = (0..3).map(|_| open_node(&bus, b"")).collect();
+        let mut receiver = open_node(&bus, b"");
+
+        nodes[0].write_all(b"t2001AA\r").unwrap();
+        nodes[1].write_all(b"r1000\r").unwrap();
+        nodes[2].write_all(b"t1000\r").unwrap();
+        bus.run_until_idle();
+
+        assert_eq!(received(&mut receiver), b"t1000\rr1000\rt2001AA\r");
+    }
+
+    #[test]
+    fn frames_wait_for_the_bus_to_become_idle() {
+        let bus = VirtualBus::new();
+        let mut first = open_node(&bus, b"");
+        let mut second = open_node(&bus, b"");
+        let mut receiver = open_node(&bus, b"");
+
+        first.write_all(b"t2000\r").unwrap();
+        bus.advance(Duration::from_micros(1));
+        second.write_all(b"t1000\r").unwrap();
+        bus.run_until_idle();
+
+        assert_eq!(received(&mut receiver), b"t2000\rt1000\r");
+    }
+
+    #[test]
+    fn retransmits_frames_destroyed_by_errors() {
+        let frame = parse_frame_from_bytes(b"t12381122334455667788").unwrap();
+        let duration = frame_bits(&frame, Stuffing::Exact)
+            .duration(NominalBitRate::Rate500Kbit, DataBitRate::Rate2Mbit);
+        let error_frame = Duration::from_secs_f64(ERROR_FRAME_BITS as f64 / 500_000.0);
+
+        let mut errors = 0;
+        let bus = VirtualBus::new().with_error_injector(move |sender, _| {
+            assert_eq!(sender, 0);
+            errors += 1;
+            errors == 1
+        });
+        let mut sender = open_node(&bus, b"");
+        let mut receiver = open_node(&bus, b"");
+
+        sender.write_all(b"t12381122334455667788\r").unwrap();
+        assert_eq!(bus.run_until_idle(), duration * 2 + error_frame);
+
+        assert_eq!(received(&mut receiver), b"t12381122334455667788\r");
+    }
+
+    #[test]
+    fn drops_destroyed_frames_without_auto_retransmission() {
+        let bus = VirtualBus::new().with_error_injector(|_, _| true);
+        let mut sender = open_node(&bus, b"A0\r");
+        let mut receiver = open_node(&bus, b"");
+
+        sender.write_all(b"t1230\r").unwrap();
+        bus.run_until_idle();
+
+        assert!(bus.is_idle());
+        assert_eq!(received(&mut sender), b"z\r");
+        assert!(received(&mut receiver).is_empty());
+    }
+}
@@ -0,0 +1,375 @@
+//! Mirroring an SLCAN adapter onto a Linux SocketCAN interface, typically a
+//! `vcan` interface, so can-utils, Wireshark and other SocketCAN tools can
+//! use the adapter without the kernel's slcan line discipline (`slcand`).
+//!
+//! [`VcanBridge`] forwards the frames received by the adapter to the
+//! interface, and the frames sent on the interface by other programs to the
+//! adapter:
+//!
+//! ```no_run
+//! use slcan_fd::{socketcan::VcanBridge, tokio::CanSocket, NominalBitRate};
+//! use tokio_serial::SerialPortBuilderExt;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! // ip link add dev vcan0 type vcan
+//! // ip link set vcan0 mtu 72 up
+//! let bridge = VcanBridge::open("vcan0")?;
+//!
+//! let port = tokio_serial::new("/dev/ttyACM0", 115_200).open_native_async()?;
+//! let mut can = CanSocket::new(port);
+//! can.open(NominalBitRate::Rate500Kbit).await?;
+//!
+//! bridge.run(&mut can).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! CAN FD frames can only be mirrored if the interface has the CAN FD MTU
+//! of 72 bytes.
+
+use std::{
+    ffi::CString,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use embedded_can::{ExtendedId, Id, StandardId};
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite};
+
+use crate::{
+    frame::{Can2Frame, CanFdFrame, CanFrame, FrameDirection, RxFrame},
+    tokio::CanSocket,
+    ReadError, SendError,
+};
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CAN_SFF_MASK: u32 = 0x7FF;
+
+const CANFD_BRS: u8 = 0x01;
+const CANFD_FDF: u8 = 0x04;
+
+/// The size of `struct can_frame`
+const CAN_MTU: usize = 16;
+/// The size of `struct canfd_frame`
+const CANFD_MTU: usize = mem::size_of::<RawFrame>();
+
+/// The layout of `struct canfd_frame`. `struct can_frame` has the same layout
+/// for its first 16 bytes, with `len` being the DLC and `flags` unused.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawFrame {
+    can_id: u32,
+    len: u8,
+    flags: u8,
+    res0: u8,
+    res1: u8,
+    data: [u8; 64],
+}
+
+const _: () = assert!(CANFD_MTU == 72);
+
+impl RawFrame {
+    /// Converts a frame received from the kernel, where `size` is the number
+    /// of bytes read. Returns `None` for error frames.
+    fn to_frame(self, size: usize) -> Option<CanFrame> {
+        if self.can_id & CAN_ERR_FLAG != 0 {
+            return None;
+        }
+
+        let id = if self.can_id & CAN_EFF_FLAG != 0 {
+            Id::Extended(ExtendedId::new(self.can_id & CAN_EFF_MASK)?)
+        } else {
+            Id::Standard(StandardId::new((self.can_id & CAN_SFF_MASK) as u16)?)
+        };
+        let len = self.len as usize;
+
+        match size {
+            CAN_MTU if self.can_id & CAN_RTR_FLAG != 0 => {
+                Can2Frame::new_remote(id, len.min(8)).map(CanFrame::Can2)
+            }
+            CAN_MTU => Can2Frame::new_data(id, self.data.get(..len)?).map(CanFrame::Can2),
+            CANFD_MTU => CanFdFrame::new(id, self.data.get(..len)?).map(|frame| {
+                CanFrame::CanFd(frame.with_bit_rate_switched(self.flags & CANFD_BRS != 0))
+            }),
+            _ => None,
+        }
+    }
+
+    /// Converts a frame for writing to the kernel, returning it along with
+    /// the number of bytes to write
+    fn from_frame(frame: &CanFrame) -> io::Result<(Self, usize)> {
+        let mut raw = RawFrame {
+            can_id: match frame.id() {
+                Id::Standard(id) => id.as_raw() as u32,
+                Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG,
+            },
+            len: 0,
+            flags: 0,
+            res0: 0,
+            res1: 0,
+            data: [0; 64],
+        };
+
+        match frame {
+            CanFrame::Can2(frame) => {
+                raw.len = frame.dlc() as u8;
+
+                match frame.data() {
+                    Some(data) => raw.data[..data.len()].copy_from_slice(data),
+                    None => raw.can_id |= CAN_RTR_FLAG,
+                }
+
+                Ok((raw, CAN_MTU))
+            }
+            CanFrame::CanFd(frame) => {
+                let data = frame.data();
+                raw.len = data.len() as u8;
+                raw.data[..data.len()].copy_from_slice(data);
+                raw.flags = CANFD_FDF;
+                if frame.is_bit_rate_switched() {
+                    raw.flags |= CANFD_BRS;
+                }
+
+                Ok((raw, CANFD_MTU))
+            }
+            CanFrame::CanXl(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "CAN XL frames can't be mirrored",
+            )),
+        }
+    }
+}
+
+/// A raw CAN socket bound to a SocketCAN interface, with CAN FD frames
+/// enabled
+#[derive(Debug)]
+pub struct SocketCanInterface {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl SocketCanInterface {
+    /// Opens a raw CAN socket on the interface with the given name, e.g.
+    /// `vcan0`. Must be called from within a tokio runtime.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let name = CString::new(name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+
+        // SAFETY: `name` is a valid C string
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: Plain system call, the returned descriptor is owned below
+        let fd = unsafe {
+            libc::socket(
+                libc::PF_CAN,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::CAN_RAW,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `fd` is a new descriptor which isn't owned by anything else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let enabled: libc::c_int = 1;
+        // SAFETY: The option value points to a c_int of the given size
+        let result = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_CAN_RAW,
+                libc::CAN_RAW_FD_FRAMES,
+                &enabled as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: All-zero is a valid `sockaddr_can`
+        let mut address: libc::sockaddr_can = unsafe { mem::zeroed() };
+        address.can_family = libc::AF_CAN as libc::sa_family_t;
+        address.can_ifindex = index as libc::c_int;
+
+        // SAFETY: The address points to a `sockaddr_can` of the given size
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_can as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Newer tokio versions replace `new` with the unsafe `register`, which
+        // requires the descriptor to stay open while registered. This holds
+        // since the `AsyncFd` owns it.
+        #[allow(deprecated)]
+        let fd = AsyncFd::new(fd)?;
+
+        Ok(Self { fd })
+    }
+
+    /// Waits for the next frame sent on the interface by another socket.
+    /// Error frames are skipped.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe.
+    pub async fn read(&self) -> io::Result<CanFrame> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+
+            // SAFETY: All-zero is a valid `RawFrame`
+            let mut raw: RawFrame = unsafe { mem::zeroed() };
+            let result = guard.try_io(|fd| {
+                // SAFETY: The buffer is a `RawFrame` of CANFD_MTU bytes
+                let count = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        &mut raw as *mut RawFrame as *mut libc::c_void,
+                        CANFD_MTU,
+                    )
+                };
+
+                if count < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(count as usize)
+                }
+            });
+
+            if let Ok(size) = result {
+                if let Some(frame) = raw.to_frame(size?) {
+                    return Ok(frame);
+                }
+            }
+        }
+    }
+
+    /// Sends a frame on the interface. CAN FD frames fail to send unless the
+    /// interface has the CAN FD MTU.
+    pub async fn write(&self, frame: &CanFrame) -> io::Result<()> {
+        let (raw, size) = RawFrame::from_frame(frame)?;
+
+        loop {
+            let mut guard = self.fd.writable().await?;
+
+            let result = guard.try_io(|fd| {
+                // SAFETY: The buffer is a `RawFrame`, of which at most
+                // CANFD_MTU bytes are written
+                let count = unsafe {
+                    libc::write(
+                        fd.as_raw_fd(),
+                        &raw as *const RawFrame as *const libc::c_void,
+                        size,
+                    )
+                };
+
+                if count < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+}
+
+/// Errors which stop a [VcanBridge]
+#[derive(Debug, thiserror::Error)]
+pub enum VcanBridgeError {
+    #[error("Failed to read from the adapter: {0}")]
+    AdapterRead(ReadError),
+    #[error("Failed to send a frame through the adapter: {0}")]
+    AdapterSend(SendError),
+    #[error("Failed to read from the interface: {0}")]
+    InterfaceRead(io::Error),
+    #[error("Failed to write a frame to the interface: {0}")]
+    InterfaceWrite(io::Error),
+}
+
+/// Mirrors an SLCAN adapter onto a SocketCAN interface in both directions.
+/// See the [module documentation](self).
+///
+/// Only frames received from the bus are written to the interface, echoes
+/// of transmitted frames are not. Since the kernel doesn't loop frames back
+/// to the socket which sent them, frames written to the interface aren't
+/// sent back to the adapter.
+#[derive(Debug)]
+pub struct VcanBridge {
+    interface: SocketCanInterface,
+}
+
+impl VcanBridge {
+    /// Opens the interface with the given name, e.g. `vcan0`. Must be called
+    /// from within a tokio runtime.
+    pub fn open(interface: &str) -> io::Result<Self> {
+        SocketCanInterface::open(interface).map(Self::new)
+    }
+
+    /// Creates a bridge onto an interface which was already opened
+    pub fn new(interface: SocketCanInterface) -> Self {
+        Self { interface }
+    }
+
+    /// Returns the interface the adapter is mirrored onto
+    pub fn interface(&self) -> &SocketCanInterface {
+        &self.interface
+    }
+
+    /// Mirrors frames until reading from or writing to either side fails.
+    /// The socket must already be open. Lines from the adapter which can't be
+    /// parsed are skipped.
+    ///
+    /// # Cancel Safety
+    ///
+    /// Cancelling only loses a frame if it happens while the frame is
+    /// being forwarded.
+    pub async fn run<P>(&self, socket: &mut CanSocket<P>) -> Result<(), VcanBridgeError>
+    where
+        P: AsyncRead + AsyncWrite,
+    {
+        loop {
+            tokio::select! {
+                result = socket.read_rx_frame() => {
+                    let frame = match result {
+                        Ok(RxFrame {
+                            frame,
+                            direction: FrameDirection::Rx,
+                            ..
+                        }) => frame,
+                        Ok(_) | Err(ReadError::Slcan(_) | ReadError::LineTooLong(_)) => continue,
+                        Err(e) => return Err(VcanBridgeError::AdapterRead(e)),
+                    };
+
+                    self.interface
+                        .write(&frame)
+                        .await
+                        .map_err(VcanBridgeError::InterfaceWrite)?;
+                }
+                result = self.interface.read() => {
+                    let frame = result.map_err(VcanBridgeError::InterfaceRead)?;
+
+                    socket
+                        .send(frame)
+                        .await
+                        .map_err(VcanBridgeError::AdapterSend)?;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{parser::MessageParseError, TransmitStatus};
+
+/// Counts of the lines received by a socket which had to be dropped, for
+/// quantifying the quality of the link to the gateway, along with the
+/// answers of the gateway to transmitted frames. See `CanSocket::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStats {
+    /// Lines which could not be parsed, by the kind of error
+    pub parse_errors: ParseErrorCounts,
+    /// Lines which exceeded the maximum line length
+    pub oversized_lines: u64,
+    /// Bytes received between two CRs which were discarded, i.e. the bytes
+    /// of all lines which could not be parsed or were too long
+    pub discarded_bytes: u64,
+    /// Frames the gateway acknowledged as queued for transmission (`z`/`Z`)
+    pub acknowledged_frames: u64,
+    /// Frames the gateway rejected (NACK), e.g. because its transmit queue
+    /// was full
+    pub rejected_frames: u64,
+}
+
+impl LinkStats {
+    pub(crate) fn record_parse_error(&mut self, error: &MessageParseError, length: usize) {
+        self.parse_errors.record(error);
+        self.discarded_bytes += length as u64;
+    }
+
+    pub(crate) fn record_oversized_line(&mut self, length: usize) {
+        self.oversized_lines += 1;
+        self.discarded_bytes += length as u64;
+    }
+
+    pub(crate) fn record_transmit_status(&mut self, status: TransmitStatus) {
+        match status {
+            TransmitStatus::Acknowledged => self.acknowledged_frames += 1,
+            TransmitStatus::Rejected => self.rejected_frames += 1,
+            TransmitStatus::TimedOut => {}
+        }
+    }
+}
+
+/// The number of lines which failed to parse with each kind of
+/// [`MessageParseError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParseErrorCounts {
+    pub empty_message: u64,
+    pub unrecognized_message: u64,
+    pub not_enough_bytes: u64,
+    pub too_many_bytes: u64,
+    pub illegal_hex_digit: u64,
+    pub illegal_decimal_digit: u64,
+    pub standard_id_out_of_range: u64,
+    pub extended_id_out_of_range: u64,
+    pub invalid_data_length: u64,
+    pub mismatched_data_length: u64,
+    pub illegal_bus_state: u64,
+}
+
+impl ParseErrorCounts {
+    /// Returns the number of lines which failed to parse for any reason
+    pub fn total(&self) -> u64 {
+        self.empty_message
+            + self.unrecognized_message
+            + self.not_enough_bytes
+            + self.too_many_bytes
+            + self.illegal_hex_digit
+            + self.illegal_decimal_digit
+            + self.standard_id_out_of_range
+            + self.extended_id_out_of_range
+            + self.invalid_data_length
+            + self.mismatched_data_length
+            + self.illegal_bus_state
+    }
+
+    fn record(&mut self, error: &MessageParseError) {
+        let count = match error {
+            MessageParseError::EmptyMessage => &mut self.empty_message,
+            MessageParseError::UnrecognizedMessage(_) => &mut self.unrecognized_message,
+            MessageParseError::NotEnoughBytes(..) => &mut self.not_enough_bytes,
+            MessageParseError::TooManyBytes(..) => &mut self.too_many_bytes,
+            MessageParseError::IllegalHexDigit(_) => &mut self.illegal_hex_digit,
+            MessageParseError::IllegalDecimalDigit(_) => &mut self.illegal_decimal_digit,
+            MessageParseError::StandardIdOutOfRange(_) => &mut self.standard_id_out_of_range,
+            MessageParseError::ExtendedIdOutOfRange(_) => &mut self.extended_id_out_of_range,
+            MessageParseError::InvalidDataLength(_) => &mut self.invalid_data_length,
+            MessageParseError::MismatchedDataLength(..) => &mut self.mismatched_data_length,
+            MessageParseError::IllegalBusState(_) => &mut self.illegal_bus_state,
+        };
+
+        *count += 1;
+    }
+}
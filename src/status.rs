@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use crate::command::{
+    AutoRetransmissionMode, DataBitRate, FdCrcMode, NominalBitRate, OperatingMode, TimestampMode,
+};
+
+/// The error state of the CAN controller in the gateway, as defined by the
+/// CAN fault confinement rules, ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusState {
+    /// Normal operation (both error counters below 96)
+    #[default]
+    ErrorActive,
+    /// One of the error counters reached the warning level (96)
+    ErrorWarning,
+    /// One of the error counters exceeded 127, the controller may no longer
+    /// signal errors actively
+    ErrorPassive,
+    /// The transmit error counter exceeded 255 and the controller
+    /// disconnected itself from the bus. It neither sends nor receives frames
+    /// until the channel is reopened.
+    BusOff,
+}
+
+impl BusState {
+    /// Returns the state implied by the transmit (TEC) and receive (REC)
+    /// error counters under the CAN fault confinement rules
+    pub fn from_error_counters(tx_error_count: u16, rx_error_count: u16) -> Self {
+        match tx_error_count.max(rx_error_count) {
+            _ if tx_error_count > 255 => BusState::BusOff,
+            128.. => BusState::ErrorPassive,
+            96.. => BusState::ErrorWarning,
+            _ => BusState::ErrorActive,
+        }
+    }
+}
+
+/// A bus state report sent by the gateway (`s` followed by the state and the
+/// receive and transmit error counters, e.g. `sb256256`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BusStatus {
+    pub state: BusState,
+    pub rx_error_count: u16,
+    pub tx_error_count: u16,
+}
+
+impl BusStatus {
+    /// Returns true if the controller is not error active or either error
+    /// counter is nonzero
+    pub fn has_errors(&self) -> bool {
+        self.state != BusState::ErrorActive || self.rx_error_count > 0 || self.tx_error_count > 0
+    }
+
+    /// Returns the state implied by the error counters under the CAN fault
+    /// confinement rules, which can differ from the reported state if the
+    /// controller updates its state lazily or the counters saturate
+    pub fn derived_state(&self) -> BusState {
+        BusState::from_error_counters(self.tx_error_count, self.rx_error_count)
+    }
+}
+
+/// What a socket does when the gateway reports that the controller went
+/// bus-off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusOffPolicy {
+    /// Reads return [`ReadError::BusOff`](crate::ReadError::BusOff) so the
+    /// application can decide what to do
+    #[default]
+    Report,
+    /// The channel is closed, and after waiting for `delay` it is
+    /// reconfigured with the settings applied so far and reopened. Reads
+    /// continue transparently.
+    Recover { delay: Duration },
+}
+
+/// How a socket confirms configuration commands. See
+/// `CanSocket::set_command_retry_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CommandRetryPolicy {
+    /// Commands are sent without waiting for the gateway to answer
+    #[default]
+    Unconfirmed,
+    /// Each command is followed by a firmware version query (`V`), and is
+    /// confirmed once the query is answered within `timeout` without a NACK
+    /// before it. Otherwise the command is sent again up to `attempts` times,
+    /// waiting `backoff` before each attempt. The gateway has to answer the
+    /// version query for commands to be confirmed.
+    Retry {
+        attempts: u32,
+        timeout: Duration,
+        backoff: Duration,
+    },
+}
+
+/// How a socket deals with the limited transmit queue of the gateway. See
+/// `CanSocket::set_tx_queue_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxQueuePolicy {
+    /// Frames are written right away, however many are still queued. Most
+    /// firmwares silently drop frames which don't fit into their queue.
+    #[default]
+    Unbounded,
+    /// Sends wait until fewer than `capacity` frames are in flight, reading
+    /// the acknowledgements (`z`/`Z`) of the gateway, and fail with
+    /// `SendError::TimedOut` if no room frees up within `timeout`. The
+    /// gateway has to acknowledge transmitted frames.
+    Wait { capacity: usize, timeout: Duration },
+}
+
+/// The settings applied to a socket, which are replayed when recovering from
+/// bus-off
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ChannelConfig {
+    pub nominal_bit_rate: Option<NominalBitRate>,
+    pub data_bit_rate: Option<DataBitRate>,
+    pub operating_mode: Option<OperatingMode>,
+    pub auto_retransmission: Option<AutoRetransmissionMode>,
+    pub timestamp_mode: Option<TimestampMode>,
+    pub fd_crc_mode: Option<FdCrcMode>,
+}
+
+/// A callback registered with `CanSocket::set_bus_status_callback`
+pub(crate) type BusStatusCallback = Box<dyn FnMut(&BusStatus) + Send>;
@@ -0,0 +1,263 @@
+use crate::{
+    command::TimestampMode,
+    parser::{parse_message_from_bytes, LineParseError, Message, NACK},
+    SLCAN_MTU,
+};
+
+/// Something noteworthy which happened while accumulating a line
+pub(crate) enum LineEvent<'a> {
+    /// A complete, non-empty line (without the CR line ending)
+    Line(&'a [u8]),
+    /// The gateway failed to execute a command. Any partially received line
+    /// is left untouched.
+    Nack,
+    /// A line of the given length which exceeded the maximum line length
+    /// was discarded
+    TooLong(usize),
+}
+
+/// Splits the bytes received from the gateway into CR terminated lines. This
+/// is shared by the sockets and [`SlcanStreamParser`], so they all split
+/// lines the same way.
+pub(crate) struct LineAccumulator {
+    buffer: Vec<u8>,
+    count: usize,
+    overflowed: bool,
+    lf_line_endings: bool,
+}
+
+impl LineAccumulator {
+    pub fn new(max_line_length: usize) -> Self {
+        Self {
+            buffer: vec![0; max_line_length],
+            count: 0,
+            overflowed: false,
+            lf_line_endings: false,
+        }
+    }
+
+    /// Changes the maximum line length, discarding any partially received
+    /// line
+    pub fn set_max_line_length(&mut self, length: usize) {
+        self.buffer = vec![0; length];
+        self.reset();
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn set_lf_line_endings(&mut self, enabled: bool) {
+        self.lf_line_endings = enabled;
+    }
+
+    pub fn lf_line_endings(&self) -> bool {
+        self.lf_line_endings
+    }
+
+    /// Discards any partially received line
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.overflowed = false;
+    }
+
+    /// Adds a received byte, returning an event once it completes a line or
+    /// a NACK. Empty lines are skipped, so the LF of a CRLF line ending only
+    /// terminates an empty line when LF line endings are accepted.
+    pub fn push(&mut self, byte: u8) -> Option<LineEvent<'_>> {
+        // A NACK is not followed by a CR, so report it on its own without
+        // disturbing any partially received line
+        if byte == NACK {
+            return Some(LineEvent::Nack);
+        }
+
+        if byte == b'\r' || (byte == b'\n' && self.lf_line_endings) {
+            let overflowed = self.overflowed;
+            let length = self.count;
+            self.reset();
+
+            return match (overflowed, length) {
+                (true, _) => Some(LineEvent::TooLong(length)),
+                (false, 0) => None,
+                (false, _) => Some(LineEvent::Line(&self.buffer[..length])),
+            };
+        }
+
+        // If we encounter a line that is too long, set the overflow flag and
+        // keep counting until we find a CR
+        if self.count >= self.buffer.len() {
+            self.overflowed = true;
+        } else {
+            self.buffer[self.count] = byte;
+        }
+
+        self.count += 1;
+        None
+    }
+}
+
+/// Errors which can arise while parsing a stream of bytes with
+/// [`SlcanStreamParser`]. Parsing continues with the next line after either.
+#[derive(Debug, thiserror::Error)]
+pub enum StreamParseError {
+    #[error("SLCAN message parsing error: {0}")]
+    Slcan(#[from] LineParseError),
+    #[error("Received a line of {0} bytes which exceeds the maximum line length")]
+    LineTooLong(usize),
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for StreamParseError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            StreamParseError::Slcan(e) => defmt::write!(f, "Slcan({})", e),
+            StreamParseError::LineTooLong(length) => defmt::write!(f, "LineTooLong({})", length),
+        }
+    }
+}
+
+/// An incremental parser for the bytes received from the gateway, for users
+/// who run their own event loop or receive into DMA buffers. It splits and
+/// parses lines exactly like the sockets do, without doing any IO itself.
+///
+/// Bytes can be pushed in chunks of any size. A line which is split across
+/// chunks is kept until the rest of it arrives.
+///
+/// ```
+/// use slcan_fd::{Message, SlcanStreamParser};
+///
+/// let mut parser = SlcanStreamParser::new();
+///
+/// assert_eq!(parser.push_bytes(b"t1231A").count(), 0);
+///
+/// for message in parser.push_bytes(b"B\rzt12\r") {
+///     match message {
+///         Ok(Message::Frame(frame, _)) => println!("{:?}", frame),
+///         Ok(other) => println!("{:?}", other),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub struct SlcanStreamParser {
+    lines: LineAccumulator,
+    timestamp_mode: TimestampMode,
+}
+
+impl Default for SlcanStreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SlcanStreamParser {
+    /// Creates a parser for frames without timestamps and with the default
+    /// maximum line length of the sockets
+    pub fn new() -> Self {
+        Self {
+            lines: LineAccumulator::new(SLCAN_MTU),
+            timestamp_mode: TimestampMode::Disabled,
+        }
+    }
+
+    /// Sets the timestamp mode the gateway was configured with, which
+    /// determines how many digits of each frame are parsed as the timestamp
+    pub fn with_timestamp_mode(mut self, mode: TimestampMode) -> Self {
+        self.timestamp_mode = mode;
+        self
+    }
+
+    /// Sets the maximum length of a line (excluding the CR). See
+    /// `CanSocket::set_max_line_length`.
+    pub fn with_max_line_length(mut self, length: usize) -> Self {
+        self.lines.set_max_line_length(length);
+        self
+    }
+
+    /// Accepts LF and CRLF line endings in addition to CR. See
+    /// `CanSocket::set_lf_line_endings`.
+    pub fn with_lf_line_endings(mut self, enabled: bool) -> Self {
+        self.lines.set_lf_line_endings(enabled);
+        self
+    }
+
+    /// Changes the timestamp mode, e.g. after reconfiguring the gateway
+    pub fn set_timestamp_mode(&mut self, mode: TimestampMode) {
+        self.timestamp_mode = mode;
+    }
+
+    /// Returns the timestamp mode frames are parsed with
+    pub fn timestamp_mode(&self) -> TimestampMode {
+        self.timestamp_mode
+    }
+
+    /// Returns the maximum length of a line
+    pub fn max_line_length(&self) -> usize {
+        self.lines.max_line_length()
+    }
+
+    /// Discards any partially received line, e.g. after the port was
+    /// reconnected
+    pub fn reset(&mut self) {
+        self.lines.reset();
+    }
+
+    /// Adds received bytes and returns the messages they complete.
+    ///
+    /// Lines which can't be parsed are returned as errors without stopping
+    /// the iterator. Unlike the sockets, the parser doesn't know whether a
+    /// firmware version was queried, so the answer to such a query is
+    /// returned as an error as well.
+    ///
+    /// All of the bytes are consumed before this returns, so dropping the
+    /// iterator early only drops the messages it didn't yield yet. A
+    /// partial line at the end is kept for the next call.
+    pub fn push_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> impl Iterator<Item = Result<Message, StreamParseError>> {
+        let mut messages = Vec::new();
+
+        for &byte in bytes {
+            let message = match self.lines.push(byte) {
+                None => continue,
+                Some(LineEvent::Nack) => Ok(Message::Nack),
+                Some(LineEvent::TooLong(length)) => Err(StreamParseError::LineTooLong(length)),
+                Some(LineEvent::Line(line)) => parse_message_from_bytes(line, self.timestamp_mode)
+                    .map_err(|error| {
+                        LineParseError {
+                            line: line.to_vec(),
+                            error,
+                        }
+                        .into()
+                    }),
+            };
+
+            messages.push(message);
+        }
+
+        messages.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, StandardId};
+
+    #[test]
+    fn keeps_lines_after_the_iterator_is_dropped() {
+        let mut parser = SlcanStreamParser::new();
+
+        let first = parser.push_bytes(b"t1230\rt4561A").next();
+        assert!(matches!(first, Some(Ok(Message::Frame(..)))));
+
+        let messages: Vec<_> = parser.push_bytes(b"B\r").collect();
+        match messages.as_slice() {
+            [Ok(Message::Frame(frame, _))] => {
+                assert_eq!(frame.id(), Id::Standard(StandardId::new(0x456).unwrap()));
+                assert_eq!(frame.data(), Some(&[0xAB][..]));
+            }
+            other => panic!("unexpected messages: {other:?}"),
+        }
+    }
+}
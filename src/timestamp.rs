@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// A hardware timestamp appended by the gateway to a received frame. See
+/// [`TimestampMode`](crate::TimestampMode).
+///
+/// Both variants are free running counters which wrap around, so a single
+/// timestamp is only meaningful relative to other timestamps from the same
+/// device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Timestamp {
+    /// A 16-bit millisecond counter
+    Milliseconds(u16),
+    /// A 32-bit microsecond counter
+    Microseconds(u32),
+}
+
+impl Timestamp {
+    /// Returns the raw counter value converted to microseconds
+    pub fn as_micros(&self) -> u64 {
+        match self {
+            Self::Milliseconds(ms) => *ms as u64 * 1000,
+            Self::Microseconds(us) => *us as u64,
+        }
+    }
+
+    /// Returns the raw counter value as a [`Duration`]
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_micros(self.as_micros())
+    }
+}
+
+/// Maps hardware [`Timestamp`]s onto the host clock.
+///
+/// Device timestamps wrap around (every 60 seconds for millisecond counters
+/// and every ~71 minutes for microsecond counters) and the device oscillator
+/// drifts relative to the host. `ClockSync` unwraps the counter into a
+/// monotonic device time and continuously estimates the offset between the
+/// two clocks, so frames from several sources can be merged into one
+/// timeline.
+///
+/// Since the serial link only ever adds latency, the offset estimate follows
+/// the smallest observed `host - device` difference immediately and otherwise
+/// creeps towards newer samples slowly, which absorbs latency jitter while
+/// still tracking oscillator drift.
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    anchor: Instant,
+    anchor_system: SystemTime,
+    millisecond_period: u32,
+    drift_gain: f64,
+    last: Option<Sample>,
+    offset_us: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    raw_us: u64,
+    device_us: u64,
+    host: Instant,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockSync {
+    /// Creates a new `ClockSync` anchored at the current host time
+    pub fn new() -> Self {
+        Self {
+            anchor: Instant::now(),
+            anchor_system: SystemTime::now(),
+            millisecond_period: 60_000,
+            drift_gain: 1.0 / 256.0,
+            last: None,
+            offset_us: 0.0,
+        }
+    }
+
+    /// Sets the value at which millisecond timestamps wrap back to zero
+    /// (defaults to 60000 as in the Lawicel protocol; some firmwares use the
+    /// full 16-bit range, i.e. 65536)
+    pub fn with_millisecond_period(mut self, period: u32) -> Self {
+        self.millisecond_period = period;
+        self
+    }
+
+    /// Sets how quickly the offset estimate follows samples which arrived
+    /// later than expected, as a fraction in `0.0..=1.0` (defaults to 1/256).
+    /// Higher values track drift faster but are more sensitive to jitter.
+    pub fn with_drift_gain(mut self, gain: f64) -> Self {
+        self.drift_gain = gain.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Forgets all previous samples, e.g. after the device was reset
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.offset_us = 0.0;
+    }
+
+    /// Feeds a timestamp along with the host time at which its frame was
+    /// received, and returns the corresponding host [`Instant`] at which the
+    /// frame was actually on the bus.
+    pub fn update(&mut self, timestamp: Timestamp, received_at: Instant) -> Instant {
+        let period_us = match timestamp {
+            Timestamp::Milliseconds(_) => self.millisecond_period as u64 * 1000,
+            Timestamp::Microseconds(_) => 1 << 32,
+        };
+        let raw_us = timestamp.as_micros() % period_us;
+        let host_us = self.host_micros(received_at);
+
+        let device_us = match self.last {
+            None => raw_us,
+            Some(last) => {
+                let raw_delta = (raw_us + period_us - last.raw_us) % period_us;
+
+                // Use the host clock to work out how many times the counter
+                // wrapped in between, in case frames were sparse
+                let host_delta =
+                    received_at.saturating_duration_since(last.host).as_micros() as u64;
+                let wraps = (host_delta.saturating_sub(raw_delta) + period_us / 2) / period_us;
+
+                last.device_us + raw_delta + wraps * period_us
+            }
+        };
+
+        let sample_offset = host_us - device_us as f64;
+
+        if self.last.is_none() || sample_offset < self.offset_us {
+            self.offset_us = sample_offset;
+        } else {
+            self.offset_us += (sample_offset - self.offset_us) * self.drift_gain;
+        }
+
+        self.last = Some(Sample {
+            raw_us,
+            device_us,
+            host: received_at,
+        });
+
+        let mapped_us = device_us as f64 + self.offset_us;
+
+        if mapped_us >= 0.0 {
+            self.anchor + Duration::from_micros(mapped_us as u64)
+        } else {
+            self.anchor - Duration::from_micros(-mapped_us as u64)
+        }
+    }
+
+    /// Same as [`update`](ClockSync::update) but returns the wall clock time
+    /// at which the frame was on the bus
+    pub fn update_system_time(&mut self, timestamp: Timestamp, received_at: Instant) -> SystemTime {
+        let instant = self.update(timestamp, received_at);
+        self.to_system_time(instant)
+    }
+
+    /// Converts an [`Instant`] returned by this `ClockSync` into wall clock
+    /// time
+    pub fn to_system_time(&self, instant: Instant) -> SystemTime {
+        if instant >= self.anchor {
+            self.anchor_system + (instant - self.anchor)
+        } else {
+            self.anchor_system - (self.anchor - instant)
+        }
+    }
+
+    fn host_micros(&self, instant: Instant) -> f64 {
+        if instant >= self.anchor {
+            (instant - self.anchor).as_micros() as f64
+        } else {
+            -((self.anchor - instant).as_micros() as f64)
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Running the async [`CanSocket`](crate::tokio::CanSocket) over UDP, for
+//! WiFi-CAN bridges which speak SLCAN in datagrams instead of over a serial
+//! port.
+//!
+//! [`UdpPort`] implements tokio's `AsyncRead` and `AsyncWrite` on top of a
+//! connected `UdpSocket`, so it can be passed to `CanSocket::new` like a
+//! native serial port:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use slcan_fd::{tokio::CanSocket, udp::UdpPort, NominalBitRate};
+//!
+//! let port = UdpPort::connect("0.0.0.0:0", "192.168.4.1:3333").await?;
+//!
+//! let mut can = CanSocket::new(port);
+//! can.open(NominalBitRate::Rate500Kbit).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each datagram carries one or more CR terminated lines. Since every
+//! received datagram is parsed on its own, a datagram which arrives out of
+//! order or gets lost only reorders or drops its own frames instead of
+//! corrupting the lines around it. Enable hardware timestamps (see
+//! `CanSocket::set_timestamp_mode`) to restore the order of frames from
+//! datagrams which were reordered.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+use crate::parser::NACK;
+
+/// The largest datagram which fits into an Ethernet frame without being
+/// fragmented (1500 byte MTU minus the IPv4 and UDP headers)
+const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1472;
+
+/// A UDP socket connected to an SLCAN bridge, usable as the port of an async
+/// [`CanSocket`](crate::tokio::CanSocket)
+#[derive(Debug)]
+pub struct UdpPort {
+    socket: UdpSocket,
+    max_datagram_size: usize,
+    rx: Vec<u8>,
+    rx_position: usize,
+    tx: Vec<u8>,
+}
+
+impl UdpPort {
+    /// Binds a socket to `local` (e.g. `0.0.0.0:0` for any port) and
+    /// connects it to the bridge at `bridge`, so only datagrams from the
+    /// bridge are received
+    pub async fn connect(
+        local: impl ToSocketAddrs,
+        bridge: impl ToSocketAddrs,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(bridge).await?;
+
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Wraps a socket which was already connected to the bridge, e.g. one
+    /// with custom socket options
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            rx: Vec::new(),
+            rx_position: 0,
+            tx: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum size of the datagrams sent and received (defaults to
+    /// 1472 bytes). Lines written between two flushes are batched into one
+    /// datagram as long as they fit. Received datagrams which are larger are
+    /// truncated.
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size.max(1);
+        self
+    }
+
+    /// Returns the underlying socket
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Sends the lines written since the last datagram, if any
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.tx.is_empty() {
+            ready!(self.socket.poll_send(cx, &self.tx))?;
+            self.tx.clear();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for UdpPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.rx_position == this.rx.len() {
+            this.rx.resize(this.max_datagram_size, 0);
+            this.rx_position = 0;
+
+            let mut datagram = ReadBuf::new(&mut this.rx);
+            let result = this.socket.poll_recv(cx, &mut datagram);
+            let len = datagram.filled().len();
+            this.rx.truncate(len);
+
+            match ready!(result) {
+                Ok(()) => {}
+                // An ICMP port unreachable from a bridge which isn't up (yet)
+                // fails the next receive on some platforms
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    this.rx.clear();
+                    continue;
+                }
+                Err(e) => {
+                    this.rx.clear();
+                    return Poll::Ready(Err(e));
+                }
+            }
+
+            // Terminate a truncated last line, so it is dropped on its own
+            // rather than joined with the first line of the next datagram
+            if !matches!(this.rx.last(), None | Some(&b'\r') | Some(&NACK)) {
+                this.rx.push(b'\r');
+            }
+        }
+
+        let count = (this.rx.len() - this.rx_position).min(buf.remaining());
+        buf.put_slice(&this.rx[this.rx_position..this.rx_position + count]);
+        this.rx_position += count;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UdpPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.tx.len() + buf.len() > this.max_datagram_size {
+            ready!(this.poll_send_pending(cx))?;
+        }
+
+        let count = buf.len().min(this.max_datagram_size);
+        this.tx.extend_from_slice(&buf[..count]);
+
+        Poll::Ready(Ok(count))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,188 @@
+//! An async facade over the synchronous [`CanSocket`](crate::sync::CanSocket),
+//! for platforms where `tokio-serial` has issues but `serialport` works fine.
+//!
+//! [`CanSocket`] runs each operation of the synchronous socket on tokio's
+//! blocking thread pool through `spawn_blocking`:
+//!
+//! ```no_run
+//! use slcan_fd::{unblock::CanSocket, NominalBitRate};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let port = serialport::new("/dev/ttyUSB0", 115_200)
+//!     .timeout(Duration::from_millis(10))
+//!     .open()?;
+//!
+//! let can = CanSocket::new(port);
+//! can.open(NominalBitRate::Rate500Kbit).await?;
+//!
+//! loop {
+//!     println!("{:?}", can.read().await?);
+//! }
+//! # }
+//! ```
+//!
+//! Only one operation runs at a time, and a read which is waiting for the
+//! port holds up the others until the timeout of the port passes. The port
+//! should therefore have a short timeout, which reads retry transparently.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    command::{DataBitRate, OperatingMode, TimestampMode},
+    frame::{CanFrame, RxFrame},
+    sync::CanSocket as SyncCanSocket,
+    NominalBitRate, ReadError, SendError, TransmitStatus,
+};
+
+/// An async interface into a CAN FD network through a serial (USB) gateway
+/// device, backed by the synchronous [`CanSocket`](crate::sync::CanSocket).
+/// See the [module documentation](self).
+///
+/// Clones share the same socket, so frames can be sent from one task while
+/// another one reads.
+pub struct CanSocket<P> {
+    socket: Arc<Mutex<SyncCanSocket<P>>>,
+    // Frames read by blocking reads whose future was dropped before they
+    // finished, returned by the next read
+    received: Arc<Mutex<VecDeque<RxFrame>>>,
+}
+
+impl<P> Clone for CanSocket<P> {
+    fn clone(&self) -> Self {
+        Self {
+            socket: self.socket.clone(),
+            received: self.received.clone(),
+        }
+    }
+}
+
+impl<P> CanSocket<P>
+where
+    P: Read + Write + Send + 'static,
+{
+    /// Constructs a new CanSocket from a synchronous serial port
+    pub fn new(port: P) -> Self {
+        Self::from_sync(SyncCanSocket::new(port))
+    }
+
+    /// Wraps a synchronous socket which was already configured
+    pub fn from_sync(socket: SyncCanSocket<P>) -> Self {
+        Self {
+            socket: Arc::new(Mutex::new(socket)),
+            received: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Runs an operation of the synchronous socket on the blocking thread
+    /// pool, e.g. one which isn't wrapped by this facade. The operation runs
+    /// to completion even if the returned future is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime, and resumes the panic if
+    /// the operation panics.
+    pub async fn run<R, F>(&self, operation: F) -> R
+    where
+        F: FnOnce(&mut SyncCanSocket<P>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let socket = self.socket.clone();
+        let task = tokio::task::spawn_blocking(move || operation(&mut socket.lock().unwrap()));
+
+        match task.await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// See `sync::CanSocket::open`
+    pub async fn open(&self, nominal_bit_rate: NominalBitRate) -> io::Result<()> {
+        self.run(move |socket| socket.open(nominal_bit_rate)).await
+    }
+
+    /// See `sync::CanSocket::close`
+    pub async fn close(&self) -> io::Result<()> {
+        self.run(|socket| socket.close()).await
+    }
+
+    /// See `sync::CanSocket::set_data_bit_rate`
+    pub async fn set_data_bit_rate(&self, rate: DataBitRate) -> io::Result<()> {
+        self.run(move |socket| socket.set_data_bit_rate(rate)).await
+    }
+
+    /// See `sync::CanSocket::set_operating_mode`
+    pub async fn set_operating_mode(&self, mode: OperatingMode) -> io::Result<()> {
+        self.run(move |socket| socket.set_operating_mode(mode))
+            .await
+    }
+
+    /// See `sync::CanSocket::set_timestamp_mode`
+    pub async fn set_timestamp_mode(&self, mode: TimestampMode) -> io::Result<()> {
+        self.run(move |socket| socket.set_timestamp_mode(mode))
+            .await
+    }
+
+    /// See `sync::CanSocket::send`
+    pub async fn send(&self, frame: impl Into<CanFrame>) -> Result<(), SendError> {
+        let frame = frame.into();
+        self.run(move |socket| socket.send(frame)).await
+    }
+
+    /// See `sync::CanSocket::send_and_confirm`
+    pub async fn send_and_confirm(
+        &self,
+        frame: impl Into<CanFrame>,
+        timeout: Duration,
+    ) -> Result<TransmitStatus, SendError> {
+        let frame = frame.into();
+        self.run(move |socket| socket.send_and_confirm(frame, timeout))
+            .await
+    }
+
+    /// Waits for the next frame. Timeouts of the port are retried, letting
+    /// other operations run in between. See `sync::CanSocket::read`.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe. A frame which is read after the future
+    /// was dropped is returned by the next read.
+    pub async fn read(&self) -> Result<CanFrame, ReadError> {
+        Ok(self.read_rx_frame().await?.frame)
+    }
+
+    /// Same as [`read`](CanSocket::read) but also returns the metadata of
+    /// the frame. See `sync::CanSocket::read_rx_frame`.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe in the same way as
+    /// [`read`](CanSocket::read).
+    pub async fn read_rx_frame(&self) -> Result<RxFrame, ReadError> {
+        loop {
+            if let Some(frame) = self.received.lock().unwrap().pop_front() {
+                return Ok(frame);
+            }
+
+            let received = self.received.clone();
+            let result: Result<(), ReadError> = self
+                .run(move |socket| {
+                    let frame = socket.read_rx_frame()?;
+                    received.lock().unwrap().push_back(frame);
+                    Ok(())
+                })
+                .await;
+
+            match result {
+                Ok(()) => {}
+                Err(e) if e.is_would_block() => tokio::task::yield_now().await,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
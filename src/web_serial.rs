@@ -0,0 +1,185 @@
+//! Running the async [`CanSocket`](crate::tokio::CanSocket) in the browser
+//! over the [Web Serial API](https://developer.mozilla.org/en-US/docs/Web/API/Web_Serial_API).
+//!
+//! [`WebSerialPort`] implements tokio's `AsyncRead` and `AsyncWrite` on top of
+//! the streams of a `SerialPort`, so it can be passed to
+//! `CanSocket::new` like a native serial port:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use slcan_fd::{tokio::CanSocket, web_serial::WebSerialPort, NominalBitRate};
+//!
+//! // Must be called from a user gesture, e.g. a button click handler
+//! let port = WebSerialPort::request(115_200).await?;
+//!
+//! let mut can = CanSocket::new(port);
+//! can.open(NominalBitRate::Rate500Kbit).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The Web Serial bindings of `web-sys` are unstable, so the crate has to be
+//! built with `RUSTFLAGS=--cfg=web_sys_unstable_apis`. Only the
+//! `wasm32-unknown-unknown` target is supported, where tokio has no timer:
+//! the methods which wait for a timeout (e.g. `CanSocket::await_frame`) and
+//! bus-off recovery can't be used.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use js_sys::{Reflect, Uint8Array};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    ReadableStreamDefaultReader, SerialOptions, SerialPort, WritableStreamDefaultWriter,
+};
+
+/// An open Web Serial port, usable as the port of an async
+/// [`CanSocket`](crate::tokio::CanSocket)
+pub struct WebSerialPort {
+    port: SerialPort,
+    reader: ReadableStreamDefaultReader,
+    writer: WritableStreamDefaultWriter,
+    pending_read: Option<JsFuture>,
+    pending_write: Option<JsFuture>,
+    leftover: Vec<u8>,
+}
+
+impl WebSerialPort {
+    /// Asks the user to pick a serial port and opens it with the given baud
+    /// rate. Browsers only allow this in response to a user gesture.
+    pub async fn request(baud_rate: u32) -> io::Result<Self> {
+        let window = web_sys::window().ok_or_else(|| unsupported("no window"))?;
+        let serial = window.navigator().serial();
+        let port = JsFuture::from(serial.request_port())
+            .await
+            .map_err(js_error)?;
+
+        Self::open(port.unchecked_into(), baud_rate).await
+    }
+
+    /// Opens a port which was previously granted to the page, e.g. one
+    /// returned by `navigator.serial.getPorts()`
+    pub async fn open(port: SerialPort, baud_rate: u32) -> io::Result<Self> {
+        let options = SerialOptions::new(baud_rate);
+        JsFuture::from(port.open(&options))
+            .await
+            .map_err(js_error)?;
+
+        let reader = port.readable().get_reader().unchecked_into();
+        let writer = port.writable().get_writer().map_err(js_error)?;
+
+        Ok(Self {
+            port,
+            reader,
+            writer,
+            pending_read: None,
+            pending_write: None,
+            leftover: Vec::new(),
+        })
+    }
+
+    /// Returns the underlying `SerialPort`
+    pub fn port(&self) -> &SerialPort {
+        &self.port
+    }
+
+    /// Releases the streams and closes the port
+    pub async fn close(self) -> io::Result<()> {
+        JsFuture::from(self.writer.close())
+            .await
+            .map_err(js_error)?;
+        JsFuture::from(self.reader.cancel())
+            .await
+            .map_err(js_error)?;
+        self.reader.release_lock();
+        JsFuture::from(self.port.close()).await.map_err(js_error)?;
+
+        Ok(())
+    }
+
+    fn poll_pending_write(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(write) = self.pending_write.as_mut() {
+            let result = ready!(Pin::new(write).poll(cx));
+            self.pending_write = None;
+            result.map_err(js_error)?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for WebSerialPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.leftover.is_empty() {
+            let read = this
+                .pending_read
+                .get_or_insert_with(|| JsFuture::from(this.reader.read()));
+            let result = ready!(Pin::new(read).poll(cx));
+            this.pending_read = None;
+            let result = result.map_err(js_error)?;
+
+            let done = Reflect::get(&result, &JsValue::from_str("done"))
+                .map_err(js_error)?
+                .is_truthy();
+            if done {
+                // End of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            let value = Reflect::get(&result, &JsValue::from_str("value")).map_err(js_error)?;
+            this.leftover = Uint8Array::new(&value).to_vec();
+        }
+
+        let count = this.leftover.len().min(buf.remaining());
+        buf.put_slice(&this.leftover[..count]);
+        this.leftover.drain(..count);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WebSerialPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending_write(cx))?;
+
+        // The chunk is copied into a new JS array, so the write can complete
+        // in the background and is awaited by the next write or flush
+        let chunk = Uint8Array::from(buf);
+        this.pending_write = Some(JsFuture::from(this.writer.write_with_chunk(&chunk)));
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_write(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+fn js_error(error: JsValue) -> io::Error {
+    io::Error::other(format!("{:?}", error))
+}
+
+fn unsupported(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, reason)
+}
@@ -0,0 +1,893 @@
+//! The basics of XCP on CAN, for prototyping measurement and calibration
+//! tooling on top of a gateway.
+//!
+//! An [`XcpMaster`] borrows a socket and talks to one slave (ECU) through a
+//! pair of IDs: commands are sent with the master ID and the slave answers
+//! with the slave ID. Besides [`connect`](XcpMaster::connect) and
+//! [`disconnect`](XcpMaster::disconnect), it can read memory with
+//! [`short_upload`](XcpMaster::short_upload) and set up, start and read
+//! synchronous data acquisition (DAQ) lists with a static ODT layout. Any
+//! other command can be sent with [`command`](XcpMaster::command).
+//!
+//! DAQ packets are expected to be identified by their absolute ODT number and
+//! to carry no timestamps, which is the most common setup of XCP on CAN.
+//! Seed and key, block transfers and calibration page handling are left to
+//! the application.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     mut socket: slcan_fd::tokio::CanSocket<tokio_serial::SerialStream>,
+//! # ) -> Result<(), slcan_fd::xcp::XcpError> {
+//! use slcan_fd::{
+//!     xcp::{DaqEntry, DaqList, XcpConfig, XcpMaster},
+//!     StandardId,
+//! };
+//!
+//! let config = XcpConfig::new(StandardId::new(0x7E0).unwrap(), StandardId::new(0x7E1).unwrap());
+//! let mut xcp = XcpMaster::new(&mut socket, config);
+//!
+//! xcp.connect().await?;
+//! let version = xcp.short_upload(0x8000_0000, 0, 4).await?;
+//! println!("Software version: {:02X?}", version);
+//!
+//! // Sample two variables on event channel 0 (e.g. the 10 ms task)
+//! let list = DaqList::new(0).with_odt(vec![
+//!     DaqEntry::new(0x2000_0010, 2),
+//!     DaqEntry::new(0x2000_0020, 4),
+//! ]);
+//! xcp.configure_daq(&[list]).await?;
+//! xcp.start_daq().await?;
+//!
+//! for _ in 0..100 {
+//!     let packet = xcp.read_daq().await?;
+//!     println!("DAQ {} ODT {}: {:02X?}", packet.list, packet.odt, packet.data);
+//! }
+//!
+//! xcp.stop_daq().await?;
+//! xcp.disconnect().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    frame::{Can2Frame, CanFdFrame, CanFrame},
+    tokio::CanSocket,
+    Id, ReadError, SendError,
+};
+
+/// How long to wait for the response to a command by default. The XCP on CAN
+/// standard recommends a timeout (T1) of 25 ms, which is too short for
+/// gateways with a slow serial link.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The command packet IDs (PIDs) used by [XcpMaster], for building packets
+/// for [`XcpMaster::command`]
+pub mod command {
+    pub const CONNECT: u8 = 0xFF;
+    pub const DISCONNECT: u8 = 0xFE;
+    pub const GET_STATUS: u8 = 0xFD;
+    pub const SHORT_UPLOAD: u8 = 0xF4;
+    pub const WRITE_DAQ: u8 = 0xE1;
+    pub const SET_DAQ_PTR: u8 = 0xE2;
+    pub const SET_DAQ_LIST_MODE: u8 = 0xE0;
+    pub const START_STOP_DAQ_LIST: u8 = 0xDE;
+    pub const START_STOP_SYNCH: u8 = 0xDD;
+    pub const FREE_DAQ: u8 = 0xD6;
+    pub const ALLOC_DAQ: u8 = 0xD5;
+    pub const ALLOC_ODT: u8 = 0xD4;
+    pub const ALLOC_ODT_ENTRY: u8 = 0xD3;
+}
+
+/// The PID of a positive response
+const RES: u8 = 0xFF;
+/// The PID of a negative response
+const ERR: u8 = 0xFE;
+/// The PIDs from here on are responses, events and service requests, the
+/// ones below are DAQ packets
+const FIRST_CTO_PID: u8 = 0xFC;
+
+/// Errors which can arise while talking to an XCP slave
+#[derive(Debug, thiserror::Error)]
+pub enum XcpError {
+    #[error("Failed to send the command: {0}")]
+    Send(#[from] SendError),
+    #[error("Failed to read the response: {0}")]
+    Read(#[from] ReadError),
+    /// The slave did not answer within the timeout
+    #[error("The slave did not respond to command 0x{0:02X} in time")]
+    Timeout(u8),
+    /// The slave answered with a negative response (`ERR`) and the given
+    /// error code, e.g. `0x20` for an unknown command
+    #[error("The slave rejected command 0x{command:02X} with error code 0x{code:02X}")]
+    Rejected { command: u8, code: u8 },
+    /// The response of the slave is too short for the command
+    #[error("The response to command 0x{0:02X} is malformed")]
+    MalformedResponse(u8),
+    /// A command was issued before [`XcpMaster::connect`] succeeded
+    #[error("Not connected to the slave")]
+    NotConnected,
+    /// The command or the requested data doesn't fit into a packet
+    #[error("{0} bytes don't fit into a packet")]
+    InvalidLength(usize),
+    /// A DAQ packet was requested before [`XcpMaster::start_daq`]
+    #[error("No DAQ lists are running")]
+    DaqNotRunning,
+}
+
+/// The properties of the slave reported in the response to `CONNECT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlaveInfo {
+    /// The resources available on the slave (`RESOURCE`), e.g. bit 2 for DAQ
+    pub resources: u8,
+    /// The basic communication mode (`COMM_MODE_BASIC`), including the byte
+    /// order and the address granularity
+    pub comm_mode_basic: u8,
+    /// The maximum length of a command or response packet
+    pub max_cto: u8,
+    /// The maximum length of a DAQ packet
+    pub max_dto: u16,
+    /// The major version of the protocol layer
+    pub protocol_version: u8,
+    /// The major version of the transport layer
+    pub transport_version: u8,
+}
+
+impl SlaveInfo {
+    /// Returns true if the slave supports data acquisition
+    pub fn supports_daq(&self) -> bool {
+        self.resources & 0x04 != 0
+    }
+
+    /// Returns true if the slave expects multi byte parameters in big endian
+    /// (Motorola) byte order
+    pub fn is_big_endian(&self) -> bool {
+        self.comm_mode_basic & 0x01 != 0
+    }
+
+    /// Returns the size of a memory element in bytes (1, 2 or 4), which is
+    /// the unit of the sizes given to [`XcpMaster::short_upload`]
+    pub fn address_granularity(&self) -> usize {
+        match (self.comm_mode_basic >> 1) & 0x03 {
+            1 => 2,
+            2 => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// The IDs and timing used by an [XcpMaster]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XcpConfig {
+    master_id: Id,
+    slave_id: Id,
+    timeout: Duration,
+    padding: Option<u8>,
+}
+
+impl XcpConfig {
+    /// Creates a configuration which sends commands with `master_id` and
+    /// expects responses and DAQ packets with `slave_id`
+    pub fn new(master_id: impl Into<Id>, slave_id: impl Into<Id>) -> Self {
+        Self {
+            master_id: master_id.into(),
+            slave_id: slave_id.into(),
+            timeout: DEFAULT_TIMEOUT,
+            padding: None,
+        }
+    }
+
+    /// Sets how long to wait for the response to a command. Defaults to
+    /// [DEFAULT_TIMEOUT].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pads commands to 8 bytes with `fill`, for slaves which require the
+    /// maximum DLC (`MAX_DLC_REQUIRED`)
+    pub fn with_padding(mut self, fill: u8) -> Self {
+        self.padding = Some(fill);
+        self
+    }
+
+    /// Returns the ID commands are sent with
+    pub fn master_id(&self) -> Id {
+        self.master_id
+    }
+
+    /// Returns the ID the slave answers with
+    pub fn slave_id(&self) -> Id {
+        self.slave_id
+    }
+
+    /// Returns how long to wait for the response to a command
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// An element of slave memory which is sampled into a DAQ packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DaqEntry {
+    /// The address of the element
+    pub address: u32,
+    /// The address extension of the element
+    pub extension: u8,
+    /// The size of the element in memory elements (see
+    /// [`SlaveInfo::address_granularity`])
+    pub size: u8,
+}
+
+impl DaqEntry {
+    /// Creates an entry for `size` memory elements at `address`, with
+    /// address extension 0
+    pub fn new(address: u32, size: u8) -> Self {
+        Self {
+            address,
+            extension: 0,
+            size,
+        }
+    }
+
+    /// Sets the address extension
+    pub fn with_extension(mut self, extension: u8) -> Self {
+        self.extension = extension;
+        self
+    }
+}
+
+/// A DAQ list to set up with [`XcpMaster::configure_daq`]: the memory
+/// sampled whenever an event channel of the slave fires, split into ODTs
+/// (object descriptor tables) which each fill one DAQ packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaqList {
+    /// The event channel which triggers the sampling
+    pub event_channel: u16,
+    /// Only every n-th event is sampled
+    pub prescaler: u8,
+    /// The priority of the list, 0 being the lowest
+    pub priority: u8,
+    /// The entries of each ODT. The entries of an ODT must fit into a DAQ
+    /// packet after its PID.
+    pub odts: Vec<Vec<DaqEntry>>,
+}
+
+impl DaqList {
+    /// Creates a list without ODTs which samples every event of
+    /// `event_channel`
+    pub fn new(event_channel: u16) -> Self {
+        Self {
+            event_channel,
+            prescaler: 1,
+            priority: 0,
+            odts: Vec::new(),
+        }
+    }
+
+    /// Appends an ODT with the given entries
+    pub fn with_odt(mut self, entries: Vec<DaqEntry>) -> Self {
+        self.odts.push(entries);
+        self
+    }
+
+    /// Sets the prescaler
+    pub fn with_prescaler(mut self, prescaler: u8) -> Self {
+        self.prescaler = prescaler;
+        self
+    }
+
+    /// Sets the priority
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// A DAQ packet received from the slave: the sampled memory of one ODT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaqPacket {
+    /// The index of the DAQ list the packet belongs to
+    pub list: u16,
+    /// The index of the ODT within the list
+    pub odt: u8,
+    /// The sampled memory, with the entries of the ODT in order
+    pub data: Vec<u8>,
+}
+
+/// The range of PIDs assigned to a running DAQ list
+#[derive(Debug, Clone, Copy)]
+struct RunningList {
+    list: u16,
+    first_pid: u8,
+    odts: u8,
+}
+
+/// Talks to an XCP slave through a socket. See the
+/// [module documentation](self).
+pub struct XcpMaster<'a, P> {
+    socket: &'a mut CanSocket<P>,
+    config: XcpConfig,
+    slave: Option<SlaveInfo>,
+    daq_lists: Vec<DaqList>,
+    running: Vec<RunningList>,
+    /// DAQ packets which arrived while waiting for a response
+    daq_queue: VecDeque<Vec<u8>>,
+}
+
+impl<'a, P: AsyncRead + AsyncWrite> XcpMaster<'a, P> {
+    /// Creates a master which talks to the slave described by `config`
+    /// through `socket`, which must be open
+    pub fn new(socket: &'a mut CanSocket<P>, config: XcpConfig) -> Self {
+        Self {
+            socket,
+            config,
+            slave: None,
+            daq_lists: Vec::new(),
+            running: Vec::new(),
+            daq_queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> &XcpConfig {
+        &self.config
+    }
+
+    /// Returns the properties of the slave if connected
+    pub fn slave_info(&self) -> Option<&SlaveInfo> {
+        self.slave.as_ref()
+    }
+
+    /// Connects to the slave in normal mode (`CONNECT`) and returns its
+    /// properties
+    pub async fn connect(&mut self) -> Result<SlaveInfo, XcpError> {
+        let response = self.command(&[command::CONNECT, 0x00]).await?;
+
+        let [_, resources, comm_mode_basic, max_cto, dto_0, dto_1, protocol_version, transport_version, ..] =
+            response[..]
+        else {
+            return Err(XcpError::MalformedResponse(command::CONNECT));
+        };
+
+        let big_endian = comm_mode_basic & 0x01 != 0;
+        let max_dto = if big_endian {
+            u16::from_be_bytes([dto_0, dto_1])
+        } else {
+            u16::from_le_bytes([dto_0, dto_1])
+        };
+
+        let info = SlaveInfo {
+            resources,
+            comm_mode_basic,
+            max_cto,
+            max_dto,
+            protocol_version,
+            transport_version,
+        };
+
+        self.slave = Some(info);
+        Ok(info)
+    }
+
+    /// Disconnects from the slave (`DISCONNECT`), which also stops all DAQ
+    /// lists
+    pub async fn disconnect(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+        self.command(&[command::DISCONNECT]).await?;
+
+        self.slave = None;
+        self.running.clear();
+        self.daq_queue.clear();
+        Ok(())
+    }
+
+    /// Reads `elements` memory elements (see
+    /// [`SlaveInfo::address_granularity`]) at `address` with a single
+    /// command (`SHORT_UPLOAD`). The data must fit into one response packet.
+    pub async fn short_upload(
+        &mut self,
+        address: u32,
+        extension: u8,
+        elements: u8,
+    ) -> Result<Vec<u8>, XcpError> {
+        let info = self.connected()?;
+        let granularity = info.address_granularity();
+        let length = elements as usize * granularity;
+
+        // The data is aligned to the granularity after the PID
+        if granularity + length > info.max_cto as usize {
+            return Err(XcpError::InvalidLength(length));
+        }
+
+        let mut packet = vec![command::SHORT_UPLOAD, elements, 0, extension];
+        packet.extend_from_slice(&self.dword(address));
+
+        let response = self.command(&packet).await?;
+        response
+            .get(granularity..granularity + length)
+            .map(<[u8]>::to_vec)
+            .ok_or(XcpError::MalformedResponse(command::SHORT_UPLOAD))
+    }
+
+    /// Replaces the DAQ lists of the slave with `lists` (`FREE_DAQ`,
+    /// `ALLOC_DAQ`, `ALLOC_ODT`, `ALLOC_ODT_ENTRY`, `WRITE_DAQ` and
+    /// `SET_DAQ_LIST_MODE`). The lists are numbered in the given order and
+    /// don't run until [`start_daq`](XcpMaster::start_daq) is called.
+    pub async fn configure_daq(&mut self, lists: &[DaqList]) -> Result<(), XcpError> {
+        let info = self.connected()?;
+
+        for list in lists {
+            for odt in &list.odts {
+                let length =
+                    odt.iter().map(|e| e.size as usize).sum::<usize>() * info.address_granularity();
+
+                if 1 + length > info.max_dto as usize {
+                    return Err(XcpError::InvalidLength(length));
+                }
+            }
+        }
+
+        self.running.clear();
+        self.daq_lists.clear();
+
+        self.command(&[command::FREE_DAQ]).await?;
+
+        let mut packet = vec![command::ALLOC_DAQ, 0];
+        packet.extend_from_slice(&self.word(lists.len() as u16));
+        self.command(&packet).await?;
+
+        for (index, list) in lists.iter().enumerate() {
+            let mut packet = vec![command::ALLOC_ODT, 0];
+            packet.extend_from_slice(&self.word(index as u16));
+            packet.push(list.odts.len() as u8);
+            self.command(&packet).await?;
+        }
+
+        for (index, list) in lists.iter().enumerate() {
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let mut packet = vec![command::ALLOC_ODT_ENTRY, 0];
+                packet.extend_from_slice(&self.word(index as u16));
+                packet.extend_from_slice(&[odt as u8, entries.len() as u8]);
+                self.command(&packet).await?;
+            }
+        }
+
+        for (index, list) in lists.iter().enumerate() {
+            for (odt, entries) in list.odts.iter().enumerate() {
+                // The pointer advances to the next entry with every WRITE_DAQ
+                let mut packet = vec![command::SET_DAQ_PTR, 0];
+                packet.extend_from_slice(&self.word(index as u16));
+                packet.extend_from_slice(&[odt as u8, 0]);
+                self.command(&packet).await?;
+
+                for entry in entries {
+                    // A bit offset of 0xFF means the whole element is sampled
+                    let mut packet = vec![command::WRITE_DAQ, 0xFF, entry.size, entry.extension];
+                    packet.extend_from_slice(&self.dword(entry.address));
+                    self.command(&packet).await?;
+                }
+            }
+
+            // Mode 0: absolute ODT numbers, no timestamps, DAQ direction
+            let mut packet = vec![command::SET_DAQ_LIST_MODE, 0];
+            packet.extend_from_slice(&self.word(index as u16));
+            packet.extend_from_slice(&self.word(list.event_channel));
+            packet.extend_from_slice(&[list.prescaler, list.priority]);
+            self.command(&packet).await?;
+        }
+
+        self.daq_lists = lists.to_vec();
+        Ok(())
+    }
+
+    /// Starts all DAQ lists set up with
+    /// [`configure_daq`](XcpMaster::configure_daq) at once
+    /// (`START_STOP_DAQ_LIST` and `START_STOP_SYNCH`)
+    pub async fn start_daq(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+
+        let odts: Vec<u8> = self.daq_lists.iter().map(|l| l.odts.len() as u8).collect();
+        let mut running = Vec::new();
+
+        for (index, odts) in odts.into_iter().enumerate() {
+            // Mode 2 selects the list for the synchronous start
+            let mut packet = vec![command::START_STOP_DAQ_LIST, 2];
+            packet.extend_from_slice(&self.word(index as u16));
+
+            let response = self.command(&packet).await?;
+            let first_pid = *response
+                .get(1)
+                .ok_or(XcpError::MalformedResponse(command::START_STOP_DAQ_LIST))?;
+
+            running.push(RunningList {
+                list: index as u16,
+                first_pid,
+                odts,
+            });
+        }
+
+        // Mode 1 starts the selected lists
+        self.command(&[command::START_STOP_SYNCH, 1]).await?;
+
+        self.running = running;
+        self.daq_queue.clear();
+        Ok(())
+    }
+
+    /// Stops all DAQ lists (`START_STOP_SYNCH`). DAQ packets which were
+    /// received but not read yet are discarded.
+    pub async fn stop_daq(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+
+        // Mode 0 stops all lists
+        self.command(&[command::START_STOP_SYNCH, 0]).await?;
+
+        self.running.clear();
+        self.daq_queue.clear();
+        Ok(())
+    }
+
+    /// Waits for the next DAQ packet of the running lists. Packets with
+    /// PIDs which don't belong to any running list are discarded.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel safe.
+    pub async fn read_daq(&mut self) -> Result<DaqPacket, XcpError> {
+        if self.running.is_empty() {
+            return Err(XcpError::DaqNotRunning);
+        }
+
+        loop {
+            let packet = match self.daq_queue.pop_front() {
+                Some(packet) => packet,
+                None => {
+                    let slave_id = self.config.slave_id;
+                    let frame = self
+                        .socket
+                        .read_matching(|frame| frame.id() == slave_id)
+                        .await?;
+
+                    match packet_data(&frame) {
+                        Some(data) if data[0] < FIRST_CTO_PID => data.to_vec(),
+                        _ => continue,
+                    }
+                }
+            };
+
+            let pid = packet[0];
+            let Some(list) = self
+                .running
+                .iter()
+                .find(|list| pid.wrapping_sub(list.first_pid) < list.odts)
+            else {
+                continue;
+            };
+
+            return Ok(DaqPacket {
+                list: list.list,
+                odt: pid - list.first_pid,
+                data: packet[1..].to_vec(),
+            });
+        }
+    }
+
+    /// Sends a command packet and waits for the positive response, which is
+    /// returned including its PID. DAQ packets received in the meantime are
+    /// kept for [`read_daq`](XcpMaster::read_daq), events and service
+    /// requests are discarded.
+    ///
+    /// # Cancel Safety
+    ///
+    /// Cancelling while waiting for the response leaves the slave in an
+    /// unknown state, so the response to the next command may be the one
+    /// of the cancelled command.
+    pub async fn command(&mut self, packet: &[u8]) -> Result<Vec<u8>, XcpError> {
+        let Some(&pid) = packet.first() else {
+            return Err(XcpError::InvalidLength(0));
+        };
+
+        let max_cto = self.slave.map_or(8, |info| info.max_cto as usize);
+        if packet.len() > max_cto {
+            return Err(XcpError::InvalidLength(packet.len()));
+        }
+
+        let mut data = packet.to_vec();
+        if let Some(fill) = self.config.padding {
+            data.resize(data.len().max(8), fill);
+        }
+
+        let frame: CanFrame = if data.len() <= 8 {
+            Can2Frame::new_data(self.config.master_id, &data)
+                .ok_or(XcpError::InvalidLength(data.len()))?
+                .into()
+        } else {
+            CanFdFrame::new_padded(self.config.master_id, &data)
+                .ok_or(XcpError::InvalidLength(data.len()))?
+                .into()
+        };
+
+        self.socket.send(frame).await?;
+
+        let slave_id = self.config.slave_id;
+        let deadline = Instant::now() + self.config.timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(frame) = self
+                .socket
+                .await_matching(|frame| frame.id() == slave_id, remaining)
+                .await?
+            else {
+                return Err(XcpError::Timeout(pid));
+            };
+
+            let Some(data) = packet_data(&frame) else {
+                continue;
+            };
+
+            match data[0] {
+                RES => return Ok(data.to_vec()),
+                ERR => {
+                    return Err(XcpError::Rejected {
+                        command: pid,
+                        code: data.get(1).copied().unwrap_or(0),
+                    })
+                }
+                pid if pid < FIRST_CTO_PID => self.daq_queue.push_back(data.to_vec()),
+                _ => {}
+            }
+        }
+    }
+
+    fn connected(&self) -> Result<SlaveInfo, XcpError> {
+        self.slave.ok_or(XcpError::NotConnected)
+    }
+
+    /// Encodes a WORD parameter in the byte order of the slave
+    fn word(&self, value: u16) -> [u8; 2] {
+        match self.slave {
+            Some(info) if info.is_big_endian() => value.to_be_bytes(),
+            _ => value.to_le_bytes(),
+        }
+    }
+
+    /// Encodes a DWORD parameter in the byte order of the slave
+    fn dword(&self, value: u32) -> [u8; 4] {
+        match self.slave {
+            Some(info) if info.is_big_endian() => value.to_be_bytes(),
+            _ => value.to_le_bytes(),
+        }
+    }
+}
+
+/// Returns the data of a frame if it holds a packet, i.e. isn't a remote
+/// frame or empty
+fn packet_data(frame: &CanFrame) -> Option<&[u8]> {
+    frame.data().filter(|data| !data.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+        task::JoinHandle,
+    };
+
+    use super::*;
+    use crate::{NominalBitRate, StandardId};
+
+    /// A CONNECT response of a slave with DAQ support, a MAX_CTO of 8 and
+    /// byte granularity
+    const CONNECTED: &str = "t7E18FF04000800080101";
+
+    /// Emulates a gateway with a slave behind it. Configuration commands are
+    /// accepted and every frame the master sends is answered with the next of
+    /// `responses`, which may hold several lines. Returns the frames sent by
+    /// the master once the socket is dropped.
+    async fn gateway(mut port: DuplexStream, responses: Vec<&'static str>) -> Vec<String> {
+        let mut responses = responses.into_iter();
+        let mut frames = Vec::new();
+        let mut line = Vec::new();
+        let mut byte = [0];
+
+        while port.read(&mut byte).await.unwrap_or(0) == 1 {
+            if byte[0] != b'\r' {
+                line.push(byte[0]);
+                continue;
+            }
+
+            let line = String::from_utf8(std::mem::take(&mut line)).unwrap();
+            let answer = if line.starts_with(['t', 'd', 'b']) {
+                frames.push(line);
+                match responses.next() {
+                    Some(response) => format!("z\r{response}\r"),
+                    None => "z\r".to_string(),
+                }
+            } else {
+                "\r".to_string()
+            };
+
+            if port.write_all(answer.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+
+        frames
+    }
+
+    /// Opens a socket connected to a [gateway] which answers with
+    /// `responses`. The gateway returns the frames the master sent once the
+    /// socket is dropped.
+    async fn open_socket(
+        responses: Vec<&'static str>,
+    ) -> (CanSocket<DuplexStream>, JoinHandle<Vec<String>>) {
+        let (port, remote) = tokio::io::duplex(1024);
+        let gateway = tokio::spawn(gateway(remote, responses));
+
+        let mut socket = CanSocket::new(port);
+        socket.open(NominalBitRate::Rate500Kbit).await.unwrap();
+        (socket, gateway)
+    }
+
+    fn config() -> XcpConfig {
+        XcpConfig::new(
+            StandardId::new(0x7E0).unwrap(),
+            StandardId::new(0x7E1).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn encodes_commands_of_any_length() {
+        let (mut socket, gateway) = open_socket(vec![CONNECTED, "t7E11FF", "t7E14FFAABBCC"]).await;
+        let mut xcp = XcpMaster::new(&mut socket, config());
+
+        xcp.connect().await.unwrap();
+        assert_eq!(xcp.command(&[command::GET_STATUS]).await.unwrap(), [RES]);
+        assert_eq!(
+            xcp.short_upload(0x1234, 0, 3).await.unwrap(),
+            [0xAA, 0xBB, 0xCC]
+        );
+        assert!(matches!(
+            xcp.command(&[0; 9]).await,
+            Err(XcpError::InvalidLength(9))
+        ));
+        assert!(matches!(
+            xcp.command(&[]).await,
+            Err(XcpError::InvalidLength(0))
+        ));
+
+        drop(socket);
+        assert_eq!(
+            gateway.await.unwrap(),
+            ["t7E02FF00", "t7E01FD", "t7E08F403000034120000"]
+        );
+    }
+
+    #[tokio::test]
+    async fn pads_commands_to_8_bytes() {
+        let (mut socket, gateway) = open_socket(vec![CONNECTED]).await;
+        let mut xcp = XcpMaster::new(&mut socket, config().with_padding(0xCC));
+
+        xcp.connect().await.unwrap();
+
+        drop(socket);
+        assert_eq!(gateway.await.unwrap(), ["t7E08FF00CCCCCCCCCCCC"]);
+    }
+
+    #[tokio::test]
+    async fn sends_long_commands_as_fd_frames() {
+        let (mut socket, gateway) = open_socket(vec!["t7E18FF04000C00080101", "t7E11FF"]).await;
+        let mut xcp = XcpMaster::new(&mut socket, config());
+
+        assert_eq!(xcp.connect().await.unwrap().max_cto, 12);
+        xcp.command(&[0xF0, 1, 2, 3, 4, 5, 6, 7, 8]).await.unwrap();
+        assert!(matches!(
+            xcp.command(&[0; 13]).await,
+            Err(XcpError::InvalidLength(13))
+        ));
+
+        drop(socket);
+        assert_eq!(
+            gateway.await.unwrap(),
+            ["t7E02FF00", "b7E09F00102030405060708000000"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_responses() {
+        let (mut socket, _gateway) = open_socket(vec![
+            "t7E17FF040008000801",
+            CONNECTED,
+            "t7E13FFAABB",
+            "t7E12FE20",
+            "t7E11FE",
+            "t7E10\rt7E11FF",
+        ])
+        .await;
+        let mut xcp = XcpMaster::new(&mut socket, config());
+
+        assert!(matches!(
+            xcp.connect().await,
+            Err(XcpError::MalformedResponse(command::CONNECT))
+        ));
+        assert!(matches!(
+            xcp.short_upload(0x1234, 0, 4).await,
+            Err(XcpError::NotConnected)
+        ));
+
+        xcp.connect().await.unwrap();
+        assert!(matches!(
+            xcp.short_upload(0x1234, 0, 4).await,
+            Err(XcpError::MalformedResponse(command::SHORT_UPLOAD))
+        ));
+        assert!(matches!(
+            xcp.command(&[command::GET_STATUS]).await,
+            Err(XcpError::Rejected {
+                command: command::GET_STATUS,
+                code: 0x20
+            })
+        ));
+        assert!(matches!(
+            xcp.command(&[command::GET_STATUS]).await,
+            Err(XcpError::Rejected {
+                command: command::GET_STATUS,
+                code: 0
+            })
+        ));
+        // Empty packets are skipped
+        assert_eq!(xcp.command(&[command::GET_STATUS]).await.unwrap(), [RES]);
+    }
+
+    #[tokio::test]
+    async fn reads_daq_packets() {
+        let mut responses = vec![CONNECTED];
+        // FREE_DAQ, ALLOC_DAQ, ALLOC_ODT, 2 ALLOC_ODT_ENTRY, 2 SET_DAQ_PTR and
+        // WRITE_DAQ and SET_DAQ_LIST_MODE
+        responses.extend(["t7E11FF"; 10]);
+        responses.extend([
+            "t7E12FF10",
+            "t7E11FF\rt7E1311AABB\rt7E1220CC\rt7E1310DDEE",
+            "t7E12100A\rt7E11FF",
+            "t7E11FF",
+        ]);
+
+        let (mut socket, _gateway) = open_socket(responses).await;
+        let mut xcp = XcpMaster::new(&mut socket, config());
+
+        xcp.connect().await.unwrap();
+        assert!(matches!(xcp.read_daq().await, Err(XcpError::DaqNotRunning)));
+
+        let list = DaqList::new(0)
+            .with_odt(vec![DaqEntry::new(0x2000_0010, 2)])
+            .with_odt(vec![DaqEntry::new(0x2000_0020, 1)]);
+        xcp.configure_daq(&[list]).await.unwrap();
+        xcp.start_daq().await.unwrap();
+
+        let packet = |odt, data: &[u8]| DaqPacket {
+            list: 0,
+            odt,
+            data: data.to_vec(),
+        };
+
+        assert_eq!(xcp.read_daq().await.unwrap(), packet(1, &[0xAA, 0xBB]));
+        // PID 0x20 doesn't belong to the running list
+        assert_eq!(xcp.read_daq().await.unwrap(), packet(0, &[0xDD, 0xEE]));
+
+        // DAQ packets received while waiting for a response are kept
+        xcp.command(&[command::GET_STATUS]).await.unwrap();
+        assert_eq!(xcp.read_daq().await.unwrap(), packet(0, &[0x0A]));
+
+        xcp.stop_daq().await.unwrap();
+        assert!(matches!(xcp.read_daq().await, Err(XcpError::DaqNotRunning)));
+    }
+}